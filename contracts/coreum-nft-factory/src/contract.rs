@@ -0,0 +1,3773 @@
+#[cfg(not(feature = "library"))]
+use cosmwasm_std::entry_point;
+use cosmwasm_std::{
+    attr, from_binary, to_binary, Addr, Api, BankMsg, Binary, BlockInfo, Coin, Deps, DepsMut,
+    Empty, Env, MessageInfo, Order, Reply, ReplyOn, Response, StdError, StdResult, Storage,
+    SubMsg, Uint128, WasmMsg,
+};
+use cw721::{Cw721ExecuteMsg, Cw721ReceiveMsg};
+use cw_storage_plus::{Bound, Index, IndexList, IndexedMap, Item, Map, MultiIndex};
+use cw_utils::{parse_reply_instantiate_data, Expiration};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+pub use coreum_nft_types::{
+    ConfigError, FinalizeActions, Phase, ProptixDeploymentConfig, ProptixRuntimeConfig,
+    TokenIdFormat,
+};
+
+// Version info for migration
+pub const CONTRACT_NAME: &str = "crates.io:coreum-nft-factory";
+pub const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
+// Commit the wasm was built from, set through the `BUILD_COMMIT` env var at build time
+pub const BUILD_COMMIT: Option<&str> = option_env!("BUILD_COMMIT");
+
+/// Ids of the submessages the factory dispatches, routed back to their handler by `reply`.
+/// The values are part of in-flight submessages and must never be reused.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReplyId {
+    InstantiateCollection = 1,
+}
+
+impl TryFrom<u64> for ReplyId {
+    type Error = StdError;
+
+    fn try_from(id: u64) -> StdResult<Self> {
+        match id {
+            1 => Ok(ReplyId::InstantiateCollection),
+            id => Err(StdError::generic_err(format!("Unknown reply id: {}", id))),
+        }
+    }
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum ContractError {
+    #[error("{0}")]
+    Std(#[from] StdError),
+
+    #[error("Factory is paused, no collection can be deployed")]
+    FactoryPaused {},
+}
+
+impl From<ConfigError> for ContractError {
+    fn from(err: ConfigError) -> Self {
+        ContractError::Std(err.into())
+    }
+}
+
+const DEFAULT_LIMIT: u32 = 10;
+const MAX_LIMIT: u32 = 100;
+// Every entry of `CollectionsDetailed` costs a query to the collection
+const MAX_DETAILED_LIMIT: u32 = 10;
+// Every entry of `CollectionStates` costs two queries to the collection
+const MAX_STATES_LIMIT: u32 = 5;
+
+// Users a single `BatchSetWhitelist` may update, across all its collections
+const MAX_WHITELIST_BATCH: usize = 100;
+
+pub const STATE: Item<State> = Item::new("state");
+
+// Collections deployed by every creator, in deployment order
+pub const CONTRACTS: Map<&Addr, Vec<String>> = Map::new("contracts");
+
+// Every collection deployed through the factory, in deployment order
+pub const ALL_COLLECTIONS: Item<Vec<String>> = Item::new("all_collections");
+
+// Number of collections ever deployed, so it can be read without loading `ALL_COLLECTIONS`
+pub const TOTAL_COLLECTIONS: Item<u64> = Item::new("total_collections");
+
+// Creator of the collection whose instantiation is in flight, read back in `reply`
+const PENDING_CREATOR: Item<Addr> = Item::new("pending_creator");
+
+// Configs of the collection whose instantiation is in flight, moved to `COLLECTION_CONFIGS`
+// in `reply`
+const PENDING_CONFIGS: Item<StoredConfigs> = Item::new("pending_configs");
+
+// Configs every collection was created with, as sent by its creator, so `CloneCollection`
+// can deploy it again. Collections deployed before they were recorded have no entry.
+pub const COLLECTION_CONFIGS: Map<&Addr, StoredConfigs> = Map::new("collection_configs");
+
+// `StoredConfigs::version` of the records written by this code. Fields added to the configs
+// later need serde defaults, so that records of earlier versions still load.
+pub const STORED_CONFIGS_VERSION: u16 = 1;
+
+// Creator of every deployed collection, keyed by collection address
+pub const COLLECTION_CREATORS: Map<&Addr, Addr> = Map::new("collection_creators");
+
+// Collections removed through `DeregisterCollection`, with the block time they were removed
+// at. They can't be registered again.
+pub const DEREGISTERED_COLLECTIONS: Map<&Addr, u64> = Map::new("deregistered_collections");
+
+// Block every collection was deployed at, keyed by collection address. Collections deployed
+// before it was recorded have no entry.
+pub const COLLECTION_CREATIONS: Map<&Addr, CollectionCreation> = Map::new("collection_creations");
+
+// `COLLECTION_CREATIONS` indexed by creation time, in seconds
+pub const COLLECTIONS_BY_CREATION: Map<(u64, &Addr), Empty> = Map::new("collections_by_creation");
+
+// Base uri and reveal status recorded through `SetBaseURI`, keyed by collection address
+pub const COLLECTION_BASE_URIS: Map<&Addr, String> = Map::new("collection_base_uris");
+pub const COLLECTION_URI_STATUSES: Map<&Addr, bool> = Map::new("collection_uri_statuses");
+
+// Mirror of the whitelists relayed to every collection, keyed by (collection, user)
+pub const COLLECTION_WHITELISTS: Map<(&Addr, &Addr), Empty> = Map::new("collection_whitelists");
+
+// One list of users per collection, as stored before `COLLECTION_WHITELISTS`. Moved over
+// by `migrate`.
+const LEGACY_WHITELISTED_USERS: Map<&Addr, Vec<String>> = Map::new("whitelisted_users");
+
+// Collection every reported class was issued in, keyed by class id
+pub const CLASS_LOCATIONS: Map<&str, String> = Map::new("class_locations");
+
+// Every `SetPaused`, keyed by a sequence number starting at 1.
+// Entries are only ever appended.
+pub const PAUSE_HISTORY: Map<u64, PauseRecord> = Map::new("pause_history");
+
+// Configs saved through `SaveTemplate`, keyed by template name
+pub const TEMPLATES: Map<&str, (ProptixDeploymentConfig, ProptixRuntimeConfig)> =
+    Map::new("templates");
+
+/// Configs a collection was created with, as recorded in `COLLECTION_CONFIGS`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct StoredConfigs {
+    /// `STORED_CONFIGS_VERSION` at the time the record was written
+    #[serde(default)]
+    pub version: u16,
+    pub deployment_config: ProptixDeploymentConfig,
+    pub runtime_config: ProptixRuntimeConfig,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PauseRecord {
+    /// Whether the factory was paused or unpaused
+    pub paused: bool,
+    pub sender: Addr,
+    /// Block time in seconds
+    pub at: u64,
+    pub reason: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CollectionCreation {
+    /// Block time of the instantiation, in seconds
+    pub created_at: u64,
+    pub created_height: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Stake {
+    pub staker: Addr,
+    /// Block time the token was staked at, in seconds
+    pub staked_at: u64,
+}
+
+pub struct StakeIndexes<'a> {
+    pub staker: MultiIndex<'a, Addr, Stake, (Addr, String)>,
+}
+
+impl<'a> IndexList<Stake> for StakeIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<Stake>> + '_> {
+        let v: Vec<&dyn Index<Stake>> = vec![&self.staker];
+        Box::new(v.into_iter())
+    }
+}
+
+/// Tokens staked into the factory keyed by (collection, token id), indexed by staker
+pub fn stakes<'a>() -> IndexedMap<'a, (&'a Addr, &'a str), Stake, StakeIndexes<'a>> {
+    let indexes = StakeIndexes {
+        staker: MultiIndex::new(|_pk, stake| stake.staker.clone(), "stakes", "stakes__staker"),
+    };
+    IndexedMap::new("stakes", indexes)
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct State {
+    /// Unset once the ownership is renounced, which locks every owner message for good
+    pub owner: Option<Addr>,
+    /// Account proposed through `ProposeOwner`, until it accepts
+    #[serde(default)]
+    pub pending_owner: Option<Addr>,
+    #[serde(default)]
+    pub pending_expiry: Option<Expiration>,
+    /// Code id collections are instantiated from
+    pub collection_code_id: u64,
+    /// Number of collections a single creator may deploy, 0 for no limit
+    pub max_collections_per_creator: u32,
+    /// Blocks `CreateCollection` while set, deployed collections keep working
+    #[serde(default)]
+    pub factory_paused: bool,
+    /// Share of the sales of collections deployed from now on paid to the factory, in
+    /// basis points
+    #[serde(default)]
+    pub factory_fee_bps: u16,
+}
+
+impl State {
+    pub fn is_owner(&self, sender: &Addr) -> bool {
+        self.owner.as_ref() == Some(sender)
+    }
+
+    /// Ensures `sender` is the owner, every owner message goes through it
+    pub fn assert_owner(&self, sender: &Addr) -> StdResult<()> {
+        if !self.is_owner(sender) {
+            return Err(StdError::generic_err("Unauthorized"));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct InstantiateMsg {
+    pub collection_code_id: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub enum ExecuteMsg {
+    /// Instantiates a new collection owned by the factory on behalf of the sender
+    CreateCollection {
+        deployment_config: ProptixDeploymentConfig,
+        runtime_config: ProptixRuntimeConfig,
+    },
+    SetBaseURI {
+        collection: String,
+        uri: String,
+        status: bool,
+    },
+    SetWhitelist {
+        collection: String,
+        user: String,
+        status: bool,
+    },
+    /// `SetWhitelist` for several users of several collections, relayed as one message per
+    /// entry. Every collection must be deployed by the sender, unless it is the owner.
+    BatchSetWhitelist { entries: Vec<WhitelistEntry> },
+    /// Sets how many collections a single creator may deploy, 0 for no limit.
+    /// Only callable by the owner.
+    SetCreatorLimit {
+        max_collections_per_creator: u32,
+    },
+    /// Sets the share of their sales collections deployed from now on pay to the factory,
+    /// in basis points. Deployed collections keep their fee. Only callable by the owner.
+    SetFactoryFee { factory_fee_bps: u16 },
+    /// Sends `amount` of the `denom` fees the factory received from collection sales to
+    /// `recipient`. Keeps working while the factory is paused. Only callable by the owner.
+    WithdrawFees {
+        denom: String,
+        amount: Uint128,
+        recipient: String,
+    },
+    /// Stops new deployments, clones included, while `paused`, or lets collections be
+    /// deployed again. `reason` is kept in the pause history. Only callable by the owner.
+    SetPaused {
+        paused: bool,
+        #[serde(default)]
+        reason: String,
+    },
+    /// cw721 receive hook, called by a collection when a token is sent to the factory
+    #[serde(rename = "receive_nft")]
+    ReceiveNft(Cw721ReceiveMsg),
+    /// Returns a staked token to its staker. Only callable by the staker.
+    Unstake {
+        collection: String,
+        token_id: String,
+    },
+    /// Proposes `new_owner` as the factory owner, replacing any pending proposal. The
+    /// ownership only moves once it is accepted. Only callable by the owner.
+    ProposeOwner {
+        new_owner: String,
+        expiry: Option<Expiration>,
+    },
+    /// Takes over the ownership proposed to the sender, if it hasn't expired
+    AcceptOwnership {},
+    /// Leaves the factory without an owner, for good. Only callable by the owner.
+    RenounceOwnership {},
+    /// Removes `address` from the registry for good. Tokens staked from it can still be
+    /// unstaked. Only callable by the collection creator or the owner.
+    DeregisterCollection { address: String },
+    /// Records `class_id` as issued in the sender. Only callable by collections
+    /// deployed through the factory, each class id can be reported once.
+    ReportClass { class_id: String },
+    /// Saves configs creators can deploy from under `name`, replacing any template of the
+    /// same name. Only callable by the owner.
+    SaveTemplate {
+        name: String,
+        deployment_config: ProptixDeploymentConfig,
+        runtime_config: ProptixRuntimeConfig,
+    },
+    /// `CreateCollection` with the configs of the template `name`, `overrides` applied
+    CreateFromTemplate {
+        name: String,
+        #[serde(default)]
+        overrides: TemplateOverrides,
+    },
+    /// `CreateCollection` with the configs `source` was created with, `overrides` applied.
+    /// Only callable by the creator of `source` or the owner.
+    CloneCollection {
+        source: String,
+        #[serde(default)]
+        overrides: TemplateOverrides,
+    },
+}
+
+/// Fields replacing the ones of a template in `CreateFromTemplate` or of the cloned
+/// collection in `CloneCollection`, unset ones are kept
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+#[serde(default)]
+pub struct TemplateOverrides {
+    pub name: Option<String>,
+    pub symbol: Option<String>,
+    pub max_supply: Option<u64>,
+    pub treasury_address: Option<String>,
+    pub base_token_uri: Option<String>,
+    pub prereveal_token_uri: Option<String>,
+    pub mint_price: Option<Uint128>,
+    pub sale_start_time: Option<u64>,
+    pub sale_end_time: Option<u64>,
+}
+
+impl TemplateOverrides {
+    fn apply(
+        self,
+        deployment_config: &mut ProptixDeploymentConfig,
+        runtime_config: &mut ProptixRuntimeConfig,
+    ) {
+        if let Some(name) = self.name {
+            deployment_config.name = name;
+        }
+        if let Some(symbol) = self.symbol {
+            deployment_config.symbol = symbol;
+        }
+        if let Some(max_supply) = self.max_supply {
+            deployment_config.max_supply = max_supply;
+        }
+        if let Some(treasury_address) = self.treasury_address {
+            deployment_config.treasury_address = treasury_address;
+        }
+        if let Some(base_token_uri) = self.base_token_uri {
+            runtime_config.base_token_uri = base_token_uri;
+        }
+        if let Some(prereveal_token_uri) = self.prereveal_token_uri {
+            runtime_config.prereveal_token_uri = prereveal_token_uri;
+        }
+        if let Some(mint_price) = self.mint_price {
+            runtime_config.mint_price = mint_price;
+        }
+        if let Some(sale_start_time) = self.sale_start_time {
+            runtime_config.sale_start_time = sale_start_time;
+        }
+        if let Some(sale_end_time) = self.sale_end_time {
+            runtime_config.sale_end_time = sale_end_time;
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct WhitelistEntry {
+    pub collection: String,
+    pub users: Vec<String>,
+    pub status: bool,
+}
+
+/// Payload of a token sent to the factory through `SendNft`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiveNftMsg {
+    Stake {},
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub enum QueryMsg {
+    /// Returns the most recent collection deployed by `owner`
+    LastDeployed { owner: String },
+    AllContracts {},
+    /// Returns the collections deployed by `owner`
+    Deployed { owner: String },
+    /// Returns the number of collections ever deployed through the factory
+    TotalCollections {},
+    /// Returns the tokens staked by `address`, ordered by (collection, token id)
+    StakedBy {
+        address: String,
+        start_after: Option<(String, String)>,
+        limit: Option<u32>,
+    },
+    /// Returns deployed collections with their deployer, name and symbol, in deployment
+    /// order. Collections that fail to answer are left out.
+    CollectionsDetailed {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Returns the collection `class_id` was reported by
+    ClassLocation { class_id: String },
+    /// Returns the pauses and unpauses of the factory, oldest first
+    PauseHistory {
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Returns the configs `address` was created with, unset for collections deployed
+    /// before the factory recorded them
+    CollectionConfig { address: String },
+    /// Returns the owner and any pending ownership transfer
+    Ownership {},
+    /// cw2 contract name and version, with the commit the code was built from if known
+    ContractVersion {},
+    /// Returns the users whitelisted through the factory for `collection`, ordered by address
+    CollectionWhitelist {
+        collection: String,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Returns the registry record of `address` merged with its config and sale phase.
+    /// A collection that can't be read is reported through `error`.
+    CollectionState { address: String },
+    /// `CollectionState` for a page of deployed collections, in deployment order
+    CollectionStates {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Returns the collections created between `from` and `to` included, in seconds,
+    /// ordered by creation time
+    CollectionsCreatedBetween {
+        from: u64,
+        to: u64,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub enum MigrateMsg {
+    /// Upgrades the factory to the current version
+    Upgrade {},
+}
+
+/// Messages the factory sends to the collections it owns
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub enum CollectionExecuteMsg {
+    Whitelist { address: String, status: bool },
+    WhitelistBatch { addresses: Vec<String>, status: bool },
+}
+
+/// Queries the factory sends to the collections it deployed
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub enum CollectionQueryMsg {
+    ContractInfo {},
+    Config {},
+    Schedule {},
+}
+
+/// Fields of the collection's `ContractInfo` response the factory reads
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CollectionContractInfo {
+    pub name: String,
+    pub symbol: String,
+}
+
+/// Fields of the collection's `Config` response the factory reads
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CollectionConfig {
+    pub mint_denom: String,
+    pub mint_price: Uint128,
+    /// 0 for an open edition that hasn't been finalized
+    pub max_supply: u64,
+    #[serde(default)]
+    pub remaining_supply: Option<u64>,
+    pub sale_active: bool,
+}
+
+/// Mirror of the collection's `SalePhase`
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+pub enum CollectionSalePhase {
+    Upcoming,
+    Presale,
+    Public,
+    Ended,
+}
+
+/// Fields of the collection's `Schedule` response the factory reads
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CollectionSchedule {
+    pub phase: CollectionSalePhase,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LastDeployedResponse {
+    pub collection: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CollectionWhitelistResponse {
+    pub users: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OwnershipResponse {
+    pub owner: Option<String>,
+    pub pending_owner: Option<String>,
+    pub pending_expiry: Option<Expiration>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ContractVersionResponse {
+    pub contract: String,
+    pub version: String,
+    pub commit: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ClassLocationResponse {
+    pub collection: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PauseHistoryEntry {
+    /// Sequence number, pass it as `start_after` to get the next page
+    pub index: u64,
+    pub record: PauseRecord,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PauseHistoryResponse {
+    pub paused: bool,
+    pub entries: Vec<PauseHistoryEntry>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CollectionConfigResponse {
+    pub configs: Option<StoredConfigs>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CollectionsResponse {
+    pub collections: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TotalCollectionsResponse {
+    pub total: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CollectionInfo {
+    pub address: String,
+    pub deployer: String,
+    pub name: String,
+    pub symbol: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CollectionsDetailedResponse {
+    pub collections: Vec<CollectionInfo>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CollectionStateResponse {
+    pub address: String,
+    /// Creator recorded by the factory, unset for a collection it didn't deploy
+    pub deployer: Option<String>,
+    pub config: Option<CollectionConfig>,
+    pub phase: Option<CollectionSalePhase>,
+    /// Why the collection couldn't be read, `config` and `phase` are unset then
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CollectionStatesResponse {
+    pub collections: Vec<CollectionStateResponse>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CreatedCollection {
+    pub address: String,
+    pub deployer: String,
+    pub created_at: u64,
+    pub created_height: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CollectionsCreatedResponse {
+    pub collections: Vec<CreatedCollection>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct StakedToken {
+    pub collection: String,
+    pub token_id: String,
+    pub staked_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct StakedResponse {
+    pub tokens: Vec<StakedToken>,
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn instantiate(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    msg: InstantiateMsg,
+) -> Result<Response, StdError> {
+    cw2::set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+
+    let state = State {
+        owner: Some(info.sender.clone()),
+        pending_owner: None,
+        pending_expiry: None,
+        collection_code_id: msg.collection_code_id,
+        max_collections_per_creator: 0,
+        factory_paused: false,
+        factory_fee_bps: 0,
+    };
+    STATE.save(deps.storage, &state)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "instantiate")
+        .add_attribute("owner", info.sender))
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn migrate(deps: DepsMut, _env: Env, msg: MigrateMsg) -> Result<Response, StdError> {
+    match msg {
+        MigrateMsg::Upgrade {} => {
+            // make sure the correct contract is being upgraded and that it isn't a downgrade
+            let stored = cw2::get_contract_version(deps.storage)?;
+            if stored.contract != CONTRACT_NAME {
+                return Err(StdError::generic_err(format!(
+                    "Cannot migrate from a different contract: {}",
+                    stored.contract
+                )));
+            }
+            if parse_version(&stored.version)? > parse_version(CONTRACT_VERSION)? {
+                return Err(StdError::generic_err(format!(
+                    "Cannot migrate from newer version {}",
+                    stored.version
+                )));
+            }
+
+            // factories deployed before the counter existed start from the stored list
+            if TOTAL_COLLECTIONS.may_load(deps.storage)?.is_none() {
+                let total = get_all_contracts(deps.storage)?.len() as u64;
+                TOTAL_COLLECTIONS.save(deps.storage, &total)?;
+            }
+
+            // whitelists stored as one list per collection move to one entry per user
+            let legacy = LEGACY_WHITELISTED_USERS
+                .range(deps.storage, None, None, Order::Ascending)
+                .collect::<StdResult<Vec<_>>>()?;
+            for (collection, users) in legacy {
+                for user in users {
+                    let user = Addr::unchecked(user);
+                    COLLECTION_WHITELISTS.save(deps.storage, (&collection, &user), &Empty {})?;
+                }
+                LEGACY_WHITELISTED_USERS.remove(deps.storage, &collection);
+            }
+
+            cw2::set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+
+            Ok(Response::new()
+                .add_attribute("action", "migrate")
+                .add_attribute("from_version", stored.version)
+                .add_attribute("to_version", CONTRACT_VERSION))
+        }
+    }
+}
+
+/// Parses a `major.minor.patch` version string so versions can be ordered
+fn parse_version(version: &str) -> StdResult<(u64, u64, u64)> {
+    let mut parts = version
+        .split(|c| c == '.' || c == '-')
+        .map(|part| part.parse::<u64>());
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(Ok(major)), Some(Ok(minor)), Some(Ok(patch))) => Ok((major, minor, patch)),
+        _ => Err(StdError::generic_err(format!(
+            "Invalid contract version: {}",
+            version
+        ))),
+    }
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn execute(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    msg: ExecuteMsg,
+) -> Result<Response, ContractError> {
+    match msg {
+        ExecuteMsg::CreateCollection {
+            deployment_config,
+            runtime_config,
+        } => create_collection(deps, env, info, deployment_config, runtime_config),
+        ExecuteMsg::SetBaseURI {
+            collection,
+            uri,
+            status,
+        } => {
+            let collection = assert_collection_creator(deps.as_ref(), &collection, &info.sender)?;
+            if status && uri.is_empty() {
+                return Err(invalid_config(
+                    "uri",
+                    "must be set to reveal the collection",
+                )
+                .into());
+            }
+            set_base_uri(deps.storage, &collection, uri, status)?;
+            Ok(Response::new()
+                .add_attribute("action", "set_base_uri")
+                .add_attribute("collection", collection))
+        }
+        ExecuteMsg::SetWhitelist {
+            collection,
+            user,
+            status,
+        } => {
+            let collection = assert_collection_creator(deps.as_ref(), &collection, &info.sender)?;
+            let user = validate_address(deps.api, "user", &user)?;
+            set_whitelist(deps.storage, &collection, &user, status)?;
+
+            // the factory owns the collection, so it relays the update
+            let relay = WasmMsg::Execute {
+                contract_addr: collection.to_string(),
+                msg: to_binary(&CollectionExecuteMsg::Whitelist {
+                    address: user.to_string(),
+                    status,
+                })?,
+                funds: vec![],
+            };
+            Ok(Response::new()
+                .add_message(relay)
+                .add_attribute("action", "set_whitelist")
+                .add_attribute("collection", collection))
+        }
+        ExecuteMsg::BatchSetWhitelist { entries } => Ok(batch_set_whitelist(deps, info, entries)?),
+        ExecuteMsg::SetCreatorLimit {
+            max_collections_per_creator,
+        } => {
+            let mut state = STATE.load(deps.storage)?;
+            state.assert_owner(&info.sender)?;
+            state.max_collections_per_creator = max_collections_per_creator;
+            STATE.save(deps.storage, &state)?;
+
+            Ok(Response::new().add_attributes(vec![
+                attr("action", "set_creator_limit"),
+                attr(
+                    "max_collections_per_creator",
+                    max_collections_per_creator.to_string(),
+                ),
+            ]))
+        }
+        ExecuteMsg::SetFactoryFee { factory_fee_bps } => {
+            let mut state = STATE.load(deps.storage)?;
+            state.assert_owner(&info.sender)?;
+            if factory_fee_bps > 10_000 {
+                return Err(invalid_config("factory_fee_bps", "must be at most 10000").into());
+            }
+            state.factory_fee_bps = factory_fee_bps;
+            STATE.save(deps.storage, &state)?;
+
+            Ok(Response::new().add_attributes(vec![
+                attr("action", "set_factory_fee"),
+                attr("factory_fee_bps", factory_fee_bps.to_string()),
+            ]))
+        }
+        ExecuteMsg::WithdrawFees {
+            denom,
+            amount,
+            recipient,
+        } => {
+            let state = STATE.load(deps.storage)?;
+            state.assert_owner(&info.sender)?;
+            let recipient = validate_address(deps.api, "recipient", &recipient)?;
+            let held = deps.querier.query_balance(&env.contract.address, &denom)?;
+            if amount.is_zero() {
+                return Err(invalid_config("amount", "must not be zero").into());
+            }
+            if amount > held.amount {
+                return Err(invalid_config(
+                    "amount",
+                    &format!("exceeds the {} held by the factory", held),
+                )
+                .into());
+            }
+            let withdrawn = Coin { denom, amount };
+
+            Ok(Response::new()
+                .add_message(BankMsg::Send {
+                    to_address: recipient.to_string(),
+                    amount: vec![withdrawn.clone()],
+                })
+                .add_attributes(vec![
+                    attr("action", "withdraw_fees"),
+                    attr("recipient", recipient),
+                    attr("amount", withdrawn.to_string()),
+                ]))
+        }
+        ExecuteMsg::SetPaused { paused, reason } => {
+            Ok(set_factory_paused(deps, env, info, paused, reason)?)
+        }
+        ExecuteMsg::ReceiveNft(msg) => Ok(receive_nft(deps, env, info, msg)?),
+        ExecuteMsg::Unstake {
+            collection,
+            token_id,
+        } => Ok(unstake(deps, info, collection, token_id)?),
+        ExecuteMsg::ReportClass { class_id } => Ok(report_class(deps, info, class_id)?),
+        ExecuteMsg::SaveTemplate {
+            name,
+            deployment_config,
+            runtime_config,
+        } => {
+            STATE.load(deps.storage)?.assert_owner(&info.sender)?;
+            if name.is_empty() {
+                return Err(invalid_config("name", "must not be empty").into());
+            }
+            deployment_config.validate()?;
+            runtime_config.validate()?;
+            validate_config_addresses(deps.api, &deployment_config, &runtime_config)?;
+            TEMPLATES.save(deps.storage, &name, &(deployment_config, runtime_config))?;
+
+            Ok(Response::new().add_attributes(vec![
+                attr("action", "save_template"),
+                attr("name", name),
+            ]))
+        }
+        ExecuteMsg::CreateFromTemplate { name, overrides } => {
+            let (mut deployment_config, mut runtime_config) = TEMPLATES
+                .may_load(deps.storage, &name)?
+                .ok_or_else(|| StdError::generic_err(format!("Template not found: {}", name)))?;
+            overrides.apply(&mut deployment_config, &mut runtime_config);
+            create_collection(deps, env, info, deployment_config, runtime_config)
+        }
+        ExecuteMsg::CloneCollection { source, overrides } => {
+            clone_collection(deps, env, info, source, overrides)
+        }
+        ExecuteMsg::DeregisterCollection { address } => {
+            Ok(deregister_collection(deps, env, info, address)?)
+        }
+        ExecuteMsg::ProposeOwner { new_owner, expiry } => {
+            let mut state = STATE.load(deps.storage)?;
+            state.assert_owner(&info.sender)?;
+            let new_owner = validate_address(deps.api, "new_owner", &new_owner)?;
+            if expiry.map_or(false, |expiry| expiry.is_expired(&env.block)) {
+                return Err(StdError::generic_err("Invalid expiry: already expired").into());
+            }
+            state.pending_owner = Some(new_owner.clone());
+            state.pending_expiry = expiry;
+            STATE.save(deps.storage, &state)?;
+
+            Ok(Response::new().add_attributes(vec![
+                attr("action", "propose_owner"),
+                attr("pending_owner", new_owner),
+            ]))
+        }
+        ExecuteMsg::AcceptOwnership {} => {
+            let mut state = STATE.load(deps.storage)?;
+            let pending_owner = state
+                .pending_owner
+                .take()
+                .ok_or_else(|| StdError::generic_err("Ownership transfer not found"))?;
+            if info.sender != pending_owner {
+                return Err(StdError::generic_err("Unauthorized").into());
+            }
+            if let Some(expiry) = state.pending_expiry.take() {
+                if expiry.is_expired(&env.block) {
+                    return Err(StdError::generic_err("Ownership transfer expired").into());
+                }
+            }
+            state.owner = Some(pending_owner.clone());
+            STATE.save(deps.storage, &state)?;
+
+            Ok(Response::new().add_attributes(vec![
+                attr("action", "accept_ownership"),
+                attr("owner", pending_owner),
+            ]))
+        }
+        ExecuteMsg::RenounceOwnership {} => {
+            let mut state = STATE.load(deps.storage)?;
+            state.assert_owner(&info.sender)?;
+            state.owner = None;
+            state.pending_owner = None;
+            state.pending_expiry = None;
+            STATE.save(deps.storage, &state)?;
+
+            Ok(Response::new().add_attribute("action", "renounce_ownership"))
+        }
+    }
+}
+
+fn deregister_collection(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    address: String,
+) -> Result<Response, StdError> {
+    let state = STATE.load(deps.storage)?;
+    let collection = validate_address(deps.api, "address", &address)?;
+    let creator = COLLECTION_CREATORS
+        .may_load(deps.storage, &collection)?
+        .ok_or_else(|| StdError::generic_err("Collection not found"))?;
+    if info.sender != creator && !state.is_owner(&info.sender) {
+        return Err(StdError::generic_err("Unauthorized"));
+    }
+
+    let mut deployed = get_deployed(deps.storage, &creator)?;
+    deployed.retain(|deployed| *deployed != collection.as_str());
+    if deployed.is_empty() {
+        CONTRACTS.remove(deps.storage, &creator);
+    } else {
+        CONTRACTS.save(deps.storage, &creator, &deployed)?;
+    }
+    let mut all_collections = get_all_contracts(deps.storage)?;
+    all_collections.retain(|deployed| *deployed != collection.as_str());
+    ALL_COLLECTIONS.save(deps.storage, &all_collections)?;
+
+    COLLECTION_CREATORS.remove(deps.storage, &collection);
+    COLLECTION_CONFIGS.remove(deps.storage, &collection);
+    COLLECTION_BASE_URIS.remove(deps.storage, &collection);
+    COLLECTION_URI_STATUSES.remove(deps.storage, &collection);
+    let whitelisted = COLLECTION_WHITELISTS
+        .prefix(&collection)
+        .keys(deps.storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    for user in whitelisted {
+        COLLECTION_WHITELISTS.remove(deps.storage, (&collection, &user));
+    }
+    if let Some(creation) = COLLECTION_CREATIONS.may_load(deps.storage, &collection)? {
+        COLLECTION_CREATIONS.remove(deps.storage, &collection);
+        COLLECTIONS_BY_CREATION.remove(deps.storage, (creation.created_at, &collection));
+    }
+    DEREGISTERED_COLLECTIONS.save(deps.storage, &collection, &env.block.time.seconds())?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "deregister_collection"),
+        attr("collection", collection),
+        attr("creator", creator),
+        attr("sender", info.sender),
+    ]))
+}
+
+fn clone_collection(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    source: String,
+    overrides: TemplateOverrides,
+) -> Result<Response, ContractError> {
+    let state = STATE.load(deps.storage)?;
+    let source = validate_address(deps.api, "source", &source)?;
+    let creator = COLLECTION_CREATORS
+        .may_load(deps.storage, &source)?
+        .ok_or_else(|| StdError::generic_err("Collection not found"))?;
+    if info.sender != creator && !state.is_owner(&info.sender) {
+        return Err(StdError::generic_err("Unauthorized").into());
+    }
+    let StoredConfigs {
+        mut deployment_config,
+        mut runtime_config,
+        ..
+    } = COLLECTION_CONFIGS
+        .may_load(deps.storage, &source)?
+        .ok_or_else(|| {
+            StdError::generic_err(format!("Configs not recorded for collection {}", source))
+        })?;
+    overrides.apply(&mut deployment_config, &mut runtime_config);
+
+    let res = create_collection(deps, env, info, deployment_config, runtime_config)?;
+    Ok(res.add_attribute("source", source))
+}
+
+fn report_class(deps: DepsMut, info: MessageInfo, class_id: String) -> Result<Response, StdError> {
+    // the sender is the collection, only those deployed through the factory are indexed
+    let collection = info.sender;
+    if !COLLECTION_CREATORS.has(deps.storage, &collection) {
+        return Err(StdError::generic_err("Collection not found"));
+    }
+    if let Some(existing) = CLASS_LOCATIONS.may_load(deps.storage, &class_id)? {
+        return Err(StdError::generic_err(format!(
+            "Class {} is already registered to {}",
+            class_id, existing
+        )));
+    }
+    CLASS_LOCATIONS.save(deps.storage, &class_id, &collection.to_string())?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "report_class"),
+        attr("class_id", class_id),
+        attr("collection", collection),
+    ]))
+}
+
+fn set_factory_paused(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    paused: bool,
+    reason: String,
+) -> StdResult<Response> {
+    let mut state = STATE.load(deps.storage)?;
+    state.assert_owner(&info.sender)?;
+    state.factory_paused = paused;
+    STATE.save(deps.storage, &state)?;
+
+    let last = PAUSE_HISTORY
+        .keys(deps.storage, None, None, Order::Descending)
+        .next()
+        .transpose()?;
+    let record = PauseRecord {
+        paused,
+        sender: info.sender,
+        at: env.block.time.seconds(),
+        reason,
+    };
+    PAUSE_HISTORY.save(deps.storage, last.unwrap_or_default() + 1, &record)?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "set_paused"),
+        attr("paused", paused.to_string()),
+    ]))
+}
+
+fn batch_set_whitelist(
+    deps: DepsMut,
+    info: MessageInfo,
+    entries: Vec<WhitelistEntry>,
+) -> Result<Response, StdError> {
+    let state = STATE.load(deps.storage)?;
+    if entries.is_empty() {
+        return Err(invalid_config("entries", "must not be empty"));
+    }
+    let total_users: usize = entries.iter().map(|entry| entry.users.len()).sum();
+    if total_users > MAX_WHITELIST_BATCH {
+        return Err(invalid_config(
+            "entries",
+            &format!("can't hold more than {} users", MAX_WHITELIST_BATCH),
+        ));
+    }
+
+    // any failing entry aborts the whole batch
+    let mut response = Response::new().add_attribute("action", "batch_set_whitelist");
+    for entry in entries {
+        let collection = validate_address(deps.api, "collection", &entry.collection)?;
+        let creator = COLLECTION_CREATORS
+            .may_load(deps.storage, &collection)?
+            .ok_or_else(|| {
+                StdError::generic_err(format!("Collection not found: {}", collection))
+            })?;
+        if info.sender != creator && !state.is_owner(&info.sender) {
+            return Err(StdError::generic_err(format!(
+                "Unauthorized for collection {}",
+                collection
+            )));
+        }
+
+        let users = entry
+            .users
+            .iter()
+            .map(|user| validate_address(deps.api, "users", user))
+            .collect::<StdResult<Vec<_>>>()?;
+        for user in &users {
+            set_whitelist(deps.storage, &collection, user, entry.status)?;
+        }
+
+        let relay = WasmMsg::Execute {
+            contract_addr: collection.to_string(),
+            msg: to_binary(&CollectionExecuteMsg::WhitelistBatch {
+                addresses: users.iter().map(Addr::to_string).collect(),
+                status: entry.status,
+            })?,
+            funds: vec![],
+        };
+        // one count per collection, keyed by its address
+        response = response
+            .add_message(relay)
+            .add_attribute(collection, users.len().to_string());
+    }
+    Ok(response)
+}
+
+fn receive_nft(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    msg: Cw721ReceiveMsg,
+) -> Result<Response, StdError> {
+    // the sender is the collection, only those deployed through the factory are accepted
+    let collection = info.sender;
+    if !COLLECTION_CREATORS.has(deps.storage, &collection) {
+        return Err(StdError::generic_err("Collection not found"));
+    }
+
+    match from_binary(&msg.msg)? {
+        ReceiveNftMsg::Stake {} => {
+            let staker = deps.api.addr_validate(&msg.sender)?;
+            let stake = Stake {
+                staker: staker.clone(),
+                staked_at: env.block.time.seconds(),
+            };
+            stakes().save(deps.storage, (&collection, &msg.token_id), &stake)?;
+
+            Ok(Response::new().add_attributes(vec![
+                attr("action", "stake"),
+                attr("collection", collection),
+                attr("token_id", msg.token_id),
+                attr("staker", staker),
+            ]))
+        }
+    }
+}
+
+fn unstake(
+    deps: DepsMut,
+    info: MessageInfo,
+    collection: String,
+    token_id: String,
+) -> Result<Response, StdError> {
+    let collection = validate_address(deps.api, "collection", &collection)?;
+    let stake = stakes()
+        .may_load(deps.storage, (&collection, &token_id))?
+        .ok_or_else(|| StdError::generic_err("Stake not found"))?;
+    if stake.staker != info.sender {
+        return Err(StdError::generic_err("Unauthorized"));
+    }
+    stakes().remove(deps.storage, (&collection, &token_id))?;
+
+    let transfer = WasmMsg::Execute {
+        contract_addr: collection.to_string(),
+        msg: to_binary(&Cw721ExecuteMsg::TransferNft {
+            recipient: stake.staker.to_string(),
+            token_id: token_id.clone(),
+        })?,
+        funds: vec![],
+    };
+    Ok(Response::new().add_message(transfer).add_attributes(vec![
+        attr("action", "unstake"),
+        attr("collection", collection),
+        attr("token_id", token_id),
+        attr("staker", stake.staker),
+    ]))
+}
+
+fn create_collection(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    mut deployment_config: ProptixDeploymentConfig,
+    runtime_config: ProptixRuntimeConfig,
+) -> Result<Response, ContractError> {
+    let state = STATE.load(deps.storage)?;
+    if state.factory_paused {
+        return Err(ContractError::FactoryPaused {});
+    }
+    deployment_config.validate()?;
+    runtime_config.validate()?;
+    validate_config_addresses(deps.api, &deployment_config, &runtime_config)?;
+    // a clone by someone else mints to them, not to the creator of the original
+    let submitted = deployment_config.clone();
+    // the factory fee is the factory's to set
+    deployment_config.factory_fee_bps = state.factory_fee_bps;
+    let fees_bps =
+        u32::from(runtime_config.protocol_fee) * 100 + u32::from(state.factory_fee_bps);
+    if fees_bps > 10_000 {
+        return Err(invalid_config(
+            "factory_fee_bps",
+            "can't exceed 100% with the protocol fee",
+        )
+        .into());
+    }
+    if fees_bps + u32::from(runtime_config.referral_bps) > 10_000 {
+        return Err(invalid_config(
+            "referral_bps",
+            "can't exceed the treasury share",
+        )
+        .into());
+    }
+    // the factory stays the collection owner, minting is up to the creator
+    deployment_config
+        .minter
+        .get_or_insert_with(|| info.sender.to_string());
+
+    // Rate-limit how many collections a single creator can deploy
+    let deployed = get_deployed(deps.storage, &info.sender)?;
+    if state.max_collections_per_creator != 0
+        && deployed.len() as u64 >= u64::from(state.max_collections_per_creator)
+    {
+        return Err(StdError::generic_err("creator collection limit reached").into());
+    }
+
+    PENDING_CREATOR.save(deps.storage, &info.sender)?;
+    let configs = StoredConfigs {
+        version: STORED_CONFIGS_VERSION,
+        deployment_config: submitted,
+        runtime_config: runtime_config.clone(),
+    };
+    PENDING_CONFIGS.save(deps.storage, &configs)?;
+    let label = format!("{} collection", deployment_config.symbol);
+    let instantiate = SubMsg {
+        msg: WasmMsg::Instantiate {
+            admin: Some(env.contract.address.to_string()),
+            code_id: state.collection_code_id,
+            msg: to_binary(&CollectionInstantiateMsg {
+                deployment_config,
+                runtime_config,
+            })?,
+            funds: vec![],
+            label,
+        }
+        .into(),
+        id: ReplyId::InstantiateCollection as u64,
+        gas_limit: None,
+        reply_on: ReplyOn::Success,
+    };
+
+    Ok(Response::new()
+        .add_submessage(instantiate)
+        .add_attribute("action", "create_collection")
+        .add_attribute("creator", info.sender))
+}
+
+/// Rejects a config the collection would refuse before its instantiation is paid for
+fn validate_config_addresses(
+    api: &dyn Api,
+    deployment_config: &ProptixDeploymentConfig,
+    runtime_config: &ProptixRuntimeConfig,
+) -> StdResult<()> {
+    let addresses = [
+        (
+            "treasury_address",
+            Some(&deployment_config.treasury_address),
+        ),
+        (
+            "protocol_address",
+            deployment_config.protocol_address.as_ref(),
+        ),
+        ("pass_collection", runtime_config.pass_collection.as_ref()),
+        ("minter", deployment_config.minter.as_ref()),
+    ];
+    for (field, value) in addresses {
+        if let Some(value) = value {
+            validate_address(api, field, value)?;
+        }
+    }
+    if runtime_config.protocol_fee > 0 && deployment_config.protocol_address.is_none() {
+        return Err(invalid_config(
+            "protocol_address",
+            "required with a protocol fee",
+        ));
+    }
+    Ok(())
+}
+
+/// Validates `value`, naming the message field it came from if it isn't a valid address
+fn validate_address(api: &dyn Api, field: &str, value: &str) -> StdResult<Addr> {
+    api.addr_validate(value)
+        .map_err(|_| StdError::generic_err(format!("Invalid address for {}: {}", field, value)))
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn reply(deps: DepsMut, env: Env, msg: Reply) -> Result<Response, StdError> {
+    match ReplyId::try_from(msg.id)? {
+        ReplyId::InstantiateCollection => reply_instantiate_collection(deps, env, msg),
+    }
+}
+
+// Reply callback triggered from collection instantiation
+fn reply_instantiate_collection(deps: DepsMut, env: Env, msg: Reply) -> StdResult<Response> {
+    let res = parse_reply_instantiate_data(msg)
+        .map_err(|err| StdError::generic_err(err.to_string()))?;
+    let collection = deps.api.addr_validate(&res.contract_address)?;
+    let creator = PENDING_CREATOR.load(deps.storage)?;
+    PENDING_CREATOR.remove(deps.storage);
+    store_collection(deps.storage, &env.block, &creator, &collection)?;
+    if let Some(configs) = PENDING_CONFIGS.may_load(deps.storage)? {
+        PENDING_CONFIGS.remove(deps.storage);
+        COLLECTION_CONFIGS.save(deps.storage, &collection, &configs)?;
+    }
+
+    Ok(Response::new()
+        .add_attribute("action", "store_collection")
+        .add_attribute("creator", creator)
+        .add_attribute("collection", collection))
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> Result<Binary, StdError> {
+    match msg {
+        QueryMsg::LastDeployed { owner } => {
+            let owner = deps.api.addr_validate(&owner)?;
+            let collection = get_last_deployed(deps.storage, &owner)?;
+            to_binary(&LastDeployedResponse { collection })
+        }
+        QueryMsg::AllContracts {} => {
+            let collections = get_all_contracts(deps.storage)?;
+            to_binary(&CollectionsResponse { collections })
+        }
+        QueryMsg::Deployed { owner } => {
+            let owner = deps.api.addr_validate(&owner)?;
+            let collections = get_deployed(deps.storage, &owner)?;
+            to_binary(&CollectionsResponse { collections })
+        }
+        QueryMsg::TotalCollections {} => {
+            let total = TOTAL_COLLECTIONS.may_load(deps.storage)?.unwrap_or_default();
+            to_binary(&TotalCollectionsResponse { total })
+        }
+        QueryMsg::StakedBy {
+            address,
+            start_after,
+            limit,
+        } => to_binary(&query_staked_by(deps, address, start_after, limit)?),
+        QueryMsg::CollectionsDetailed { start_after, limit } => {
+            to_binary(&query_collections_detailed(deps, start_after, limit)?)
+        }
+        QueryMsg::CollectionWhitelist {
+            collection,
+            start_after,
+            limit,
+        } => to_binary(&query_collection_whitelist(
+            deps,
+            collection,
+            start_after,
+            limit,
+        )?),
+        QueryMsg::Ownership {} => {
+            let state = STATE.load(deps.storage)?;
+            to_binary(&OwnershipResponse {
+                owner: state.owner.map(Addr::into_string),
+                pending_owner: state.pending_owner.map(Addr::into_string),
+                pending_expiry: state.pending_expiry,
+            })
+        }
+        QueryMsg::ContractVersion {} => {
+            let version = cw2::get_contract_version(deps.storage)?;
+            to_binary(&ContractVersionResponse {
+                contract: version.contract,
+                version: version.version,
+                commit: BUILD_COMMIT.map(str::to_string),
+            })
+        }
+        QueryMsg::ClassLocation { class_id } => {
+            let collection = CLASS_LOCATIONS.may_load(deps.storage, &class_id)?;
+            to_binary(&ClassLocationResponse { collection })
+        }
+        QueryMsg::PauseHistory { start_after, limit } => {
+            let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+            let entries = PAUSE_HISTORY
+                .range(
+                    deps.storage,
+                    start_after.map(Bound::exclusive),
+                    None,
+                    Order::Ascending,
+                )
+                .take(limit)
+                .map(|item| item.map(|(index, record)| PauseHistoryEntry { index, record }))
+                .collect::<StdResult<Vec<_>>>()?;
+            let paused = STATE.load(deps.storage)?.factory_paused;
+            to_binary(&PauseHistoryResponse { paused, entries })
+        }
+        QueryMsg::CollectionConfig { address } => {
+            let address = validate_address(deps.api, "address", &address)?;
+            let configs = COLLECTION_CONFIGS.may_load(deps.storage, &address)?;
+            to_binary(&CollectionConfigResponse { configs })
+        }
+        QueryMsg::CollectionState { address } => {
+            to_binary(&query_collection_state(deps, address)?)
+        }
+        QueryMsg::CollectionStates { start_after, limit } => {
+            let limit = limit.unwrap_or(MAX_STATES_LIMIT).min(MAX_STATES_LIMIT) as usize;
+            let collections = collections_page(deps.storage, start_after, limit)?
+                .into_iter()
+                .map(|address| query_collection_state(deps, address))
+                .collect::<StdResult<Vec<_>>>()?;
+            to_binary(&CollectionStatesResponse { collections })
+        }
+        QueryMsg::CollectionsCreatedBetween {
+            from,
+            to,
+            start_after,
+            limit,
+        } => to_binary(&query_collections_created_between(
+            deps,
+            from,
+            to,
+            start_after,
+            limit,
+        )?),
+    }
+}
+
+fn query_collections_created_between(
+    deps: Deps,
+    from: u64,
+    to: u64,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<CollectionsCreatedResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    // sorts before every address created at the same time
+    let no_address = Addr::unchecked("");
+    let start_after = start_after
+        .map(|address| validate_address(deps.api, "start_after", &address))
+        .transpose()?;
+    let min = match &start_after {
+        Some(address) => {
+            let creation = COLLECTION_CREATIONS
+                .may_load(deps.storage, address)?
+                .ok_or_else(|| StdError::generic_err("Collection not found"))?;
+            if creation.created_at < from {
+                Bound::inclusive((from, &no_address))
+            } else {
+                Bound::exclusive((creation.created_at, address))
+            }
+        }
+        None => Bound::inclusive((from, &no_address)),
+    };
+    let max = to
+        .checked_add(1)
+        .map(|end| Bound::exclusive((end, &no_address)));
+
+    let collections = COLLECTIONS_BY_CREATION
+        .keys(deps.storage, Some(min), max, Order::Ascending)
+        .take(limit)
+        .map(|item| {
+            let (_, address) = item?;
+            let creation = COLLECTION_CREATIONS.load(deps.storage, &address)?;
+            let deployer = COLLECTION_CREATORS.load(deps.storage, &address)?;
+            Ok(CreatedCollection {
+                address: address.into_string(),
+                deployer: deployer.into_string(),
+                created_at: creation.created_at,
+                created_height: creation.created_height,
+            })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(CollectionsCreatedResponse { collections })
+}
+
+/// Deployed collections following `start_after`, in deployment order
+fn collections_page(
+    storage: &dyn Storage,
+    start_after: Option<String>,
+    limit: usize,
+) -> StdResult<Vec<String>> {
+    let all_collections = get_all_contracts(storage)?;
+    let start = match start_after {
+        Some(start_after) => all_collections
+            .iter()
+            .position(|collection| *collection == start_after)
+            .map_or(all_collections.len(), |index| index + 1),
+        None => 0,
+    };
+    Ok(all_collections.into_iter().skip(start).take(limit).collect())
+}
+
+fn query_collection_state(deps: Deps, address: String) -> StdResult<CollectionStateResponse> {
+    let mut state = CollectionStateResponse {
+        address,
+        deployer: None,
+        config: None,
+        phase: None,
+        error: None,
+    };
+    let deployer = match deps.api.addr_validate(&state.address) {
+        Ok(collection) => COLLECTION_CREATORS.may_load(deps.storage, &collection)?,
+        Err(_) => None,
+    };
+    let deployer = match deployer {
+        Some(deployer) => deployer,
+        None => {
+            state.error = Some("Collection not found".to_string());
+            return Ok(state);
+        }
+    };
+    state.deployer = Some(deployer.into_string());
+
+    // an unresponsive collection is reported rather than failing the whole page
+    let read = deps
+        .querier
+        .query_wasm_smart(&state.address, &CollectionQueryMsg::Config {})
+        .and_then(|config: CollectionConfig| {
+            let schedule: CollectionSchedule = deps
+                .querier
+                .query_wasm_smart(&state.address, &CollectionQueryMsg::Schedule {})?;
+            Ok((config, schedule.phase))
+        });
+    match read {
+        Ok((config, phase)) => {
+            state.config = Some(config);
+            state.phase = Some(phase);
+        }
+        Err(err) => state.error = Some(err.to_string()),
+    }
+    Ok(state)
+}
+
+fn query_collections_detailed(
+    deps: Deps,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<CollectionsDetailedResponse> {
+    let limit = limit.unwrap_or(MAX_DETAILED_LIMIT).min(MAX_DETAILED_LIMIT) as usize;
+    let mut collections = Vec::with_capacity(limit);
+    for address in collections_page(deps.storage, start_after, limit)? {
+        let info: CollectionContractInfo = match deps
+            .querier
+            .query_wasm_smart(&address, &CollectionQueryMsg::ContractInfo {})
+        {
+            Ok(info) => info,
+            // a broken collection shouldn't hide the others
+            Err(_) => continue,
+        };
+        let deployer = COLLECTION_CREATORS.load(deps.storage, &Addr::unchecked(&address))?;
+        collections.push(CollectionInfo {
+            address,
+            deployer: deployer.into_string(),
+            name: info.name,
+            symbol: info.symbol,
+        });
+    }
+
+    Ok(CollectionsDetailedResponse { collections })
+}
+
+fn query_collection_whitelist(
+    deps: Deps,
+    collection: String,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<CollectionWhitelistResponse> {
+    let collection = validate_address(deps.api, "collection", &collection)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start_after = start_after
+        .map(|user| validate_address(deps.api, "start_after", &user))
+        .transpose()?;
+
+    let users = COLLECTION_WHITELISTS
+        .prefix(&collection)
+        .keys(
+            deps.storage,
+            start_after.as_ref().map(Bound::exclusive),
+            None,
+            Order::Ascending,
+        )
+        .take(limit)
+        .map(|user| user.map(Addr::into_string))
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(CollectionWhitelistResponse { users })
+}
+
+fn query_staked_by(
+    deps: Deps,
+    address: String,
+    start_after: Option<(String, String)>,
+    limit: Option<u32>,
+) -> StdResult<StakedResponse> {
+    let staker = deps.api.addr_validate(&address)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after
+        .map(|(collection, token_id)| -> StdResult<_> {
+            Ok(Bound::exclusive((deps.api.addr_validate(&collection)?, token_id)))
+        })
+        .transpose()?;
+
+    let tokens = stakes()
+        .idx
+        .staker
+        .prefix(staker)
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| {
+            item.map(|((collection, token_id), stake)| StakedToken {
+                collection: collection.into_string(),
+                token_id,
+                staked_at: stake.staked_at,
+            })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(StakedResponse { tokens })
+}
+
+fn store_collection(
+    storage: &mut dyn Storage,
+    block: &BlockInfo,
+    owner: &Addr,
+    collection: &Addr,
+) -> StdResult<()> {
+    if DEREGISTERED_COLLECTIONS.has(storage, collection) {
+        return Err(StdError::generic_err(format!(
+            "Collection {} was deregistered",
+            collection
+        )));
+    }
+    // a creator's first collection starts their list
+    CONTRACTS.update(storage, owner, |collections| -> StdResult<_> {
+        let mut collections = collections.unwrap_or_default();
+        collections.push(collection.to_string());
+        Ok(collections)
+    })?;
+
+    let mut all_collections = get_all_contracts(storage)?;
+    all_collections.push(collection.to_string());
+    ALL_COLLECTIONS.save(storage, &all_collections)?;
+    let total = TOTAL_COLLECTIONS.may_load(storage)?.unwrap_or_default();
+    TOTAL_COLLECTIONS.save(storage, &(total + 1))?;
+
+    let creation = CollectionCreation {
+        created_at: block.time.seconds(),
+        created_height: block.height,
+    };
+    COLLECTION_CREATIONS.save(storage, collection, &creation)?;
+    COLLECTIONS_BY_CREATION.save(storage, (creation.created_at, collection), &Empty {})?;
+
+    COLLECTION_CREATORS.save(storage, collection, owner)
+}
+
+fn get_last_deployed(storage: &dyn Storage, owner: &Addr) -> StdResult<Option<String>> {
+    Ok(get_deployed(storage, owner)?.last().cloned())
+}
+
+fn get_all_contracts(storage: &dyn Storage) -> StdResult<Vec<String>> {
+    Ok(ALL_COLLECTIONS.may_load(storage)?.unwrap_or_default())
+}
+
+fn get_deployed(storage: &dyn Storage, owner: &Addr) -> StdResult<Vec<String>> {
+    Ok(CONTRACTS.may_load(storage, owner)?.unwrap_or_default())
+}
+
+/// Ensures `collection` was deployed through the factory by `sender`
+fn assert_collection_creator(deps: Deps, collection: &str, sender: &Addr) -> StdResult<Addr> {
+    let collection = validate_address(deps.api, "collection", collection)?;
+    let creator = COLLECTION_CREATORS
+        .may_load(deps.storage, &collection)?
+        .ok_or_else(|| StdError::generic_err("Collection not found"))?;
+    if &creator != sender {
+        return Err(StdError::generic_err("Unauthorized"));
+    }
+    Ok(collection)
+}
+
+fn set_base_uri(
+    storage: &mut dyn Storage,
+    collection: &Addr,
+    uri: String,
+    status: bool,
+) -> StdResult<()> {
+    // Update the base URI and status for the specified collection
+    COLLECTION_BASE_URIS.save(storage, collection, &uri)?;
+    COLLECTION_URI_STATUSES.save(storage, collection, &status)
+}
+
+fn set_whitelist(
+    storage: &mut dyn Storage,
+    collection: &Addr,
+    user: &Addr,
+    status: bool,
+) -> StdResult<()> {
+    // Adding twice or removing an absent user leaves the whitelist as is
+    if status {
+        COLLECTION_WHITELISTS.save(storage, (collection, user), &Empty {})
+    } else {
+        COLLECTION_WHITELISTS.remove(storage, (collection, user));
+        Ok(())
+    }
+}
+
+fn invalid_config(field: &str, reason: &str) -> StdError {
+    StdError::generic_err(format!("Invalid {}: {}", field, reason))
+}
+
+/// Instantiate message of the collection contract
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CollectionInstantiateMsg {
+    pub deployment_config: ProptixDeploymentConfig,
+    pub runtime_config: ProptixRuntimeConfig,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::{mock_dependencies, mock_env};
+    use cosmwasm_std::{from_slice, Empty, SubMsgResponse, SubMsgResult};
+    use cosmwasm_std::coins;
+    use cw_multi_test::{App, BankSudo, Contract, ContractWrapper, Executor};
+
+    const OWNER: &str = "owner";
+    const CREATOR: &str = "creator";
+    const HOLDER: &str = "holder";
+
+    fn factory_contract() -> Box<dyn Contract<Empty>> {
+        Box::new(ContractWrapper::new(execute, instantiate, query).with_reply(reply))
+    }
+
+    // Token owners of the stand-in collection
+    const STUB_OWNERS: Map<&str, Addr> = Map::new("owners");
+
+    // Name and symbol the stand-in collection was deployed with
+    const STUB_INFO: Item<CollectionContractInfo> = Item::new("info");
+
+    // Minter the stand-in collection was deployed with
+    const STUB_MINTER: Item<Option<String>> = Item::new("minter");
+
+    // Factory fee the stand-in collection was deployed with
+    const STUB_FACTORY_FEE: Item<u16> = Item::new("factory_fee");
+
+    // Config the stand-in collection reports, derived from its instantiate message
+    const STUB_CONFIG: Item<CollectionConfig> = Item::new("config");
+
+    // Whitelist statuses relayed to the stand-in collection
+    const STUB_WHITELIST: Map<&str, bool> = Map::new("whitelist");
+
+    // Factory that deployed the stand-in collection, paid its fee on every purchase
+    const STUB_FACTORY: Item<Addr> = Item::new("factory");
+
+    #[derive(Serialize, Deserialize, Debug)]
+    #[serde(rename_all = "snake_case")]
+    enum StubMintMsg {
+        Mint { token_id: String, owner: String },
+        /// Sells for the attached funds, forwarding the factory fee like the collection
+        Purchase {},
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    #[serde(untagged)]
+    enum StubExecuteMsg {
+        Factory(CollectionExecuteMsg),
+        Cw721(Cw721ExecuteMsg),
+        Mint(StubMintMsg),
+    }
+
+    // Stand-in for the collection, which lives in its own contract. It only
+    // tracks owners, enough to send tokens around, and its contract info.
+    fn collection_contract() -> Box<dyn Contract<Empty>> {
+        fn instantiate(
+            deps: DepsMut,
+            _: Env,
+            info: MessageInfo,
+            msg: CollectionInstantiateMsg,
+        ) -> StdResult<Response> {
+            STUB_FACTORY.save(deps.storage, &info.sender)?;
+            let info = CollectionContractInfo {
+                name: msg.deployment_config.name,
+                symbol: msg.deployment_config.symbol,
+            };
+            STUB_INFO.save(deps.storage, &info)?;
+            STUB_MINTER.save(deps.storage, &msg.deployment_config.minter)?;
+            STUB_FACTORY_FEE.save(deps.storage, &msg.deployment_config.factory_fee_bps)?;
+            let config = CollectionConfig {
+                mint_denom: msg.runtime_config.mint_denom,
+                mint_price: msg.runtime_config.mint_price,
+                max_supply: msg.deployment_config.max_supply,
+                remaining_supply: Some(msg.deployment_config.max_supply),
+                sale_active: true,
+            };
+            STUB_CONFIG.save(deps.storage, &config)?;
+            Ok(Response::new())
+        }
+        fn take_token(deps: DepsMut, info: &MessageInfo, token_id: &str) -> StdResult<()> {
+            if STUB_OWNERS.load(deps.storage, token_id)? != info.sender {
+                return Err(StdError::generic_err("Unauthorized"));
+            }
+            STUB_OWNERS.remove(deps.storage, token_id);
+            Ok(())
+        }
+        fn execute(
+            mut deps: DepsMut,
+            _: Env,
+            info: MessageInfo,
+            msg: StubExecuteMsg,
+        ) -> StdResult<Response> {
+            match msg {
+                StubExecuteMsg::Factory(CollectionExecuteMsg::WhitelistBatch {
+                    addresses,
+                    status,
+                }) => {
+                    for address in addresses {
+                        STUB_WHITELIST.save(deps.storage, &address, &status)?;
+                    }
+                    Ok(Response::new())
+                }
+                StubExecuteMsg::Factory(_) => Ok(Response::new()),
+                StubExecuteMsg::Mint(StubMintMsg::Mint { token_id, owner }) => {
+                    STUB_OWNERS.save(deps.storage, &token_id, &Addr::unchecked(owner))?;
+                    Ok(Response::new())
+                }
+                StubExecuteMsg::Mint(StubMintMsg::Purchase {}) => {
+                    let fee_bps = Uint128::from(STUB_FACTORY_FEE.load(deps.storage)?);
+                    let fees = info
+                        .funds
+                        .into_iter()
+                        .map(|coin| Coin {
+                            amount: coin.amount * fee_bps / Uint128::new(10_000),
+                            denom: coin.denom,
+                        })
+                        .collect();
+                    Ok(Response::new().add_message(BankMsg::Send {
+                        to_address: STUB_FACTORY.load(deps.storage)?.into_string(),
+                        amount: fees,
+                    }))
+                }
+                StubExecuteMsg::Cw721(Cw721ExecuteMsg::TransferNft {
+                    recipient,
+                    token_id,
+                }) => {
+                    take_token(deps.branch(), &info, &token_id)?;
+                    STUB_OWNERS.save(deps.storage, &token_id, &Addr::unchecked(recipient))?;
+                    Ok(Response::new())
+                }
+                StubExecuteMsg::Cw721(Cw721ExecuteMsg::SendNft {
+                    contract,
+                    token_id,
+                    msg,
+                }) => {
+                    take_token(deps.branch(), &info, &token_id)?;
+                    STUB_OWNERS.save(deps.storage, &token_id, &Addr::unchecked(&contract))?;
+                    let receive = Cw721ReceiveMsg {
+                        sender: info.sender.to_string(),
+                        token_id,
+                        msg,
+                    };
+                    Ok(Response::new().add_message(receive.into_cosmos_msg(contract)?))
+                }
+                StubExecuteMsg::Cw721(_) => Err(StdError::generic_err("Unsupported")),
+            }
+        }
+        fn query(deps: Deps, _: Env, msg: CollectionQueryMsg) -> StdResult<Binary> {
+            let info = STUB_INFO.load(deps.storage)?;
+            // lets tests deploy a collection that can't be queried
+            if info.symbol == "BROKEN" {
+                return Err(StdError::generic_err("Broken collection"));
+            }
+            match msg {
+                CollectionQueryMsg::ContractInfo {} => to_binary(&info),
+                CollectionQueryMsg::Config {} => to_binary(&STUB_CONFIG.load(deps.storage)?),
+                CollectionQueryMsg::Schedule {} => to_binary(&CollectionSchedule {
+                    phase: CollectionSalePhase::Public,
+                }),
+            }
+        }
+        Box::new(ContractWrapper::new(execute, instantiate, query))
+    }
+
+    fn setup_app() -> (App, Addr) {
+        let mut app = App::default();
+        let collection_code_id = app.store_code(collection_contract());
+        let factory_code_id = app.store_code(factory_contract());
+        let factory = app
+            .instantiate_contract(
+                factory_code_id,
+                Addr::unchecked(OWNER),
+                &InstantiateMsg { collection_code_id },
+                &[],
+                "factory",
+                None,
+            )
+            .unwrap();
+        (app, factory)
+    }
+
+    fn create_collection_msg() -> ExecuteMsg {
+        ExecuteMsg::CreateCollection {
+            deployment_config: ProptixDeploymentConfig {
+                name: "Proptix".to_string(),
+                symbol: "PTX".to_string(),
+                max_supply: 10,
+                treasury_address: "treasury".to_string(),
+                protocol_address: None,
+                users_disabled: false,
+                royalty_enforced: false,
+                max_per_wallet_holding: None,
+                minter: None,
+                token_id_format: None,
+                factory_fee_bps: 0,
+            },
+            runtime_config: ProptixRuntimeConfig {
+                base_token_uri: "ipfs://base/".to_string(),
+                base_token_uri_extension: ".json".to_string(),
+                prereveal_token_uri: "ipfs://prereveal".to_string(),
+                mint_denom: "ucore".to_string(),
+                mint_price: Uint128::new(100),
+                sale_start_time: 0,
+                sale_end_time: 0,
+                protocol_fee: 0,
+                presale_start_time: 0,
+                presale_end_time: 0,
+                max_per_wallet: 0,
+                lock_schedule_on_start: false,
+                lock_whitelist_after_start: false,
+                pass_collection: None,
+                referral_bps: 0,
+                reveal_at: None,
+                phases: vec![],
+                grace_period_secs: 0,
+                grace_threshold_pct: 0,
+                max_uri_len: 0,
+                min_threshold: 0,
+                early_bird_cutoff: 0,
+                finalize_actions: FinalizeActions::default(),
+                mint_receipts: false,
+                uri_veto_threshold: 0,
+                max_mints_per_block: 0,
+            },
+        }
+    }
+
+    fn query_deployed(app: &App, factory: &Addr, owner: &str) -> Vec<String> {
+        let res: CollectionsResponse = app
+            .wrap()
+            .query_wasm_smart(
+                factory,
+                &QueryMsg::Deployed {
+                    owner: owner.to_string(),
+                },
+            )
+            .unwrap();
+        res.collections
+    }
+
+    #[test]
+    fn create_collection_records_the_creator() {
+        let (mut app, factory) = setup_app();
+
+        app.execute_contract(
+            Addr::unchecked(CREATOR),
+            factory.clone(),
+            &create_collection_msg(),
+            &[],
+        )
+        .unwrap();
+
+        let deployed = query_deployed(&app, &factory, CREATOR);
+        assert_eq!(deployed.len(), 1);
+        let res: LastDeployedResponse = app
+            .wrap()
+            .query_wasm_smart(
+                &factory,
+                &QueryMsg::LastDeployed {
+                    owner: CREATOR.to_string(),
+                },
+            )
+            .unwrap();
+        assert_eq!(res.collection, Some(deployed[0].clone()));
+
+        // the factory owns the collection it deployed
+        let info = app.wrap().query_wasm_contract_info(&deployed[0]).unwrap();
+        assert_eq!(info.admin, Some(factory.to_string()));
+    }
+
+    #[test]
+    fn replies_are_routed_by_their_id() {
+        let mut deps = mock_dependencies();
+        let creator = Addr::unchecked(CREATOR);
+        PENDING_CREATOR.save(deps.as_mut().storage, &creator).unwrap();
+
+        // instantiate data is a protobuf message holding the contract address in field 1
+        let address = "collection";
+        let mut data = vec![0x0a, address.len() as u8];
+        data.extend_from_slice(address.as_bytes());
+        let instantiated = |id: u64| Reply {
+            id,
+            result: SubMsgResult::Ok(SubMsgResponse {
+                events: vec![],
+                data: Some(Binary::from(data.clone())),
+            }),
+        };
+
+        let res = reply(
+            deps.as_mut(),
+            mock_env(),
+            instantiated(ReplyId::InstantiateCollection as u64),
+        )
+        .unwrap();
+        assert_eq!(
+            res.attributes,
+            vec![
+                attr("action", "store_collection"),
+                attr("creator", CREATOR),
+                attr("collection", address),
+            ]
+        );
+        let deployed = get_deployed(deps.as_ref().storage, &creator).unwrap();
+        assert_eq!(deployed, vec![address.to_string()]);
+
+        let err = reply(deps.as_mut(), mock_env(), instantiated(99)).unwrap_err();
+        assert_eq!(err, StdError::generic_err("Unknown reply id: 99"));
+        assert_eq!(
+            ReplyId::try_from(1).unwrap(),
+            ReplyId::InstantiateCollection
+        );
+    }
+
+    #[test]
+    fn store_collection_starts_the_list_of_a_new_creator() {
+        let mut deps = mock_dependencies();
+        let storage = deps.as_mut().storage;
+        let (creator, other) = (Addr::unchecked(CREATOR), Addr::unchecked(OWNER));
+        let block = mock_env().block;
+
+        store_collection(storage, &block, &creator, &Addr::unchecked("collection0")).unwrap();
+        assert_eq!(get_deployed(storage, &creator).unwrap(), vec!["collection0"]);
+        assert!(get_deployed(storage, &other).unwrap().is_empty());
+
+        store_collection(storage, &block, &other, &Addr::unchecked("collection1")).unwrap();
+        store_collection(storage, &block, &creator, &Addr::unchecked("collection2")).unwrap();
+        assert_eq!(
+            get_deployed(storage, &creator).unwrap(),
+            vec!["collection0", "collection2"]
+        );
+        assert_eq!(get_deployed(storage, &other).unwrap(), vec!["collection1"]);
+        assert_eq!(get_all_contracts(storage).unwrap().len(), 3);
+        assert_eq!(TOTAL_COLLECTIONS.load(storage).unwrap(), 3);
+    }
+
+    #[test]
+    fn deregistered_collections_leave_the_registry_for_good() {
+        let (mut app, factory) = setup_app();
+        for creator in [CREATOR, CREATOR, HOLDER] {
+            app.execute_contract(
+                Addr::unchecked(creator),
+                factory.clone(),
+                &create_collection_msg(),
+                &[],
+            )
+            .unwrap();
+        }
+        let deregister = |address: &str| ExecuteMsg::DeregisterCollection {
+            address: address.to_string(),
+        };
+
+        // the creator removes their own collection
+        let res = app
+            .execute_contract(
+                Addr::unchecked(CREATOR),
+                factory.clone(),
+                &deregister("contract1"),
+                &[],
+            )
+            .unwrap();
+        let wasm = res.events.iter().find(|event| event.ty == "wasm").unwrap();
+        assert!(wasm
+            .attributes
+            .iter()
+            .any(|attr| attr.key == "action" && attr.value == "deregister_collection"));
+
+        // a stranger can't remove anything
+        for sender in [HOLDER, "stranger"] {
+            let err = app
+                .execute_contract(
+                    Addr::unchecked(sender),
+                    factory.clone(),
+                    &deregister("contract2"),
+                    &[],
+                )
+                .unwrap_err();
+            assert_eq!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::Std(StdError::generic_err("Unauthorized"))
+            );
+        }
+
+        // the owner removes anyone's collection
+        app.execute_contract(
+            Addr::unchecked(OWNER),
+            factory.clone(),
+            &deregister("contract3"),
+            &[],
+        )
+        .unwrap();
+
+        assert_eq!(query_deployed(&app, &factory, CREATOR), vec!["contract2"]);
+        assert!(query_deployed(&app, &factory, HOLDER).is_empty());
+        let all: CollectionsResponse = app
+            .wrap()
+            .query_wasm_smart(&factory, &QueryMsg::AllContracts {})
+            .unwrap();
+        assert_eq!(all.collections, vec!["contract2"]);
+        let state: CollectionStateResponse = app
+            .wrap()
+            .query_wasm_smart(
+                &factory,
+                &QueryMsg::CollectionState {
+                    address: "contract1".to_string(),
+                },
+            )
+            .unwrap();
+        assert_eq!(state.error, Some("Collection not found".to_string()));
+        let created: CollectionsCreatedResponse = app
+            .wrap()
+            .query_wasm_smart(
+                &factory,
+                &QueryMsg::CollectionsCreatedBetween {
+                    from: 0,
+                    to: u64::MAX,
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap();
+        assert_eq!(created.collections.len(), 1);
+
+        // removed collections can't be removed again nor registered anew
+        let err = app
+            .execute_contract(
+                Addr::unchecked(OWNER),
+                factory.clone(),
+                &deregister("contract1"),
+                &[],
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::Std(StdError::generic_err("Collection not found"))
+        );
+        let mut deps = mock_dependencies();
+        let collection = Addr::unchecked("collection0");
+        DEREGISTERED_COLLECTIONS
+            .save(deps.as_mut().storage, &collection, &0)
+            .unwrap();
+        let (block, creator) = (mock_env().block, Addr::unchecked(CREATOR));
+        let err = store_collection(deps.as_mut().storage, &block, &creator, &collection);
+        assert_eq!(
+            err.unwrap_err(),
+            StdError::generic_err("Collection collection0 was deregistered")
+        );
+    }
+
+    #[test]
+    fn create_collection_forwards_its_metadata_and_the_creator_as_minter() {
+        let (mut app, factory) = setup_app();
+
+        app.execute_contract(
+            Addr::unchecked(CREATOR),
+            factory.clone(),
+            &create_collection_msg(),
+            &[],
+        )
+        .unwrap();
+
+        let collection = query_deployed(&app, &factory, CREATOR).remove(0);
+        let info: CollectionContractInfo = app
+            .wrap()
+            .query_wasm_smart(&collection, &CollectionQueryMsg::ContractInfo {})
+            .unwrap();
+        assert_eq!(info.name, "Proptix");
+        assert_eq!(info.symbol, "PTX");
+        let minter = app
+            .wrap()
+            .query_wasm_raw(collection, STUB_MINTER.as_slice())
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            from_slice::<Option<String>>(&minter).unwrap(),
+            Some(CREATOR.to_string())
+        );
+    }
+
+    #[test]
+    fn collection_configs_are_recorded_as_submitted() {
+        let (mut app, factory) = setup_app();
+        let query_configs = |app: &App, address: &str| {
+            let res: CollectionConfigResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    &factory,
+                    &QueryMsg::CollectionConfig {
+                        address: address.to_string(),
+                    },
+                )
+                .unwrap();
+            res.configs
+        };
+        let ExecuteMsg::CreateCollection {
+            deployment_config,
+            runtime_config,
+        } = create_collection_msg()
+        else {
+            unreachable!()
+        };
+        let mut all_set = (deployment_config.clone(), runtime_config.clone());
+        all_set.0.protocol_address = Some("protocol".to_string());
+        all_set.0.max_per_wallet_holding = Some(5);
+        all_set.0.minter = Some("minter".to_string());
+        all_set.0.token_id_format = Some(TokenIdFormat {
+            prefix: "PTX-".to_string(),
+            width: 4,
+        });
+        all_set.1.pass_collection = Some("pass".to_string());
+        all_set.1.reveal_at = Some(1_000);
+        all_set.1.phases = vec![Phase {
+            label: "og".to_string(),
+            start: 100,
+            end: 200,
+            price: Uint128::new(50),
+            per_wallet_limit: 2,
+            whitelist_only: true,
+        }];
+        all_set.1.finalize_actions = FinalizeActions {
+            sweep_proceeds: true,
+            freeze_supply: true,
+            reveal: true,
+        };
+
+        // optional fields round-trip both unset and set, the minter as submitted
+        for (deployment_config, runtime_config) in [(deployment_config, runtime_config), all_set] {
+            let msg = ExecuteMsg::CreateCollection {
+                deployment_config: deployment_config.clone(),
+                runtime_config: runtime_config.clone(),
+            };
+            app.execute_contract(Addr::unchecked(CREATOR), factory.clone(), &msg, &[])
+                .unwrap();
+            let deployed = query_deployed(&app, &factory, CREATOR);
+            assert_eq!(
+                query_configs(&app, deployed.last().unwrap()),
+                Some(StoredConfigs {
+                    version: STORED_CONFIGS_VERSION,
+                    deployment_config,
+                    runtime_config,
+                })
+            );
+        }
+        assert_eq!(query_configs(&app, "unknown"), None);
+    }
+
+    #[test]
+    fn creators_can_clone_their_collections() {
+        let (mut app, factory) = setup_app();
+        app.execute_contract(
+            Addr::unchecked(CREATOR),
+            factory.clone(),
+            &create_collection_msg(),
+            &[],
+        )
+        .unwrap();
+        let source = query_deployed(&app, &factory, CREATOR)[0].clone();
+        let clone_msg = |overrides: TemplateOverrides| ExecuteMsg::CloneCollection {
+            source: source.clone(),
+            overrides,
+        };
+        let cheaper = TemplateOverrides {
+            mint_price: Some(Uint128::new(50)),
+            ..TemplateOverrides::default()
+        };
+
+        // someone else's collection can't be cloned
+        let err = app
+            .execute_contract(
+                Addr::unchecked(HOLDER),
+                factory.clone(),
+                &clone_msg(cheaper.clone()),
+                &[],
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::Std(StdError::generic_err("Unauthorized"))
+        );
+
+        app.execute_contract(Addr::unchecked(CREATOR), factory.clone(), &clone_msg(cheaper), &[])
+            .unwrap();
+        let deployed = query_deployed(&app, &factory, CREATOR);
+        assert_eq!(deployed.len(), 2);
+        let config: CollectionConfig = app
+            .wrap()
+            .query_wasm_smart(&deployed[1], &CollectionQueryMsg::Config {})
+            .unwrap();
+        assert_eq!(config.mint_price, Uint128::new(50));
+        assert_eq!((config.mint_denom.as_str(), config.max_supply), ("ucore", 10));
+
+        // the owner may clone any collection, the clone is theirs
+        app.execute_contract(
+            Addr::unchecked(OWNER),
+            factory.clone(),
+            &clone_msg(TemplateOverrides::default()),
+            &[],
+        )
+        .unwrap();
+        assert_eq!(query_deployed(&app, &factory, OWNER).len(), 1);
+    }
+
+    #[test]
+    fn collections_are_created_from_templates() {
+        let (mut app, factory) = setup_app();
+        let ExecuteMsg::CreateCollection {
+            deployment_config,
+            runtime_config,
+        } = create_collection_msg()
+        else {
+            unreachable!()
+        };
+        let save_template = ExecuteMsg::SaveTemplate {
+            name: "drop".to_string(),
+            deployment_config,
+            runtime_config,
+        };
+        let create_from = |name: &str, overrides: TemplateOverrides| {
+            ExecuteMsg::CreateFromTemplate {
+                name: name.to_string(),
+                overrides,
+            }
+        };
+
+        // only the owner saves templates
+        let err = app
+            .execute_contract(Addr::unchecked(CREATOR), factory.clone(), &save_template, &[])
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::Std(StdError::generic_err("Unauthorized"))
+        );
+        app.execute_contract(Addr::unchecked(OWNER), factory.clone(), &save_template, &[])
+            .unwrap();
+
+        let err = app
+            .execute_contract(
+                Addr::unchecked(CREATOR),
+                factory.clone(),
+                &create_from("unknown", TemplateOverrides::default()),
+                &[],
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::Std(StdError::generic_err("Template not found: unknown"))
+        );
+
+        let overrides = TemplateOverrides {
+            symbol: Some("PTX2".to_string()),
+            mint_price: Some(Uint128::new(250)),
+            ..TemplateOverrides::default()
+        };
+        app.execute_contract(
+            Addr::unchecked(CREATOR),
+            factory.clone(),
+            &create_from("drop", overrides),
+            &[],
+        )
+        .unwrap();
+        let deployed = query_deployed(&app, &factory, CREATOR);
+        assert_eq!(deployed.len(), 1);
+        let config: CollectionConfig = app
+            .wrap()
+            .query_wasm_smart(&deployed[0], &CollectionQueryMsg::Config {})
+            .unwrap();
+        assert_eq!(config.mint_price, Uint128::new(250));
+        // fields without an override come from the template
+        assert_eq!((config.mint_denom.as_str(), config.max_supply), ("ucore", 10));
+        let info: CollectionContractInfo = app
+            .wrap()
+            .query_wasm_smart(&deployed[0], &CollectionQueryMsg::ContractInfo {})
+            .unwrap();
+        assert_eq!((info.name.as_str(), info.symbol.as_str()), ("Proptix", "PTX2"));
+
+        // overrides are validated like any other config
+        let overrides = TemplateOverrides {
+            treasury_address: Some("ab".to_string()),
+            ..TemplateOverrides::default()
+        };
+        let err = app
+            .execute_contract(
+                Addr::unchecked(CREATOR),
+                factory.clone(),
+                &create_from("drop", overrides),
+                &[],
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::Std(StdError::generic_err("Invalid address for treasury_address: ab"))
+        );
+    }
+
+    #[test]
+    fn invalid_config_addresses_are_rejected_before_instantiation() {
+        let (mut app, factory) = setup_app();
+        let ExecuteMsg::CreateCollection {
+            deployment_config,
+            runtime_config,
+        } = create_collection_msg()
+        else {
+            unreachable!()
+        };
+
+        // too short and not normalized
+        for invalid in ["ab", "Treasury"] {
+            let cases = [
+                (
+                    "treasury_address",
+                    ProptixDeploymentConfig {
+                        treasury_address: invalid.to_string(),
+                        ..deployment_config.clone()
+                    },
+                    runtime_config.clone(),
+                ),
+                (
+                    "protocol_address",
+                    ProptixDeploymentConfig {
+                        protocol_address: Some(invalid.to_string()),
+                        ..deployment_config.clone()
+                    },
+                    runtime_config.clone(),
+                ),
+                (
+                    "pass_collection",
+                    deployment_config.clone(),
+                    ProptixRuntimeConfig {
+                        pass_collection: Some(invalid.to_string()),
+                        ..runtime_config.clone()
+                    },
+                ),
+            ];
+            for (field, deployment_config, runtime_config) in cases {
+                let msg = ExecuteMsg::CreateCollection {
+                    deployment_config,
+                    runtime_config,
+                };
+                let err = app
+                    .execute_contract(Addr::unchecked(CREATOR), factory.clone(), &msg, &[])
+                    .unwrap_err();
+                assert_eq!(
+                    err.downcast::<ContractError>().unwrap(),
+                    ContractError::Std(StdError::generic_err(format!(
+                        "Invalid address for {}: {}",
+                        field, invalid
+                    )))
+                );
+            }
+        }
+        assert!(query_deployed(&app, &factory, CREATOR).is_empty());
+    }
+
+    #[test]
+    fn invalid_configs_are_rejected_before_instantiation() {
+        let (mut app, factory) = setup_app();
+        type Customize = fn(&mut ProptixDeploymentConfig, &mut ProptixRuntimeConfig);
+        let cases: [(Customize, &str); 16] = [
+            (
+                |deployment, _| deployment.name = String::new(),
+                "Invalid name: must not be empty",
+            ),
+            (
+                |deployment, _| deployment.symbol = " ".to_string(),
+                "Invalid symbol: must not be empty",
+            ),
+            (
+                |deployment, _| deployment.symbol = "PROPTIXGENESIS".to_string(),
+                "Invalid symbol: must be 3 to 12 characters",
+            ),
+            (
+                |deployment, _| deployment.symbol = "PTX!".to_string(),
+                "Invalid symbol: must only hold letters and digits",
+            ),
+            (
+                |deployment, _| {
+                    deployment.token_id_format = Some(TokenIdFormat {
+                        prefix: "PTX".to_string(),
+                        width: 21,
+                    })
+                },
+                "Invalid token_id_format: width must be at most 20",
+            ),
+            (
+                |_, runtime| {
+                    runtime.sale_start_time = 200;
+                    runtime.sale_end_time = 100;
+                },
+                "Invalid sale_end_time: must be after the sale start",
+            ),
+            (
+                |_, runtime| {
+                    runtime.presale_start_time = 100;
+                    runtime.presale_end_time = 50;
+                },
+                "Invalid presale_end_time: must be after the presale start",
+            ),
+            (
+                |_, runtime| runtime.mint_denom = String::new(),
+                "Invalid mint_denom: must not be empty",
+            ),
+            (
+                |_, runtime| runtime.protocol_fee = 101,
+                "Invalid protocol_fee: must be at most 100",
+            ),
+            (
+                |_, runtime| {
+                    runtime.protocol_fee = 1;
+                    runtime.mint_price = Uint128::zero();
+                },
+                "Invalid mint_price: must be set when a protocol fee is charged",
+            ),
+            (
+                |_, runtime| runtime.referral_bps = 10_001,
+                "Invalid referral_bps: can't exceed the treasury share",
+            ),
+            (
+                |_, runtime| runtime.grace_threshold_pct = 101,
+                "Invalid grace_threshold_pct: must be at most 100",
+            ),
+            (
+                |_, runtime| runtime.max_uri_len = 8,
+                "base_token_uri is longer than 8 bytes",
+            ),
+            (
+                // milliseconds instead of seconds
+                |_, runtime| runtime.reveal_at = Some(1_700_000_000_000),
+                "Invalid reveal_at: must be in seconds",
+            ),
+            (
+                |_, runtime| runtime.protocol_fee = 1,
+                "Invalid protocol_address: required with a protocol fee",
+            ),
+            (
+                |_, runtime| {
+                    runtime.reveal_at = Some(0);
+                    runtime.base_token_uri = String::new();
+                },
+                "Invalid base_token_uri: must be set to reveal from the start",
+            ),
+        ];
+        for (customize, expected) in cases {
+            let mut msg = create_collection_msg();
+            if let ExecuteMsg::CreateCollection {
+                deployment_config,
+                runtime_config,
+            } = &mut msg
+            {
+                customize(deployment_config, runtime_config);
+            }
+            let err = app
+                .execute_contract(Addr::unchecked(CREATOR), factory.clone(), &msg, &[])
+                .unwrap_err();
+            assert_eq!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::Std(StdError::generic_err(expected))
+            );
+        }
+        assert!(query_deployed(&app, &factory, CREATOR).is_empty());
+
+        // a collection can't be revealed without a base uri
+        app.execute_contract(
+            Addr::unchecked(CREATOR),
+            factory.clone(),
+            &create_collection_msg(),
+            &[],
+        )
+        .unwrap();
+        let collection = query_deployed(&app, &factory, CREATOR).remove(0);
+        let err = app
+            .execute_contract(
+                Addr::unchecked(CREATOR),
+                factory.clone(),
+                &ExecuteMsg::SetBaseURI {
+                    collection,
+                    uri: String::new(),
+                    status: true,
+                },
+                &[],
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::Std(StdError::generic_err(
+                "Invalid uri: must be set to reveal the collection"
+            ))
+        );
+    }
+
+    #[test]
+    fn total_collections_counts_every_deployment() {
+        let (mut app, factory) = setup_app();
+        let total = |app: &App| -> u64 {
+            let res: TotalCollectionsResponse = app
+                .wrap()
+                .query_wasm_smart(&factory, &QueryMsg::TotalCollections {})
+                .unwrap();
+            res.total
+        };
+        assert_eq!(total(&app), 0);
+
+        for (creator, expected) in [(CREATOR, 1), (CREATOR, 2), (OWNER, 3)] {
+            app.execute_contract(
+                Addr::unchecked(creator),
+                factory.clone(),
+                &create_collection_msg(),
+                &[],
+            )
+            .unwrap();
+            assert_eq!(total(&app), expected);
+        }
+    }
+
+    #[test]
+    fn collections_detailed_reads_every_collection_info() {
+        let (mut app, factory) = setup_app();
+        for (creator, name, symbol) in [
+            (CREATOR, "Proptix", "PTX"),
+            (CREATOR, "Broken", "BROKEN"),
+            (OWNER, "Second", "SEC"),
+        ] {
+            let mut msg = create_collection_msg();
+            if let ExecuteMsg::CreateCollection {
+                deployment_config, ..
+            } = &mut msg
+            {
+                deployment_config.name = name.to_string();
+                deployment_config.symbol = symbol.to_string();
+            }
+            app.execute_contract(Addr::unchecked(creator), factory.clone(), &msg, &[])
+                .unwrap();
+        }
+        let detailed = |start_after: Option<&str>, limit: Option<u32>| {
+            let res: CollectionsDetailedResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    &factory,
+                    &QueryMsg::CollectionsDetailed {
+                        start_after: start_after.map(String::from),
+                        limit,
+                    },
+                )
+                .unwrap();
+            res.collections
+        };
+
+        // the broken collection is skipped
+        let first = CollectionInfo {
+            address: "contract1".to_string(),
+            deployer: CREATOR.to_string(),
+            name: "Proptix".to_string(),
+            symbol: "PTX".to_string(),
+        };
+        let second = CollectionInfo {
+            address: "contract3".to_string(),
+            deployer: OWNER.to_string(),
+            name: "Second".to_string(),
+            symbol: "SEC".to_string(),
+        };
+        assert_eq!(detailed(None, None), vec![first.clone(), second.clone()]);
+        assert_eq!(detailed(None, Some(1)), vec![first]);
+        assert_eq!(detailed(Some("contract1"), Some(50)), vec![second]);
+    }
+
+    #[test]
+    fn collection_states_merge_the_registry_with_each_collection() {
+        let (mut app, factory) = setup_app();
+        for (creator, symbol, max_supply) in [
+            (CREATOR, "PTX", 10),
+            (CREATOR, "BROKEN", 10),
+            (OWNER, "SEC", 25),
+        ] {
+            let mut msg = create_collection_msg();
+            if let ExecuteMsg::CreateCollection {
+                deployment_config, ..
+            } = &mut msg
+            {
+                deployment_config.symbol = symbol.to_string();
+                deployment_config.max_supply = max_supply;
+            }
+            app.execute_contract(Addr::unchecked(creator), factory.clone(), &msg, &[])
+                .unwrap();
+        }
+        let config = |max_supply| CollectionConfig {
+            mint_denom: "ucore".to_string(),
+            mint_price: Uint128::new(100),
+            max_supply,
+            remaining_supply: Some(max_supply),
+            sale_active: true,
+        };
+
+        let first = CollectionStateResponse {
+            address: "contract1".to_string(),
+            deployer: Some(CREATOR.to_string()),
+            config: Some(config(10)),
+            phase: Some(CollectionSalePhase::Public),
+            error: None,
+        };
+        let state: CollectionStateResponse = app
+            .wrap()
+            .query_wasm_smart(
+                &factory,
+                &QueryMsg::CollectionState {
+                    address: "contract1".to_string(),
+                },
+            )
+            .unwrap();
+        assert_eq!(state, first);
+
+        // unknown collections are reported rather than failing the query
+        let state: CollectionStateResponse = app
+            .wrap()
+            .query_wasm_smart(
+                &factory,
+                &QueryMsg::CollectionState {
+                    address: "unknown".to_string(),
+                },
+            )
+            .unwrap();
+        assert_eq!(state.deployer, None);
+        assert_eq!(state.error, Some("Collection not found".to_string()));
+
+        let states = |start_after: Option<&str>, limit: Option<u32>| {
+            let res: CollectionStatesResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    &factory,
+                    &QueryMsg::CollectionStates {
+                        start_after: start_after.map(String::from),
+                        limit,
+                    },
+                )
+                .unwrap();
+            res.collections
+        };
+        let page = states(None, None);
+        assert_eq!(page.len(), 3);
+        assert_eq!(page[0], first);
+        // the broken collection keeps its registry record next to the error
+        assert_eq!(page[1].deployer, Some(CREATOR.to_string()));
+        assert_eq!(page[1].config, None);
+        assert!(page[1].error.as_ref().unwrap().contains("Broken collection"));
+        assert_eq!(
+            page[2],
+            CollectionStateResponse {
+                address: "contract3".to_string(),
+                deployer: Some(OWNER.to_string()),
+                config: Some(config(25)),
+                phase: Some(CollectionSalePhase::Public),
+                error: None,
+            }
+        );
+        assert_eq!(states(Some("contract1"), Some(1)), vec![page[1].clone()]);
+    }
+
+    #[test]
+    fn collections_are_queried_by_creation_time() {
+        let (mut app, factory) = setup_app();
+        let start = app.block_info();
+        for (creator, delay) in [(CREATOR, 0), (OWNER, 100), (CREATOR, 200)] {
+            app.update_block(|block| {
+                block.time = start.time.plus_seconds(delay);
+                block.height = start.height + delay;
+            });
+            app.execute_contract(
+                Addr::unchecked(creator),
+                factory.clone(),
+                &create_collection_msg(),
+                &[],
+            )
+            .unwrap();
+        }
+        let t0 = start.time.seconds();
+        let created = |address: &str, deployer: &str, delay: u64| CreatedCollection {
+            address: address.to_string(),
+            deployer: deployer.to_string(),
+            created_at: t0 + delay,
+            created_height: start.height + delay,
+        };
+        let between = |from: u64, to: u64, start_after: Option<&str>, limit: Option<u32>| {
+            let res: CollectionsCreatedResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    &factory,
+                    &QueryMsg::CollectionsCreatedBetween {
+                        from,
+                        to,
+                        start_after: start_after.map(String::from),
+                        limit,
+                    },
+                )
+                .unwrap();
+            res.collections
+        };
+
+        // the bounds are included
+        assert_eq!(
+            between(t0, t0 + 100, None, None),
+            vec![created("contract1", CREATOR, 0), created("contract2", OWNER, 100)]
+        );
+        assert_eq!(
+            between(t0 + 50, t0 + 250, None, None),
+            vec![created("contract2", OWNER, 100), created("contract3", CREATOR, 200)]
+        );
+        assert_eq!(between(t0 + 201, u64::MAX, None, None), vec![]);
+
+        // pages through a range
+        assert_eq!(
+            between(t0, u64::MAX, None, Some(1)),
+            vec![created("contract1", CREATOR, 0)]
+        );
+        assert_eq!(
+            between(t0, u64::MAX, Some("contract1"), Some(1)),
+            vec![created("contract2", OWNER, 100)]
+        );
+        // a cursor before the range starts at the range
+        assert_eq!(
+            between(t0 + 150, u64::MAX, Some("contract1"), None),
+            vec![created("contract3", CREATOR, 200)]
+        );
+    }
+
+    #[test]
+    fn migrate_backfills_total_collections() {
+        let mut deps = mock_dependencies();
+        cw2::set_contract_version(deps.as_mut().storage, CONTRACT_NAME, "0.1.0").unwrap();
+        let collections = vec!["collection0".to_string(), "collection1".to_string()];
+        ALL_COLLECTIONS.save(deps.as_mut().storage, &collections).unwrap();
+
+        migrate(deps.as_mut(), mock_env(), MigrateMsg::Upgrade {}).unwrap();
+        assert_eq!(TOTAL_COLLECTIONS.load(deps.as_ref().storage).unwrap(), 2);
+    }
+
+    #[test]
+    fn collections_are_deployed_with_the_factory_fee() {
+        let (mut app, factory) = setup_app();
+        let set_fee = |factory_fee_bps| ExecuteMsg::SetFactoryFee { factory_fee_bps };
+
+        let err = app
+            .execute_contract(Addr::unchecked(CREATOR), factory.clone(), &set_fee(300), &[])
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::Std(StdError::generic_err("Unauthorized"))
+        );
+        let err = app
+            .execute_contract(Addr::unchecked(OWNER), factory.clone(), &set_fee(10_001), &[])
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::Std(invalid_config("factory_fee_bps", "must be at most 10000"))
+        );
+        app.execute_contract(Addr::unchecked(OWNER), factory.clone(), &set_fee(300), &[])
+            .unwrap();
+
+        // the creator can't waive the fee
+        app.execute_contract(
+            Addr::unchecked(CREATOR),
+            factory.clone(),
+            &create_collection_msg(),
+            &[],
+        )
+        .unwrap();
+        let collection = query_deployed(&app, &factory, CREATOR).remove(0);
+        let fee = app
+            .wrap()
+            .query_wasm_raw(collection, STUB_FACTORY_FEE.as_slice())
+            .unwrap()
+            .unwrap();
+        assert_eq!(from_slice::<u16>(&fee).unwrap(), 300);
+
+        // together with the protocol fee it can't take more than the whole sale
+        app.execute_contract(Addr::unchecked(OWNER), factory.clone(), &set_fee(9_500), &[])
+            .unwrap();
+        let mut msg = create_collection_msg();
+        if let ExecuteMsg::CreateCollection {
+            deployment_config,
+            runtime_config,
+        } = &mut msg
+        {
+            deployment_config.protocol_address = Some("protocol".to_string());
+            runtime_config.protocol_fee = 10;
+        }
+        let err = app
+            .execute_contract(Addr::unchecked(CREATOR), factory.clone(), &msg, &[])
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::Std(invalid_config(
+                "factory_fee_bps",
+                "can't exceed 100% with the protocol fee"
+            ))
+        );
+    }
+
+    #[test]
+    fn sale_fees_can_be_withdrawn_while_paused() {
+        let (mut app, factory) = setup_app();
+        let execute = |app: &mut App, sender: &str, msg: &ExecuteMsg| {
+            app.execute_contract(Addr::unchecked(sender), factory.clone(), msg, &[])
+        };
+        execute(
+            &mut app,
+            OWNER,
+            &ExecuteMsg::SetFactoryFee {
+                factory_fee_bps: 300,
+            },
+        )
+        .unwrap();
+        execute(&mut app, CREATOR, &create_collection_msg()).unwrap();
+        let collection = query_deployed(&app, &factory, CREATOR).remove(0);
+
+        // a sale pays the factory its fee
+        app.sudo(
+            BankSudo::Mint {
+                to_address: HOLDER.to_string(),
+                amount: coins(1_000, "ucore"),
+            }
+            .into(),
+        )
+        .unwrap();
+        app.execute_contract(
+            Addr::unchecked(HOLDER),
+            collection,
+            &StubMintMsg::Purchase {},
+            &coins(1_000, "ucore"),
+        )
+        .unwrap();
+        let balance = |app: &App, address: &str| {
+            app.wrap().query_balance(address, "ucore").unwrap().amount
+        };
+        assert_eq!(balance(&app, factory.as_str()), Uint128::new(30));
+
+        execute(
+            &mut app,
+            OWNER,
+            &ExecuteMsg::SetPaused {
+                paused: true,
+                reason: "incident".to_string(),
+            },
+        )
+        .unwrap();
+        let withdraw = |amount: u128| ExecuteMsg::WithdrawFees {
+            denom: "ucore".to_string(),
+            amount: Uint128::new(amount),
+            recipient: "treasury".to_string(),
+        };
+        let cases = [
+            (CREATOR, withdraw(30), StdError::generic_err("Unauthorized")),
+            (OWNER, withdraw(0), invalid_config("amount", "must not be zero")),
+            (
+                OWNER,
+                withdraw(31),
+                invalid_config("amount", "exceeds the 30ucore held by the factory"),
+            ),
+        ];
+        for (sender, msg, expected) in cases {
+            let err = execute(&mut app, sender, &msg).unwrap_err();
+            assert_eq!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::Std(expected),
+                "{:?}",
+                msg
+            );
+        }
+
+        let res = execute(&mut app, OWNER, &withdraw(30)).unwrap();
+        let wasm = res.events.iter().find(|event| event.ty == "wasm").unwrap();
+        assert_eq!(
+            wasm.attributes[1..],
+            [
+                attr("action", "withdraw_fees"),
+                attr("recipient", "treasury"),
+                attr("amount", "30ucore"),
+            ]
+        );
+        assert_eq!(balance(&app, "treasury"), Uint128::new(30));
+        assert_eq!(balance(&app, factory.as_str()), Uint128::zero());
+    }
+
+    #[test]
+    fn creator_limit_is_enforced() {
+        let (mut app, factory) = setup_app();
+
+        // only the owner sets the limit
+        app.execute_contract(
+            Addr::unchecked(CREATOR),
+            factory.clone(),
+            &ExecuteMsg::SetCreatorLimit {
+                max_collections_per_creator: 2,
+            },
+            &[],
+        )
+        .unwrap_err();
+        app.execute_contract(
+            Addr::unchecked(OWNER),
+            factory.clone(),
+            &ExecuteMsg::SetCreatorLimit {
+                max_collections_per_creator: 2,
+            },
+            &[],
+        )
+        .unwrap();
+
+        for _ in 0..2 {
+            app.execute_contract(
+                Addr::unchecked(CREATOR),
+                factory.clone(),
+                &create_collection_msg(),
+                &[],
+            )
+            .unwrap();
+        }
+        let err = app
+            .execute_contract(
+                Addr::unchecked(CREATOR),
+                factory.clone(),
+                &create_collection_msg(),
+                &[],
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::Std(StdError::generic_err("creator collection limit reached"))
+        );
+        assert_eq!(query_deployed(&app, &factory, CREATOR).len(), 2);
+
+        // the limit is per creator
+        app.execute_contract(
+            Addr::unchecked(OWNER),
+            factory.clone(),
+            &create_collection_msg(),
+            &[],
+        )
+        .unwrap();
+
+        // 0 lifts the limit
+        app.execute_contract(
+            Addr::unchecked(OWNER),
+            factory.clone(),
+            &ExecuteMsg::SetCreatorLimit {
+                max_collections_per_creator: 0,
+            },
+            &[],
+        )
+        .unwrap();
+        app.execute_contract(
+            Addr::unchecked(CREATOR),
+            factory.clone(),
+            &create_collection_msg(),
+            &[],
+        )
+        .unwrap();
+        assert_eq!(query_deployed(&app, &factory, CREATOR).len(), 3);
+    }
+
+    fn query_staked_by(
+        app: &App,
+        factory: &Addr,
+        address: &str,
+        start_after: Option<(String, String)>,
+    ) -> Vec<StakedToken> {
+        let res: StakedResponse = app
+            .wrap()
+            .query_wasm_smart(
+                factory,
+                &QueryMsg::StakedBy {
+                    address: address.to_string(),
+                    start_after,
+                    limit: Some(1),
+                },
+            )
+            .unwrap();
+        res.tokens
+    }
+
+    fn query_ownership(app: &App, factory: &Addr) -> OwnershipResponse {
+        app.wrap()
+            .query_wasm_smart(factory, &QueryMsg::Ownership {})
+            .unwrap()
+    }
+
+    #[test]
+    fn contract_version_query_matches_stored_cw2_info() {
+        let (app, factory) = setup_app();
+        let raw = app
+            .wrap()
+            .query_wasm_raw(&factory, cw2::CONTRACT.as_slice())
+            .unwrap()
+            .unwrap();
+        let stored: cw2::ContractVersion = from_slice(&raw).unwrap();
+        assert_eq!(stored.contract, CONTRACT_NAME);
+        assert_eq!(stored.version, CONTRACT_VERSION);
+
+        let res: ContractVersionResponse = app
+            .wrap()
+            .query_wasm_smart(&factory, &QueryMsg::ContractVersion {})
+            .unwrap();
+        assert_eq!(
+            res,
+            ContractVersionResponse {
+                contract: stored.contract,
+                version: stored.version,
+                commit: BUILD_COMMIT.map(str::to_string),
+            }
+        );
+    }
+
+    #[test]
+    fn ownership_moves_once_accepted_and_can_be_renounced() {
+        let (mut app, factory) = setup_app();
+        let new_owner = "new_owner";
+        let set_limit = ExecuteMsg::SetCreatorLimit {
+            max_collections_per_creator: 1,
+        };
+        let assert_unauthorized = |app: &mut App, sender: &str, msg: &ExecuteMsg| {
+            let err = app
+                .execute_contract(Addr::unchecked(sender), factory.clone(), msg, &[])
+                .unwrap_err();
+            assert_eq!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::Std(StdError::generic_err("Unauthorized"))
+            );
+        };
+
+        // only the owner proposes
+        let propose = |new_owner: &str, expiry| ExecuteMsg::ProposeOwner {
+            new_owner: new_owner.to_string(),
+            expiry,
+        };
+        assert_unauthorized(&mut app, CREATOR, &propose(CREATOR, None));
+        let expiry = Expiration::AtHeight(app.block_info().height + 10);
+        app.execute_contract(
+            Addr::unchecked(OWNER),
+            factory.clone(),
+            &propose(new_owner, Some(expiry)),
+            &[],
+        )
+        .unwrap();
+        assert_eq!(
+            query_ownership(&app, &factory),
+            OwnershipResponse {
+                owner: Some(OWNER.to_string()),
+                pending_owner: Some(new_owner.to_string()),
+                pending_expiry: Some(expiry),
+            }
+        );
+
+        // the proposal changes nothing until the pending owner accepts it
+        assert_unauthorized(&mut app, CREATOR, &ExecuteMsg::AcceptOwnership {});
+        app.execute_contract(
+            Addr::unchecked(new_owner),
+            factory.clone(),
+            &ExecuteMsg::AcceptOwnership {},
+            &[],
+        )
+        .unwrap();
+        assert_eq!(
+            query_ownership(&app, &factory),
+            OwnershipResponse {
+                owner: Some(new_owner.to_string()),
+                pending_owner: None,
+                pending_expiry: None,
+            }
+        );
+        assert_unauthorized(&mut app, OWNER, &set_limit);
+        app.execute_contract(Addr::unchecked(new_owner), factory.clone(), &set_limit, &[])
+            .unwrap();
+
+        // proposals lapse at their expiry
+        let expiry = Expiration::AtHeight(app.block_info().height + 1);
+        app.execute_contract(
+            Addr::unchecked(new_owner),
+            factory.clone(),
+            &propose(OWNER, Some(expiry)),
+            &[],
+        )
+        .unwrap();
+        app.update_block(|block| block.height += 1);
+        let err = app
+            .execute_contract(
+                Addr::unchecked(OWNER),
+                factory.clone(),
+                &ExecuteMsg::AcceptOwnership {},
+                &[],
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::Std(StdError::generic_err("Ownership transfer expired"))
+        );
+
+        // renouncing locks every owner message
+        app.execute_contract(
+            Addr::unchecked(new_owner),
+            factory.clone(),
+            &ExecuteMsg::RenounceOwnership {},
+            &[],
+        )
+        .unwrap();
+        assert_eq!(query_ownership(&app, &factory).owner, None);
+        app.execute_contract(
+            Addr::unchecked(CREATOR),
+            factory.clone(),
+            &create_collection_msg(),
+            &[],
+        )
+        .unwrap();
+        let owner_messages = [
+            set_limit.clone(),
+            propose(new_owner, None),
+            ExecuteMsg::RenounceOwnership {},
+            ExecuteMsg::DeregisterCollection {
+                address: "contract1".to_string(),
+            },
+        ];
+        for msg in owner_messages {
+            assert_unauthorized(&mut app, new_owner, &msg);
+        }
+        let err = app
+            .execute_contract(
+                Addr::unchecked(OWNER),
+                factory.clone(),
+                &ExecuteMsg::AcceptOwnership {},
+                &[],
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::Std(StdError::generic_err("Ownership transfer not found"))
+        );
+    }
+
+    #[test]
+    fn state_stored_with_a_plain_owner_still_loads() {
+        let mut deps = mock_dependencies();
+        let raw = br#"{"owner":"owner","collection_code_id":1,"max_collections_per_creator":0}"#;
+        deps.as_mut().storage.set(STATE.as_slice(), raw);
+
+        let state = STATE.load(deps.as_ref().storage).unwrap();
+        assert_eq!(state.owner, Some(Addr::unchecked(OWNER)));
+        assert_eq!(state.pending_owner, None);
+        assert!(!state.factory_paused);
+    }
+
+    #[test]
+    fn paused_factory_blocks_deployments_only() {
+        let (mut app, factory) = setup_app();
+        app.execute_contract(
+            Addr::unchecked(CREATOR),
+            factory.clone(),
+            &create_collection_msg(),
+            &[],
+        )
+        .unwrap();
+
+        let pause = ExecuteMsg::SetPaused {
+            paused: true,
+            reason: "incident".to_string(),
+        };
+
+        // only the owner pauses
+        let err = app
+            .execute_contract(Addr::unchecked(CREATOR), factory.clone(), &pause, &[])
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::Std(StdError::generic_err("Unauthorized"))
+        );
+        app.execute_contract(Addr::unchecked(OWNER), factory.clone(), &pause, &[])
+            .unwrap();
+        let paused_at = app.block_info().time.seconds();
+
+        let err = app
+            .execute_contract(
+                Addr::unchecked(CREATOR),
+                factory.clone(),
+                &create_collection_msg(),
+                &[],
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::FactoryPaused {}
+        );
+        let clone = ExecuteMsg::CloneCollection {
+            source: "contract1".to_string(),
+            overrides: TemplateOverrides::default(),
+        };
+        let err = app
+            .execute_contract(Addr::unchecked(CREATOR), factory.clone(), &clone, &[])
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::FactoryPaused {}
+        );
+        // deployed collections are still managed and queried
+        app.execute_contract(
+            Addr::unchecked(CREATOR),
+            factory.clone(),
+            &ExecuteMsg::SetWhitelist {
+                collection: "contract1".to_string(),
+                user: HOLDER.to_string(),
+                status: true,
+            },
+            &[],
+        )
+        .unwrap();
+        assert_eq!(query_deployed(&app, &factory, CREATOR).len(), 1);
+
+        app.update_block(|block| block.time = block.time.plus_seconds(60));
+        app.execute_contract(
+            Addr::unchecked(OWNER),
+            factory.clone(),
+            &ExecuteMsg::SetPaused {
+                paused: false,
+                reason: "resolved".to_string(),
+            },
+            &[],
+        )
+        .unwrap();
+        app.execute_contract(
+            Addr::unchecked(CREATOR),
+            factory.clone(),
+            &create_collection_msg(),
+            &[],
+        )
+        .unwrap();
+        assert_eq!(query_deployed(&app, &factory, CREATOR).len(), 2);
+
+        // the history lists both switches, oldest first
+        let query_history = |start_after: Option<u64>| -> PauseHistoryResponse {
+            app.wrap()
+                .query_wasm_smart(
+                    &factory,
+                    &QueryMsg::PauseHistory {
+                        start_after,
+                        limit: None,
+                    },
+                )
+                .unwrap()
+        };
+        let record = |paused: bool, at: u64, reason: &str| PauseRecord {
+            paused,
+            sender: Addr::unchecked(OWNER),
+            at,
+            reason: reason.to_string(),
+        };
+        let history = query_history(None);
+        assert!(!history.paused);
+        assert_eq!(
+            history.entries,
+            vec![
+                PauseHistoryEntry {
+                    index: 1,
+                    record: record(true, paused_at, "incident"),
+                },
+                PauseHistoryEntry {
+                    index: 2,
+                    record: record(false, paused_at + 60, "resolved"),
+                },
+            ]
+        );
+        assert_eq!(query_history(Some(1)).entries, history.entries[1..]);
+    }
+
+    #[test]
+    fn stake_and_unstake_round_trip() {
+        let (mut app, factory) = setup_app();
+        app.execute_contract(
+            Addr::unchecked(CREATOR),
+            factory.clone(),
+            &create_collection_msg(),
+            &[],
+        )
+        .unwrap();
+        let collection = Addr::unchecked(query_deployed(&app, &factory, CREATOR)[0].clone());
+        let staked_at = app.block_info().time.seconds();
+
+        for token_id in ["1", "2"] {
+            app.execute_contract(
+                Addr::unchecked(OWNER),
+                collection.clone(),
+                &StubMintMsg::Mint {
+                    token_id: token_id.to_string(),
+                    owner: HOLDER.to_string(),
+                },
+                &[],
+            )
+            .unwrap();
+            app.execute_contract(
+                Addr::unchecked(HOLDER),
+                collection.clone(),
+                &Cw721ExecuteMsg::SendNft {
+                    contract: factory.to_string(),
+                    token_id: token_id.to_string(),
+                    msg: to_binary(&ReceiveNftMsg::Stake {}).unwrap(),
+                },
+                &[],
+            )
+            .unwrap();
+        }
+
+        // pages through the holder's stakes
+        let first = query_staked_by(&app, &factory, HOLDER, None);
+        assert_eq!(
+            first,
+            vec![StakedToken {
+                collection: collection.to_string(),
+                token_id: "1".to_string(),
+                staked_at,
+            }]
+        );
+        let start_after = Some((collection.to_string(), "1".to_string()));
+        let second = query_staked_by(&app, &factory, HOLDER, start_after);
+        assert_eq!(second[0].token_id, "2");
+        assert!(query_staked_by(&app, &factory, CREATOR, None).is_empty());
+
+        // only the staker gets the token back
+        let unstake = ExecuteMsg::Unstake {
+            collection: collection.to_string(),
+            token_id: "1".to_string(),
+        };
+        let err = app
+            .execute_contract(Addr::unchecked(CREATOR), factory.clone(), &unstake, &[])
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::Std(StdError::generic_err("Unauthorized"))
+        );
+        app.execute_contract(Addr::unchecked(HOLDER), factory.clone(), &unstake, &[])
+            .unwrap();
+
+        let owner = STUB_OWNERS
+            .query(&app.wrap(), collection.clone(), "1")
+            .unwrap();
+        assert_eq!(owner, Some(Addr::unchecked(HOLDER)));
+        let remaining = query_staked_by(&app, &factory, HOLDER, None);
+        assert_eq!(remaining[0].token_id, "2");
+
+        // unstaking twice fails
+        app.execute_contract(Addr::unchecked(HOLDER), factory.clone(), &unstake, &[])
+            .unwrap_err();
+    }
+
+    fn query_class_location(app: &App, factory: &Addr, class_id: &str) -> Option<String> {
+        let res: ClassLocationResponse = app
+            .wrap()
+            .query_wasm_smart(
+                factory,
+                &QueryMsg::ClassLocation {
+                    class_id: class_id.to_string(),
+                },
+            )
+            .unwrap();
+        res.collection
+    }
+
+    #[test]
+    fn reported_classes_resolve_to_their_collection() {
+        let (mut app, factory) = setup_app();
+        for _ in 0..2 {
+            app.execute_contract(
+                Addr::unchecked(CREATOR),
+                factory.clone(),
+                &create_collection_msg(),
+                &[],
+            )
+            .unwrap();
+        }
+        let deployed = query_deployed(&app, &factory, CREATOR);
+        let (first, second) = (Addr::unchecked(&deployed[0]), Addr::unchecked(&deployed[1]));
+        let report = |class_id: &str| ExecuteMsg::ReportClass {
+            class_id: class_id.to_string(),
+        };
+
+        app.execute_contract(first.clone(), factory.clone(), &report("ptx-1"), &[])
+            .unwrap();
+        app.execute_contract(second.clone(), factory.clone(), &report("ptx-2"), &[])
+            .unwrap();
+        assert_eq!(
+            query_class_location(&app, &factory, "ptx-1"),
+            Some(first.to_string())
+        );
+        assert_eq!(
+            query_class_location(&app, &factory, "ptx-2"),
+            Some(second.to_string())
+        );
+        assert_eq!(query_class_location(&app, &factory, "ptx-3"), None);
+
+        // a class id can't be claimed by another collection
+        let err = app
+            .execute_contract(second.clone(), factory.clone(), &report("ptx-1"), &[])
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::Std(StdError::generic_err(format!(
+                "Class ptx-1 is already registered to {}",
+                first
+            )))
+        );
+        assert_eq!(
+            query_class_location(&app, &factory, "ptx-1"),
+            Some(first.to_string())
+        );
+
+        // only collections deployed through the factory report classes
+        let err = app
+            .execute_contract(Addr::unchecked("rogue"), factory.clone(), &report("ptx-3"), &[])
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::Std(StdError::generic_err("Collection not found"))
+        );
+        assert_eq!(query_class_location(&app, &factory, "ptx-3"), None);
+    }
+
+    fn query_collection_whitelist(
+        app: &App,
+        factory: &Addr,
+        collection: &Addr,
+        start_after: Option<&str>,
+        limit: Option<u32>,
+    ) -> Vec<String> {
+        let res: CollectionWhitelistResponse = app
+            .wrap()
+            .query_wasm_smart(
+                factory,
+                &QueryMsg::CollectionWhitelist {
+                    collection: collection.to_string(),
+                    start_after: start_after.map(String::from),
+                    limit,
+                },
+            )
+            .unwrap();
+        res.users
+    }
+
+    #[test]
+    fn collection_whitelist_holds_every_user_once() {
+        let (mut app, factory) = setup_app();
+        app.execute_contract(
+            Addr::unchecked(CREATOR),
+            factory.clone(),
+            &create_collection_msg(),
+            &[],
+        )
+        .unwrap();
+        let collection = Addr::unchecked(&query_deployed(&app, &factory, CREATOR)[0]);
+        let set_whitelist = |app: &mut App, user: &str, status: bool| {
+            app.execute_contract(
+                Addr::unchecked(CREATOR),
+                factory.clone(),
+                &ExecuteMsg::SetWhitelist {
+                    collection: collection.to_string(),
+                    user: user.to_string(),
+                    status,
+                },
+                &[],
+            )
+            .unwrap();
+        };
+
+        // adding twice lists the user once, removing an absent user is harmless
+        set_whitelist(&mut app, "alice", true);
+        set_whitelist(&mut app, "alice", true);
+        set_whitelist(&mut app, "bob", false);
+        let users = query_collection_whitelist(&app, &factory, &collection, None, None);
+        assert_eq!(users, vec!["alice"]);
+        set_whitelist(&mut app, "alice", false);
+        assert!(query_collection_whitelist(&app, &factory, &collection, None, None).is_empty());
+
+        let users: Vec<String> = (0..60).map(|i| format!("user{:02}", i)).collect();
+        app.execute_contract(
+            Addr::unchecked(CREATOR),
+            factory.clone(),
+            &ExecuteMsg::BatchSetWhitelist {
+                entries: vec![WhitelistEntry {
+                    collection: collection.to_string(),
+                    users: users.clone(),
+                    status: true,
+                }],
+            },
+            &[],
+        )
+        .unwrap();
+        let mut listed = vec![];
+        let mut start_after = None;
+        loop {
+            let page = query_collection_whitelist(
+                &app,
+                &factory,
+                &collection,
+                start_after.as_deref(),
+                Some(25),
+            );
+            if page.is_empty() {
+                break;
+            }
+            assert!(page.len() <= 25);
+            start_after = page.last().cloned();
+            listed.extend(page);
+        }
+        assert_eq!(listed, users);
+    }
+
+    #[test]
+    fn migrate_moves_whitelists_to_one_entry_per_user() {
+        let mut deps = mock_dependencies();
+        cw2::set_contract_version(deps.as_mut().storage, CONTRACT_NAME, "0.1.0").unwrap();
+        let collection = Addr::unchecked("collection0");
+        let legacy = vec!["bob".to_string(), "alice".to_string(), "bob".to_string()];
+        LEGACY_WHITELISTED_USERS
+            .save(deps.as_mut().storage, &collection, &legacy)
+            .unwrap();
+
+        migrate(deps.as_mut(), mock_env(), MigrateMsg::Upgrade {}).unwrap();
+        let users = COLLECTION_WHITELISTS
+            .prefix(&collection)
+            .keys(deps.as_ref().storage, None, None, Order::Ascending)
+            .collect::<StdResult<Vec<_>>>()
+            .unwrap();
+        assert_eq!(users, vec![Addr::unchecked("alice"), Addr::unchecked("bob")]);
+        assert!(!LEGACY_WHITELISTED_USERS.has(deps.as_ref().storage, &collection));
+    }
+
+    #[test]
+    fn batch_set_whitelist_authorizes_every_collection() {
+        let (mut app, factory) = setup_app();
+        for creator in [CREATOR, HOLDER] {
+            app.execute_contract(
+                Addr::unchecked(creator),
+                factory.clone(),
+                &create_collection_msg(),
+                &[],
+            )
+            .unwrap();
+        }
+        let own = Addr::unchecked(&query_deployed(&app, &factory, CREATOR)[0]);
+        let other = Addr::unchecked(&query_deployed(&app, &factory, HOLDER)[0]);
+        let entry = |collection: &Addr, users: &[&str]| WhitelistEntry {
+            collection: collection.to_string(),
+            users: users.iter().map(|user| user.to_string()).collect(),
+            status: true,
+        };
+        let mirrored = |app: &App, collection: &Addr| {
+            query_collection_whitelist(app, &factory, collection, None, None)
+        };
+
+        // a creator can't touch a collection deployed by someone else, the whole batch fails
+        let batch = ExecuteMsg::BatchSetWhitelist {
+            entries: vec![entry(&own, &["alice"]), entry(&other, &["bob"])],
+        };
+        let err = app
+            .execute_contract(Addr::unchecked(CREATOR), factory.clone(), &batch, &[])
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::Std(StdError::generic_err(format!(
+                "Unauthorized for collection {}",
+                other
+            )))
+        );
+        assert!(mirrored(&app, &own).is_empty());
+        let relayed = STUB_WHITELIST.query(&app.wrap(), own.clone(), "alice").unwrap();
+        assert_eq!(relayed, None);
+
+        // the factory owner can update any collection
+        let batch = ExecuteMsg::BatchSetWhitelist {
+            entries: vec![entry(&own, &["alice", "carol"]), entry(&other, &["bob"])],
+        };
+        let res = app
+            .execute_contract(Addr::unchecked(OWNER), factory.clone(), &batch, &[])
+            .unwrap();
+        let wasm = res.events.iter().find(|event| event.ty == "wasm").unwrap();
+        let count = |collection: &Addr| {
+            wasm.attributes
+                .iter()
+                .find(|attr| attr.key == collection.as_str())
+                .map(|attr| attr.value.clone())
+        };
+        assert_eq!(count(&own), Some("2".to_string()));
+        assert_eq!(count(&other), Some("1".to_string()));
+        assert_eq!(mirrored(&app, &own), vec!["alice", "carol"]);
+        assert_eq!(mirrored(&app, &other), vec!["bob"]);
+        for (collection, user) in [(&own, "alice"), (&own, "carol"), (&other, "bob")] {
+            let relayed = STUB_WHITELIST.query(&app.wrap(), collection.clone(), user).unwrap();
+            assert_eq!(relayed, Some(true));
+        }
+
+        // creators can batch over their own collections
+        let batch = ExecuteMsg::BatchSetWhitelist {
+            entries: vec![WhitelistEntry {
+                status: false,
+                ..entry(&own, &["carol"])
+            }],
+        };
+        app.execute_contract(Addr::unchecked(CREATOR), factory.clone(), &batch, &[])
+            .unwrap();
+        assert_eq!(mirrored(&app, &own), vec!["alice"]);
+
+        // unknown collections and oversized batches are rejected
+        let batch = ExecuteMsg::BatchSetWhitelist {
+            entries: vec![entry(&Addr::unchecked("rogue"), &["alice"])],
+        };
+        let err = app
+            .execute_contract(Addr::unchecked(OWNER), factory.clone(), &batch, &[])
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::Std(StdError::generic_err("Collection not found: rogue"))
+        );
+        let users: Vec<String> = (0..=MAX_WHITELIST_BATCH).map(|i| format!("user{}", i)).collect();
+        let users: Vec<&str> = users.iter().map(String::as_str).collect();
+        let batch = ExecuteMsg::BatchSetWhitelist {
+            entries: vec![entry(&own, &users[..50]), entry(&other, &users[50..])],
+        };
+        let err = app
+            .execute_contract(Addr::unchecked(OWNER), factory.clone(), &batch, &[])
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::Std(invalid_config("entries", "can't hold more than 100 users"))
+        );
+    }
+
+    #[test]
+    fn receive_from_unregistered_collection_is_rejected() {
+        let (mut app, factory) = setup_app();
+
+        let err = app
+            .execute_contract(
+                Addr::unchecked("rogue"),
+                factory.clone(),
+                &ExecuteMsg::ReceiveNft(Cw721ReceiveMsg {
+                    sender: HOLDER.to_string(),
+                    token_id: "1".to_string(),
+                    msg: to_binary(&ReceiveNftMsg::Stake {}).unwrap(),
+                }),
+                &[],
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::Std(StdError::generic_err("Collection not found"))
+        );
+    }
+
+    #[test]
+    fn migrate_from_v1() {
+        let mut deps = mock_dependencies();
+        cw2::set_contract_version(deps.as_mut().storage, CONTRACT_NAME, "0.1.0").unwrap();
+
+        let res = migrate(deps.as_mut(), mock_env(), MigrateMsg::Upgrade {}).unwrap();
+        assert_eq!(
+            res.attributes,
+            vec![
+                attr("action", "migrate"),
+                attr("from_version", "0.1.0"),
+                attr("to_version", CONTRACT_VERSION),
+            ]
+        );
+
+        let version = cw2::get_contract_version(deps.as_ref().storage).unwrap();
+        assert_eq!(version.contract, CONTRACT_NAME);
+        assert_eq!(version.version, CONTRACT_VERSION);
+    }
+
+    #[test]
+    fn migrate_rejects_other_contract_and_downgrade() {
+        let mut deps = mock_dependencies();
+
+        cw2::set_contract_version(deps.as_mut().storage, "crates.io:coreum-nft", "0.1.0").unwrap();
+        let err = migrate(deps.as_mut(), mock_env(), MigrateMsg::Upgrade {}).unwrap_err();
+        assert_eq!(
+            err,
+            StdError::generic_err("Cannot migrate from a different contract: crates.io:coreum-nft")
+        );
+
+        cw2::set_contract_version(deps.as_mut().storage, CONTRACT_NAME, "999.0.0").unwrap();
+        let err = migrate(deps.as_mut(), mock_env(), MigrateMsg::Upgrade {}).unwrap_err();
+        assert_eq!(
+            err,
+            StdError::generic_err("Cannot migrate from newer version 999.0.0")
+        );
+    }
+}
@@ -0,0 +1,14549 @@
+#[cfg(not(feature = "library"))]
+use cosmwasm_std::entry_point;
+use cosmwasm_std::{
+    attr,
+    from_binary,
+    to_binary,
+    to_vec,
+    Addr,
+    Api,
+    Attribute,
+    BankMsg,
+    Binary,
+    BlockInfo,
+    CanonicalAddr,
+    Coin,
+    CosmosMsg,
+    Decimal,
+    Deps,
+    DepsMut,
+    Empty,
+    Env,
+    MessageInfo,
+    Order,
+    OverflowError,
+    Reply,
+    Response,
+    StdError,
+    StdResult,
+    Storage,
+    SubMsg,
+    SubMsgResult,
+    Timestamp,
+    Uint128,
+    WasmMsg,
+};
+pub use coreum_nft_types::{
+    ConfigError,
+    FinalizeActions,
+    Phase,
+    ProptixDeploymentConfig,
+    ProptixRuntimeConfig,
+    TokenIdFormat,
+    MAX_TIME_SECONDS,
+};
+use cw20::{ Cw20ExecuteMsg, Cw20ReceiveMsg };
+use cw721::{
+    AllNftInfoResponse,
+    Approval,
+    Cw721ExecuteMsg,
+    Cw721ReceiveMsg,
+    NftInfoResponse,
+    NumTokensResponse,
+    OwnerOfResponse,
+    TokensResponse,
+};
+use cw721_base::{ Cw721Contract, InstantiateMsg as Cw721InstantiateMsg };
+use cw_storage_plus::{ Bound, Index, IndexList, IndexedMap, Item, Map, MultiIndex, PrimaryKey };
+use cw_utils::{ must_pay, one_coin, Expiration, PaymentError };
+use schemars::JsonSchema;
+use serde::{ Deserialize, Deserializer, Serialize };
+use sha2::{ Digest, Sha256 };
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+pub const STATE: Item<State> = Item::new("state");
+
+// Pull-payment ledger of buyers, the treasury and the protocol, by address and denom
+pub const BALANCES: Map<(&Addr, &str), Uint128> = Map::new("denom_balances");
+
+// Payments held while a sale with a `State::min_threshold` runs, by buyer and denom. They
+// are paid back through `ClaimRefund` if the sale misses its threshold.
+pub const SALE_ESCROW: Map<(&Addr, &str), Uint128> = Map::new("sale_escrow");
+
+// Tokens bought with an escrowed payment, by payer and token id. `ClaimRefund` burns them
+// along with paying the payment back.
+pub const ESCROWED_TOKENS: Map<(&Addr, &str), Empty> = Map::new("escrowed_tokens");
+
+// Sales income held along with the payments, by the address it is owed to and denom.
+// `ReleaseEscrow` moves it to `BALANCES` once the sale met its threshold.
+pub const ESCROWED_PROCEEDS: Map<(&Addr, &str), Uint128> = Map::new("escrowed_proceeds");
+
+// Sale whitelist. Removed addresses are dropped so the map only lists whitelisted ones,
+// entries stored as `false` before that are skipped.
+pub const WHITELIST: Map<&Addr, bool> = Map::new("whitelist");
+
+// Tokens a whitelisted wallet may still buy, seeded by `AddToWhitelist`. Wallets without
+// an entry aren't limited beyond `State::max_per_wallet`.
+pub const WHITELIST_ALLOCATIONS: Map<&Addr, u64> = Map::new("whitelist_allocations");
+
+// Addresses barred from buying, receiving and transferring tokens. Burning stays possible.
+pub const BLACKLIST: Map<&Addr, Empty> = Map::new("blacklist");
+
+// Addresses barred from receiving tokens, by mint or transfer, such as sanctioned wallets or
+// marketplaces the collection doesn't trade on. Unlike the blacklist they can still buy for
+// someone else and move the tokens they hold.
+pub const RECIPIENT_BLOCKLIST: Map<&Addr, Empty> = Map::new("recipient_blocklist");
+
+// Base uri change proposed through `ProposeBaseUriChange`, until it is applied or vetoed
+pub const PENDING_URI_CHANGE: Item<UriChange> = Item::new("pending_uri_change");
+
+// Addresses `State::max_per_wallet_holding` doesn't apply to, such as the owner or the treasury
+pub const HOLDING_CAP_EXEMPT: Map<&Addr, Empty> = Map::new("holding_cap_exempt");
+
+// Rank of the first `State::early_bird_cutoff` buyers, starting at 1, and the buyer of each
+// rank so the list pages in rank order. Entries are never changed once written.
+pub const EARLY_BIRDS: Map<&Addr, u64> = Map::new("early_birds");
+pub const EARLY_BIRD_RANKS: Map<u64, Addr> = Map::new("early_bird_ranks");
+
+// Contracts notified of every mint, transfer and burn, at most `MAX_TRANSFER_HOOKS`
+pub const TRANSFER_HOOKS: Map<&Addr, Empty> = Map::new("transfer_hooks");
+
+// Number of tokens held by every holder, kept in step with the owner index of `tokens()`.
+// Holders without tokens have no entry.
+pub const HOLDER_BALANCES: Map<&Addr, u64> = Map::new("holder_balances");
+
+// Fixed-price listings of the collection's own marketplace, keyed by token id. A listing
+// is dropped whenever the token moves or is burned.
+pub const LISTINGS: Map<&str, Listing> = Map::new("listings");
+
+// Tokens escrowed for an ICS-721 transfer, keyed by token id. They can't be moved or burned
+// until the bridge releases them.
+pub const BRIDGED: Map<&str, BridgedToken> = Map::new("bridged");
+
+// Auctions started through `StartAuction` and not settled yet, keyed by token id. The token
+// stays with the seller but can't be moved or burned until the auction is settled.
+pub const AUCTIONS: Map<&str, Auction> = Map::new("auctions");
+
+// Rewards paid to holders through `FundRewards`, unset until the first funding
+pub const HOLDER_REWARDS: Item<HolderRewards> = Item::new("holder_rewards");
+
+// Reward index every token was paid up to, keyed by token id. Tokens minted before the
+// first funding have no entry and are paid from zero.
+pub const REWARD_CHECKPOINTS: Map<&str, Decimal> = Map::new("reward_checkpoints");
+
+// Unlock time in seconds of tokens their holder locked through `LockUntil`, keyed by token
+// id. Unlike `TokenRecord::locked_until` it can only be set and extended by the holder.
+pub const SELF_LOCKS: Map<&str, u64> = Map::new("self_locks");
+
+// Passes of `State::pass_collection` burned through `RedeemPass`, mapped to the token
+// minted for them
+pub const REDEEMED_PASSES: Map<&str, u64> = Map::new("redeemed_passes");
+
+// Tokens bought for every wallet, counted against `State::max_per_wallet`
+pub const MINTED_PER_WALLET: Map<&Addr, u64> = Map::new("minted_per_wallet");
+
+// Tokens sold to every wallet within a sale phase, keyed by (phase label, wallet)
+pub const MINTED_PER_PHASE: Map<(&str, &Addr), u64> = Map::new("minted_per_phase");
+
+// Tokens sold in the latest block with sales as (height, count), counted against
+// `State::max_mints_per_block`
+pub const BLOCK_MINTS: Item<(u64, u64)> = Item::new("block_mints");
+
+// Tokens sold per day, keyed by `block time / SECONDS_PER_DAY`. Sales only happen within
+// the sale windows, so the number of entries is bounded by their length in days.
+pub const MINTS_PER_DAY: Map<u64, u64> = Map::new("mints_per_day");
+
+// Classes issued through `ExecuteMsg::IssueClass`, keyed by class id
+pub const CLASSES: Map<&str, ClassInfo> = Map::new("classes");
+
+// Pending `CommitMint` commitments, one per committer
+pub const COMMITMENTS: Map<&Addr, Commitment> = Map::new("commitments");
+
+// Sparse Fisher-Yates pool used to assign token ids. A missing entry at position `p` means
+// the id at that position is `p` itself, so sequential mints never touch storage.
+pub const TOKEN_ID_POOL: Map<u64, u64> = Map::new("token_id_pool");
+
+// Allowlist merkle root of every sale tier, keyed by tier
+pub const PHASE_ROOTS: Map<u8, Binary> = Map::new("phase_roots");
+
+// Presale tiers for OG holders and the regular allowlist
+pub const TIER_OG: u8 = 0;
+pub const TIER_WL: u8 = 1;
+// Tier of a pre-indexed allowlist: its root commits to `(index, buyer)` pairs and a buyer
+// is eligible while the bit of their index is set in `ALLOWLIST_BITMAP`
+pub const TIER_BITMAP: u8 = 2;
+
+// Eligibility bits of the pre-indexed allowlist in pages of `ALLOWLIST_PAGE_BITS` bits,
+// keyed by `index / ALLOWLIST_PAGE_BITS`. Pages without a set bit are not stored.
+pub const ALLOWLIST_BITMAP: Map<u32, Vec<u8>> = Map::new("allowlist_bitmap");
+pub const ALLOWLIST_PAGE_BITS: u32 = 8_192;
+// Most bits a single `SetAllowlistRange` writes
+pub const MAX_ALLOWLIST_RANGE: u32 = 8 * ALLOWLIST_PAGE_BITS;
+
+// Reply to a failed transfer hook notification, which is swallowed
+pub const TRANSFER_HOOK_REPLY_ID: u64 = 1;
+pub const MAX_TRANSFER_HOOKS: usize = 5;
+// Gas a hook gets per notification, so running out of it can't revert the operation
+pub const TRANSFER_HOOK_GAS_LIMIT: u64 = 500_000;
+
+// A bid this close to the end of an auction pushes the end to this long after the bid
+pub const AUCTION_EXTENSION_SECONDS: u64 = 600;
+
+// Blocks between `CommitMint` and the first block its `RevealMint` is accepted in
+pub const MIN_REVEAL_DELAY_BLOCKS: u64 = 1;
+// Blocks after `CommitMint` up to which its `RevealMint` is accepted
+pub const REVEAL_WINDOW_BLOCKS: u64 = 10;
+// Blocks after which an unrevealed commitment can be cancelled
+pub const COMMIT_TIMEOUT_BLOCKS: u64 = 100;
+
+// Version info for migration
+pub const CONTRACT_NAME: &str = "crates.io:coreum-nft";
+pub const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
+// Commit the wasm was built from, set through the `BUILD_COMMIT` env var at build time
+pub const BUILD_COMMIT: Option<&str> = option_env!("BUILD_COMMIT");
+
+// Versions before this one stored `protocol_fee` as a whole percent instead of basis points
+const PROTOCOL_FEE_BPS_VERSION: &str = "0.2.0";
+// First version storing tokens as `TokenRecord`s
+const TOKEN_RECORDS_VERSION: &str = "0.3.0";
+// Versions before this one stored amounts as raw integers instead of `Uint128`
+const UINT128_AMOUNTS_VERSION: &str = "0.4.0";
+// Versions before this one stored the owner as a `CanonicalAddr`
+const OWNER_ADDR_VERSION: &str = "0.5.0";
+
+const SECONDS_PER_DAY: u64 = 86_400;
+
+const DEFAULT_LIMIT: u32 = 10;
+const MAX_LIMIT: u32 = 100;
+const MAX_FROZEN_TOKENS_LIMIT: u32 = 50;
+const MAX_WHITELIST_LIMIT: u32 = 30;
+const MAX_METADATA_BATCH: usize = 50;
+const MAX_MINT_PER_TX: u64 = 50;
+const MAX_TRANSFER_BATCH: usize = 50;
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, JsonSchema)]
+pub struct State {
+    pub owner: Addr,
+    pub base_token_uri: String,
+    pub base_token_uri_extension: String,
+    pub prereveal_token_uri: String,
+    pub treasury_address: Addr,
+    /// Unset until given at instantiate or through `SetProtocolAddress`
+    #[serde(deserialize_with = "deserialize_stored_address")]
+    pub protocol_address: Option<Addr>,
+    pub mint_denom: String,
+    pub mint_price: Uint128,
+    /// Public sale window in seconds, both ends inclusive
+    pub sale_start_time: u64,
+    pub sale_end_time: u64,
+    /// Protocol fee in basis points (1/100th of a percent)
+    pub protocol_fee_bps: u16,
+    pub max_total_mint: u64,
+    pub current_token_id: u64,
+    pub uri_status: bool,
+    /// Number of tokens burned so far. `current_token_id` stays the mint
+    /// cursor, so burned ids are never reissued.
+    #[serde(default)]
+    pub burned_count: u64,
+    /// Number of tokens minted so far, by sales and by the owner through `Mint`. The
+    /// tokens in existence are `minted_count - burned_count`.
+    #[serde(default)]
+    pub minted_count: u64,
+    /// Tier whose merkle root gates `Purchase`. Without one the `WHITELIST` map
+    /// is used.
+    #[serde(default)]
+    pub active_tier: Option<u8>,
+    /// Presale window, both zero when the drop has no presale
+    #[serde(default)]
+    pub presale_start_time: u64,
+    #[serde(default)]
+    pub presale_end_time: u64,
+    /// Cumulative sales income, protocol fees included
+    #[serde(default)]
+    pub gross_proceeds: Uint128,
+    #[serde(default)]
+    pub protocol_fees_accrued: Uint128,
+    /// Tokens sold through `Purchase` and `RevealMint`
+    #[serde(default)]
+    pub total_mints: u64,
+    /// Tokens a single wallet may receive from sales, 0 for no limit
+    #[serde(default)]
+    pub max_per_wallet: u64,
+    /// ics721 bridge contract handling interchain transfers
+    #[serde(default, deserialize_with = "deserialize_stored_address")]
+    pub ics721_address: Option<Addr>,
+    /// Collection level metadata json read by marketplaces (`contractURI`)
+    #[serde(default)]
+    pub contract_uri: Option<String>,
+    /// Set once through `FreezeMetadata`, after which the contract uri can't change
+    #[serde(default)]
+    pub metadata_frozen: bool,
+    /// Set once through `FreezeSupply`, after which `ReduceMaxSupply` is rejected
+    #[serde(default)]
+    pub supply_frozen: bool,
+    /// Forbids schedule changes once the first sale window has opened
+    #[serde(default)]
+    pub lock_schedule_on_start: bool,
+    /// Forbids whitelist removals once the public sale has started
+    #[serde(default)]
+    pub lock_whitelist_after_start: bool,
+    /// Set from the deployment config, forbids `SetUser`
+    #[serde(default)]
+    pub users_disabled: bool,
+    /// Set from the deployment config, rejects `TransferNft` without a sale price
+    #[serde(default)]
+    pub royalty_enforced: bool,
+    /// Tokens a wallet may hold at once, enforced on mints and transfers
+    #[serde(default)]
+    pub max_per_wallet_holding: Option<u64>,
+    /// cw721 collection whose tokens can be burned for a free mint through `RedeemPass`
+    #[serde(default)]
+    pub pass_collection: Option<Addr>,
+    /// Share of a sale credited to its referrer in basis points, taken from the treasury share
+    #[serde(default)]
+    pub referral_bps: u16,
+    /// Deployed with a max supply of 0. Sales are bounded only by the sale window
+    /// until `FinalizeSupply` fixes `max_total_mint` to the minted count.
+    #[serde(default)]
+    pub open_edition: bool,
+    /// Public key signing `RedeemVoucher` allowlist passes, empty until set
+    #[serde(default)]
+    pub signer_pubkey: Binary,
+    /// Curve of `signer_pubkey`
+    #[serde(default)]
+    pub signature_scheme: SignatureScheme,
+    /// Contract the treasury share is forwarded to, executing `proceeds_hook_msg`.
+    /// Without one sales income stays in the ledger and royalties go to the treasury.
+    #[serde(default)]
+    pub proceeds_hook: Option<Addr>,
+    #[serde(default)]
+    pub proceeds_hook_msg: Binary,
+    /// Paid to the burner of every token out of `reward_pool`
+    #[serde(default)]
+    pub burn_reward: Option<Coin>,
+    /// Funds escrowed for burn rewards, in the denom of `burn_reward`
+    #[serde(default)]
+    pub reward_pool: Uint128,
+    /// Format of the ids `mint` assigns, which keeps counting tokens by number
+    #[serde(default)]
+    pub token_id_format: Option<TokenIdFormat>,
+    /// Contract queried for the uri of tokens without an override, replacing the base uri
+    #[serde(default)]
+    pub metadata_resolver: Option<Addr>,
+    /// Time in seconds the collection reveals at if `Reveal` wasn't called before
+    #[serde(default)]
+    pub reveal_at: Option<u64>,
+    /// Sale phases from the runtime config, the flat sale windows apply without any
+    #[serde(default)]
+    pub phases: Vec<Phase>,
+    /// Seconds the public sale runs past `sale_end_time` while more than
+    /// `grace_threshold_pct` percent of the supply remains, see `effective_sale_end`
+    #[serde(default)]
+    pub grace_period_secs: u64,
+    #[serde(default)]
+    pub grace_threshold_pct: u8,
+    /// Longest uri accepted in bytes, 0 for no limit
+    #[serde(default)]
+    pub max_uri_len: u32,
+    /// Tokens the sale must sell before it ends, 0 for no threshold, see `escrows_sales`
+    #[serde(default)]
+    pub min_threshold: u64,
+    /// Set by `ReleaseEscrow`, sales income is credited directly from then on
+    #[serde(default)]
+    pub escrow_released: bool,
+    /// Buyers recorded in `EARLY_BIRDS` before the list closes, 0 to keep no list
+    #[serde(default)]
+    pub early_bird_cutoff: u64,
+    /// Buyers recorded in `EARLY_BIRDS` so far
+    #[serde(default)]
+    pub early_birds: u64,
+    #[serde(default)]
+    pub finalize_actions: FinalizeActions,
+    /// Set by `FinalizeSale`, which only runs once
+    #[serde(default)]
+    pub sale_finalized: bool,
+    #[serde(default)]
+    pub mint_receipts: bool,
+    /// Tokens a holder needs to veto a pending base uri change, any holder can when 0
+    #[serde(default)]
+    pub uri_veto_threshold: u64,
+    /// Tokens that can be sold within a single block, 0 for no limit
+    #[serde(default)]
+    pub max_mints_per_block: u64,
+    /// Share of every sale paid to `factory_address`, in basis points
+    #[serde(default)]
+    pub factory_fee_bps: u16,
+    /// Contract that instantiated the collection with a factory fee. Unlike the owner it
+    /// never changes.
+    #[serde(default)]
+    pub factory_address: Option<Addr>,
+}
+
+/// Curve allowlist vouchers are signed with
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default, JsonSchema)]
+pub enum SignatureScheme {
+    /// Compressed (33 bytes) or uncompressed (65 bytes) public key
+    #[default]
+    Secp256k1,
+    /// 32 bytes public key
+    Ed25519,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, JsonSchema)]
+pub struct Listing {
+    pub seller: Addr,
+    pub price: Coin,
+}
+
+/// Bid on a token, its price is escrowed by the contract until the offer is accepted or
+/// withdrawn
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, JsonSchema)]
+pub struct Offer {
+    pub bidder: Addr,
+    pub price: Coin,
+    pub expires: Expiration,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, JsonSchema)]
+pub struct AuctionBid {
+    pub bidder: Addr,
+    pub amount: Uint128,
+}
+
+/// English auction of a token. The highest bid is escrowed by the contract.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, JsonSchema)]
+pub struct Auction {
+    pub seller: Addr,
+    /// Lowest highest bid the token is sold for, bids below it are still accepted
+    pub reserve_price: Coin,
+    pub min_bid_increment: Uint128,
+    /// End in seconds, pushed back by late bids
+    pub end_time: u64,
+    pub highest_bid: Option<AuctionBid>,
+}
+
+impl Auction {
+    /// Lowest amount a new bid has to offer
+    pub fn min_bid(&self) -> StdResult<Uint128> {
+        match &self.highest_bid {
+            Some(bid) => Ok(bid.amount.checked_add(self.min_bid_increment.max(Uint128::one()))?),
+            None => Ok(Uint128::one()),
+        }
+    }
+}
+
+/// Native or cw20 token holder rewards are paid in
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RewardAsset {
+    Native(String),
+    Cw20(Addr),
+}
+
+impl RewardAsset {
+    /// Sends `amount` of the asset to `recipient`
+    pub fn payout(&self, recipient: &Addr, amount: Uint128) -> StdResult<CosmosMsg> {
+        Ok(match self {
+            RewardAsset::Native(denom) =>
+                BankMsg::Send {
+                    to_address: recipient.to_string(),
+                    amount: vec![Coin { denom: denom.clone(), amount }],
+                }.into(),
+            RewardAsset::Cw20(contract) =>
+                WasmMsg::Execute {
+                    contract_addr: contract.to_string(),
+                    msg: to_binary(
+                        &(Cw20ExecuteMsg::Transfer { recipient: recipient.to_string(), amount })
+                    )?,
+                    funds: vec![],
+                }.into(),
+        })
+    }
+}
+
+impl fmt::Display for RewardAsset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RewardAsset::Native(denom) => write!(f, "{}", denom),
+            RewardAsset::Cw20(contract) => write!(f, "cw20:{}", contract),
+        }
+    }
+}
+
+/// Rewards shared equally between the tokens in existence whenever they are funded
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, JsonSchema)]
+pub struct HolderRewards {
+    /// Fixed by the first funding
+    pub asset: RewardAsset,
+    /// Rewards funded per token so far
+    pub index: Decimal,
+    /// Rounding remainder of past fundings, added to the next one
+    pub undistributed: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, JsonSchema)]
+pub enum BridgeStatus {
+    /// Sent to the bridge, waiting for the acknowledgement
+    Pending,
+    /// Acknowledged, the token lives on the counterparty chain
+    Transferred,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, JsonSchema)]
+pub struct BridgedToken {
+    /// Owner the token is released to if the transfer fails
+    pub owner: Addr,
+    pub channel: String,
+    pub receiver: String,
+    pub status: BridgeStatus,
+}
+
+/// Outcome of an interchain transfer, reported by the ics721 bridge
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub enum IbcOutcome {
+    AckSuccess {},
+    AckError {
+        error: String,
+    },
+    Timeout {},
+    /// The token was sent back from the counterparty chain to `recipient`
+    Returned {
+        recipient: String,
+    },
+}
+
+/// Payload the ics721 bridge expects with a received NFT
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct IbcOutgoingMsg {
+    pub receiver: String,
+    pub channel_id: String,
+    pub timeout: IbcTimeoutMsg,
+    pub memo: Option<String>,
+}
+
+/// Timestamp-only `IbcTimeout`, which is not available without the `stargate` feature
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct IbcTimeoutMsg {
+    pub block: Option<Empty>,
+    pub timestamp: Option<Timestamp>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Ics721ExecuteMsg {
+    ReceiveNft(Cw721ReceiveMsg),
+}
+
+/// Notification sent to the transfer hooks, once per operation for all the tokens it moved.
+/// `from` is unset for mints, `to` for burns.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TransferHookMsg {
+    pub token_ids: Vec<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    /// `mint`, `transfer`, `send` or `burn`
+    pub action: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferHookExecuteMsg {
+    TransferHook(TransferHookMsg),
+}
+
+/// Query answered by the metadata resolver with a `TokenUriResponse`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MetadataResolverQueryMsg {
+    TokenUri {
+        token_id: String,
+    },
+}
+
+/// Parameters of a purchase as signed by the payer
+pub struct PurchaseOrder<'a> {
+    pub payer: &'a Addr,
+    /// Wallet receiving the tokens, the payer unless gifting
+    pub recipient: &'a Addr,
+    pub count: u64,
+    pub proof: Option<&'a [Binary]>,
+    pub max_price_per_token: Option<Uint128>,
+    /// Wallet credited with `State::referral_bps` of the sale
+    pub referrer: Option<&'a Addr>,
+    /// The payer redeemed a verified voucher, which replaces the allowlist check
+    pub voucher: bool,
+    /// Index of the payer in the `TIER_BITMAP` allowlist
+    pub allowlist_index: Option<u32>,
+}
+
+/// Phase of the sale at a given time, see `State::phase`
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, JsonSchema)]
+pub enum SalePhase {
+    /// Before the presale, or between the presale and the public sale
+    Upcoming,
+    Presale,
+    Public,
+    Ended,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, JsonSchema)]
+pub struct ClassInfo {
+    pub name: String,
+    pub symbol: String,
+    pub description: Option<String>,
+    pub uri: Option<String>,
+    /// sha256 digest of the metadata behind `uri`, see `validate_uri_hash`
+    #[serde(default)]
+    pub uri_hash: Option<String>,
+    pub data: Option<Binary>,
+    pub features: Vec<u32>,
+    pub royalty_rate: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, JsonSchema)]
+pub struct TokenRecord {
+    pub owner: Addr,
+    /// Approvals are cleared whenever the token changes hands
+    pub approvals: Vec<Approval>,
+    /// Uri set explicitly through `ExecuteMsg::Mint`, takes precedence over the base uri
+    pub uri_override: Option<String>,
+    /// sha256 digest of the token metadata given at mint, see `validate_uri_hash`
+    #[serde(default)]
+    pub uri_hash: Option<String>,
+    pub frozen: bool,
+    /// Transfers and listings are blocked until the lock expires, burns are not
+    #[serde(default)]
+    pub locked_until: Option<Expiration>,
+    /// Account allowed to use the token without owning it, cleared on transfer
+    #[serde(default)]
+    pub user: Option<TokenUser>,
+    /// On-chain metadata decoded from the `data` of `ExecuteMsg::Mint`
+    #[serde(default)]
+    pub extension: Option<Metadata>,
+}
+
+/// On-chain token metadata, the extension of the cw721-metadata-onchain interface
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default, JsonSchema)]
+pub struct Metadata {
+    pub image: Option<String>,
+    pub image_data: Option<String>,
+    pub external_url: Option<String>,
+    pub description: Option<String>,
+    pub name: Option<String>,
+    pub attributes: Option<Vec<Trait>>,
+    pub background_color: Option<String>,
+    pub animation_url: Option<String>,
+    pub youtube_url: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, JsonSchema)]
+pub struct Trait {
+    pub display_type: Option<String>,
+    pub trait_type: String,
+    pub value: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, JsonSchema)]
+pub struct TokenUser {
+    pub user: Addr,
+    pub expires: Expiration,
+}
+
+impl TokenRecord {
+    pub fn new(owner: Addr, uri_override: Option<String>) -> Self {
+        TokenRecord {
+            owner,
+            approvals: vec![],
+            uri_override,
+            uri_hash: None,
+            frozen: false,
+            locked_until: None,
+            user: None,
+            extension: None,
+        }
+    }
+}
+
+pub struct TokenIndexes<'a> {
+    pub owner: MultiIndex<'a, Addr, TokenRecord, String>,
+    pub frozen: MultiIndex<'a, u8, TokenRecord, String>,
+}
+
+impl<'a> IndexList<TokenRecord> for TokenIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<TokenRecord>> + '_> {
+        let v: Vec<&dyn Index<TokenRecord>> = vec![&self.owner, &self.frozen];
+        Box::new(v.into_iter())
+    }
+}
+
+/// Every minted token keyed by token id, indexed by owner and by frozen flag
+pub fn tokens<'a>() -> IndexedMap<'a, &'a str, TokenRecord, TokenIndexes<'a>> {
+    let indexes = TokenIndexes {
+        owner: MultiIndex::new(|_pk, token| token.owner.clone(), "tokens", "tokens__owner"),
+        frozen: MultiIndex::new(|_pk, token| token.frozen as u8, "tokens", "tokens__frozen"),
+    };
+    IndexedMap::new("tokens", indexes)
+}
+
+pub struct OfferIndexes<'a> {
+    pub bidder: MultiIndex<'a, Addr, Offer, (String, Addr)>,
+}
+
+impl<'a> IndexList<Offer> for OfferIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<Offer>> + '_> {
+        let v: Vec<&dyn Index<Offer>> = vec![&self.bidder];
+        Box::new(v.into_iter())
+    }
+}
+
+/// Open offers keyed by token id and bidder, indexed by bidder
+pub fn offers<'a>() -> IndexedMap<'a, (&'a str, &'a Addr), Offer, OfferIndexes<'a>> {
+    let indexes = OfferIndexes {
+        bidder: MultiIndex::new(|_pk, offer| offer.bidder.clone(), "offers", "offers__bidder"),
+    };
+    IndexedMap::new("offers", indexes)
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, JsonSchema)]
+pub struct Commitment {
+    /// sha256 of `(recipient, salt, count)`, see `commitment_hash`
+    pub hash: Binary,
+    /// Payment held until the commitment is revealed or cancelled
+    pub escrow: Coin,
+    /// Height of the block the commitment was made in
+    pub committed_at: u64,
+    /// Time of the block the commitment was made in, the entropy of the draw
+    #[serde(default)]
+    pub committed_time: Timestamp,
+}
+
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Params {
+    pub mint_fee: Coin,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct InstantiateMsg {
+    pub deployment_config: ProptixDeploymentConfig,
+    pub runtime_config: ProptixRuntimeConfig,
+}
+
+impl InstantiateMsg {
+    /// Checks both configs and every address they hold, before `instantiate` stores anything
+    pub fn validate(&self, api: &dyn Api) -> Result<(), ContractError> {
+        let (deployment_config, runtime_config) = (&self.deployment_config, &self.runtime_config);
+        deployment_config.validate()?;
+        runtime_config.validate()?;
+        if runtime_config.protocol_fee > 0 && deployment_config.protocol_address.is_none() {
+            return Err(
+                ContractError::invalid_config("protocol_address", "required with a protocol fee")
+            );
+        }
+        let fees_bps =
+            u32::from(runtime_config.protocol_fee) * 100 +
+            u32::from(deployment_config.factory_fee_bps);
+        if fees_bps > 10_000 {
+            return Err(
+                ContractError::invalid_config(
+                    "factory_fee_bps",
+                    "can't exceed 100% with the protocol fee"
+                )
+            );
+        }
+        if fees_bps + u32::from(runtime_config.referral_bps) > 10_000 {
+            return Err(
+                ContractError::invalid_config("referral_bps", "can't exceed the treasury share")
+            );
+        }
+        let addresses = [
+            ("treasury_address", Some(&deployment_config.treasury_address)),
+            ("protocol_address", deployment_config.protocol_address.as_ref()),
+            ("pass_collection", runtime_config.pass_collection.as_ref()),
+            ("minter", deployment_config.minter.as_ref()),
+        ];
+        for (field, address) in addresses {
+            if let Some(address) = address {
+                validate_address(api, field, address)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub enum ExecuteMsg {
+    IssueClass {
+        name: String,
+        symbol: String,
+        description: Option<String>,
+        uri: Option<String>,
+        uri_hash: Option<String>,
+        data: Option<Binary>,
+        features: Option<Vec<u32>>,
+        royalty_rate: Option<String>,
+    },
+    /// Mints a token to the cw721 minter, the only one allowed to. `data`, if given, must hold
+    /// the token's `Metadata`.
+    Mint {
+        class_id: String,
+        id: String,
+        uri: Option<String>,
+        uri_hash: Option<String>,
+        data: Option<Binary>,
+    },
+    /// Burns a token owned by the sender, paying the burn reward if one is set
+    Burn {
+        class_id: String,
+        id: String,
+    },
+    /// Burns several tokens owned by the sender at once. Ownership of every id is
+    /// checked before anything is burned, so the batch is all or nothing. The burn
+    /// reward is paid for every token.
+    BurnBatch {
+        class_id: String,
+        ids: Vec<String>,
+    },
+    Freeze {
+        class_id: String,
+        id: String,
+    },
+    Unfreeze {
+        class_id: String,
+        id: String,
+    },
+    /// Whitelists `account`. With an `allocation` the wallet may buy that many tokens
+    /// through the whitelist in total, whatever the phase.
+    AddToWhitelist {
+        class_id: String,
+        id: String,
+        account: String,
+        #[serde(default)]
+        allocation: Option<u64>,
+    },
+    RemoveFromWhitelist {
+        class_id: String,
+        id: String,
+        account: String,
+    },
+    Whitelist {
+        address: String,
+        status: bool,
+    },
+    /// `Whitelist` for several addresses at once, lets the factory relay a batch in one message
+    WhitelistBatch {
+        addresses: Vec<String>,
+        status: bool,
+    },
+    /// Bars `address` from buying, receiving and transferring tokens. Only callable by the owner.
+    AddToBlacklist {
+        address: String,
+    },
+    /// Lifts the bar on `address`. Only callable by the owner.
+    RemoveFromBlacklist {
+        address: String,
+    },
+    /// Bars `address` from receiving tokens. Only callable by the owner.
+    AddToBlocklist {
+        address: String,
+    },
+    /// Lets `address` receive tokens again. Only callable by the owner.
+    RemoveFromBlocklist {
+        address: String,
+    },
+    /// Notifies `contract` of every mint, transfer and burn through a `TransferHookExecuteMsg`.
+    /// Only callable by the owner.
+    RegisterTransferHook {
+        contract: String,
+    },
+    /// Stops notifying `contract`. Only callable by the owner.
+    RemoveTransferHook {
+        contract: String,
+    },
+    /// Exempts `address` from the per-wallet holding cap, or ends its exemption.
+    /// Only callable by the owner.
+    SetHoldingCapExemption {
+        address: String,
+        exempt: bool,
+    },
+    /// Mints `count` tokens to the sender. Attached funds in the mint denom are
+    /// credited to the sender's balance, which has to cover the total price.
+    /// While a tier is active, `proof` has to prove the sender against that
+    /// tier's merkle root. The purchase fails if the price per token is above
+    /// `max_price_per_token`. The tokens go to `recipient` if set, which is
+    /// also the wallet the per-wallet limit applies to. A `referrer` other
+    /// than the sender earns the referral share of the sale. While `TIER_BITMAP` is
+    /// active, `proof` proves the sender holds `allowlist_index` instead.
+    Purchase {
+        count: u64,
+        proof: Option<Vec<Binary>>,
+        max_price_per_token: Option<Uint128>,
+        recipient: Option<String>,
+        referrer: Option<String>,
+        allowlist_index: Option<u32>,
+    },
+    /// First phase of a front-running resistant mint. `commitment` is the
+    /// sha256 of `(recipient, salt, count)`; the attached funds are escrowed.
+    /// The commit is checked like a purchase of a single token, as the count stays hidden
+    /// until the reveal. The draw depends on the time of the commit block, which isn't
+    /// known when the salt is chosen, so the committer can't pick it, only discard it by
+    /// not revealing. A discarded draw keeps the escrow locked until `CancelCommit` after
+    /// `COMMIT_TIMEOUT_BLOCKS`, which bounds how often a committer can retry for a better one.
+    CommitMint {
+        commitment: Binary,
+        #[serde(default)]
+        proof: Option<Vec<Binary>>,
+        #[serde(default)]
+        allowlist_index: Option<u32>,
+    },
+    /// Second phase, from `MIN_REVEAL_DELAY_BLOCKS` up to `REVEAL_WINDOW_BLOCKS` after the
+    /// commit. Checks the purchase of `count` tokens again and mints them to the committer.
+    RevealMint {
+        salt: Binary,
+        count: u64,
+        #[serde(default)]
+        proof: Option<Vec<Binary>>,
+        #[serde(default)]
+        allowlist_index: Option<u32>,
+    },
+    /// Refunds the escrow of a commitment that was not revealed in time
+    CancelCommit {},
+    /// Sets the address receiving the protocol fee while none is set. Only callable by the
+    /// owner, the protocol rotates its address through `UpdateProtocolAddress`.
+    SetProtocolAddress {
+        address: String,
+    },
+    /// Moves the protocol fee to `address`. Only callable by the current protocol address.
+    UpdateProtocolAddress {
+        address: String,
+    },
+    /// Blocks transfers of a token until `until` passes. Only callable by the owner.
+    LockToken {
+        token_id: String,
+        until: Expiration,
+    },
+    /// Blocks transfers of a token until the block time reaches `unlock_time`, in seconds.
+    /// Only callable by the token owner, an existing lock can only be extended.
+    LockUntil {
+        token_id: String,
+        unlock_time: u64,
+    },
+    /// cw721 receive hook, called by a collection when a token is sent to this contract.
+    /// `msg` holds a `ReceiveNftMsg`.
+    #[serde(rename = "receive_nft")]
+    ReceiveNft(Cw721ReceiveMsg),
+    /// cw20 receive hook, called by a cw20 contract when tokens are sent to this contract.
+    /// `msg` holds a `ReceiveMsg`.
+    #[serde(rename = "receive")]
+    Receive(Cw20ReceiveMsg),
+    /// Shares the attached funds equally between the tokens in existence. The first
+    /// funding fixes the denom of the rewards.
+    FundRewards {},
+    /// Pays the rewards accrued by tokens of the sender since they were last claimed
+    ClaimRewards {
+        token_ids: Vec<String>,
+    },
+    /// Moves a token to `recipient`. Callable by the token owner or an approved spender.
+    /// With a `sale_price`, its royalty has to be attached and is paid to the treasury.
+    TransferNft {
+        recipient: String,
+        token_id: String,
+        #[serde(default)]
+        sale_price: Option<Coin>,
+    },
+    /// Moves up to `MAX_TRANSFER_BATCH` tokens to their recipients, all or none. Each
+    /// entry is checked like a `TransferNft` without a sale price.
+    BatchTransfer {
+        transfers: Vec<BatchTransferEntry>,
+    },
+    /// Moves a token to `contract` and calls its cw721 receive hook with `msg`
+    SendNft {
+        contract: String,
+        token_id: String,
+        msg: Binary,
+    },
+    /// Allows `spender` to transfer or send a token until `expires`, forever without one.
+    /// Only callable by the token owner.
+    Approve {
+        spender: String,
+        token_id: String,
+        expires: Option<Expiration>,
+    },
+    /// Withdraws the approval of `spender`. Only callable by the token owner.
+    Revoke {
+        spender: String,
+        token_id: String,
+    },
+    /// Lets `user` use a token until `expires`. Callable by the token owner or an
+    /// approved spender.
+    SetUser {
+        token_id: String,
+        user: String,
+        expires: Expiration,
+    },
+    /// Lists a token owned by the sender for sale at `price`, replacing any
+    /// previous listing of the token
+    List {
+        token_id: String,
+        price: Coin,
+    },
+    CancelListing {
+        token_id: String,
+    },
+    /// Buys a listed token. The attached funds must cover the listing price, which
+    /// is split between the seller, the royalty to the treasury and the protocol fee.
+    Buy {
+        token_id: String,
+    },
+    /// Bids the attached funds on a token, replacing and refunding any previous offer of
+    /// the sender on it. The funds stay escrowed until the offer is accepted or withdrawn.
+    MakeOffer {
+        token_id: String,
+        expires: Option<Expiration>,
+    },
+    /// Refunds the offer of the sender on a token, also once it expired or the token
+    /// was burned
+    WithdrawOffer {
+        token_id: String,
+    },
+    /// Sells a token of the sender to `bidder` for its offer, which is split like a
+    /// `Buy`. Every other offer on the token is refunded.
+    AcceptOffer {
+        token_id: String,
+        bidder: String,
+    },
+    /// Auctions a token of the sender for `duration` seconds, in the denom of
+    /// `reserve_price`. The token can't be moved until the auction is settled.
+    StartAuction {
+        token_id: String,
+        reserve_price: Coin,
+        duration: u64,
+        min_bid_increment: Uint128,
+    },
+    /// Outbids the highest bid of an auction with the attached funds, refunding the
+    /// previous highest bidder. A bid close to the end extends the auction.
+    Bid {
+        token_id: String,
+    },
+    /// Ends an auction once its end time passed. The token is sold to the highest bidder
+    /// if the bid meets the reserve price, otherwise the bid is refunded and the token
+    /// stays with the seller.
+    Settle {
+        token_id: String,
+    },
+    /// Escrows a token owned by the sender and hands it to the ics721 bridge
+    /// for a transfer to `receiver` over `channel`
+    IbcTransfer {
+        token_id: String,
+        channel: String,
+        receiver: String,
+        timeout: Timestamp,
+    },
+    /// Reports the outcome of an interchain transfer. Only callable by the ics721 bridge.
+    IbcCallback {
+        token_id: String,
+        outcome: IbcOutcome,
+    },
+    /// Sets the ics721 bridge contract. Only callable by the owner.
+    SetIcs721Address {
+        address: String,
+    },
+    /// Sets the collection metadata uri. Only callable by the owner before the
+    /// metadata is frozen.
+    SetContractUri {
+        contract_uri: String,
+    },
+    /// Sets the uri revealed token uris are built from. Only callable by the owner before
+    /// the metadata is frozen.
+    SetBaseUri {
+        base_token_uri: String,
+    },
+    /// Announces a base uri change that `ApplyBaseUriChange` can make once the time
+    /// `effective_after` has passed, replacing any pending one. Only callable by the owner
+    /// before the metadata is frozen.
+    ProposeBaseUriChange {
+        new_uri: String,
+        effective_after: u64,
+    },
+    /// Makes the pending base uri change once it is due. Only callable by the owner before
+    /// the metadata is frozen.
+    ApplyBaseUriChange {},
+    /// Cancels the pending base uri change before it is due. Callable by holders of at
+    /// least `uri_veto_threshold` tokens.
+    VetoUriChange {},
+    /// Sets the contract token uris are resolved from, or goes back to the base uri
+    /// when unset. Only callable by the owner before the metadata is frozen.
+    SetMetadataResolver {
+        resolver: Option<String>,
+    },
+    /// Switches token uris from the prereveal uri to the base uri ahead of any scheduled
+    /// reveal. Only callable by the owner.
+    Reveal {},
+    /// Permanently locks the collection metadata. Only callable by the owner.
+    FreezeMetadata {},
+    /// Fixes the supply of an open edition to the tokens minted so far. Callable by
+    /// anyone once the sale has ended, a no-op for collections with a fixed supply.
+    FinalizeSupply {},
+    /// Runs the `finalize_actions` of the runtime config once the sale has ended or sold
+    /// out. Callable by anyone, only once.
+    FinalizeSale {},
+    /// Credits the escrowed sales income once the sale ended having sold its
+    /// `min_threshold`. Callable by anyone.
+    ReleaseEscrow {},
+    /// Pays back the escrowed payments of the sender once the sale ended short of its
+    /// `min_threshold` and burns the tokens they bought, which the sender has to hold.
+    /// Fails if any of them was burned since.
+    ClaimRefund {},
+    /// Lowers the max supply to `new_max`, which can't be below the tokens minted so far.
+    /// Only callable by the owner until the supply is frozen.
+    ReduceMaxSupply {
+        new_max: u64,
+    },
+    /// Permanently forbids reducing the max supply. Only callable by the owner.
+    FreezeSupply {},
+    /// Moves the public sale window, in seconds with both ends inclusive. Only callable by
+    /// the owner.
+    SetSaleWindow {
+        start: u64,
+        end: u64,
+    },
+    /// Moves the presale window like `SetSaleWindow`, `0` for both removes the presale.
+    /// Only callable by the owner.
+    SetPresaleWindow {
+        start: u64,
+        end: u64,
+    },
+    /// Sets the allowlist merkle root of a sale tier. Only callable by the owner.
+    SetPhaseRoot {
+        tier: u8,
+        root: Binary,
+    },
+    /// Selects the tier gating purchases, `None` falls back to the whitelist.
+    /// Only callable by the owner.
+    SetActiveTier {
+        tier: Option<u8>,
+    },
+    /// Sets the bit of `index` in the `TIER_BITMAP` allowlist. Only callable by the owner.
+    SetAllowlistBit {
+        index: u32,
+        value: bool,
+    },
+    /// Sets the bits of the `count` indexes from `start` in the `TIER_BITMAP` allowlist,
+    /// at most `MAX_ALLOWLIST_RANGE` at once. Only callable by the owner.
+    SetAllowlistRange {
+        start: u32,
+        count: u32,
+        value: bool,
+    },
+    /// Sets the key signing allowlist vouchers and its curve. Only callable by the owner.
+    SetVoucherSigner {
+        pubkey: Binary,
+        scheme: SignatureScheme,
+    },
+    /// Mints `count` tokens to the sender like `Purchase`, with a voucher signed by the
+    /// voucher signer over `voucher_digest` in place of the allowlist check
+    RedeemVoucher {
+        count: u64,
+        signature: Binary,
+    },
+    /// Routes the treasury share to `contract` by executing `msg` with the funds attached,
+    /// e.g. a deposit into a DAO. `None` restores payments to the treasury address.
+    /// Only callable by the owner.
+    SetProceedsHook {
+        contract: Option<String>,
+        msg: Binary,
+    },
+    /// Pays `reward` out of the reward pool for every burned token, `None` stops the
+    /// rewards. The denom can't change while the pool is funded. Only callable by the owner.
+    SetBurnReward {
+        reward: Option<Coin>,
+    },
+    /// Adds the attached funds to the burn reward pool. Only callable by the owner.
+    FundRewardPool {},
+    /// Changes the denom purchases are paid in. Balances accrued in the previous denom stay
+    /// withdrawable. Only callable by the owner.
+    SetMintDenom {
+        denom: String,
+    },
+    /// Pays out the sender's ledger balance in `denom`
+    Withdraw {
+        denom: String,
+    },
+}
+
+/// A token of `ExecuteMsg::BatchTransfer` and its recipient
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BatchTransferEntry {
+    pub recipient: String,
+    pub token_id: String,
+}
+
+/// Payload of cw20 tokens sent to this contract
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiveMsg {
+    /// Funds holder rewards like `FundRewards`
+    FundRewards {},
+}
+
+/// Payload of a token sent to this contract through `SendNft`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiveNftMsg {
+    /// Burns a token of the pass collection and mints a token to its sender free of charge
+    RedeemPass {},
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub enum QueryMsg {
+    /// Lists the ids of the tokens that are currently frozen, paginated.
+    /// The limit is capped at 50.
+    FrozenTokens {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Lists the ids of the tokens held by `owner`, paginated.
+    /// Returns `cw721::TokensResponse`.
+    Tokens {
+        owner: String,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Lists the ids of all tokens, paginated. Returns `cw721::TokensResponse`.
+    AllTokens {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Returns the sale windows and the phase at the current block time
+    Schedule {},
+    /// Returns the collection configuration, see `ConfigResponse`
+    Config {},
+    /// Collection name, symbol, royalty and metadata uri
+    ContractInfo {},
+    /// cw2 contract name and version, with the commit the code was built from if known
+    ContractVersion {},
+    /// Returns the cumulative sale totals and the tokens sold per day
+    SaleStats {},
+    /// Tokens minted and burned so far with the resulting supply
+    SupplyInfo {},
+    /// Number of tokens in existence. Returns `cw721::NumTokensResponse`.
+    NumTokens {},
+    /// Lists holders with the number of tokens they hold, paginated by address
+    Holders {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Lists whitelisted addresses with their remaining allocation, paginated by address
+    Whitelist {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Lists the early birds in rank order, paginated by rank
+    EarlyBirds {
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Early bird rank of `address`, if it has one
+    EarlyBirdRank {
+        address: String,
+    },
+    /// Contracts registered through `RegisterTransferHook`
+    Hooks {},
+    /// Whether `address` is blacklisted
+    IsBlacklisted {
+        address: String,
+    },
+    /// Lists the blacklisted addresses, paginated
+    Blacklist {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Whether `address` is barred from receiving tokens
+    IsBlocked {
+        address: String,
+    },
+    /// Base uri change awaiting `ApplyBaseUriChange`, if any
+    PendingUriChange {},
+    /// Number of tokens held by `owner`
+    BalanceOf {
+        owner: String,
+    },
+    /// Ledger balances of `address` in every denom it accrued
+    AccruedByDenom {
+        address: String,
+    },
+    /// Lists the open marketplace listings, paginated
+    Listings {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Lists the offers on a token by bidder, paginated
+    OffersByToken {
+        token_id: String,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Lists the offers of a bidder by token id, paginated
+    OffersByBidder {
+        bidder: String,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Burn reward and what is left in its pool
+    BurnReward {},
+    /// Holder rewards accrued by a token and not claimed yet
+    PendingRewards {
+        token_id: String,
+    },
+    /// Lists the auctions not settled yet, paginated
+    Auctions {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Lists the tokens escrowed for interchain transfers, paginated
+    Bridged {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// User of `token_id`, if the assignment hasn't expired yet
+    UserOf {
+        token_id: String,
+    },
+    /// Transfer lock of `token_id`, if it hasn't expired yet
+    TokenLock {
+        token_id: String,
+    },
+    /// Unlock time of the lock the holder put on `token_id`, if it hasn't passed yet
+    LockStatus {
+        token_id: String,
+    },
+    /// Owner of `token_id` with its unexpired approvals.
+    /// Returns `cw721::OwnerOfResponse`.
+    OwnerOf {
+        token_id: String,
+    },
+    /// Price `buyer` would pay for `count` tokens through `Purchase`, see `State::quote`
+    QuotePurchase {
+        buyer: String,
+        count: u64,
+        promo_code: Option<String>,
+    },
+    /// Sale phases of the collection, empty when it uses the flat sale windows
+    Phases {},
+    /// Phase open at the current block time, if any
+    ActivePhase {},
+    /// Everything gating the purchases of `address` at the current block time, as
+    /// `Purchase` reads it
+    BuyerProfile {
+        address: String,
+    },
+    /// Class issued through `IssueClass`. Returns `ClassInfo`.
+    ClassInfo {
+        class_id: String,
+    },
+    /// Uri of a minted token. Returns `TokenUriResponse`.
+    TokenUri {
+        token_id: String,
+    },
+    /// cw721 token info with the token's `Metadata` as extension
+    NftInfo {
+        token_id: String,
+    },
+    /// `OwnerOf` and `NftInfo` of a token at once
+    AllNftInfo {
+        token_id: String,
+        include_expired: Option<bool>,
+    },
+    /// Metadata of up to `MAX_METADATA_BATCH` tokens at once
+    BatchTokenMetadata {
+        token_ids: Vec<String>,
+    },
+    /// Runs the preconditions of a `Purchase` of `count` tokens by `buyer` with `proof`
+    /// and `allowlist_index` at the current block and returns the funds to attach or why
+    /// it would fail
+    SimulatePurchase {
+        buyer: String,
+        count: u64,
+        proof: Option<Vec<Binary>>,
+        allowlist_index: Option<u32>,
+    },
+    /// Whether a `Purchase` of `count` tokens by `buyer` without a proof would pass its
+    /// preconditions at the current block, with the error of the first one failing
+    CanMint {
+        buyer: String,
+        count: u64,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub enum MigrateMsg {
+    /// Upgrades the contract to the current version, transforming any state
+    /// written by older versions. `new_max_supply` replaces the max supply, as long as it
+    /// covers the tokens already minted.
+    Upgrade {
+        #[serde(default)]
+        new_max_supply: Option<u64>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FrozenTokensResponse {
+    pub tokens: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TokenMetadata {
+    pub owner: String,
+    /// Uri given at mint, otherwise resolved by the metadata resolver or derived from the
+    /// base uri
+    pub token_uri: String,
+    /// Digest of the metadata given at mint, if any
+    pub uri_hash: Option<String>,
+    pub frozen: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BuyerProfileResponse {
+    pub whitelisted: bool,
+    pub blacklisted: bool,
+    /// Tier gating purchases instead of the whitelist. Membership is only proven with
+    /// the proof given to `Purchase`.
+    pub active_tier: Option<u8>,
+    /// Tokens the buyer may still buy through the whitelist, unlimited when unset
+    pub remaining_allocation: Option<u64>,
+    /// Tokens sold to the buyer so far
+    pub minted: u64,
+    /// Tokens sold to the buyer in the active phase
+    pub minted_in_phase: u64,
+    /// Tokens the buyer may still receive under the wallet and phase limits, unlimited
+    /// when unset
+    pub remaining_wallet_limit: Option<u64>,
+    /// Height a pending `CommitMint` of the buyer can be revealed from
+    pub reveal_height: Option<u64>,
+    /// Price of a token at the current block time
+    pub price: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PhasesResponse {
+    pub phases: Vec<Phase>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ActivePhaseResponse {
+    pub phase: Option<Phase>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TokenUriResponse {
+    pub token_uri: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BatchTokenMetadataResponse {
+    /// Requested ids in order, with `None` for ids that don't exist
+    pub tokens: Vec<(String, Option<TokenMetadata>)>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct UserOfResponse {
+    pub user: Option<String>,
+    pub expires: Option<Expiration>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TokenLockResponse {
+    pub locked_until: Option<Expiration>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LockStatusResponse {
+    pub unlock_time: Option<u64>,
+}
+
+/// Outcome of `SimulatePurchase`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub enum SimulatePurchaseResponse {
+    /// Funds to attach to the `Purchase`
+    Ok {
+        funds: Vec<Coin>,
+    },
+    /// The `Purchase` would fail with the `ContractError` matching `reason`
+    Blocked {
+        reason: PurchaseBlocker,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CanMintResponse {
+    pub allowed: bool,
+    /// Message of the error the purchase would fail with
+    pub reason: Option<String>,
+}
+
+/// Purchase preconditions a buyer can fail, mirroring their `ContractError`s
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub enum PurchaseBlocker {
+    Blacklisted {
+        address: String,
+    },
+    RecipientBlocked {
+        address: String,
+    },
+    NotWhitelisted {},
+    PhaseRootUnset {
+        tier: u8,
+    },
+    InvalidMerkleProof {
+        tier: u8,
+    },
+    AllowlistIndexUnset {
+        index: u32,
+    },
+    SaleNotActive {
+        starts_at: u64,
+        ends_at: u64,
+        now: u64,
+    },
+    ZeroPurchaseCount {},
+    MintPerTxExceeded {
+        max: u64,
+    },
+    SoldOut {
+        remaining: u64,
+    },
+    ProtocolAddressUnset {},
+    WalletLimitExceeded {
+        wallet: String,
+        limit: u64,
+        minted: u64,
+    },
+    HoldingCapExceeded {
+        cap: u64,
+        current: u64,
+    },
+    AllocationExceeded {
+        remaining: u64,
+    },
+}
+
+/// Amounts in the mint denom, `total` is what the purchase takes from the buyer
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PurchaseQuoteResponse {
+    pub per_token_price: Uint128,
+    pub subtotal: Uint128,
+    pub discount: Uint128,
+    pub total: Uint128,
+}
+
+/// Shares of a sale's income in the mint denom, the referral reward comes out of the
+/// treasury share
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SaleSplit {
+    pub protocol_fee: Uint128,
+    pub factory_fee: Uint128,
+    pub referral_reward: Uint128,
+    pub treasury_share: Uint128,
+}
+
+/// Receipt of a purchase, emitted as the JSON `mint_receipt` attribute when `mint_receipts`
+/// is enabled. Fields serialize in declaration order, so equal purchases give equal receipts.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MintReceipt {
+    pub buyer: String,
+    pub recipient: String,
+    pub token_ids: Vec<String>,
+    pub price_paid: Coin,
+    pub fee_split: SaleSplit,
+    pub height: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ListingInfo {
+    pub token_id: String,
+    pub seller: String,
+    pub price: Coin,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ListingsResponse {
+    pub listings: Vec<ListingInfo>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OfferInfo {
+    pub token_id: String,
+    pub bidder: String,
+    pub price: Coin,
+    pub expires: Expiration,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OffersResponse {
+    pub offers: Vec<OfferInfo>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BurnRewardResponse {
+    pub reward: Option<Coin>,
+    pub pool: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingRewardsResponse {
+    /// Unset before the first funding
+    pub asset: Option<RewardAsset>,
+    pub amount: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AuctionInfo {
+    pub token_id: String,
+    pub seller: String,
+    pub reserve_price: Coin,
+    pub min_bid_increment: Uint128,
+    pub end_time: u64,
+    pub highest_bidder: Option<String>,
+    pub highest_bid: Option<Uint128>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AuctionsResponse {
+    pub auctions: Vec<AuctionInfo>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BridgedTokenInfo {
+    pub token_id: String,
+    pub owner: String,
+    pub channel: String,
+    pub receiver: String,
+    pub status: BridgeStatus,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BridgedResponse {
+    pub tokens: Vec<BridgedTokenInfo>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct DailyMints {
+    /// Days since the unix epoch
+    pub day: u64,
+    pub mints: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SaleStatsResponse {
+    pub gross_proceeds: Uint128,
+    pub protocol_fees_accrued: Uint128,
+    pub total_mints: u64,
+    pub mints_per_day: Vec<DailyMints>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SupplyInfoResponse {
+    pub minted: u64,
+    pub burned: u64,
+    /// `minted - burned`
+    pub current_supply: u64,
+    pub max_total_mint: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct HolderBalance {
+    pub address: String,
+    pub count: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct HoldersResponse {
+    pub holders: Vec<HolderBalance>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct WhitelistEntry {
+    pub address: String,
+    /// Tokens left to buy through the whitelist, unset without an allocation
+    pub allocation: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct WhitelistResponse {
+    pub entries: Vec<WhitelistEntry>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct EarlyBird {
+    pub rank: u64,
+    pub address: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct EarlyBirdsResponse {
+    pub early_birds: Vec<EarlyBird>,
+    /// Whether the list reached the cutoff and won't change anymore
+    pub closed: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct EarlyBirdRankResponse {
+    pub rank: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct HooksResponse {
+    pub hooks: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct IsBlacklistedResponse {
+    pub blacklisted: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BlacklistResponse {
+    pub addresses: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct IsBlockedResponse {
+    pub blocked: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct UriChange {
+    pub new_uri: String,
+    pub effective_after: u64,
+    pub proposed_by: Addr,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingUriChangeResponse {
+    pub change: Option<UriChange>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BalanceOfResponse {
+    pub count: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AccruedByDenomResponse {
+    pub balances: Vec<Coin>,
+}
+
+/// Collection configuration as read by frontends. Fields added later must come
+/// with a default so older responses keep deserializing.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+#[serde(default)]
+pub struct ConfigResponse {
+    pub owner: String,
+    pub base_token_uri: String,
+    pub base_token_uri_extension: String,
+    pub prereveal_token_uri: String,
+    pub uri_status: bool,
+    pub treasury_address: String,
+    pub protocol_address: String,
+    pub protocol_fee_bps: u16,
+    pub mint_denom: String,
+    pub mint_price: Uint128,
+    /// Sale windows in seconds, both ends inclusive
+    pub presale_start_time: u64,
+    pub presale_end_time: u64,
+    pub sale_start_time: u64,
+    pub sale_end_time: u64,
+    pub active_tier: Option<u8>,
+    /// 0 for an open edition that hasn't been finalized
+    pub max_supply: u64,
+    /// Tokens that can still be minted, omitted for an unbounded open edition
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remaining_supply: Option<u64>,
+    /// Whether a purchase could succeed at the current block time
+    pub sale_active: bool,
+    /// Format of minted ids, plain numbers when unset
+    pub token_id_format: Option<TokenIdFormat>,
+    /// Time in seconds the collection reveals at on its own, `uri_status` already accounts
+    /// for it
+    pub reveal_at: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ContractInfoResponse {
+    pub name: String,
+    pub symbol: String,
+    /// cw721 minter, see `ProptixDeploymentConfig::minter`
+    pub minter: Addr,
+    /// Royalty rate of the class issued for the collection symbol, if any
+    pub royalty_rate: Option<String>,
+    pub contract_uri: Option<String>,
+    pub metadata_frozen: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ContractVersionResponse {
+    pub contract: String,
+    pub version: String,
+    pub commit: Option<String>,
+}
+
+/// Sale windows in seconds, both ends inclusive
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ScheduleResponse {
+    pub presale_start: u64,
+    pub presale_end: u64,
+    pub sale_start_time: u64,
+    pub sale_end_time: u64,
+    /// `sale_end_time` with the grace period, if the remaining supply extends the sale
+    #[serde(default)]
+    pub effective_sale_end_time: u64,
+    pub phase: SalePhase,
+}
+
+/// Errors returned by the collection. Frontends parse the messages, so they
+/// must stay stable.
+#[derive(Error, Debug, PartialEq)]
+pub enum ContractError {
+    #[error(transparent)]
+    Std(#[from] StdError),
+
+    #[error(transparent)]
+    Payment(#[from] PaymentError),
+
+    #[error(transparent)]
+    Overflow(#[from] OverflowError),
+
+    #[error("Invalid {field}: {reason}")]
+    InvalidConfig {
+        field: String,
+        reason: String,
+    },
+
+    #[error("Invalid address for {field}: {value}")]
+    InvalidAddress {
+        field: String,
+        value: String,
+    },
+
+    #[error("Unauthorized: sender is not the {expected_role}")]
+    Unauthorized {
+        expected_role: String,
+    },
+
+    #[error("Sale is not active: it runs from {starts_at} to {ends_at}, now is {now}")]
+    SaleNotActive {
+        starts_at: u64,
+        ends_at: u64,
+        now: u64,
+    },
+
+    #[error("Sale has not ended: it ends at {ends_at}, now is {now}")]
+    SaleNotEnded {
+        ends_at: u64,
+        now: u64,
+    },
+
+    #[error("The sale has no minimum threshold")]
+    NoSaleThreshold {},
+
+    #[error("The sale sold {sold} of the {threshold} tokens it needed")]
+    ThresholdNotMet {
+        sold: u64,
+        threshold: u64,
+    },
+
+    #[error("The sale sold its threshold of {threshold} tokens")]
+    ThresholdMet {
+        threshold: u64,
+    },
+
+    #[error("The sale escrow was already released")]
+    EscrowReleased {},
+
+    #[error("The sale was already finalized")]
+    SaleFinalized {},
+
+    #[error("Base uri change time {effective_after} must be later than the block time")]
+    InvalidUriChangeTime {
+        effective_after: u64,
+    },
+
+    #[error("No base uri change is pending")]
+    NoPendingUriChange {},
+
+    #[error("The base uri change can only be applied after {effective_after}, it is {now}")]
+    UriChangeNotDue {
+        effective_after: u64,
+        now: u64,
+    },
+
+    #[error("The veto window of the base uri change closed at {effective_after}")]
+    VetoWindowClosed {
+        effective_after: u64,
+    },
+
+    #[error("At most {limit} tokens can be minted per block, {minted} already were")]
+    BlockMintLimitReached {
+        limit: u64,
+        minted: u64,
+    },
+
+    #[error("No payment to refund")]
+    NothingToRefund {},
+
+    #[error("Purchase count must be at least 1")]
+    ZeroPurchaseCount {},
+
+    #[error("At most {max} tokens can be minted per transaction")]
+    MintPerTxExceeded {
+        max: u64,
+    },
+
+    #[error("Sold out: {remaining} tokens remaining")]
+    SoldOut {
+        remaining: u64,
+    },
+
+    #[error("Insufficient funds: {required} required, {provided} provided")]
+    InsufficientFunds {
+        required: Uint128,
+        provided: Uint128,
+    },
+
+    #[error("Price per token is {quoted}, above the accepted maximum of {max}")]
+    PriceSlippage {
+        quoted: Uint128,
+        max: Uint128,
+    },
+
+    #[error("{wallet} can receive at most {limit} tokens, {minted} already minted")]
+    WalletLimitExceeded {
+        wallet: String,
+        limit: u64,
+        minted: u64,
+    },
+
+    #[error("Whitelist allocation exceeded: {remaining} tokens remaining")]
+    AllocationExceeded {
+        remaining: u64,
+    },
+
+    #[error("Token {token_id} is frozen")]
+    TokenFrozen {
+        token_id: String,
+    },
+
+    #[error("Token {token_id} is locked ({until})")]
+    TokenLocked {
+        token_id: String,
+        until: Expiration,
+    },
+
+    #[error("Approval expiry {expires} has already passed")]
+    ApprovalExpired {
+        expires: Expiration,
+    },
+
+    #[error("Unlock time {unlock_time} must be later than the block time and any current lock")]
+    InvalidUnlockTime {
+        unlock_time: u64,
+    },
+
+    #[error("Pass {token_id} has already been redeemed")]
+    PassRedeemed {
+        token_id: String,
+    },
+
+    #[error("Buyers can't refer themselves")]
+    SelfReferral {},
+
+    #[error("Batch of {size} items exceeds the limit of {max}")]
+    BatchTooLarge {
+        size: usize,
+        max: usize,
+    },
+
+    #[error("{address} is blacklisted")]
+    Blacklisted {
+        address: String,
+    },
+
+    #[error("{address} is blocked from receiving tokens")]
+    RecipientBlocked {
+        address: String,
+    },
+
+    #[error("Holding cap of {cap} tokens exceeded, {current} already held")]
+    HoldingCapExceeded {
+        cap: u64,
+        current: u64,
+    },
+
+    #[error("At most {max} transfer hooks can be registered")]
+    TooManyTransferHooks {
+        max: usize,
+    },
+
+    #[error("Unknown reply id {id}")]
+    UnknownReplyId {
+        id: u64,
+    },
+
+    #[error("Unknown promo code {code}")]
+    UnknownPromoCode {
+        code: String,
+    },
+
+    #[error("Token users are disabled for this collection")]
+    UsersDisabled {},
+
+    #[error("Token {token_id} is not listed")]
+    TokenNotListed {
+        token_id: String,
+    },
+
+    #[error("Listing price must be above zero")]
+    ZeroListingPrice {},
+
+    #[error("Invalid royalty rate {rate}, expected a decimal between 0 and 1")]
+    InvalidRoyaltyRate {
+        rate: String,
+    },
+
+    #[error("Transfers must pay the royalty on their sale price")]
+    RoyaltyRequired {},
+
+    #[error("Token {token_id} is escrowed for an interchain transfer")]
+    TokenBridged {
+        token_id: String,
+    },
+
+    #[error("Token {token_id} is up for auction")]
+    TokenAuctioned {
+        token_id: String,
+    },
+
+    #[error("Token {token_id} isn't up for auction")]
+    NoAuction {
+        token_id: String,
+    },
+
+    #[error("Auction duration must be above zero")]
+    ZeroAuctionDuration {},
+
+    #[error("Auction of token {token_id} ended at {end_time}")]
+    AuctionEnded {
+        token_id: String,
+        end_time: u64,
+    },
+
+    #[error("Auction of token {token_id} only ends at {end_time}")]
+    AuctionNotEnded {
+        token_id: String,
+        end_time: u64,
+    },
+
+    #[error("Bid of {bid} is below the minimum of {min_bid}")]
+    BidTooLow {
+        bid: Uint128,
+        min_bid: Uint128,
+    },
+
+    #[error("Whitelist removals are locked since the sale started at {sale_start_time}")]
+    WhitelistLocked {
+        sale_start_time: u64,
+    },
+
+    #[error("Invalid schedule: {reason}")]
+    InvalidSchedule {
+        reason: String,
+    },
+
+    #[error("Collection metadata is frozen")]
+    MetadataFrozen {},
+
+    #[error("Collection supply is frozen")]
+    SupplyFrozen {},
+
+    #[error("Max supply can only be lowered from {max_supply} down to {minted}, got {new_max}")]
+    InvalidSupplyReduction {
+        new_max: u64,
+        max_supply: u64,
+        minted: u64,
+    },
+
+    #[error("Max supply {new_max} is below the {minted} tokens already minted")]
+    InvalidMaxSupply {
+        new_max: u64,
+        minted: u64,
+    },
+
+    #[error("No offer from {bidder} on token {token_id}")]
+    NoOffer {
+        token_id: String,
+        bidder: String,
+    },
+
+    #[error("Offer expiry {expires} has already passed")]
+    OfferExpired {
+        expires: Expiration,
+    },
+
+    #[error("Owners can't bid on their own tokens")]
+    OwnerOffer {},
+
+    #[error("Burn reward pool holds {available}, {required} needed")]
+    RewardPoolEmpty {
+        required: Uint128,
+        available: Uint128,
+    },
+
+    #[error("Rewards are paid in {expected}")]
+    RewardAssetMismatch {
+        expected: String,
+    },
+
+    #[error("No tokens to share the rewards between")]
+    NoRewardHolders {},
+
+    #[error("No ics721 bridge is configured")]
+    BridgeUnset {},
+
+    #[error("Unexpected bridge callback for token {token_id}")]
+    UnexpectedBridgeCallback {
+        token_id: String,
+    },
+
+    #[error("Token {token_id} not found")]
+    TokenNotFound {
+        token_id: String,
+    },
+
+    #[error("Token {token_id} already exists")]
+    TokenExists {
+        token_id: String,
+    },
+
+    #[error("Token {token_id} is listed more than once")]
+    DuplicateTokenId {
+        token_id: String,
+    },
+
+    #[error("Invalid uri hash {uri_hash}, expected a hex or base64 sha256 digest")]
+    InvalidUriHash {
+        uri_hash: String,
+    },
+
+    #[error("{field} is longer than {max_len} bytes")]
+    UriTooLong {
+        field: String,
+        max_len: u32,
+    },
+
+    #[error("Class {class_id} already exists")]
+    ClassExists {
+        class_id: String,
+    },
+
+    #[error("No merkle root is set for tier {tier}")]
+    PhaseRootUnset {
+        tier: u8,
+    },
+
+    #[error("Invalid merkle proof for tier {tier}")]
+    InvalidMerkleProof {
+        tier: u8,
+    },
+
+    #[error("Merkle root must be 32 bytes, got {length}")]
+    InvalidMerkleRoot {
+        length: usize,
+    },
+
+    #[error("Allowlist index {index} is not eligible")]
+    AllowlistIndexUnset {
+        index: u32,
+    },
+
+    #[error("Invalid allowlist range of {count} indexes from {start}")]
+    InvalidAllowlistRange {
+        start: u32,
+        count: u32,
+    },
+
+    #[error("No voucher signer is set")]
+    VoucherSignerUnset {},
+
+    #[error("Invalid voucher signature")]
+    InvalidVoucher {},
+
+    #[error("Protocol fee is set but the protocol address is unset")]
+    ProtocolAddressUnset {},
+
+    #[error("A commitment is already pending")]
+    CommitmentExists {},
+
+    #[error("No {denom} balance to withdraw")]
+    NothingToWithdraw {
+        denom: String,
+    },
+
+    #[error("No pending commitment")]
+    NoCommitment {},
+
+    #[error("Commitment can only be revealed from height {reveal_height}")]
+    RevealTooEarly {
+        reveal_height: u64,
+    },
+
+    #[error("Commitment expired")]
+    CommitmentExpired {},
+
+    #[error("Commitment can only be cancelled after height {expires_at}")]
+    CommitmentNotExpired {
+        expires_at: u64,
+    },
+
+    #[error("Commitment does not match the revealed salt and count")]
+    CommitmentMismatch {},
+
+    #[error("Cannot migrate from a different contract: {contract}")]
+    InvalidContractName {
+        contract: String,
+    },
+
+    #[error("Cannot migrate from newer version {version}")]
+    CannotDowngrade {
+        version: String,
+    },
+}
+
+impl TryFrom<ContractError> for PurchaseBlocker {
+    type Error = ContractError;
+
+    /// Gives back errors that aren't a failed precondition
+    fn try_from(err: ContractError) -> Result<Self, Self::Error> {
+        Ok(match err {
+            ContractError::Blacklisted { address } => PurchaseBlocker::Blacklisted { address },
+            ContractError::RecipientBlocked { address } => {
+                PurchaseBlocker::RecipientBlocked { address }
+            }
+            err if err == ContractError::unauthorized("whitelisted buyer") => {
+                PurchaseBlocker::NotWhitelisted {}
+            }
+            ContractError::PhaseRootUnset { tier } => PurchaseBlocker::PhaseRootUnset { tier },
+            ContractError::InvalidMerkleProof { tier } => {
+                PurchaseBlocker::InvalidMerkleProof { tier }
+            }
+            ContractError::AllowlistIndexUnset { index } => {
+                PurchaseBlocker::AllowlistIndexUnset { index }
+            }
+            ContractError::SaleNotActive { starts_at, ends_at, now } => {
+                PurchaseBlocker::SaleNotActive { starts_at, ends_at, now }
+            }
+            ContractError::ZeroPurchaseCount {} => PurchaseBlocker::ZeroPurchaseCount {},
+            ContractError::MintPerTxExceeded { max } => PurchaseBlocker::MintPerTxExceeded { max },
+            ContractError::SoldOut { remaining } => PurchaseBlocker::SoldOut { remaining },
+            ContractError::ProtocolAddressUnset {} => PurchaseBlocker::ProtocolAddressUnset {},
+            ContractError::WalletLimitExceeded { wallet, limit, minted } => {
+                PurchaseBlocker::WalletLimitExceeded { wallet, limit, minted }
+            }
+            ContractError::HoldingCapExceeded { cap, current } => {
+                PurchaseBlocker::HoldingCapExceeded { cap, current }
+            }
+            ContractError::AllocationExceeded { remaining } => {
+                PurchaseBlocker::AllocationExceeded { remaining }
+            }
+            err => {
+                return Err(err);
+            }
+        })
+    }
+}
+
+impl From<PurchaseBlocker> for ContractError {
+    fn from(reason: PurchaseBlocker) -> Self {
+        match reason {
+            PurchaseBlocker::Blacklisted { address } => ContractError::Blacklisted { address },
+            PurchaseBlocker::RecipientBlocked { address } => {
+                ContractError::RecipientBlocked { address }
+            }
+            PurchaseBlocker::NotWhitelisted {} => ContractError::unauthorized("whitelisted buyer"),
+            PurchaseBlocker::PhaseRootUnset { tier } => ContractError::PhaseRootUnset { tier },
+            PurchaseBlocker::InvalidMerkleProof { tier } => {
+                ContractError::InvalidMerkleProof { tier }
+            }
+            PurchaseBlocker::AllowlistIndexUnset { index } => {
+                ContractError::AllowlistIndexUnset { index }
+            }
+            PurchaseBlocker::SaleNotActive { starts_at, ends_at, now } => {
+                ContractError::SaleNotActive { starts_at, ends_at, now }
+            }
+            PurchaseBlocker::ZeroPurchaseCount {} => ContractError::ZeroPurchaseCount {},
+            PurchaseBlocker::MintPerTxExceeded { max } => ContractError::MintPerTxExceeded { max },
+            PurchaseBlocker::SoldOut { remaining } => ContractError::SoldOut { remaining },
+            PurchaseBlocker::ProtocolAddressUnset {} => ContractError::ProtocolAddressUnset {},
+            PurchaseBlocker::WalletLimitExceeded { wallet, limit, minted } => {
+                ContractError::WalletLimitExceeded { wallet, limit, minted }
+            }
+            PurchaseBlocker::HoldingCapExceeded { cap, current } => {
+                ContractError::HoldingCapExceeded { cap, current }
+            }
+            PurchaseBlocker::AllocationExceeded { remaining } => {
+                ContractError::AllocationExceeded { remaining }
+            }
+        }
+    }
+}
+
+impl From<ConfigError> for ContractError {
+    fn from(err: ConfigError) -> Self {
+        match err {
+            ConfigError::Invalid { field, reason } => {
+                ContractError::InvalidConfig { field, reason }
+            }
+            ConfigError::UriTooLong { field, max_len } => {
+                ContractError::UriTooLong { field, max_len }
+            }
+        }
+    }
+}
+
+impl ContractError {
+    pub fn unauthorized(expected_role: &str) -> Self {
+        ContractError::Unauthorized { expected_role: expected_role.to_string() }
+    }
+
+    pub fn invalid_config(field: &str, reason: &str) -> Self {
+        ContractError::InvalidConfig { field: field.to_string(), reason: reason.to_string() }
+    }
+}
+
+/// Attributes emitted by the token handlers. They follow the cw721 event keys and
+/// indexers depend on them, so they must stay stable.
+pub mod events {
+    use cosmwasm_std::{ attr, Attribute };
+
+    pub fn mint(minter: &str, owner: &str, token_id: &str) -> Vec<Attribute> {
+        vec![
+            attr("action", "mint"),
+            attr("minter", minter),
+            attr("owner", owner),
+            attr("token_id", token_id)
+        ]
+    }
+
+    pub fn transfer_nft(sender: &str, recipient: &str, token_id: &str) -> Vec<Attribute> {
+        vec![
+            attr("action", "transfer_nft"),
+            attr("sender", sender),
+            attr("recipient", recipient),
+            attr("token_id", token_id)
+        ]
+    }
+
+    pub fn send_nft(sender: &str, contract: &str, token_id: &str) -> Vec<Attribute> {
+        vec![
+            attr("action", "send_nft"),
+            attr("sender", sender),
+            attr("recipient", contract),
+            attr("token_id", token_id)
+        ]
+    }
+
+    pub fn burn(sender: &str, token_id: &str) -> Vec<Attribute> {
+        vec![attr("action", "burn"), attr("sender", sender), attr("token_id", token_id)]
+    }
+
+    pub fn freeze(token_id: &str) -> Vec<Attribute> {
+        vec![attr("action", "freeze"), attr("token_id", token_id)]
+    }
+
+    pub fn unfreeze(token_id: &str) -> Vec<Attribute> {
+        vec![attr("action", "unfreeze"), attr("token_id", token_id)]
+    }
+
+    /// Attributes of an operation on several tokens: `action` and `fields`,
+    /// followed by one `token_id` attribute per token
+    pub fn batch(action: &str, fields: Vec<Attribute>, token_ids: &[String]) -> Vec<Attribute> {
+        let mut attributes = vec![attr("action", action)];
+        attributes.extend(fields);
+        attributes.extend(token_ids.iter().map(|token_id| attr("token_id", token_id)));
+        attributes
+    }
+}
+
+impl State {
+    /// Builds the initial state, validating every address of the configs
+    pub fn new(
+        api: &dyn Api,
+        owner: Addr,
+        deployment_config: &ProptixDeploymentConfig,
+        runtime_config: &ProptixRuntimeConfig
+    ) -> Result<Self, ContractError> {
+        let protocol_address = deployment_config.protocol_address
+            .as_deref()
+            .map(|address| validate_address(api, "protocol_address", address))
+            .transpose()?;
+        let pass_collection = runtime_config.pass_collection
+            .as_deref()
+            .map(|address| validate_address(api, "pass_collection", address))
+            .transpose()?;
+        let open_edition = deployment_config.max_supply == 0;
+        if !open_edition && runtime_config.min_threshold > deployment_config.max_supply {
+            return Err(
+                ContractError::invalid_config("min_threshold", "must not exceed max_supply")
+            );
+        }
+
+        Ok(State {
+            owner,
+            base_token_uri: runtime_config.base_token_uri.clone(),
+            base_token_uri_extension: runtime_config.base_token_uri_extension.clone(),
+            prereveal_token_uri: runtime_config.prereveal_token_uri.clone(),
+            treasury_address: validate_address(
+                api,
+                "treasury_address",
+                &deployment_config.treasury_address
+            )?,
+            protocol_address,
+            mint_denom: runtime_config.mint_denom.clone(),
+            mint_price: runtime_config.mint_price,
+            sale_start_time: runtime_config.sale_start_time,
+            sale_end_time: runtime_config.sale_end_time,
+            protocol_fee_bps: u16::from(runtime_config.protocol_fee) * 100,
+            max_total_mint: deployment_config.max_supply,
+            current_token_id: 0,
+            uri_status: false,
+            burned_count: 0,
+            minted_count: 0,
+            active_tier: None,
+            presale_start_time: runtime_config.presale_start_time,
+            presale_end_time: runtime_config.presale_end_time,
+            gross_proceeds: Uint128::zero(),
+            protocol_fees_accrued: Uint128::zero(),
+            total_mints: 0,
+            max_per_wallet: runtime_config.max_per_wallet,
+            ics721_address: None,
+            contract_uri: None,
+            metadata_frozen: false,
+            supply_frozen: false,
+            lock_schedule_on_start: runtime_config.lock_schedule_on_start,
+            lock_whitelist_after_start: runtime_config.lock_whitelist_after_start,
+            users_disabled: deployment_config.users_disabled,
+            royalty_enforced: deployment_config.royalty_enforced,
+            max_per_wallet_holding: deployment_config.max_per_wallet_holding,
+            pass_collection,
+            referral_bps: runtime_config.referral_bps,
+            open_edition,
+            signer_pubkey: Binary::default(),
+            signature_scheme: SignatureScheme::default(),
+            proceeds_hook: None,
+            proceeds_hook_msg: Binary::default(),
+            burn_reward: None,
+            reward_pool: Uint128::zero(),
+            token_id_format: deployment_config.token_id_format.clone(),
+            metadata_resolver: None,
+            reveal_at: runtime_config.reveal_at,
+            phases: runtime_config.phases.clone(),
+            grace_period_secs: runtime_config.grace_period_secs,
+            grace_threshold_pct: runtime_config.grace_threshold_pct,
+            max_uri_len: runtime_config.max_uri_len,
+            min_threshold: runtime_config.min_threshold,
+            escrow_released: false,
+            early_bird_cutoff: runtime_config.early_bird_cutoff,
+            early_birds: 0,
+            finalize_actions: runtime_config.finalize_actions.clone(),
+            sale_finalized: false,
+            mint_receipts: runtime_config.mint_receipts,
+            uri_veto_threshold: runtime_config.uri_veto_threshold,
+            max_mints_per_block: runtime_config.max_mints_per_block,
+            factory_fee_bps: deployment_config.factory_fee_bps,
+            factory_address: None,
+        })
+    }
+
+    /// Takes the cost of `order` from the payer's ledger balance and mints the tokens. The
+    /// response carries the proceeds hook payment and the transfer hook notifications, which
+    /// run after the caller saved the state.
+    pub fn purchase(
+        &mut self,
+        storage: &mut dyn Storage,
+        env: &Env,
+        order: PurchaseOrder
+    ) -> Result<Response, ContractError> {
+        self.check_purchase(storage, env.block.time.seconds(), &order)?;
+        if let Some(remaining) = self.check_allocation(storage, &order)? {
+            WHITELIST_ALLOCATIONS.save(storage, order.payer, &(remaining - order.count))?;
+        }
+        let PurchaseOrder { payer, recipient, count, max_price_per_token, referrer, voucher, .. } =
+            order;
+        let now = env.block.time.seconds();
+        self.count_wallet_mints(storage, recipient, count)?;
+        self.count_block_mints(storage, env.block.height, count)?;
+        if let Some(phase) = self.active_phase(now) {
+            let minted = self.check_phase_mints(storage, phase, recipient, count)?;
+            MINTED_PER_PHASE.save(storage, (phase.label.as_str(), recipient), &minted)?;
+        }
+
+        // Protect the buyer from a price above the one they signed for
+        let quote = self.quote(count, None, now)?;
+        if let Some(max) = max_price_per_token {
+            if quote.per_token_price > max {
+                return Err(ContractError::PriceSlippage { quoted: quote.per_token_price, max });
+            }
+        }
+
+        // Take the total cost from the payer and distribute it as sales income
+        let total_cost = quote.total;
+        debit_balance(storage, payer, &self.mint_denom, total_cost)?;
+        let split = self.distribute_sale(storage, payer, total_cost, referrer)?;
+        self.record_sale(storage, &env.block, count, total_cost, split.protocol_fee)?;
+
+        // Mint the purchased tokens
+        let mut token_ids = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let number = self.mint(storage, recipient, None)?;
+            token_ids.push(self.token_id(number));
+        }
+        self.record_escrowed_tokens(storage, payer, &token_ids)?;
+        self.record_early_bird(storage, payer)?;
+
+        // Voucher redemptions always mint to the payer
+        let (action, mut attrs) = if voucher {
+            ("redeem_voucher", vec![])
+        } else {
+            ("purchase", vec![attr("payer", payer.as_str())])
+        };
+        attrs.extend([attr("owner", recipient.as_str()), attr("count", count.to_string())]);
+        let receipt = self.mint_receipt(
+            &(MintReceipt {
+                buyer: payer.to_string(),
+                recipient: recipient.to_string(),
+                token_ids: token_ids.clone(),
+                price_paid: Coin { denom: self.mint_denom.clone(), amount: total_cost },
+                fee_split: split,
+                height: env.block.height,
+            })
+        )?;
+        let forward = self.forward_proceeds(storage)?;
+        let hooks = transfer_hook_msgs(storage, "mint", &token_ids, None, Some(recipient))?;
+        Ok(
+            Response::new()
+                .add_messages(forward)
+                .add_submessages(hooks)
+                .add_attributes(events::batch(action, attrs, &token_ids))
+                .add_attributes(receipt)
+        )
+    }
+
+    /// Preconditions of `purchase` at `now`, also run by `SimulatePurchase`
+    pub fn check_purchase(
+        &self,
+        storage: &dyn Storage,
+        now: u64,
+        order: &PurchaseOrder
+    ) -> Result<(), ContractError> {
+        self.check_count(order.count)?;
+        let payer = order.payer;
+        if order.referrer == Some(payer) {
+            return Err(ContractError::SelfReferral {});
+        }
+        assert_not_blacklisted(storage, payer)?;
+        assert_not_blacklisted(storage, order.recipient)?;
+        assert_recipient_allowed(storage, order.recipient)?;
+        let phase = self.active_phase(now);
+
+        // Ensure that the payer is allowed to buy in the active tier
+        match self.active_tier {
+            _ if order.voucher => {}
+            _ if phase.map_or(false, |phase| !phase.whitelist_only) => {}
+            Some(tier) => {
+                let root = PHASE_ROOTS.may_load(storage, tier)?.ok_or(
+                    ContractError::PhaseRootUnset { tier }
+                )?;
+                let proof = order.proof.unwrap_or_default();
+                // the bitmap tier proves the payer's index, whose bit has to be set
+                let index = match order.allowlist_index {
+                    Some(index) if tier == TIER_BITMAP => Some(index),
+                    None if tier == TIER_BITMAP => {
+                        return Err(ContractError::InvalidMerkleProof { tier });
+                    }
+                    _ => None,
+                };
+                let leaf = match index {
+                    Some(index) => indexed_merkle_leaf(index, payer),
+                    None => merkle_leaf(payer),
+                };
+                if !verify_merkle_proof(&root, leaf, proof) {
+                    return Err(ContractError::InvalidMerkleProof { tier });
+                }
+                if let Some(index) = index {
+                    if !allowlist_bit(storage, index)? {
+                        return Err(ContractError::AllowlistIndexUnset { index });
+                    }
+                }
+            }
+            None => {
+                if !is_whitelisted(storage, payer)? {
+                    return Err(ContractError::unauthorized("whitelisted buyer"));
+                }
+            }
+        }
+
+        // Ensure that the sale is active
+        if !matches!(self.phase(now), SalePhase::Presale | SalePhase::Public) {
+            // between phases the next one is reported, after them the last one
+            let window = self.phases.iter().find(|phase| phase.end >= now).or(self.phases.last());
+            let (starts_at, ends_at) = match window {
+                Some(phase) => (phase.start, phase.end),
+                None => (self.sale_start_time, self.effective_sale_end()),
+            };
+            return Err(ContractError::SaleNotActive { starts_at, ends_at, now });
+        }
+
+        self.assert_protocol_address_set()?;
+        self.check_wallet_mints(storage, order.recipient, order.count)?;
+        if let Some(phase) = phase {
+            self.check_phase_mints(storage, phase, order.recipient, order.count)?;
+        }
+        self.check_holding_cap(storage, order.recipient, order.count)?;
+        self.check_allocation(storage, order)?;
+        Ok(())
+    }
+
+    /// Bounds of a purchase count, checked before any funds are taken
+    pub fn check_count(&self, count: u64) -> Result<(), ContractError> {
+        if count == 0 {
+            return Err(ContractError::ZeroPurchaseCount {});
+        }
+        if count > MAX_MINT_PER_TX {
+            return Err(ContractError::MintPerTxExceeded { max: MAX_MINT_PER_TX });
+        }
+        // Ensure that enough tokens are left
+        if let Some(remaining) = self.remaining_supply() {
+            if count > remaining {
+                return Err(ContractError::SoldOut { remaining });
+            }
+        }
+        Ok(())
+    }
+
+    /// Phase of `phases` open at `now`, if any
+    pub fn active_phase(&self, now: u64) -> Option<&Phase> {
+        self.phases.iter().find(|phase| phase.start <= now && now <= phase.end)
+    }
+
+    /// Price of a token at `now`, the one of the active phase if the sale has phases
+    pub fn price_at(&self, now: u64) -> Uint128 {
+        self.active_phase(now).map_or(self.mint_price, |phase| phase.price)
+    }
+
+    /// Ensures `wallet` may receive `count` more tokens within `phase` and returns its new
+    /// total for the phase
+    fn check_phase_mints(
+        &self,
+        storage: &dyn Storage,
+        phase: &Phase,
+        wallet: &Addr,
+        count: u64
+    ) -> Result<u64, ContractError> {
+        let minted = MINTED_PER_PHASE.may_load(storage, (phase.label.as_str(), wallet))?;
+        let minted = minted.unwrap_or_default();
+        if phase.per_wallet_limit != 0 && minted + count > phase.per_wallet_limit {
+            return Err(ContractError::WalletLimitExceeded {
+                wallet: wallet.to_string(),
+                limit: phase.per_wallet_limit,
+                minted,
+            });
+        }
+        Ok(minted + count)
+    }
+
+    /// Ensures a purchase let through by the whitelist stays within the payer's allocation
+    /// and returns the allocation left before it, if the payer has one
+    fn check_allocation(
+        &self,
+        storage: &dyn Storage,
+        order: &PurchaseOrder
+    ) -> Result<Option<u64>, ContractError> {
+        // tiers and vouchers gate the purchase instead of the whitelist
+        if self.active_tier.is_some() || order.voucher {
+            return Ok(None);
+        }
+        let remaining = WHITELIST_ALLOCATIONS.may_load(storage, order.payer)?;
+        if let Some(remaining) = remaining {
+            if order.count > remaining {
+                return Err(ContractError::AllocationExceeded { remaining });
+            }
+        }
+        Ok(remaining)
+    }
+
+    /// Price of `count` tokens as `purchase` charges it at `now`. The price is the same for
+    /// every buyer and the collection has no promo codes, so any `promo_code` is rejected.
+    pub fn quote(
+        &self,
+        count: u64,
+        promo_code: Option<&str>,
+        now: u64
+    ) -> Result<PurchaseQuoteResponse, ContractError> {
+        if let Some(code) = promo_code {
+            return Err(ContractError::UnknownPromoCode { code: code.to_string() });
+        }
+
+        let per_token_price = self.price_at(now);
+        let subtotal = per_token_price.checked_mul(Uint128::from(count))?;
+        let discount = Uint128::zero();
+        Ok(PurchaseQuoteResponse {
+            per_token_price,
+            subtotal,
+            discount,
+            total: subtotal.checked_sub(discount)?,
+        })
+    }
+
+    /// Ensures `wallet` may receive `count` more sold tokens and returns its new total
+    fn check_wallet_mints(
+        &self,
+        storage: &dyn Storage,
+        wallet: &Addr,
+        count: u64
+    ) -> Result<u64, ContractError> {
+        let minted = MINTED_PER_WALLET.may_load(storage, wallet)?.unwrap_or_default();
+        if self.max_per_wallet != 0 && minted + count > self.max_per_wallet {
+            return Err(ContractError::WalletLimitExceeded {
+                wallet: wallet.to_string(),
+                limit: self.max_per_wallet,
+                minted,
+            });
+        }
+        Ok(minted + count)
+    }
+
+    /// Ensures `holder` stays within the holding cap after receiving `incoming` more tokens
+    pub fn check_holding_cap(
+        &self,
+        storage: &dyn Storage,
+        holder: &Addr,
+        incoming: u64
+    ) -> Result<(), ContractError> {
+        let cap = match self.max_per_wallet_holding {
+            Some(cap) if !HOLDING_CAP_EXEMPT.has(storage, holder) => cap,
+            _ => {
+                return Ok(());
+            }
+        };
+        let current = HOLDER_BALANCES.may_load(storage, holder)?.unwrap_or_default();
+        if current + incoming > cap {
+            return Err(ContractError::HoldingCapExceeded { cap, current });
+        }
+        Ok(())
+    }
+
+    /// Counts `count` more sold tokens against the limit of `wallet`
+    fn count_wallet_mints(
+        &self,
+        storage: &mut dyn Storage,
+        wallet: &Addr,
+        count: u64
+    ) -> Result<(), ContractError> {
+        let minted = self.check_wallet_mints(storage, wallet, count)?;
+        MINTED_PER_WALLET.save(storage, wallet, &minted)?;
+        Ok(())
+    }
+
+    /// Counts `count` more sold tokens against the limit of the block at `height`. The count
+    /// starts over with every new block.
+    fn count_block_mints(
+        &self,
+        storage: &mut dyn Storage,
+        height: u64,
+        count: u64
+    ) -> Result<(), ContractError> {
+        if self.max_mints_per_block == 0 {
+            return Ok(());
+        }
+        let minted = match BLOCK_MINTS.may_load(storage)? {
+            Some((last_height, minted)) if last_height == height => minted,
+            _ => 0,
+        };
+        if minted + count > self.max_mints_per_block {
+            return Err(ContractError::BlockMintLimitReached {
+                limit: self.max_mints_per_block,
+                minted,
+            });
+        }
+        BLOCK_MINTS.save(storage, &(height, minted + count))?;
+        Ok(())
+    }
+
+    /// Splits `total_cost` between the protocol, the factory, the treasury and the referrer,
+    /// if any, and returns the split. The referral reward comes out of the treasury share.
+    /// While `escrows_sales`, the payment of `payer` and the shares are held in escrow.
+    fn distribute_sale(
+        &self,
+        storage: &mut dyn Storage,
+        payer: &Addr,
+        total_cost: Uint128,
+        referrer: Option<&Addr>
+    ) -> Result<SaleSplit, ContractError> {
+        if self.escrows_sales() {
+            SALE_ESCROW.update(storage, (payer, self.mint_denom.as_str()), |paid| -> StdResult<_> {
+                Ok(paid.unwrap_or_default().checked_add(total_cost)?)
+            })?;
+        }
+        let protocol_fee_amount =
+            total_cost.checked_mul(Uint128::from(self.protocol_fee_bps))? / Uint128::new(10_000);
+        let factory_fee =
+            total_cost.checked_mul(Uint128::from(self.factory_fee_bps))? / Uint128::new(10_000);
+        let mut treasury_amount = total_cost
+            .checked_sub(protocol_fee_amount)?
+            .checked_sub(factory_fee)?;
+        let mut referral_reward = Uint128::zero();
+
+        if let Some(referrer) = referrer {
+            referral_reward =
+                total_cost.checked_mul(Uint128::from(self.referral_bps))? / Uint128::new(10_000);
+            treasury_amount = treasury_amount.checked_sub(referral_reward)?;
+            self.credit_proceeds(storage, referrer, referral_reward)?;
+        }
+
+        if let Some(protocol_address) = &self.protocol_address {
+            self.credit_proceeds(storage, protocol_address, protocol_fee_amount)?;
+        }
+        if let Some(factory_address) = &self.factory_address {
+            self.credit_proceeds(storage, factory_address, factory_fee)?;
+        }
+        self.credit_proceeds(storage, &self.treasury_address, treasury_amount)?;
+        Ok(SaleSplit {
+            protocol_fee: protocol_fee_amount,
+            factory_fee,
+            referral_reward,
+            treasury_share: treasury_amount,
+        })
+    }
+
+    /// The `mint_receipt` attribute of a sale when `mint_receipts` is enabled
+    fn mint_receipt(&self, receipt: &MintReceipt) -> StdResult<Option<Attribute>> {
+        if !self.mint_receipts {
+            return Ok(None);
+        }
+        let json = String::from_utf8(to_vec(receipt)?)?;
+        Ok(Some(attr("mint_receipt", json)))
+    }
+
+    /// Ranks `buyer` among the early birds if the list is still open and doesn't hold it yet
+    fn record_early_bird(&mut self, storage: &mut dyn Storage, buyer: &Addr) -> StdResult<()> {
+        if self.early_birds >= self.early_bird_cutoff || EARLY_BIRDS.has(storage, buyer) {
+            return Ok(());
+        }
+        self.early_birds += 1;
+        EARLY_BIRDS.save(storage, buyer, &self.early_birds)?;
+        EARLY_BIRD_RANKS.save(storage, self.early_birds, buyer)
+    }
+
+    /// Whether sales are paid into escrow, from instantiation until `ReleaseEscrow` for
+    /// sales with a `min_threshold`
+    pub fn escrows_sales(&self) -> bool {
+        self.min_threshold > 0 && !self.escrow_released
+    }
+
+    /// Records `token_ids` as bought by `payer` while their payment is held in escrow
+    fn record_escrowed_tokens(
+        &self,
+        storage: &mut dyn Storage,
+        payer: &Addr,
+        token_ids: &[String]
+    ) -> StdResult<()> {
+        if self.escrows_sales() {
+            for token_id in token_ids {
+                ESCROWED_TOKENS.save(storage, (payer, token_id.as_str()), &Empty {})?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Credits sales income to `addr`, in escrow while `escrows_sales`
+    fn credit_proceeds(
+        &self,
+        storage: &mut dyn Storage,
+        addr: &Addr,
+        amount: Uint128
+    ) -> Result<(), ContractError> {
+        if !self.escrows_sales() {
+            return credit_balance(storage, addr, &self.mint_denom, amount);
+        }
+        let key = (addr, self.mint_denom.as_str());
+        ESCROWED_PROCEEDS.update(storage, key, |held| -> StdResult<_> {
+            Ok(held.unwrap_or_default().checked_add(amount)?)
+        })?;
+        Ok(())
+    }
+
+    /// Runs the `finalize_actions` once a scheduled sale has ended or the supply is sold
+    /// out, paying the treasury its ledger balances if they are swept
+    pub fn finalize_sale(
+        &mut self,
+        storage: &mut dyn Storage,
+        now: u64
+    ) -> Result<Response, ContractError> {
+        if self.sale_finalized {
+            return Err(ContractError::SaleFinalized {});
+        }
+        let scheduled = self.sale_end_time != 0 || !self.phases.is_empty();
+        let ended = scheduled && self.phase(now) == SalePhase::Ended;
+        if !ended && self.remaining_supply() != Some(0) {
+            return Err(ContractError::SaleNotEnded { ends_at: self.effective_sale_end(), now });
+        }
+
+        let actions = self.finalize_actions.clone();
+        let mut payouts = vec![];
+        if actions.sweep_proceeds {
+            let balances = BALANCES.prefix(&self.treasury_address)
+                .range(storage, None, None, Order::Ascending)
+                .collect::<StdResult<Vec<_>>>()?;
+            for (denom, amount) in balances {
+                debit_balance(storage, &self.treasury_address, &denom, amount)?;
+                payouts.push(self.treasury_payout(Coin { denom, amount }));
+            }
+        }
+        if actions.freeze_supply {
+            if self.open_edition {
+                self.open_edition = false;
+                self.max_total_mint = self.current_token_id;
+            } else if !self.supply_frozen && self.current_token_id < self.max_total_mint {
+                self.reduce_max_supply(storage, self.current_token_id)?;
+            }
+            self.supply_frozen = true;
+        }
+        if actions.reveal {
+            self.uri_status = true;
+        }
+        self.sale_finalized = true;
+
+        Ok(
+            Response::new()
+                .add_messages(payouts)
+                .add_attributes(
+                    vec![
+                        attr("action", "finalize_sale"),
+                        attr("minted", self.minted_count.to_string()),
+                        attr("burned", self.burned_count.to_string()),
+                        attr("sold", self.total_mints.to_string()),
+                        attr("max_supply", self.max_total_mint.to_string())
+                    ]
+                )
+        )
+    }
+
+    /// Whether the sale sold its `min_threshold` of tokens, only known once it ended
+    pub fn threshold_met(&self, now: u64) -> Result<bool, ContractError> {
+        if self.min_threshold == 0 {
+            return Err(ContractError::NoSaleThreshold {});
+        }
+        if self.phase(now) != SalePhase::Ended {
+            return Err(ContractError::SaleNotEnded { ends_at: self.effective_sale_end(), now });
+        }
+        Ok(self.total_mints >= self.min_threshold)
+    }
+
+    /// Adds a sale of `count` tokens to the sale statistics
+    fn record_sale(
+        &mut self,
+        storage: &mut dyn Storage,
+        block: &BlockInfo,
+        count: u64,
+        total_cost: Uint128,
+        protocol_fee_amount: Uint128
+    ) -> Result<(), ContractError> {
+        self.gross_proceeds = self.gross_proceeds.checked_add(total_cost)?;
+        self.protocol_fees_accrued = self.protocol_fees_accrued.checked_add(protocol_fee_amount)?;
+        self.total_mints += count;
+
+        let day = block.time.seconds() / SECONDS_PER_DAY;
+        MINTS_PER_DAY.update(storage, day, |mints| -> StdResult<_> {
+            Ok(mints.unwrap_or_default() + count)
+        })?;
+        Ok(())
+    }
+
+    /// Tokens that can still be minted, `None` while an open edition is unbounded
+    pub fn remaining_supply(&self) -> Option<u64> {
+        if self.open_edition {
+            None
+        } else {
+            Some(self.max_total_mint.saturating_sub(self.current_token_id))
+        }
+    }
+
+    /// Mints the next token to `recipient` and returns its number, `token_id` gives the id
+    /// it is stored under. Without `entropy` numbers are assigned sequentially, with it the
+    /// number is drawn from the remaining pool. Open editions have no pool to draw from and
+    /// always mint sequentially.
+    ///
+    /// This is the only place advancing `current_token_id`.
+    pub fn mint(
+        &mut self,
+        storage: &mut dyn Storage,
+        recipient: &Addr,
+        entropy: Option<&[u8]>
+    ) -> Result<u64, ContractError> {
+        assert_recipient_allowed(storage, recipient)?;
+        self.check_holding_cap(storage, recipient, 1)?;
+        // ids minted by the owner through `ExecuteMsg::Mint` may already be taken, the sale
+        // skips them as if they were sold
+        let (token_id, id) = loop {
+            let token_id = if self.open_edition {
+                self.current_token_id
+            } else {
+                draw_token_id(storage, self.current_token_id, self.max_total_mint, entropy)?
+            };
+            let id = self.token_id(token_id);
+            if !tokens().has(storage, &id) {
+                break (token_id, id);
+            }
+            self.current_token_id += 1;
+        };
+        tokens().save(storage, &id, &TokenRecord::new(recipient.clone(), None))?;
+        checkpoint_rewards(storage, &id)?;
+        add_holding(storage, recipient)?;
+
+        // Update state
+        self.current_token_id += 1;
+        self.minted_count += 1;
+        if !self.open_edition && self.current_token_id > self.max_total_mint {
+            return Err(
+                StdError::generic_err(
+                    format!(
+                        "Invariant violated: current_token_id {} exceeds max_total_mint {}",
+                        self.current_token_id,
+                        self.max_total_mint
+                    )
+                ).into()
+            );
+        }
+
+        Ok(token_id)
+    }
+
+    /// Id of the token numbered `number`, following `token_id_format`
+    pub fn token_id(&self, number: u64) -> String {
+        match &self.token_id_format {
+            Some(format) => format.format(number),
+            None => number.to_string(),
+        }
+    }
+
+    /// Lowers `max_total_mint` to `new_max` and drops the ids beyond it from the id pool,
+    /// returning the previous max supply
+    pub fn reduce_max_supply(
+        &mut self,
+        storage: &mut dyn Storage,
+        new_max: u64
+    ) -> Result<u64, ContractError> {
+        if self.supply_frozen {
+            return Err(ContractError::SupplyFrozen {});
+        }
+        // open editions have a max supply of 0, so they are rejected here as well
+        if new_max >= self.max_total_mint || new_max < self.current_token_id {
+            return Err(ContractError::InvalidSupplyReduction {
+                new_max,
+                max_supply: self.max_total_mint,
+                minted: self.current_token_id,
+            });
+        }
+
+        truncate_token_id_pool(storage, new_max)?;
+        let old_max = self.max_total_mint;
+        self.max_total_mint = new_max;
+        Ok(old_max)
+    }
+
+    /// Replaces `max_total_mint` with any cap covering the minted tokens, returning the
+    /// previous max supply. Only reachable through a migration.
+    pub fn set_max_supply(
+        &mut self,
+        storage: &mut dyn Storage,
+        new_max: u64
+    ) -> Result<u64, ContractError> {
+        if self.supply_frozen {
+            return Err(ContractError::SupplyFrozen {});
+        }
+        if new_max < self.current_token_id {
+            return Err(ContractError::InvalidMaxSupply { new_max, minted: self.current_token_id });
+        }
+
+        if new_max < self.max_total_mint {
+            truncate_token_id_pool(storage, new_max)?;
+        }
+        let old_max = self.max_total_mint;
+        self.max_total_mint = new_max;
+        Ok(old_max)
+    }
+
+    /// Takes the reward for burning `count` tokens out of the pool, if a reward is set
+    pub fn take_burn_reward(&mut self, count: u64) -> Result<Option<Coin>, ContractError> {
+        let Some(reward) = &self.burn_reward else {
+            return Ok(None);
+        };
+        let required = reward.amount.checked_mul(Uint128::from(count))?;
+        if required > self.reward_pool {
+            return Err(ContractError::RewardPoolEmpty { required, available: self.reward_pool });
+        }
+        self.reward_pool -= required;
+        Ok((!required.is_zero()).then(|| Coin { denom: reward.denom.clone(), amount: required }))
+    }
+
+    /// Phase of the sale at `now` in seconds. Both windows are inclusive and the
+    /// public sale takes precedence where they overlap. With `phases`, whitelist only
+    /// phases count as presale and the others as public sale.
+    pub fn phase(&self, now: u64) -> SalePhase {
+        if let Some(last) = self.phases.last() {
+            return match self.active_phase(now) {
+                Some(phase) if phase.whitelist_only => SalePhase::Presale,
+                Some(_) => SalePhase::Public,
+                None if now > last.end => SalePhase::Ended,
+                None => SalePhase::Upcoming,
+            };
+        }
+        let has_presale = self.presale_end_time != 0;
+        if now > self.effective_sale_end() {
+            SalePhase::Ended
+        } else if now >= self.sale_start_time {
+            SalePhase::Public
+        } else if has_presale && now >= self.presale_start_time && now <= self.presale_end_time {
+            SalePhase::Presale
+        } else {
+            SalePhase::Upcoming
+        }
+    }
+
+    /// End of the public sale, `grace_period_secs` past `sale_end_time` while more than
+    /// `grace_threshold_pct` percent of the supply remains. Purchases during the grace
+    /// period can end it early by bringing the remaining supply down to the threshold.
+    /// Open editions have no supply to measure and are never extended.
+    pub fn effective_sale_end(&self) -> u64 {
+        let threshold = u128::from(self.max_total_mint) * u128::from(self.grace_threshold_pct);
+        let extended = self.remaining_supply().map_or(false, |remaining| {
+            u128::from(remaining) * 100 > threshold
+        });
+        if self.grace_period_secs > 0 && extended {
+            self.sale_end_time.saturating_add(self.grace_period_secs)
+        } else {
+            self.sale_end_time
+        }
+    }
+
+    /// Ensures the public sale window is well ordered and the presale, if any, ends
+    /// before the public sale starts
+    pub fn validate_schedule(&self) -> Result<(), ContractError> {
+        let times = [
+            self.sale_start_time,
+            self.sale_end_time,
+            self.presale_start_time,
+            self.presale_end_time,
+        ];
+        if times.into_iter().any(|time| sale_timestamp(time).is_none()) {
+            return Err(ContractError::InvalidSchedule {
+                reason: "times must be in seconds".to_string(),
+            });
+        }
+        if self.sale_start_time >= self.sale_end_time {
+            return Err(ContractError::InvalidSchedule {
+                reason: "sale must start before it ends".to_string(),
+            });
+        }
+        if self.presale_start_time == 0 && self.presale_end_time == 0 {
+            return Ok(());
+        }
+        if self.presale_start_time >= self.presale_end_time {
+            return Err(ContractError::InvalidSchedule {
+                reason: "presale must start before it ends".to_string(),
+            });
+        }
+        if self.presale_end_time >= self.sale_start_time {
+            return Err(ContractError::InvalidSchedule {
+                reason: "presale must end before the public sale starts".to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Ensures the schedule may still change at `now`
+    fn assert_schedule_unlocked(&self, now: u64) -> Result<(), ContractError> {
+        let has_presale = self.presale_end_time != 0;
+        let opens_at = if has_presale { self.presale_start_time } else { self.sale_start_time };
+        if self.lock_schedule_on_start && now >= opens_at {
+            return Err(ContractError::InvalidSchedule {
+                reason: "the sale has already started".to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    fn assert_whitelist_unlocked(&self, now: u64) -> Result<(), ContractError> {
+        if self.lock_whitelist_after_start && now >= self.sale_start_time {
+            return Err(ContractError::WhitelistLocked { sale_start_time: self.sale_start_time });
+        }
+        Ok(())
+    }
+
+    /// Fees can't be routed to the protocol before its address is known
+    pub fn assert_protocol_address_set(&self) -> Result<(), ContractError> {
+        if self.protocol_fee_bps > 0 && self.protocol_address.is_none() {
+            return Err(ContractError::ProtocolAddressUnset {});
+        }
+        Ok(())
+    }
+
+    /// Pays `amount` to the treasury, through the proceeds hook when one is set
+    pub fn treasury_payout(&self, amount: Coin) -> CosmosMsg {
+        match &self.proceeds_hook {
+            Some(hook) =>
+                WasmMsg::Execute {
+                    contract_addr: hook.to_string(),
+                    msg: self.proceeds_hook_msg.clone(),
+                    funds: vec![amount],
+                }.into(),
+            None =>
+                BankMsg::Send {
+                    to_address: self.treasury_address.to_string(),
+                    amount: vec![amount],
+                }.into(),
+        }
+    }
+
+    /// Pays out the sales income that isn't withdrawn: the treasury's to the proceeds hook,
+    /// if one is set, and the factory fee to the factory
+    pub fn forward_proceeds(
+        &self,
+        storage: &mut dyn Storage
+    ) -> Result<Vec<CosmosMsg>, ContractError> {
+        let mut payouts = vec![];
+        if self.proceeds_hook.is_some() {
+            let amount = get_balance(storage, &self.treasury_address, &self.mint_denom)?;
+            if !amount.is_zero() {
+                debit_balance(storage, &self.treasury_address, &self.mint_denom, amount)?;
+                let payout = Coin { denom: self.mint_denom.clone(), amount };
+                payouts.push(self.treasury_payout(payout));
+            }
+        }
+        if let Some(factory_address) = &self.factory_address {
+            let amount = get_balance(storage, factory_address, &self.mint_denom)?;
+            if !amount.is_zero() {
+                debit_balance(storage, factory_address, &self.mint_denom, amount)?;
+                payouts.push(
+                    (BankMsg::Send {
+                        to_address: factory_address.to_string(),
+                        amount: vec![Coin { denom: self.mint_denom.clone(), amount }],
+                    }).into()
+                );
+            }
+        }
+        Ok(payouts)
+    }
+
+    /// Whether token uris come from the base uri at `now`, either after `Reveal` or once
+    /// `reveal_at` has passed
+    pub fn revealed(&self, now: u64) -> bool {
+        self.uri_status || self.reveal_at.map_or(false, |reveal_at| now >= reveal_at)
+    }
+
+    /// Uri of a minted token, the prereveal uri until the collection is revealed
+    pub fn token_uri(&self, token_id: &str, now: u64) -> String {
+        if self.revealed(now) {
+            format!("{}{}{}", self.base_token_uri, token_id, self.base_token_uri_extension)
+        } else {
+            self.prereveal_token_uri.clone()
+        }
+    }
+
+    /// Mints the tokens of `order` committed to through `CommitMint`, paid from the escrow.
+    /// The payer is the committer and receives the tokens.
+    pub fn reveal_mint(
+        &mut self,
+        storage: &mut dyn Storage,
+        env: &Env,
+        order: PurchaseOrder,
+        salt: Binary
+    ) -> Result<Response, ContractError> {
+        let (sender, count) = (order.payer, order.count);
+        let commitment = take_commitment(storage, &env.block, sender, &salt, count)?;
+
+        // The revealed count is held to every limit of a purchase
+        let now = env.block.time.seconds();
+        self.check_purchase(storage, now, &order)?;
+        if let Some(remaining) = self.check_allocation(storage, &order)? {
+            WHITELIST_ALLOCATIONS.save(storage, sender, &(remaining - count))?;
+        }
+        self.count_wallet_mints(storage, sender, count)?;
+        self.count_block_mints(storage, env.block.height, count)?;
+        if let Some(phase) = self.active_phase(now) {
+            let minted = self.check_phase_mints(storage, phase, sender, count)?;
+            MINTED_PER_PHASE.save(storage, (phase.label.as_str(), sender), &minted)?;
+        }
+
+        // The escrow must cover the revealed count
+        let price = self.price_at(now);
+        let total_cost = price.checked_mul(Uint128::from(count))?;
+        if commitment.escrow.amount < total_cost {
+            return Err(ContractError::InsufficientFunds {
+                required: total_cost,
+                provided: commitment.escrow.amount,
+            });
+        }
+
+        // Distribute sales income
+        let split = self.distribute_sale(storage, sender, total_cost, None)?;
+        self.record_sale(storage, &env.block, count, total_cost, split.protocol_fee)?;
+
+        // CosmWasm does not expose block hashes to contracts, so the time of the commit block
+        // stands in as the extra entropy. It was unknown when the salt was chosen and is fixed
+        // once committed, so the block the revealer picks within the window doesn't change it.
+        let entropy = Sha256::new()
+            .chain_update(commitment.hash.as_slice())
+            .chain_update(commitment.committed_at.to_be_bytes())
+            .chain_update(commitment.committed_time.nanos().to_be_bytes())
+            .finalize();
+
+        let mut token_ids = Vec::with_capacity(count as usize);
+        for i in 0..count {
+            let seed = Sha256::new().chain_update(entropy).chain_update(i.to_be_bytes()).finalize();
+            let number = self.mint(storage, sender, Some(&seed))?;
+            token_ids.push(self.token_id(number));
+        }
+        self.record_escrowed_tokens(storage, sender, &token_ids)?;
+        self.record_early_bird(storage, sender)?;
+
+        let receipt = self.mint_receipt(
+            &(MintReceipt {
+                buyer: sender.to_string(),
+                recipient: sender.to_string(),
+                token_ids: token_ids.clone(),
+                price_paid: Coin { denom: self.mint_denom.clone(), amount: total_cost },
+                fee_split: split,
+                height: env.block.height,
+            })
+        )?;
+        let forward = self.forward_proceeds(storage)?;
+        let hooks = transfer_hook_msgs(storage, "mint", &token_ids, None, Some(sender))?;
+        let mut response = Response::new()
+            .add_messages(forward)
+            .add_submessages(hooks)
+            .add_attributes(
+                events::batch(
+                    "reveal_mint",
+                    vec![attr("buyer", sender.as_str()), attr("count", count.to_string())],
+                    &token_ids
+                )
+            )
+            .add_attributes(receipt);
+
+        // Refund whatever the escrow holds above the cost of the revealed count
+        let refund = commitment.escrow.amount - total_cost;
+        if !refund.is_zero() {
+            response = response.add_message(BankMsg::Send {
+                to_address: sender.to_string(),
+                amount: vec![Coin { denom: commitment.escrow.denom, amount: refund }],
+            });
+        }
+
+        Ok(response)
+    }
+}
+
+pub fn whitelist(storage: &mut dyn Storage, account: &Addr, status: bool) -> StdResult<()> {
+    if !status {
+        WHITELIST_ALLOCATIONS.remove(storage, account);
+        WHITELIST.remove(storage, account);
+        return Ok(());
+    }
+    WHITELIST.save(storage, account, &status)
+}
+
+pub fn is_whitelisted(storage: &dyn Storage, account: &Addr) -> StdResult<bool> {
+    Ok(WHITELIST.may_load(storage, account)?.unwrap_or(false))
+}
+
+/// Whether the bit of `index` is set in the `TIER_BITMAP` allowlist
+pub fn allowlist_bit(storage: &dyn Storage, index: u32) -> StdResult<bool> {
+    let page = ALLOWLIST_BITMAP.may_load(storage, index / ALLOWLIST_PAGE_BITS)?;
+    let offset = (index % ALLOWLIST_PAGE_BITS) as usize;
+    Ok(page.map_or(false, |page| page[offset / 8] & (1 << (offset % 8)) != 0))
+}
+
+/// Sets the bits of the `count` indexes from `start`, one page write per page touched
+pub fn set_allowlist_bits(
+    storage: &mut dyn Storage,
+    start: u32,
+    count: u32,
+    value: bool
+) -> StdResult<()> {
+    let end = u64::from(start) + u64::from(count);
+    let mut index = u64::from(start);
+    while index < end {
+        let page_id = (index / u64::from(ALLOWLIST_PAGE_BITS)) as u32;
+        let page_end = end.min((u64::from(page_id) + 1) * u64::from(ALLOWLIST_PAGE_BITS));
+        let mut page = ALLOWLIST_BITMAP.may_load(storage, page_id)?.unwrap_or_else(
+            || vec![0; (ALLOWLIST_PAGE_BITS / 8) as usize]
+        );
+        for bit in index..page_end {
+            let offset = (bit % u64::from(ALLOWLIST_PAGE_BITS)) as usize;
+            if value {
+                page[offset / 8] |= 1 << (offset % 8);
+            } else {
+                page[offset / 8] &= !(1 << (offset % 8));
+            }
+        }
+        if page.iter().all(|byte| *byte == 0) {
+            ALLOWLIST_BITMAP.remove(storage, page_id);
+        } else {
+            ALLOWLIST_BITMAP.save(storage, page_id, &page)?;
+        }
+        index = page_end;
+    }
+    Ok(())
+}
+
+/// Ensures `uri` is at most `max_len` bytes long, any length passes when `max_len` is 0
+pub fn check_uri_len(field: &str, uri: &str, max_len: u32) -> Result<(), ContractError> {
+    if max_len > 0 && uri.len() > max_len as usize {
+        return Err(ContractError::UriTooLong { field: field.to_string(), max_len });
+    }
+    Ok(())
+}
+
+/// Ensures `uri_hash` is a sha256 digest, hex or base64 encoded
+pub fn validate_uri_hash(uri_hash: &str) -> Result<(), ContractError> {
+    let is_hex = uri_hash.len() == 64 && uri_hash.chars().all(|c| c.is_ascii_hexdigit());
+    let is_base64 = Binary::from_base64(uri_hash).map_or(false, |digest| digest.len() == 32);
+    if !is_hex && !is_base64 {
+        return Err(ContractError::InvalidUriHash { uri_hash: uri_hash.to_string() });
+    }
+    Ok(())
+}
+
+pub fn assert_not_blacklisted(storage: &dyn Storage, account: &Addr) -> Result<(), ContractError> {
+    if BLACKLIST.has(storage, account) {
+        return Err(ContractError::Blacklisted { address: account.to_string() });
+    }
+    Ok(())
+}
+
+pub fn assert_recipient_allowed(
+    storage: &dyn Storage,
+    recipient: &Addr
+) -> Result<(), ContractError> {
+    if RECIPIENT_BLOCKLIST.has(storage, recipient) {
+        return Err(ContractError::RecipientBlocked { address: recipient.to_string() });
+    }
+    Ok(())
+}
+
+/// Merkle leaf of an allowlisted address
+pub fn merkle_leaf(account: &Addr) -> [u8; 32] {
+    Sha256::digest(account.as_bytes()).into()
+}
+
+/// Leaf of the `TIER_BITMAP` allowlist, assigning `index` to `account`
+pub fn indexed_merkle_leaf(index: u32, account: &Addr) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(index.to_be_bytes());
+    hasher.update(account.as_bytes());
+    hasher.finalize().into()
+}
+
+/// Hashes two nodes in sorted order, so proofs don't need to carry positions
+pub fn merkle_parent(a: &[u8], b: &[u8]) -> [u8; 32] {
+    let (left, right) = if a <= b { (a, b) } else { (b, a) };
+    let mut hasher = Sha256::new();
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+pub fn verify_merkle_proof(root: &[u8], leaf: [u8; 32], proof: &[Binary]) -> bool {
+    let computed = proof.iter().fold(leaf, |node, sibling| merkle_parent(&node, sibling));
+    computed.as_slice() == root
+}
+
+/// Digest a voucher signs, binding it to this contract and the buyer
+pub fn voucher_digest(contract: &Addr, buyer: &Addr) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(contract.as_bytes());
+    hasher.update(buyer.as_bytes());
+    hasher.finalize().into()
+}
+
+/// Checks `signature` over the voucher digest of `buyer` with the configured signer.
+/// Malformed signatures are reported as invalid.
+pub fn verify_voucher(
+    api: &dyn Api,
+    state: &State,
+    contract: &Addr,
+    buyer: &Addr,
+    signature: &[u8]
+) -> Result<(), ContractError> {
+    if state.signer_pubkey.is_empty() {
+        return Err(ContractError::VoucherSignerUnset {});
+    }
+
+    let digest = voucher_digest(contract, buyer);
+    let pubkey = &state.signer_pubkey;
+    let verified = match state.signature_scheme {
+        SignatureScheme::Secp256k1 => api.secp256k1_verify(&digest, signature, pubkey),
+        SignatureScheme::Ed25519 => api.ed25519_verify(&digest, signature, pubkey),
+    };
+    if !matches!(verified, Ok(true)) {
+        return Err(ContractError::InvalidVoucher {});
+    }
+    Ok(())
+}
+
+pub fn get_balance(storage: &dyn Storage, addr: &Addr, denom: &str) -> StdResult<Uint128> {
+    Ok(BALANCES.may_load(storage, (addr, denom))?.unwrap_or_default())
+}
+
+pub fn credit_balance(
+    storage: &mut dyn Storage,
+    addr: &Addr,
+    denom: &str,
+    amount: Uint128
+) -> Result<(), ContractError> {
+    let balance = get_balance(storage, addr, denom)?.checked_add(amount)?;
+    BALANCES.save(storage, (addr, denom), &balance)?;
+    Ok(())
+}
+
+pub fn debit_balance(
+    storage: &mut dyn Storage,
+    addr: &Addr,
+    denom: &str,
+    amount: Uint128
+) -> Result<(), ContractError> {
+    let balance = get_balance(storage, addr, denom)?;
+    if amount > balance {
+        return Err(ContractError::InsufficientFunds { required: amount, provided: balance });
+    }
+
+    // emptied balances are dropped so `AccruedByDenom` only lists what is left
+    if amount == balance {
+        BALANCES.remove(storage, (addr, denom));
+    } else {
+        BALANCES.save(storage, (addr, denom), &(balance - amount))?;
+    }
+    Ok(())
+}
+
+/// Ensures `sender` is the owner recorded in `state`
+fn assert_owner(state: &State, sender: &Addr) -> Result<(), ContractError> {
+    if state.owner != *sender {
+        return Err(ContractError::unauthorized("owner"));
+    }
+    Ok(())
+}
+
+/// Ensures `sender` is the cw721 minter, which a factory sets to the creator
+fn assert_minter(storage: &dyn Storage, sender: &Addr) -> Result<(), ContractError> {
+    let minter = Cw721Contract::<Empty, Empty, Empty, Empty>::default().minter.load(storage)?;
+    if minter != *sender {
+        return Err(ContractError::unauthorized("minter"));
+    }
+    Ok(())
+}
+
+fn burn_reward_payout(burner: &Addr, reward: Option<Coin>) -> Option<BankMsg> {
+    reward.map(|reward| BankMsg::Send { to_address: burner.to_string(), amount: vec![reward] })
+}
+
+/// Removes a token whose ownership was already checked and counts it as burned. Its unclaimed
+/// holder rewards go back to the next funding instead of staying stranded in the escrow.
+fn burn_token(storage: &mut dyn Storage, state: &mut State, token_id: &str) -> StdResult<()> {
+    let token = tokens().load(storage, token_id)?;
+    tokens().remove(storage, token_id)?;
+    LISTINGS.remove(storage, token_id);
+    SELF_LOCKS.remove(storage, token_id);
+    if let Some(mut rewards) = HOLDER_REWARDS.may_load(storage)? {
+        let (_, pending) = pending_rewards(storage, &rewards, token_id)?;
+        rewards.undistributed = rewards.undistributed.checked_add(pending)?;
+        HOLDER_REWARDS.save(storage, &rewards)?;
+    }
+    REWARD_CHECKPOINTS.remove(storage, token_id);
+    remove_holding(storage, &token.owner)?;
+    state.burned_count += 1;
+    Ok(())
+}
+
+fn add_holding(storage: &mut dyn Storage, holder: &Addr) -> StdResult<()> {
+    HOLDER_BALANCES.update(storage, holder, |count| -> StdResult<_> {
+        Ok(count.unwrap_or_default() + 1)
+    })?;
+    Ok(())
+}
+
+fn remove_holding(storage: &mut dyn Storage, holder: &Addr) -> StdResult<()> {
+    let count = HOLDER_BALANCES.may_load(storage, holder)?.unwrap_or_default();
+    if count <= 1 {
+        HOLDER_BALANCES.remove(storage, holder);
+    } else {
+        HOLDER_BALANCES.save(storage, holder, &(count - 1))?;
+    }
+    Ok(())
+}
+
+fn load_token(storage: &dyn Storage, token_id: &str) -> Result<TokenRecord, ContractError> {
+    tokens()
+        .may_load(storage, token_id)?
+        .ok_or_else(|| ContractError::TokenNotFound { token_id: token_id.to_string() })
+}
+
+/// Ensures `sender` owns `token` or holds an unexpired approval for it
+fn assert_owner_or_approved(
+    token: &TokenRecord,
+    sender: &Addr,
+    block: &BlockInfo
+) -> Result<(), ContractError> {
+    let approved = token.approvals.iter().any(|approval| {
+        approval.spender == sender.as_str() && !approval.expires.is_expired(block)
+    });
+    if token.owner != *sender && !approved {
+        return Err(ContractError::unauthorized("token owner or approved spender"));
+    }
+    Ok(())
+}
+
+/// Ensures `token` may change hands: it is neither frozen, locked nor escrowed by the bridge
+fn assert_transferable(
+    storage: &dyn Storage,
+    block: &BlockInfo,
+    token_id: &str,
+    token: &TokenRecord
+) -> Result<(), ContractError> {
+    if token.frozen {
+        return Err(ContractError::TokenFrozen { token_id: token_id.to_string() });
+    }
+    if let Some(until) = token.locked_until {
+        if !until.is_expired(block) {
+            return Err(ContractError::TokenLocked { token_id: token_id.to_string(), until });
+        }
+    }
+    if let Some(unlock_time) = self_lock(storage, block, token_id)? {
+        return Err(ContractError::TokenLocked {
+            token_id: token_id.to_string(),
+            until: Expiration::AtTime(Timestamp::from_seconds(unlock_time)),
+        });
+    }
+    assert_not_bridged(storage, token_id)?;
+    assert_not_auctioned(storage, token_id)
+}
+
+/// Unlock time of the holder's lock on `token_id`, if it hasn't passed at `block`
+fn self_lock(
+    storage: &dyn Storage,
+    block: &BlockInfo,
+    token_id: &str
+) -> StdResult<Option<u64>> {
+    let unlock_time = SELF_LOCKS.may_load(storage, token_id)?;
+    Ok(unlock_time.filter(|unlock_time| block.time.seconds() < *unlock_time))
+}
+
+fn assert_not_bridged(storage: &dyn Storage, token_id: &str) -> Result<(), ContractError> {
+    if BRIDGED.has(storage, token_id) {
+        return Err(ContractError::TokenBridged { token_id: token_id.to_string() });
+    }
+    Ok(())
+}
+
+fn assert_not_auctioned(storage: &dyn Storage, token_id: &str) -> Result<(), ContractError> {
+    if AUCTIONS.has(storage, token_id) {
+        return Err(ContractError::TokenAuctioned { token_id: token_id.to_string() });
+    }
+    Ok(())
+}
+
+/// Ensures `token_id` exists and is owned by `sender`
+fn assert_token_owner(
+    storage: &dyn Storage,
+    token_id: &str,
+    sender: &Addr
+) -> Result<TokenRecord, ContractError> {
+    let token = load_token(storage, token_id)?;
+    if &token.owner != sender {
+        return Err(ContractError::unauthorized("token owner"));
+    }
+    Ok(token)
+}
+
+/// Validates `value`, naming the message field it came from if it isn't a valid address
+fn validate_address(api: &dyn Api, field: &str, value: &str) -> Result<Addr, ContractError> {
+    api.addr_validate(value).map_err(|_| ContractError::InvalidAddress {
+        field: field.to_string(),
+        value: value.to_string(),
+    })
+}
+
+/// Address kept as a plain string by earlier versions, where unset was the empty string
+fn stored_address(address: String) -> Option<Addr> {
+    Some(address).filter(|address| !address.is_empty()).map(Addr::unchecked)
+}
+
+fn deserialize_stored_address<'de, D: Deserializer<'de>>(
+    deserializer: D
+) -> Result<Option<Addr>, D::Error> {
+    Ok(Option::<String>::deserialize(deserializer)?.and_then(stored_address))
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn instantiate(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    msg: InstantiateMsg
+) -> Result<Response, ContractError> {
+    msg.validate(deps.api)?;
+    cw2::set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+
+    let minter = match &msg.deployment_config.minter {
+        Some(minter) => validate_address(deps.api, "minter", minter)?,
+        None => info.sender.clone(),
+    };
+
+    // Initialize the CW721 contract info
+    let cw721_msg = Cw721InstantiateMsg {
+        name: msg.deployment_config.name.clone(),
+        symbol: msg.deployment_config.symbol.to_uppercase(),
+        minter: minter.to_string(),
+    };
+    let cw721_response = Cw721Contract::<Empty, Empty, Empty, Empty>
+        ::default()
+        .instantiate(deps.branch(), env, info.clone(), cw721_msg)?;
+
+    // Initialize the collection state
+    let mut state = State::new(
+        deps.api,
+        info.sender.clone(),
+        &msg.deployment_config,
+        &msg.runtime_config
+    )?;
+    // the factory fee goes to the deployer, wherever the ownership moves later
+    if state.factory_fee_bps > 0 {
+        state.factory_address = Some(info.sender.clone());
+    }
+    STATE.save(deps.storage, &state)?;
+
+    Ok(cw721_response.add_attribute("action", "instantiate"))
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn execute(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    msg: ExecuteMsg
+) -> Result<Response, ContractError> {
+    match msg {
+        ExecuteMsg::IssueClass {
+            name,
+            symbol,
+            description,
+            uri,
+            uri_hash,
+            data,
+            features,
+            royalty_rate,
+        } => {
+            let state = STATE.load(deps.storage)?;
+            assert_owner(&state, &info.sender)?;
+            uri_hash.as_deref().map(validate_uri_hash).transpose()?;
+
+            let class_id = format!("{}-{}", symbol.to_lowercase(), env.contract.address);
+            if CLASSES.has(deps.storage, &class_id) {
+                return Err(ContractError::ClassExists { class_id });
+            }
+            CLASSES.save(
+                deps.storage,
+                &class_id,
+                &(ClassInfo {
+                    name,
+                    symbol,
+                    description,
+                    uri,
+                    uri_hash,
+                    data,
+                    features: features.unwrap_or_default(),
+                    royalty_rate,
+                })
+            )?;
+
+            Ok(
+                Response::new().add_attributes(
+                    vec![attr("action", "issue_class"), attr("class_id", class_id)]
+                )
+            )
+        }
+        ExecuteMsg::Mint { class_id: _, id, uri, uri_hash, data } => {
+            let mut state = STATE.load(deps.storage)?;
+            assert_minter(deps.storage, &info.sender)?;
+            if let Some(uri) = &uri {
+                check_uri_len("uri", uri, state.max_uri_len)?;
+            }
+            uri_hash.as_deref().map(validate_uri_hash).transpose()?;
+            let extension = data
+                .map(|data| {
+                    from_binary::<Metadata>(&data).map_err(|_| {
+                        ContractError::invalid_config("data", "must hold the token metadata")
+                    })
+                })
+                .transpose()?;
+
+            if tokens().has(deps.storage, &id) {
+                return Err(ContractError::TokenExists { token_id: id });
+            }
+            assert_recipient_allowed(deps.storage, &info.sender)?;
+            state.check_holding_cap(deps.storage, &info.sender, 1)?;
+            let token = TokenRecord {
+                uri_hash,
+                extension,
+                ..TokenRecord::new(info.sender.clone(), uri)
+            };
+            tokens().save(deps.storage, &id, &token)?;
+            checkpoint_rewards(deps.storage, &id)?;
+            add_holding(deps.storage, &info.sender)?;
+            state.minted_count += 1;
+            STATE.save(deps.storage, &state)?;
+
+            let hooks = transfer_hook_msgs(
+                deps.storage,
+                "mint",
+                &[id.clone()],
+                None,
+                Some(&info.sender)
+            )?;
+            Ok(
+                Response::new()
+                    .add_submessages(hooks)
+                    .add_attributes(events::mint(info.sender.as_str(), info.sender.as_str(), &id))
+            )
+        }
+        ExecuteMsg::Burn { class_id: _, id } => {
+            // Ensure that the sender owns the token
+            assert_token_owner(deps.storage, &id, &info.sender)?;
+            assert_not_bridged(deps.storage, &id)?;
+            assert_not_auctioned(deps.storage, &id)?;
+
+            let mut state = STATE.load(deps.storage)?;
+            burn_token(deps.storage, &mut state, &id)?;
+            let reward = state.take_burn_reward(1)?;
+            STATE.save(deps.storage, &state)?;
+
+            let hooks = transfer_hook_msgs(
+                deps.storage,
+                "burn",
+                &[id.clone()],
+                Some(&info.sender),
+                None
+            )?;
+            Ok(
+                Response::new()
+                    .add_messages(burn_reward_payout(&info.sender, reward))
+                    .add_submessages(hooks)
+                    .add_attributes(events::burn(info.sender.as_str(), &id))
+            )
+        }
+        ExecuteMsg::BurnBatch { class_id: _, ids } => {
+            // Verify every id before burning any of them
+            let mut seen: Vec<&String> = Vec::with_capacity(ids.len());
+            for id in &ids {
+                if seen.contains(&id) {
+                    return Err(ContractError::DuplicateTokenId { token_id: id.clone() });
+                }
+                assert_token_owner(deps.storage, id, &info.sender)?;
+                assert_not_bridged(deps.storage, id)?;
+                assert_not_auctioned(deps.storage, id)?;
+                seen.push(id);
+            }
+
+            let mut state = STATE.load(deps.storage)?;
+            for id in &ids {
+                burn_token(deps.storage, &mut state, id)?;
+            }
+            let reward = state.take_burn_reward(ids.len() as u64)?;
+            STATE.save(deps.storage, &state)?;
+
+            let hooks = transfer_hook_msgs(deps.storage, "burn", &ids, Some(&info.sender), None)?;
+            Ok(
+                Response::new()
+                    .add_messages(burn_reward_payout(&info.sender, reward))
+                    .add_submessages(hooks)
+                    .add_attributes(
+                        events::batch("burn_batch", vec![attr("sender", info.sender)], &ids)
+                    )
+            )
+        }
+        ExecuteMsg::AddToBlacklist { address } => execute_blacklist(deps, info, address, true),
+        ExecuteMsg::AddToBlocklist { address } => execute_blocklist(deps, info, address, true),
+        ExecuteMsg::RemoveFromBlocklist { address } => {
+            execute_blocklist(deps, info, address, false)
+        }
+        ExecuteMsg::RemoveFromBlacklist { address } => {
+            execute_blacklist(deps, info, address, false)
+        }
+        ExecuteMsg::RegisterTransferHook { contract } => {
+            let state = STATE.load(deps.storage)?;
+            assert_owner(&state, &info.sender)?;
+
+            let contract = validate_address(deps.api, "contract", &contract)?;
+            let is_new = !TRANSFER_HOOKS.has(deps.storage, &contract);
+            let registered = TRANSFER_HOOKS.keys(deps.storage, None, None, Order::Ascending).count();
+            if is_new && registered >= MAX_TRANSFER_HOOKS {
+                return Err(ContractError::TooManyTransferHooks { max: MAX_TRANSFER_HOOKS });
+            }
+            TRANSFER_HOOKS.save(deps.storage, &contract, &Empty {})?;
+
+            Ok(
+                Response::new().add_attributes(
+                    vec![attr("action", "register_transfer_hook"), attr("contract", contract)]
+                )
+            )
+        }
+        ExecuteMsg::RemoveTransferHook { contract } => {
+            let state = STATE.load(deps.storage)?;
+            assert_owner(&state, &info.sender)?;
+
+            let contract = validate_address(deps.api, "contract", &contract)?;
+            TRANSFER_HOOKS.remove(deps.storage, &contract);
+
+            Ok(
+                Response::new().add_attributes(
+                    vec![attr("action", "remove_transfer_hook"), attr("contract", contract)]
+                )
+            )
+        }
+        ExecuteMsg::SetHoldingCapExemption { address, exempt } => {
+            let state = STATE.load(deps.storage)?;
+            assert_owner(&state, &info.sender)?;
+
+            let account = validate_address(deps.api, "address", &address)?;
+            if exempt {
+                HOLDING_CAP_EXEMPT.save(deps.storage, &account, &Empty {})?;
+            } else {
+                HOLDING_CAP_EXEMPT.remove(deps.storage, &account);
+            }
+
+            Ok(
+                Response::new().add_attributes(
+                    vec![
+                        attr("action", "set_holding_cap_exemption"),
+                        attr("account", account),
+                        attr("exempt", exempt.to_string())
+                    ]
+                )
+            )
+        }
+        ExecuteMsg::AddToWhitelist { class_id: _, id: _, account, allocation } => {
+            execute_add_to_whitelist(deps, env, info, account, allocation)
+        }
+        ExecuteMsg::RemoveFromWhitelist { class_id: _, id: _, account } => {
+            let account = validate_address(deps.api, "account", &account)?;
+            execute_whitelist(deps, env, info, account.into_string(), false)
+        }
+        ExecuteMsg::Whitelist { address, status } => {
+            execute_whitelist(deps, env, info, address, status)
+        }
+        ExecuteMsg::WhitelistBatch { addresses, status } => {
+            execute_whitelist_batch(deps, env, info, addresses, status)
+        }
+        ExecuteMsg::Purchase {
+            count,
+            proof,
+            max_price_per_token,
+            recipient,
+            referrer,
+            allowlist_index,
+        } => {
+            let mut state = STATE.load(deps.storage)?;
+            state.check_count(count)?;
+
+            // Attached funds are credited to the buyer before the price is taken
+            let paid = must_pay(&info, &state.mint_denom)?;
+            credit_balance(deps.storage, &info.sender, &state.mint_denom, paid)?;
+
+            let recipient = match recipient {
+                Some(recipient) => validate_address(deps.api, "recipient", &recipient)?,
+                None => info.sender.clone(),
+            };
+            let referrer = referrer
+                .map(|referrer| validate_address(deps.api, "referrer", &referrer))
+                .transpose()?;
+            let response = state.purchase(deps.storage, &env, PurchaseOrder {
+                payer: &info.sender,
+                recipient: &recipient,
+                count,
+                proof: proof.as_deref(),
+                max_price_per_token,
+                referrer: referrer.as_ref(),
+                voucher: false,
+                allowlist_index,
+            })?;
+            STATE.save(deps.storage, &state)?;
+            Ok(response)
+        }
+        ExecuteMsg::CommitMint { commitment, proof, allowlist_index } => {
+            commit_mint(deps, env, info, commitment, proof, allowlist_index)
+        }
+        ExecuteMsg::RevealMint { salt, count, proof, allowlist_index } => {
+            let mut state = STATE.load(deps.storage)?;
+            let order = PurchaseOrder {
+                payer: &info.sender,
+                recipient: &info.sender,
+                count,
+                proof: proof.as_deref(),
+                max_price_per_token: None,
+                referrer: None,
+                voucher: false,
+                allowlist_index,
+            };
+            let response = state.reveal_mint(deps.storage, &env, order, salt)?;
+            STATE.save(deps.storage, &state)?;
+            Ok(response)
+        }
+        ExecuteMsg::CancelCommit {} => cancel_commit(deps, env, info),
+        ExecuteMsg::Freeze { class_id: _, id } => {
+            // Ensure that the sender is the contract owner
+            let state = STATE.load(deps.storage)?;
+            assert_owner(&state, &info.sender)?;
+
+            freeze_token(deps.storage, &id)?;
+
+            Ok(Response::new().add_attributes(events::freeze(&id)))
+        }
+        ExecuteMsg::SetProtocolAddress { address } => {
+            let mut state = STATE.load(deps.storage)?;
+            assert_owner(&state, &info.sender)?;
+            // once set, the fee destination is up to the protocol
+            if state.protocol_address.is_some() {
+                return Err(ContractError::unauthorized("protocol"));
+            }
+
+            let protocol_address = validate_address(deps.api, "address", &address)?;
+            state.protocol_address = Some(protocol_address.clone());
+            STATE.save(deps.storage, &state)?;
+
+            Ok(
+                Response::new().add_attributes(
+                    vec![
+                        attr("action", "set_protocol_address"),
+                        attr("protocol_address", protocol_address)
+                    ]
+                )
+            )
+        }
+        ExecuteMsg::UpdateProtocolAddress { address } => {
+            let mut state = STATE.load(deps.storage)?;
+            if state.protocol_address.as_ref() != Some(&info.sender) {
+                return Err(ContractError::unauthorized("protocol"));
+            }
+
+            let protocol_address = validate_address(deps.api, "address", &address)?;
+            state.protocol_address = Some(protocol_address.clone());
+            STATE.save(deps.storage, &state)?;
+
+            Ok(
+                Response::new().add_attributes(
+                    vec![
+                        attr("action", "update_protocol_address"),
+                        attr("previous", info.sender),
+                        attr("protocol_address", protocol_address)
+                    ]
+                )
+            )
+        }
+        ExecuteMsg::LockToken { token_id, until } => {
+            let state = STATE.load(deps.storage)?;
+            assert_owner(&state, &info.sender)?;
+
+            let mut token = load_token(deps.storage, &token_id)?;
+            token.locked_until = Some(until);
+            tokens().save(deps.storage, &token_id, &token)?;
+
+            Ok(
+                Response::new().add_attributes(
+                    vec![
+                        attr("action", "lock_token"),
+                        attr("token_id", token_id),
+                        attr("until", until.to_string())
+                    ]
+                )
+            )
+        }
+        ExecuteMsg::LockUntil { token_id, unlock_time } => {
+            lock_until(deps, env, info, token_id, unlock_time)
+        }
+        ExecuteMsg::ReceiveNft(msg) => receive_nft(deps, info, msg),
+        ExecuteMsg::Receive(msg) => {
+            match from_binary(&msg.msg)? {
+                ReceiveMsg::FundRewards {} => {
+                    let sender = validate_address(deps.api, "sender", &msg.sender)?;
+                    let asset = RewardAsset::Cw20(info.sender);
+                    fund_rewards(deps, &sender, asset, msg.amount)
+                }
+            }
+        }
+        ExecuteMsg::FundRewards {} => {
+            let coin = one_coin(&info)?;
+            fund_rewards(deps, &info.sender, RewardAsset::Native(coin.denom), coin.amount)
+        }
+        ExecuteMsg::ClaimRewards { token_ids } => claim_rewards(deps, info, token_ids),
+        ExecuteMsg::TransferNft { recipient, token_id, sale_price } => {
+            transfer_nft(deps, env, info, recipient, token_id, sale_price)
+        }
+        ExecuteMsg::BatchTransfer { transfers } => batch_transfer(deps, env, info, transfers),
+        ExecuteMsg::SendNft { contract, token_id, msg } => {
+            send_nft(deps, env, info, contract, token_id, msg)
+        }
+        ExecuteMsg::Approve { spender, token_id, expires } => {
+            approve(deps, env, info, spender, token_id, expires)
+        }
+        ExecuteMsg::Revoke { spender, token_id } => revoke(deps, info, spender, token_id),
+        ExecuteMsg::SetUser { token_id, user, expires } => {
+            let state = STATE.load(deps.storage)?;
+            if state.users_disabled {
+                return Err(ContractError::UsersDisabled {});
+            }
+
+            let mut token = load_token(deps.storage, &token_id)?;
+            assert_owner_or_approved(&token, &info.sender, &env.block)?;
+            let user = validate_address(deps.api, "user", &user)?;
+            token.user = Some(TokenUser { user: user.clone(), expires });
+            tokens().save(deps.storage, &token_id, &token)?;
+
+            Ok(
+                Response::new().add_attributes(
+                    vec![
+                        attr("action", "set_user"),
+                        attr("token_id", token_id),
+                        attr("user", user),
+                        attr("expires", expires.to_string())
+                    ]
+                )
+            )
+        }
+        ExecuteMsg::List { token_id, price } => list(deps, env, info, token_id, price),
+        ExecuteMsg::CancelListing { token_id } => {
+            let listing = LISTINGS.may_load(deps.storage, &token_id)?.ok_or_else(|| {
+                ContractError::TokenNotListed { token_id: token_id.clone() }
+            })?;
+            if listing.seller != info.sender {
+                return Err(ContractError::unauthorized("seller"));
+            }
+            LISTINGS.remove(deps.storage, &token_id);
+
+            Ok(
+                Response::new().add_attributes(
+                    vec![attr("action", "cancel_listing"), attr("token_id", token_id)]
+                )
+            )
+        }
+        ExecuteMsg::Buy { token_id } => buy(deps, env, info, token_id),
+        ExecuteMsg::MakeOffer { token_id, expires } => {
+            make_offer(deps, env, info, token_id, expires)
+        }
+        ExecuteMsg::WithdrawOffer { token_id } => {
+            let offer = offers()
+                .may_load(deps.storage, (&token_id, &info.sender))?
+                .ok_or_else(|| ContractError::NoOffer {
+                    token_id: token_id.clone(),
+                    bidder: info.sender.to_string(),
+                })?;
+            offers().remove(deps.storage, (&token_id, &info.sender))?;
+
+            Ok(
+                Response::new()
+                    .add_message(BankMsg::Send {
+                        to_address: info.sender.to_string(),
+                        amount: vec![offer.price.clone()],
+                    })
+                    .add_attributes(
+                        vec![
+                            attr("action", "withdraw_offer"),
+                            attr("bidder", info.sender),
+                            attr("token_id", token_id),
+                            attr("price", offer.price.to_string())
+                        ]
+                    )
+            )
+        }
+        ExecuteMsg::AcceptOffer { token_id, bidder } => {
+            accept_offer(deps, env, info, token_id, bidder)
+        }
+        ExecuteMsg::StartAuction { token_id, reserve_price, duration, min_bid_increment } => {
+            start_auction(deps, env, info, token_id, reserve_price, duration, min_bid_increment)
+        }
+        ExecuteMsg::Bid { token_id } => bid(deps, env, info, token_id),
+        ExecuteMsg::Settle { token_id } => settle(deps, env, token_id),
+        ExecuteMsg::IbcTransfer { token_id, channel, receiver, timeout } => {
+            ibc_transfer(deps, env, info, token_id, channel, receiver, timeout)
+        }
+        ExecuteMsg::IbcCallback { token_id, outcome } => ibc_callback(deps, info, token_id, outcome),
+        ExecuteMsg::SetIcs721Address { address } => {
+            let mut state = STATE.load(deps.storage)?;
+            assert_owner(&state, &info.sender)?;
+
+            let ics721_address = validate_address(deps.api, "address", &address)?;
+            state.ics721_address = Some(ics721_address.clone());
+            STATE.save(deps.storage, &state)?;
+
+            Ok(
+                Response::new().add_attributes(
+                    vec![
+                        attr("action", "set_ics721_address"),
+                        attr("ics721_address", ics721_address)
+                    ]
+                )
+            )
+        }
+        ExecuteMsg::SetContractUri { contract_uri } => {
+            let mut state = STATE.load(deps.storage)?;
+            assert_owner(&state, &info.sender)?;
+            if state.metadata_frozen {
+                return Err(ContractError::MetadataFrozen {});
+            }
+
+            state.contract_uri = Some(contract_uri.clone());
+            STATE.save(deps.storage, &state)?;
+
+            Ok(
+                Response::new().add_attributes(
+                    vec![attr("action", "set_contract_uri"), attr("contract_uri", contract_uri)]
+                )
+            )
+        }
+        ExecuteMsg::SetBaseUri { base_token_uri } => {
+            let mut state = STATE.load(deps.storage)?;
+            assert_owner(&state, &info.sender)?;
+            if state.metadata_frozen {
+                return Err(ContractError::MetadataFrozen {});
+            }
+            check_uri_len("base_token_uri", &base_token_uri, state.max_uri_len)?;
+
+            state.base_token_uri = base_token_uri.clone();
+            STATE.save(deps.storage, &state)?;
+
+            Ok(
+                Response::new().add_attributes(
+                    vec![attr("action", "set_base_uri"), attr("base_token_uri", base_token_uri)]
+                )
+            )
+        }
+        ExecuteMsg::ProposeBaseUriChange { new_uri, effective_after } => {
+            let state = STATE.load(deps.storage)?;
+            assert_owner(&state, &info.sender)?;
+            if state.metadata_frozen {
+                return Err(ContractError::MetadataFrozen {});
+            }
+            check_uri_len("new_uri", &new_uri, state.max_uri_len)?;
+            if effective_after <= env.block.time.seconds() {
+                return Err(ContractError::InvalidUriChangeTime { effective_after });
+            }
+
+            let change = UriChange {
+                new_uri: new_uri.clone(),
+                effective_after,
+                proposed_by: info.sender,
+            };
+            PENDING_URI_CHANGE.save(deps.storage, &change)?;
+
+            Ok(
+                Response::new().add_attributes(
+                    vec![
+                        attr("action", "propose_base_uri_change"),
+                        attr("new_uri", new_uri),
+                        attr("effective_after", effective_after.to_string())
+                    ]
+                )
+            )
+        }
+        ExecuteMsg::ApplyBaseUriChange {} => {
+            let mut state = STATE.load(deps.storage)?;
+            assert_owner(&state, &info.sender)?;
+            // a freeze made during the window still stops the change
+            if state.metadata_frozen {
+                return Err(ContractError::MetadataFrozen {});
+            }
+            let change = PENDING_URI_CHANGE.may_load(deps.storage)?.ok_or(
+                ContractError::NoPendingUriChange {}
+            )?;
+            let now = env.block.time.seconds();
+            if now <= change.effective_after {
+                return Err(ContractError::UriChangeNotDue {
+                    effective_after: change.effective_after,
+                    now,
+                });
+            }
+
+            PENDING_URI_CHANGE.remove(deps.storage);
+            state.base_token_uri = change.new_uri.clone();
+            STATE.save(deps.storage, &state)?;
+
+            Ok(
+                Response::new().add_attributes(
+                    vec![
+                        attr("action", "apply_base_uri_change"),
+                        attr("base_token_uri", change.new_uri)
+                    ]
+                )
+            )
+        }
+        ExecuteMsg::VetoUriChange {} => {
+            let state = STATE.load(deps.storage)?;
+            let change = PENDING_URI_CHANGE.may_load(deps.storage)?.ok_or(
+                ContractError::NoPendingUriChange {}
+            )?;
+            if env.block.time.seconds() > change.effective_after {
+                return Err(ContractError::VetoWindowClosed {
+                    effective_after: change.effective_after,
+                });
+            }
+            let held = HOLDER_BALANCES.may_load(deps.storage, &info.sender)?.unwrap_or_default();
+            if held == 0 || held < state.uri_veto_threshold {
+                return Err(ContractError::unauthorized("token holder"));
+            }
+
+            PENDING_URI_CHANGE.remove(deps.storage);
+
+            Ok(
+                Response::new().add_attributes(
+                    vec![
+                        attr("action", "veto_uri_change"),
+                        attr("vetoed_by", info.sender),
+                        attr("new_uri", change.new_uri)
+                    ]
+                )
+            )
+        }
+        ExecuteMsg::SetMetadataResolver { resolver } => {
+            let mut state = STATE.load(deps.storage)?;
+            assert_owner(&state, &info.sender)?;
+            if state.metadata_frozen {
+                return Err(ContractError::MetadataFrozen {});
+            }
+
+            state.metadata_resolver = resolver
+                .map(|resolver| validate_address(deps.api, "resolver", &resolver))
+                .transpose()?;
+            STATE.save(deps.storage, &state)?;
+
+            let resolver = state.metadata_resolver.map(String::from);
+            Ok(
+                Response::new().add_attributes(
+                    vec![
+                        attr("action", "set_metadata_resolver"),
+                        attr("resolver", resolver.unwrap_or_else(|| "none".to_string()))
+                    ]
+                )
+            )
+        }
+        ExecuteMsg::Reveal {} => {
+            let mut state = STATE.load(deps.storage)?;
+            assert_owner(&state, &info.sender)?;
+
+            state.uri_status = true;
+            STATE.save(deps.storage, &state)?;
+
+            Ok(Response::new().add_attribute("action", "reveal"))
+        }
+        ExecuteMsg::FreezeMetadata {} => {
+            let mut state = STATE.load(deps.storage)?;
+            assert_owner(&state, &info.sender)?;
+
+            state.metadata_frozen = true;
+            STATE.save(deps.storage, &state)?;
+
+            Ok(Response::new().add_attribute("action", "freeze_metadata"))
+        }
+        ExecuteMsg::FinalizeSupply {} => {
+            let mut state = STATE.load(deps.storage)?;
+            let now = env.block.time.seconds();
+            if state.phase(now) != SalePhase::Ended {
+                let ends_at = state.effective_sale_end();
+                return Err(ContractError::SaleNotEnded { ends_at, now });
+            }
+
+            if state.open_edition {
+                state.open_edition = false;
+                state.max_total_mint = state.current_token_id;
+                STATE.save(deps.storage, &state)?;
+            }
+
+            Ok(
+                Response::new().add_attributes(
+                    vec![
+                        attr("action", "finalize_supply"),
+                        attr("max_supply", state.max_total_mint.to_string())
+                    ]
+                )
+            )
+        }
+        ExecuteMsg::FinalizeSale {} => {
+            let mut state = STATE.load(deps.storage)?;
+            let response = state.finalize_sale(deps.storage, env.block.time.seconds())?;
+            STATE.save(deps.storage, &state)?;
+            Ok(response)
+        }
+        ExecuteMsg::ReleaseEscrow {} => {
+            let mut state = STATE.load(deps.storage)?;
+            if state.escrow_released {
+                return Err(ContractError::EscrowReleased {});
+            }
+            let sold = state.total_mints;
+            if !state.threshold_met(env.block.time.seconds())? {
+                return Err(ContractError::ThresholdNotMet { sold, threshold: state.min_threshold });
+            }
+
+            let proceeds = ESCROWED_PROCEEDS.range(deps.storage, None, None, Order::Ascending)
+                .collect::<StdResult<Vec<_>>>()?;
+            for ((addr, denom), amount) in proceeds {
+                ESCROWED_PROCEEDS.remove(deps.storage, (&addr, denom.as_str()));
+                credit_balance(deps.storage, &addr, &denom, amount)?;
+            }
+            state.escrow_released = true;
+            let forward = state.forward_proceeds(deps.storage)?;
+            STATE.save(deps.storage, &state)?;
+
+            Ok(
+                Response::new()
+                    .add_messages(forward)
+                    .add_attributes(
+                        vec![attr("action", "release_escrow"), attr("sold", sold.to_string())]
+                    )
+            )
+        }
+        ExecuteMsg::ClaimRefund {} => {
+            let mut state = STATE.load(deps.storage)?;
+            if state.threshold_met(env.block.time.seconds())? {
+                return Err(ContractError::ThresholdMet { threshold: state.min_threshold });
+            }
+
+            let payments = SALE_ESCROW.prefix(&info.sender)
+                .range(deps.storage, None, None, Order::Ascending)
+                .collect::<StdResult<Vec<_>>>()?;
+            if payments.is_empty() {
+                return Err(ContractError::NothingToRefund {});
+            }
+            let mut refund = Vec::with_capacity(payments.len());
+            for (denom, amount) in payments {
+                SALE_ESCROW.remove(deps.storage, (&info.sender, denom.as_str()));
+                refund.push(Coin { denom, amount });
+            }
+            let amount = refund.iter().map(Coin::to_string).collect::<Vec<_>>().join(",");
+
+            // The whole payment is refunded, so every bought token has to go back with it
+            let burned = ESCROWED_TOKENS.prefix(&info.sender)
+                .keys(deps.storage, None, None, Order::Ascending)
+                .collect::<StdResult<Vec<_>>>()?;
+            for token_id in &burned {
+                ESCROWED_TOKENS.remove(deps.storage, (&info.sender, token_id.as_str()));
+                assert_token_owner(deps.storage, token_id, &info.sender)?;
+                assert_not_bridged(deps.storage, token_id)?;
+                assert_not_auctioned(deps.storage, token_id)?;
+                burn_token(deps.storage, &mut state, token_id)?;
+            }
+            STATE.save(deps.storage, &state)?;
+
+            let hooks =
+                transfer_hook_msgs(deps.storage, "burn", &burned, Some(&info.sender), None)?;
+            Ok(
+                Response::new()
+                    .add_message(BankMsg::Send {
+                        to_address: info.sender.to_string(),
+                        amount: refund,
+                    })
+                    .add_submessages(hooks)
+                    .add_attributes(
+                        events::batch(
+                            "claim_refund",
+                            vec![attr("buyer", info.sender), attr("amount", amount)],
+                            &burned
+                        )
+                    )
+            )
+        }
+        ExecuteMsg::ReduceMaxSupply { new_max } => {
+            let mut state = STATE.load(deps.storage)?;
+            assert_owner(&state, &info.sender)?;
+
+            let old_max = state.reduce_max_supply(deps.storage, new_max)?;
+            STATE.save(deps.storage, &state)?;
+
+            Ok(
+                Response::new().add_attributes(
+                    vec![
+                        attr("action", "reduce_max_supply"),
+                        attr("old_max_supply", old_max.to_string()),
+                        attr("new_max_supply", new_max.to_string())
+                    ]
+                )
+            )
+        }
+        ExecuteMsg::FreezeSupply {} => {
+            let mut state = STATE.load(deps.storage)?;
+            assert_owner(&state, &info.sender)?;
+
+            state.supply_frozen = true;
+            STATE.save(deps.storage, &state)?;
+
+            Ok(Response::new().add_attribute("action", "freeze_supply"))
+        }
+        ExecuteMsg::SetSaleWindow { start, end } => {
+            let mut state = STATE.load(deps.storage)?;
+            assert_owner(&state, &info.sender)?;
+            state.assert_schedule_unlocked(env.block.time.seconds())?;
+
+            state.sale_start_time = start;
+            state.sale_end_time = end;
+            state.validate_schedule()?;
+            STATE.save(deps.storage, &state)?;
+
+            Ok(
+                Response::new().add_attributes(
+                    vec![
+                        attr("action", "set_sale_window"),
+                        attr("start", start.to_string()),
+                        attr("end", end.to_string())
+                    ]
+                )
+            )
+        }
+        ExecuteMsg::SetPresaleWindow { start, end } => {
+            let mut state = STATE.load(deps.storage)?;
+            assert_owner(&state, &info.sender)?;
+            state.assert_schedule_unlocked(env.block.time.seconds())?;
+
+            state.presale_start_time = start;
+            state.presale_end_time = end;
+            state.validate_schedule()?;
+            STATE.save(deps.storage, &state)?;
+
+            Ok(
+                Response::new().add_attributes(
+                    vec![
+                        attr("action", "set_presale_window"),
+                        attr("start", start.to_string()),
+                        attr("end", end.to_string())
+                    ]
+                )
+            )
+        }
+        ExecuteMsg::SetPhaseRoot { tier, root } => {
+            let state = STATE.load(deps.storage)?;
+            assert_owner(&state, &info.sender)?;
+
+            if root.len() != 32 {
+                return Err(ContractError::InvalidMerkleRoot { length: root.len() });
+            }
+            PHASE_ROOTS.save(deps.storage, tier, &root)?;
+
+            Ok(
+                Response::new().add_attributes(
+                    vec![
+                        attr("action", "set_phase_root"),
+                        attr("tier", tier.to_string()),
+                        attr("root", root.to_base64())
+                    ]
+                )
+            )
+        }
+        ExecuteMsg::SetActiveTier { tier } => {
+            let mut state = STATE.load(deps.storage)?;
+            assert_owner(&state, &info.sender)?;
+
+            state.active_tier = tier;
+            STATE.save(deps.storage, &state)?;
+
+            Ok(
+                Response::new().add_attributes(
+                    vec![
+                        attr("action", "set_active_tier"),
+                        attr(
+                            "tier",
+                            tier.map(|tier| tier.to_string()).unwrap_or_else(|| "none".to_string())
+                        )
+                    ]
+                )
+            )
+        }
+        ExecuteMsg::SetAllowlistBit { index, value } => {
+            let state = STATE.load(deps.storage)?;
+            assert_owner(&state, &info.sender)?;
+
+            set_allowlist_bits(deps.storage, index, 1, value)?;
+
+            Ok(
+                Response::new().add_attributes(
+                    vec![
+                        attr("action", "set_allowlist_bit"),
+                        attr("index", index.to_string()),
+                        attr("value", value.to_string())
+                    ]
+                )
+            )
+        }
+        ExecuteMsg::SetAllowlistRange { start, count, value } => {
+            let state = STATE.load(deps.storage)?;
+            assert_owner(&state, &info.sender)?;
+
+            let in_bounds = u64::from(start) + u64::from(count) <= u64::from(u32::MAX) + 1;
+            if count == 0 || count > MAX_ALLOWLIST_RANGE || !in_bounds {
+                return Err(ContractError::InvalidAllowlistRange { start, count });
+            }
+            set_allowlist_bits(deps.storage, start, count, value)?;
+
+            Ok(
+                Response::new().add_attributes(
+                    vec![
+                        attr("action", "set_allowlist_range"),
+                        attr("start", start.to_string()),
+                        attr("count", count.to_string()),
+                        attr("value", value.to_string())
+                    ]
+                )
+            )
+        }
+        ExecuteMsg::SetVoucherSigner { pubkey, scheme } => {
+            let mut state = STATE.load(deps.storage)?;
+            assert_owner(&state, &info.sender)?;
+
+            let valid_length = match scheme {
+                SignatureScheme::Secp256k1 => matches!(pubkey.len(), 33 | 65),
+                SignatureScheme::Ed25519 => pubkey.len() == 32,
+            };
+            if !valid_length {
+                return Err(
+                    ContractError::invalid_config("signer_pubkey", "wrong length for the scheme")
+                );
+            }
+            state.signer_pubkey = pubkey;
+            state.signature_scheme = scheme;
+            STATE.save(deps.storage, &state)?;
+
+            Ok(
+                Response::new().add_attributes(
+                    vec![
+                        attr("action", "set_voucher_signer"),
+                        attr("pubkey", state.signer_pubkey.to_base64()),
+                        attr("scheme", format!("{:?}", scheme))
+                    ]
+                )
+            )
+        }
+        ExecuteMsg::SetProceedsHook { contract, msg } => {
+            let mut state = STATE.load(deps.storage)?;
+            assert_owner(&state, &info.sender)?;
+
+            let hook = contract
+                .map(|contract| validate_address(deps.api, "contract", &contract))
+                .transpose()?;
+            if hook.is_some() && msg.is_empty() {
+                return Err(
+                    ContractError::invalid_config("proceeds_hook_msg", "must be set with a hook")
+                );
+            }
+            state.proceeds_hook = hook;
+            state.proceeds_hook_msg = msg;
+            STATE.save(deps.storage, &state)?;
+
+            let contract = state.proceeds_hook.map(String::from);
+            Ok(
+                Response::new().add_attributes(
+                    vec![
+                        attr("action", "set_proceeds_hook"),
+                        attr("contract", contract.unwrap_or_else(|| "none".to_string()))
+                    ]
+                )
+            )
+        }
+        ExecuteMsg::SetBurnReward { reward } => {
+            let mut state = STATE.load(deps.storage)?;
+            assert_owner(&state, &info.sender)?;
+
+            let denom = |reward: &Option<Coin>| reward.as_ref().map(|coin| coin.denom.clone());
+            if !state.reward_pool.is_zero() && denom(&reward) != denom(&state.burn_reward) {
+                return Err(
+                    ContractError::invalid_config("burn_reward", "must keep the funded pool denom")
+                );
+            }
+            state.burn_reward = reward;
+            STATE.save(deps.storage, &state)?;
+
+            let reward = state.burn_reward.map(|coin| coin.to_string());
+            Ok(
+                Response::new().add_attributes(
+                    vec![
+                        attr("action", "set_burn_reward"),
+                        attr("reward", reward.unwrap_or_else(|| "none".to_string()))
+                    ]
+                )
+            )
+        }
+        ExecuteMsg::SetMintDenom { denom } => {
+            let mut state = STATE.load(deps.storage)?;
+            assert_owner(&state, &info.sender)?;
+
+            if denom.is_empty() {
+                return Err(ContractError::invalid_config("mint_denom", "must not be empty"));
+            }
+            state.mint_denom = denom;
+            STATE.save(deps.storage, &state)?;
+
+            Ok(
+                Response::new().add_attributes(
+                    vec![attr("action", "set_mint_denom"), attr("denom", state.mint_denom)]
+                )
+            )
+        }
+        ExecuteMsg::Withdraw { denom } => {
+            let amount = get_balance(deps.storage, &info.sender, &denom)?;
+            if amount.is_zero() {
+                return Err(ContractError::NothingToWithdraw { denom });
+            }
+            debit_balance(deps.storage, &info.sender, &denom, amount)?;
+
+            let payout = Coin { denom, amount };
+            Ok(
+                Response::new()
+                    .add_message(BankMsg::Send {
+                        to_address: info.sender.to_string(),
+                        amount: vec![payout.clone()],
+                    })
+                    .add_attributes(
+                        vec![
+                            attr("action", "withdraw"),
+                            attr("address", info.sender),
+                            attr("amount", payout.to_string())
+                        ]
+                    )
+            )
+        }
+        ExecuteMsg::FundRewardPool {} => {
+            let mut state = STATE.load(deps.storage)?;
+            assert_owner(&state, &info.sender)?;
+
+            let denom = match &state.burn_reward {
+                Some(reward) => reward.denom.clone(),
+                None => {
+                    return Err(ContractError::invalid_config("burn_reward", "must be set"));
+                }
+            };
+            let amount = must_pay(&info, &denom)?;
+            state.reward_pool = state.reward_pool.checked_add(amount)?;
+            STATE.save(deps.storage, &state)?;
+
+            Ok(
+                Response::new().add_attributes(
+                    vec![
+                        attr("action", "fund_reward_pool"),
+                        attr("amount", amount),
+                        attr("pool", state.reward_pool)
+                    ]
+                )
+            )
+        }
+        ExecuteMsg::RedeemVoucher { count, signature } => {
+            let mut state = STATE.load(deps.storage)?;
+            verify_voucher(deps.api, &state, &env.contract.address, &info.sender, &signature)?;
+            state.check_count(count)?;
+
+            let paid = must_pay(&info, &state.mint_denom)?;
+            credit_balance(deps.storage, &info.sender, &state.mint_denom, paid)?;
+
+            let response = state.purchase(deps.storage, &env, PurchaseOrder {
+                payer: &info.sender,
+                recipient: &info.sender,
+                count,
+                proof: None,
+                max_price_per_token: None,
+                referrer: None,
+                voucher: true,
+                allowlist_index: None,
+            })?;
+            STATE.save(deps.storage, &state)?;
+            Ok(response)
+        }
+        ExecuteMsg::Unfreeze { class_id: _, id } => {
+            // Ensure that the sender is the contract owner
+            let state = STATE.load(deps.storage)?;
+            assert_owner(&state, &info.sender)?;
+
+            unfreeze_token(deps.storage, &id)?;
+
+            Ok(Response::new().add_attributes(events::unfreeze(&id)))
+        }
+    }
+}
+
+fn execute_whitelist(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    address: String,
+    status: bool
+) -> Result<Response, ContractError> {
+    // Ensure that the sender is the contract owner
+    let state = STATE.load(deps.storage)?;
+    assert_owner(&state, &info.sender)?;
+    if !status {
+        state.assert_whitelist_unlocked(env.block.time.seconds())?;
+    }
+
+    let account = validate_address(deps.api, "address", &address)?;
+    whitelist(deps.storage, &account, status)?;
+
+    let event_type = if status { "whitelist_add" } else { "whitelist_remove" };
+    Ok(
+        Response::new().add_attributes(
+            vec![attr("action", "whitelist"), attr("type", event_type), attr("account", account)]
+        )
+    )
+}
+
+fn execute_add_to_whitelist(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    account: String,
+    allocation: Option<u64>
+) -> Result<Response, ContractError> {
+    let account = validate_address(deps.api, "account", &account)?;
+    let res = execute_whitelist(deps.branch(), env, info, account.to_string(), true)?;
+    match allocation {
+        Some(allocation) => {
+            WHITELIST_ALLOCATIONS.save(deps.storage, &account, &allocation)?;
+            Ok(res.add_attribute("allocation", allocation.to_string()))
+        }
+        None => {
+            WHITELIST_ALLOCATIONS.remove(deps.storage, &account);
+            Ok(res)
+        }
+    }
+}
+
+fn execute_whitelist_batch(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    addresses: Vec<String>,
+    status: bool
+) -> Result<Response, ContractError> {
+    let state = STATE.load(deps.storage)?;
+    assert_owner(&state, &info.sender)?;
+    if !status {
+        state.assert_whitelist_unlocked(env.block.time.seconds())?;
+    }
+
+    // every address is validated before any is stored
+    let accounts = addresses
+        .iter()
+        .map(|address| validate_address(deps.api, "addresses", address))
+        .collect::<Result<Vec<_>, _>>()?;
+    for account in &accounts {
+        whitelist(deps.storage, account, status)?;
+    }
+
+    let event_type = if status { "whitelist_add" } else { "whitelist_remove" };
+    Ok(
+        Response::new().add_attributes(
+            vec![
+                attr("action", "whitelist_batch"),
+                attr("type", event_type),
+                attr("count", accounts.len().to_string())
+            ]
+        )
+    )
+}
+
+fn execute_blacklist(
+    deps: DepsMut,
+    info: MessageInfo,
+    address: String,
+    status: bool
+) -> Result<Response, ContractError> {
+    let state = STATE.load(deps.storage)?;
+    assert_owner(&state, &info.sender)?;
+
+    let account = validate_address(deps.api, "address", &address)?;
+    if status {
+        BLACKLIST.save(deps.storage, &account, &Empty {})?;
+    } else {
+        BLACKLIST.remove(deps.storage, &account);
+    }
+
+    let event_type = if status { "blacklist_add" } else { "blacklist_remove" };
+    Ok(
+        Response::new().add_attributes(
+            vec![attr("action", "blacklist"), attr("type", event_type), attr("account", account)]
+        )
+    )
+}
+
+fn execute_blocklist(
+    deps: DepsMut,
+    info: MessageInfo,
+    address: String,
+    status: bool
+) -> Result<Response, ContractError> {
+    let state = STATE.load(deps.storage)?;
+    assert_owner(&state, &info.sender)?;
+
+    let account = validate_address(deps.api, "address", &address)?;
+    if status {
+        RECIPIENT_BLOCKLIST.save(deps.storage, &account, &Empty {})?;
+    } else {
+        RECIPIENT_BLOCKLIST.remove(deps.storage, &account);
+    }
+
+    let event_type = if status { "blocklist_add" } else { "blocklist_remove" };
+    Ok(
+        Response::new().add_attributes(
+            vec![attr("action", "blocklist"), attr("type", event_type), attr("account", account)]
+        )
+    )
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> Result<Binary, ContractError> {
+    match msg {
+        QueryMsg::FrozenTokens { start_after, limit } => {
+            Ok(to_binary(&query_frozen_tokens(deps, start_after, limit)?)?)
+        }
+        QueryMsg::Tokens { owner, start_after, limit } => {
+            Ok(to_binary(&query_tokens(deps, owner, start_after, limit)?)?)
+        }
+        QueryMsg::AllTokens { start_after, limit } => {
+            Ok(to_binary(&query_all_tokens(deps, start_after, limit)?)?)
+        }
+        QueryMsg::Schedule {} => Ok(to_binary(&query_schedule(deps, env)?)?),
+        QueryMsg::Config {} => Ok(to_binary(&query_config(deps, env)?)?),
+        QueryMsg::ContractInfo {} => Ok(to_binary(&query_contract_info(deps, env)?)?),
+        QueryMsg::ContractVersion {} => Ok(to_binary(&query_contract_version(deps)?)?),
+        QueryMsg::SaleStats {} => Ok(to_binary(&query_sale_stats(deps)?)?),
+        QueryMsg::SupplyInfo {} => Ok(to_binary(&query_supply_info(deps)?)?),
+        QueryMsg::NumTokens {} => {
+            let count = query_supply_info(deps)?.current_supply;
+            Ok(to_binary(&(NumTokensResponse { count }))?)
+        }
+        QueryMsg::Holders { start_after, limit } => {
+            Ok(to_binary(&query_holders(deps, start_after, limit)?)?)
+        }
+        QueryMsg::Whitelist { start_after, limit } => {
+            Ok(to_binary(&query_whitelist(deps, start_after, limit)?)?)
+        }
+        QueryMsg::EarlyBirds { start_after, limit } => {
+            Ok(to_binary(&query_early_birds(deps, start_after, limit)?)?)
+        }
+        QueryMsg::EarlyBirdRank { address } => {
+            let address = deps.api.addr_validate(&address)?;
+            let rank = EARLY_BIRDS.may_load(deps.storage, &address)?;
+            Ok(to_binary(&(EarlyBirdRankResponse { rank }))?)
+        }
+        QueryMsg::Hooks {} => Ok(to_binary(&query_hooks(deps)?)?),
+        QueryMsg::IsBlacklisted { address } => {
+            Ok(to_binary(&query_is_blacklisted(deps, address)?)?)
+        }
+        QueryMsg::IsBlocked { address } => {
+            let address = deps.api.addr_validate(&address)?;
+            let blocked = RECIPIENT_BLOCKLIST.has(deps.storage, &address);
+            Ok(to_binary(&(IsBlockedResponse { blocked }))?)
+        }
+        QueryMsg::PendingUriChange {} => {
+            let change = PENDING_URI_CHANGE.may_load(deps.storage)?;
+            Ok(to_binary(&(PendingUriChangeResponse { change }))?)
+        }
+        QueryMsg::Blacklist { start_after, limit } => {
+            Ok(to_binary(&query_blacklist(deps, start_after, limit)?)?)
+        }
+        QueryMsg::BalanceOf { owner } => Ok(to_binary(&query_balance_of(deps, owner)?)?),
+        QueryMsg::AccruedByDenom { address } => {
+            Ok(to_binary(&query_accrued_by_denom(deps, address)?)?)
+        }
+        QueryMsg::Listings { start_after, limit } => {
+            Ok(to_binary(&query_listings(deps, start_after, limit)?)?)
+        }
+        QueryMsg::OffersByToken { token_id, start_after, limit } => {
+            Ok(to_binary(&query_offers_by_token(deps, token_id, start_after, limit)?)?)
+        }
+        QueryMsg::OffersByBidder { bidder, start_after, limit } => {
+            Ok(to_binary(&query_offers_by_bidder(deps, bidder, start_after, limit)?)?)
+        }
+        QueryMsg::PendingRewards { token_id } => {
+            Ok(to_binary(&query_pending_rewards(deps, token_id)?)?)
+        }
+        QueryMsg::BurnReward {} => {
+            let state = STATE.load(deps.storage)?;
+            let res = BurnRewardResponse { reward: state.burn_reward, pool: state.reward_pool };
+            Ok(to_binary(&res)?)
+        }
+        QueryMsg::Auctions { start_after, limit } => {
+            Ok(to_binary(&query_auctions(deps, start_after, limit)?)?)
+        }
+        QueryMsg::Bridged { start_after, limit } => {
+            Ok(to_binary(&query_bridged(deps, start_after, limit)?)?)
+        }
+        QueryMsg::UserOf { token_id } => Ok(to_binary(&query_user_of(deps, env, token_id)?)?),
+        QueryMsg::TokenLock { token_id } => {
+            Ok(to_binary(&query_token_lock(deps, env, token_id)?)?)
+        }
+        QueryMsg::LockStatus { token_id } => {
+            Ok(to_binary(&query_lock_status(deps, env, token_id)?)?)
+        }
+        QueryMsg::OwnerOf { token_id } => Ok(to_binary(&query_owner_of(deps, env, token_id)?)?),
+        QueryMsg::QuotePurchase { buyer, count, promo_code } => {
+            Ok(to_binary(&query_quote_purchase(deps, env, buyer, count, promo_code)?)?)
+        }
+        QueryMsg::Phases {} => {
+            Ok(to_binary(&(PhasesResponse { phases: STATE.load(deps.storage)?.phases }))?)
+        }
+        QueryMsg::BuyerProfile { address } => {
+            Ok(to_binary(&query_buyer_profile(deps, env, address)?)?)
+        }
+        QueryMsg::ActivePhase {} => {
+            let state = STATE.load(deps.storage)?;
+            let phase = state.active_phase(env.block.time.seconds()).cloned();
+            Ok(to_binary(&(ActivePhaseResponse { phase }))?)
+        }
+        QueryMsg::ClassInfo { class_id } => {
+            Ok(to_binary(&CLASSES.load(deps.storage, &class_id)?)?)
+        }
+        QueryMsg::TokenUri { token_id } => {
+            Ok(to_binary(&query_token_uri(deps, env, token_id)?)?)
+        }
+        QueryMsg::NftInfo { token_id } => Ok(to_binary(&query_nft_info(deps, env, token_id)?)?),
+        QueryMsg::AllNftInfo { token_id, include_expired } => {
+            Ok(to_binary(&query_all_nft_info(deps, env, token_id, include_expired)?)?)
+        }
+        QueryMsg::BatchTokenMetadata { token_ids } => {
+            Ok(to_binary(&query_batch_token_metadata(deps, env, token_ids)?)?)
+        }
+        QueryMsg::SimulatePurchase { buyer, count, proof, allowlist_index } => {
+            let res = query_simulate_purchase(deps, env, buyer, count, proof, allowlist_index)?;
+            Ok(to_binary(&res)?)
+        }
+        QueryMsg::CanMint { buyer, count } => {
+            Ok(to_binary(&query_can_mint(deps, env, buyer, count)?)?)
+        }
+    }
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn reply(_deps: DepsMut, _env: Env, msg: Reply) -> Result<Response, ContractError> {
+    match (msg.id, msg.result) {
+        // hooks can't revert the operation they were notified of
+        (TRANSFER_HOOK_REPLY_ID, SubMsgResult::Err(error)) => {
+            Ok(
+                Response::new().add_attributes(
+                    vec![attr("action", "transfer_hook_failed"), attr("error", error)]
+                )
+            )
+        }
+        (TRANSFER_HOOK_REPLY_ID, SubMsgResult::Ok(_)) => Ok(Response::new()),
+        (id, _) => Err(ContractError::UnknownReplyId { id }),
+    }
+}
+
+/// Notification of `action` on `token_ids` for every transfer hook, one per hook however many
+/// tokens moved. A failing hook can't revert the operation, its error is swallowed in `reply`.
+pub fn transfer_hook_msgs(
+    storage: &dyn Storage,
+    action: &str,
+    token_ids: &[String],
+    from: Option<&Addr>,
+    to: Option<&Addr>
+) -> StdResult<Vec<SubMsg>> {
+    let msg = to_binary(
+        &TransferHookExecuteMsg::TransferHook(TransferHookMsg {
+            token_ids: token_ids.to_vec(),
+            from: from.map(Addr::to_string),
+            to: to.map(Addr::to_string),
+            action: action.to_string(),
+        })
+    )?;
+    TRANSFER_HOOKS.keys(storage, None, None, Order::Ascending)
+        .map(|hook| -> StdResult<SubMsg> {
+            let notify = WasmMsg::Execute {
+                contract_addr: hook?.into_string(),
+                msg: msg.clone(),
+                funds: vec![],
+            };
+            Ok(
+                SubMsg::reply_on_error(notify, TRANSFER_HOOK_REPLY_ID).with_gas_limit(
+                    TRANSFER_HOOK_GAS_LIMIT
+                )
+            )
+        })
+        .collect()
+}
+
+/// Hash a buyer has to commit to in `CommitMint`
+pub fn commitment_hash(recipient: &Addr, salt: &[u8], count: u64) -> Binary {
+    let hash = Sha256::new()
+        .chain_update(recipient.as_bytes())
+        .chain_update(salt)
+        .chain_update(count.to_be_bytes())
+        .finalize();
+    Binary::from(hash.as_slice())
+}
+
+pub fn commit_mint(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    commitment: Binary,
+    proof: Option<Vec<Binary>>,
+    allowlist_index: Option<u32>
+) -> Result<Response, ContractError> {
+    if COMMITMENTS.has(deps.storage, &info.sender) {
+        return Err(ContractError::CommitmentExists {});
+    }
+    let state = STATE.load(deps.storage)?;
+    let amount = must_pay(&info, &state.mint_denom)?;
+
+    // The count is hidden until the reveal, so the buyer has to be able to buy a single token
+    // now. `RevealMint` checks the purchase again with the revealed count.
+    let now = env.block.time.seconds();
+    let order = PurchaseOrder {
+        payer: &info.sender,
+        recipient: &info.sender,
+        count: 1,
+        proof: proof.as_deref(),
+        max_price_per_token: None,
+        referrer: None,
+        voucher: false,
+        allowlist_index,
+    };
+    state.check_purchase(deps.storage, now, &order)?;
+    let price = state.price_at(now);
+    if amount < price {
+        return Err(ContractError::InsufficientFunds { required: price, provided: amount });
+    }
+
+    let escrow = Coin { denom: state.mint_denom, amount };
+    COMMITMENTS.save(
+        deps.storage,
+        &info.sender,
+        &(Commitment {
+            hash: commitment,
+            escrow: escrow.clone(),
+            committed_at: env.block.height,
+            committed_time: env.block.time,
+        })
+    )?;
+
+    Ok(
+        Response::new().add_attributes(
+            vec![
+                attr("action", "commit_mint"),
+                attr("buyer", info.sender),
+                attr("escrow", escrow.to_string())
+            ]
+        )
+    )
+}
+
+/// Verifies a reveal against the sender's commitment and removes it
+fn take_commitment(
+    storage: &mut dyn Storage,
+    block: &BlockInfo,
+    sender: &Addr,
+    salt: &[u8],
+    count: u64
+) -> Result<Commitment, ContractError> {
+    let commitment = COMMITMENTS.may_load(storage, sender)?.ok_or(ContractError::NoCommitment {})?;
+
+    let reveal_height = commitment.committed_at + MIN_REVEAL_DELAY_BLOCKS;
+    if block.height < reveal_height {
+        return Err(ContractError::RevealTooEarly { reveal_height });
+    }
+    // a missed block doesn't strand the escrow, the window is short all the same
+    if block.height > commitment.committed_at + REVEAL_WINDOW_BLOCKS {
+        return Err(ContractError::CommitmentExpired {});
+    }
+    if commitment_hash(sender, salt, count) != commitment.hash {
+        return Err(ContractError::CommitmentMismatch {});
+    }
+
+    COMMITMENTS.remove(storage, sender);
+    Ok(commitment)
+}
+
+pub fn cancel_commit(deps: DepsMut, env: Env, info: MessageInfo) -> Result<Response, ContractError> {
+    let commitment = COMMITMENTS.may_load(deps.storage, &info.sender)?.ok_or(
+        ContractError::NoCommitment {}
+    )?;
+
+    let expires_at = commitment.committed_at + COMMIT_TIMEOUT_BLOCKS;
+    if env.block.height <= expires_at {
+        return Err(ContractError::CommitmentNotExpired { expires_at });
+    }
+    COMMITMENTS.remove(deps.storage, &info.sender);
+
+    Ok(
+        Response::new()
+            .add_message(BankMsg::Send {
+                to_address: info.sender.to_string(),
+                amount: vec![commitment.escrow],
+            })
+            .add_attributes(vec![attr("action", "cancel_commit"), attr("buyer", info.sender)])
+    )
+}
+
+/// Returns the token id for mint cursor `cursor` and advances the id pool. Without entropy
+/// the id at the cursor is used, otherwise a position in `[cursor, max_total_mint)` is picked
+/// and swapped with the cursor, so every id is handed out exactly once either way.
+pub fn draw_token_id(
+    storage: &mut dyn Storage,
+    cursor: u64,
+    max_total_mint: u64,
+    entropy: Option<&[u8]>
+) -> Result<u64, ContractError> {
+    if cursor >= max_total_mint {
+        return Err(ContractError::SoldOut { remaining: 0 });
+    }
+
+    let pick = match entropy {
+        Some(seed) => {
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(&seed[..8]);
+            cursor + (u64::from_be_bytes(bytes) % (max_total_mint - cursor))
+        }
+        None => cursor,
+    };
+
+    let token_id = TOKEN_ID_POOL.may_load(storage, pick)?.unwrap_or(pick);
+    if pick != cursor {
+        let at_cursor = TOKEN_ID_POOL.may_load(storage, cursor)?.unwrap_or(cursor);
+        TOKEN_ID_POOL.save(storage, pick, &at_cursor)?;
+    }
+    TOKEN_ID_POOL.remove(storage, cursor);
+
+    Ok(token_id)
+}
+
+/// Drops the pool positions from `max_total_mint` on, so the ids still left to draw are the
+/// ones at the positions below it
+pub fn truncate_token_id_pool(storage: &mut dyn Storage, max_total_mint: u64) -> StdResult<()> {
+    let positions = TOKEN_ID_POOL.keys(
+        storage,
+        Some(Bound::inclusive(max_total_mint)),
+        None,
+        Order::Ascending
+    ).collect::<StdResult<Vec<_>>>()?;
+    for position in positions {
+        TOKEN_ID_POOL.remove(storage, position);
+    }
+    Ok(())
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn migrate(deps: DepsMut, _env: Env, msg: MigrateMsg) -> Result<Response, ContractError> {
+    match msg {
+        MigrateMsg::Upgrade { new_max_supply } => {
+            // make sure the correct contract is being upgraded and that it isn't a downgrade
+            let stored = cw2::get_contract_version(deps.storage)?;
+            if stored.contract != CONTRACT_NAME {
+                return Err(ContractError::InvalidContractName { contract: stored.contract });
+            }
+            if parse_version(&stored.version)? > parse_version(CONTRACT_VERSION)? {
+                return Err(ContractError::CannotDowngrade { version: stored.version });
+            }
+
+            // perform the state transformations, the older ones already write `Uint128`
+            // amounts and the owner as an address
+            if parse_version(&stored.version)? < parse_version(PROTOCOL_FEE_BPS_VERSION)? {
+                migrate_protocol_fee_to_bps(deps.api, deps.storage)?;
+            } else if parse_version(&stored.version)? < parse_version(UINT128_AMOUNTS_VERSION)? {
+                migrate_state_amounts(deps.api, deps.storage)?;
+            } else if parse_version(&stored.version)? < parse_version(OWNER_ADDR_VERSION)? {
+                migrate_owner_addr(deps.api, deps.storage)?;
+            }
+            if parse_version(&stored.version)? < parse_version(TOKEN_RECORDS_VERSION)? {
+                migrate_token_records(deps.storage)?;
+            }
+            if parse_version(&stored.version)? < parse_version(UINT128_AMOUNTS_VERSION)? {
+                migrate_balance_amounts(deps.storage)?;
+            }
+            // the ledger is drained, so this is a no-op once balances are per denom
+            migrate_balance_denoms(deps.storage)?;
+            migrate_minted_count(deps.storage)?;
+
+            cw2::set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+
+            let mut response = Response::new().add_attributes(
+                vec![
+                    attr("action", "migrate"),
+                    attr("from_version", stored.version),
+                    attr("to_version", CONTRACT_VERSION)
+                ]
+            );
+            if let Some(new_max) = new_max_supply {
+                let mut state = STATE.load(deps.storage)?;
+                let old_max = state.set_max_supply(deps.storage, new_max)?;
+                STATE.save(deps.storage, &state)?;
+                response = response.add_attributes(
+                    vec![
+                        attr("old_max_supply", old_max.to_string()),
+                        attr("new_max_supply", new_max.to_string())
+                    ]
+                );
+            }
+
+            Ok(response)
+        }
+    }
+}
+
+/// Timestamp of a sale time stored in seconds, `None` if it can't be a time in seconds.
+/// Block times are truncated to whole seconds before they are compared with sale times, so
+/// a window ending at `end` is open until the last nanosecond of that second.
+pub fn sale_timestamp(seconds: u64) -> Option<Timestamp> {
+    (seconds <= MAX_TIME_SECONDS).then(|| Timestamp::from_seconds(seconds))
+}
+
+/// Parses a `major.minor.patch` version string so versions can be ordered
+fn parse_version(version: &str) -> StdResult<(u64, u64, u64)> {
+    let mut parts = version.split(|c| c == '.' || c == '-').map(|part| part.parse::<u64>());
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(Ok(major)), Some(Ok(minor)), Some(Ok(patch))) => Ok((major, minor, patch)),
+        _ => Err(StdError::generic_err(format!("Invalid contract version: {}", version))),
+    }
+}
+
+/// State layout written before `protocol_fee` was stored in basis points
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+struct StateV1 {
+    pub owner: CanonicalAddr,
+    pub base_token_uri: String,
+    pub base_token_uri_extension: String,
+    pub prereveal_token_uri: String,
+    pub treasury_address: String,
+    pub protocol_address: String,
+    pub mint_denom: String,
+    pub mint_price: u128,
+    pub sale_start_time: u64,
+    pub sale_end_time: u64,
+    pub protocol_fee: u8,
+    pub max_total_mint: u64,
+    pub current_token_id: u64,
+    pub uri_status: bool,
+}
+
+const STATE_V1: Item<StateV1> = Item::new("state");
+
+fn migrate_protocol_fee_to_bps(api: &dyn Api, storage: &mut dyn Storage) -> StdResult<()> {
+    let old = STATE_V1.load(storage)?;
+    let state = State {
+        owner: api.addr_humanize(&old.owner)?,
+        base_token_uri: old.base_token_uri,
+        base_token_uri_extension: old.base_token_uri_extension,
+        prereveal_token_uri: old.prereveal_token_uri,
+        treasury_address: Addr::unchecked(old.treasury_address),
+        protocol_address: stored_address(old.protocol_address),
+        mint_denom: old.mint_denom,
+        mint_price: Uint128::new(old.mint_price),
+        sale_start_time: old.sale_start_time,
+        sale_end_time: old.sale_end_time,
+        protocol_fee_bps: u16::from(old.protocol_fee) * 100,
+        max_total_mint: old.max_total_mint,
+        current_token_id: old.current_token_id,
+        uri_status: old.uri_status,
+        burned_count: 0,
+        minted_count: 0,
+        active_tier: None,
+        presale_start_time: 0,
+        presale_end_time: 0,
+        gross_proceeds: Uint128::zero(),
+        protocol_fees_accrued: Uint128::zero(),
+        total_mints: 0,
+        max_per_wallet: 0,
+        ics721_address: None,
+        contract_uri: None,
+        metadata_frozen: false,
+        supply_frozen: false,
+        lock_schedule_on_start: false,
+        lock_whitelist_after_start: false,
+        users_disabled: false,
+        royalty_enforced: false,
+        max_per_wallet_holding: None,
+        pass_collection: None,
+        referral_bps: 0,
+        open_edition: false,
+        signer_pubkey: Binary::default(),
+        signature_scheme: SignatureScheme::default(),
+        proceeds_hook: None,
+        proceeds_hook_msg: Binary::default(),
+        burn_reward: None,
+        reward_pool: Uint128::zero(),
+        token_id_format: None,
+        metadata_resolver: None,
+        reveal_at: None,
+        phases: Vec::new(),
+        grace_period_secs: 0,
+        grace_threshold_pct: 0,
+        max_uri_len: 0,
+        min_threshold: 0,
+        escrow_released: false,
+        early_bird_cutoff: 0,
+        early_birds: 0,
+        finalize_actions: FinalizeActions::default(),
+        sale_finalized: false,
+        mint_receipts: false,
+        uri_veto_threshold: 0,
+        max_mints_per_block: 0,
+        factory_fee_bps: 0,
+        factory_address: None,
+    };
+    STATE.save(storage, &state)
+}
+
+/// State layout written before amounts were stored as `Uint128`
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+struct StateV2 {
+    pub owner: CanonicalAddr,
+    pub base_token_uri: String,
+    pub base_token_uri_extension: String,
+    pub prereveal_token_uri: String,
+    pub treasury_address: String,
+    pub protocol_address: String,
+    pub mint_denom: String,
+    pub mint_price: u128,
+    pub sale_start_time: u64,
+    pub sale_end_time: u64,
+    pub protocol_fee_bps: u16,
+    pub max_total_mint: u64,
+    pub current_token_id: u64,
+    pub uri_status: bool,
+    #[serde(default)]
+    pub burned_count: u64,
+    #[serde(default)]
+    pub active_tier: Option<u8>,
+    #[serde(default)]
+    pub presale_start_time: u64,
+    #[serde(default)]
+    pub presale_end_time: u64,
+    #[serde(default)]
+    pub gross_proceeds: u128,
+    #[serde(default)]
+    pub protocol_fees_accrued: u128,
+    #[serde(default)]
+    pub total_mints: u64,
+    #[serde(default)]
+    pub max_per_wallet: u64,
+    #[serde(default)]
+    pub ics721_address: String,
+    #[serde(default)]
+    pub contract_uri: Option<String>,
+    #[serde(default)]
+    pub metadata_frozen: bool,
+}
+
+const STATE_V2: Item<StateV2> = Item::new("state");
+const BALANCES_V2: Map<&Addr, u64> = Map::new("balances");
+/// Ledger written before balances were kept per denom
+const BALANCES_V4: Map<&Addr, Uint128> = Map::new("balances");
+
+fn migrate_state_amounts(api: &dyn Api, storage: &mut dyn Storage) -> StdResult<()> {
+    let old = STATE_V2.load(storage)?;
+    let state = State {
+        owner: api.addr_humanize(&old.owner)?,
+        base_token_uri: old.base_token_uri,
+        base_token_uri_extension: old.base_token_uri_extension,
+        prereveal_token_uri: old.prereveal_token_uri,
+        treasury_address: Addr::unchecked(old.treasury_address),
+        protocol_address: stored_address(old.protocol_address),
+        mint_denom: old.mint_denom,
+        mint_price: Uint128::new(old.mint_price),
+        sale_start_time: old.sale_start_time,
+        sale_end_time: old.sale_end_time,
+        protocol_fee_bps: old.protocol_fee_bps,
+        max_total_mint: old.max_total_mint,
+        current_token_id: old.current_token_id,
+        uri_status: old.uri_status,
+        burned_count: old.burned_count,
+        minted_count: 0,
+        active_tier: old.active_tier,
+        presale_start_time: old.presale_start_time,
+        presale_end_time: old.presale_end_time,
+        gross_proceeds: Uint128::new(old.gross_proceeds),
+        protocol_fees_accrued: Uint128::new(old.protocol_fees_accrued),
+        total_mints: old.total_mints,
+        max_per_wallet: old.max_per_wallet,
+        ics721_address: stored_address(old.ics721_address),
+        contract_uri: old.contract_uri,
+        metadata_frozen: old.metadata_frozen,
+        supply_frozen: false,
+        lock_schedule_on_start: false,
+        lock_whitelist_after_start: false,
+        users_disabled: false,
+        royalty_enforced: false,
+        max_per_wallet_holding: None,
+        pass_collection: None,
+        referral_bps: 0,
+        open_edition: false,
+        signer_pubkey: Binary::default(),
+        signature_scheme: SignatureScheme::default(),
+        proceeds_hook: None,
+        proceeds_hook_msg: Binary::default(),
+        burn_reward: None,
+        reward_pool: Uint128::zero(),
+        token_id_format: None,
+        metadata_resolver: None,
+        reveal_at: None,
+        phases: Vec::new(),
+        grace_period_secs: 0,
+        grace_threshold_pct: 0,
+        max_uri_len: 0,
+        min_threshold: 0,
+        escrow_released: false,
+        early_bird_cutoff: 0,
+        early_birds: 0,
+        finalize_actions: FinalizeActions::default(),
+        sale_finalized: false,
+        mint_receipts: false,
+        uri_veto_threshold: 0,
+        max_mints_per_block: 0,
+        factory_fee_bps: 0,
+        factory_address: None,
+    };
+    STATE.save(storage, &state)
+}
+
+/// Humanizes the owner, which was stored as the base64 of its canonical address. `Addr`
+/// reads any string, so the rest of the state loads as is.
+fn migrate_owner_addr(api: &dyn Api, storage: &mut dyn Storage) -> StdResult<()> {
+    let mut state = STATE.load(storage)?;
+    let canonical = CanonicalAddr::from(Binary::from_base64(state.owner.as_str())?);
+    state.owner = api.addr_humanize(&canonical)?;
+    STATE.save(storage, &state)
+}
+
+fn migrate_balance_amounts(storage: &mut dyn Storage) -> StdResult<()> {
+    let balances = BALANCES_V2.range(storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    for (addr, balance) in balances {
+        BALANCES_V4.save(storage, &addr, &Uint128::from(balance))?;
+    }
+    Ok(())
+}
+
+/// Moves the ledger kept before balances were per denom under the mint denom
+fn migrate_balance_denoms(storage: &mut dyn Storage) -> StdResult<()> {
+    let denom = STATE.load(storage)?.mint_denom;
+    let balances = BALANCES_V4.range(storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    for (addr, balance) in balances {
+        BALANCES_V4.remove(storage, &addr);
+        BALANCES.save(storage, (&addr, denom.as_str()), &balance)?;
+    }
+    Ok(())
+}
+
+/// Counts the tokens minted before `minted_count` was tracked. A state that never minted
+/// has neither tokens nor burns, so the recount leaves it at 0.
+fn migrate_minted_count(storage: &mut dyn Storage) -> StdResult<()> {
+    let mut state = STATE.load(storage)?;
+    if state.minted_count == 0 {
+        let existing = tokens().keys(storage, None, None, Order::Ascending).count() as u64;
+        state.minted_count = existing + state.burned_count;
+        STATE.save(storage, &state)?;
+    }
+    Ok(())
+}
+
+/// Token layout written before tokens were stored as `TokenRecord`s
+const TOKENS_V1: Map<&str, Addr> = Map::new("tokens");
+const TOKEN_URIS_V1: Map<&str, String> = Map::new("token_uris");
+const FROZEN_TOKENS_V1: Map<&str, Empty> = Map::new("frozen_tokens");
+const NFTS_V1: Item<Vec<u64>> = Item::new("nfts");
+
+fn migrate_token_records(storage: &mut dyn Storage) -> StdResult<()> {
+    let owners = TOKENS_V1.range(storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    for (token_id, owner) in owners {
+        let token = TokenRecord {
+            owner,
+            approvals: vec![],
+            uri_override: TOKEN_URIS_V1.may_load(storage, &token_id)?,
+            uri_hash: None,
+            frozen: FROZEN_TOKENS_V1.has(storage, &token_id),
+            locked_until: None,
+            user: None,
+            extension: None,
+        };
+        // the stored value is a bare owner, so it can't be read back as the old record
+        tokens().replace(storage, &token_id, Some(&token), None)?;
+        add_holding(storage, &token.owner)?;
+    }
+
+    // freezes used to be allowed for tokens that were never minted
+    let frozen = FROZEN_TOKENS_V1.keys(storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    for token_id in frozen {
+        FROZEN_TOKENS_V1.remove(storage, &token_id);
+    }
+    let uris = TOKEN_URIS_V1.keys(storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    for token_id in uris {
+        TOKEN_URIS_V1.remove(storage, &token_id);
+    }
+    NFTS_V1.remove(storage);
+    Ok(())
+}
+
+/// Sets the frozen flag of an existing token
+fn set_frozen(storage: &mut dyn Storage, token_id: &str, frozen: bool) -> Result<(), ContractError> {
+    let mut token = load_token(storage, token_id)?;
+    token.frozen = frozen;
+    tokens().save(storage, token_id, &token)?;
+    Ok(())
+}
+
+pub fn freeze_token(storage: &mut dyn Storage, token_id: &str) -> Result<(), ContractError> {
+    set_frozen(storage, token_id, true)
+}
+
+pub fn unfreeze_token(storage: &mut dyn Storage, token_id: &str) -> Result<(), ContractError> {
+    set_frozen(storage, token_id, false)
+}
+
+pub fn query_frozen_tokens(
+    deps: Deps,
+    start_after: Option<String>,
+    limit: Option<u32>
+) -> StdResult<FrozenTokensResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_FROZEN_TOKENS_LIMIT) as usize;
+    let start = start_after.map(|token_id| Bound::ExclusiveRaw(token_id.into()));
+
+    let tokens = tokens().idx.frozen
+        .prefix(1)
+        .keys(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(FrozenTokensResponse { tokens })
+}
+
+pub fn transfer(
+    deps: DepsMut,
+    block: &BlockInfo,
+    sender: Addr,
+    recipient: Addr,
+    token_id: String
+) -> Result<Response, ContractError> {
+    // Check if the sender owns the token
+    let token = assert_token_owner(deps.storage, &token_id, &sender)?;
+    assert_not_blacklisted(deps.storage, &sender)?;
+    assert_not_blacklisted(deps.storage, &recipient)?;
+    move_token(deps.storage, block, &token_id, token, &recipient)?;
+    let hooks = transfer_hook_msgs(
+        deps.storage,
+        "transfer",
+        &[token_id.clone()],
+        Some(&sender),
+        Some(&recipient)
+    )?;
+
+    Ok(
+        Response::new()
+            .add_submessages(hooks)
+            .add_attributes(events::transfer_nft(sender.as_str(), recipient.as_str(), &token_id))
+    )
+}
+
+/// Moves a token to `recipient` on behalf of its owner or an approved spender
+pub fn transfer_nft(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    recipient: String,
+    token_id: String,
+    sale_price: Option<Coin>
+) -> Result<Response, ContractError> {
+    let recipient = validate_address(deps.api, "recipient", &recipient)?;
+    let token = check_transfer(deps.storage, &env.block, &info.sender, &token_id, &recipient)?;
+    let royalty = transfer_royalty(deps.as_ref(), &env, &info, sale_price)?;
+    let owner = token.owner.clone();
+    move_token(deps.storage, &env.block, &token_id, token, &recipient)?;
+    let hooks = transfer_hook_msgs(
+        deps.storage,
+        "transfer",
+        &[token_id.clone()],
+        Some(&owner),
+        Some(&recipient)
+    )?;
+
+    Ok(
+        Response::new()
+            .add_messages(royalty)
+            .add_submessages(hooks)
+            .add_attributes(
+                events::transfer_nft(info.sender.as_str(), recipient.as_str(), &token_id)
+            )
+    )
+}
+
+/// Moves every token of `transfers` to its recipient, failing the whole batch on the first
+/// entry `TransferNft` would refuse
+pub fn batch_transfer(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    transfers: Vec<BatchTransferEntry>
+) -> Result<Response, ContractError> {
+    if transfers.len() > MAX_TRANSFER_BATCH {
+        return Err(ContractError::BatchTooLarge {
+            size: transfers.len(),
+            max: MAX_TRANSFER_BATCH,
+        });
+    }
+    // without a sale price, only the owner may transfer when royalties are enforced
+    transfer_royalty(deps.as_ref(), &env, &info, None)?;
+
+    let mut hooks = vec![];
+    let mut attributes = vec![attr("action", "batch_transfer"), attr("sender", &info.sender)];
+    for BatchTransferEntry { recipient, token_id } in transfers {
+        let recipient = validate_address(deps.api, "recipient", &recipient)?;
+        let token = check_transfer(deps.storage, &env.block, &info.sender, &token_id, &recipient)?;
+        let owner = token.owner.clone();
+        move_token(deps.storage, &env.block, &token_id, token, &recipient)?;
+        hooks.extend(
+            transfer_hook_msgs(
+                deps.storage,
+                "transfer",
+                &[token_id.clone()],
+                Some(&owner),
+                Some(&recipient)
+            )?
+        );
+        attributes.push(attr("token_id", token_id));
+        attributes.push(attr("recipient", recipient));
+    }
+
+    Ok(Response::new().add_submessages(hooks).add_attributes(attributes))
+}
+
+/// Loads a token `sender` may move to `recipient`, with neither party blacklisted
+fn check_transfer(
+    storage: &dyn Storage,
+    block: &BlockInfo,
+    sender: &Addr,
+    token_id: &str,
+    recipient: &Addr
+) -> Result<TokenRecord, ContractError> {
+    let token = load_token(storage, token_id)?;
+    assert_owner_or_approved(&token, sender, block)?;
+    assert_not_blacklisted(storage, &token.owner)?;
+    assert_not_blacklisted(storage, recipient)?;
+    Ok(token)
+}
+
+/// Payouts of the royalty on a transfer sold for `sale_price`, paid by the sender with any
+/// excess refunded. Collections enforcing royalties refuse transfers without a sale price,
+/// unless made by the owner, such as the factory returning a staked token.
+fn transfer_royalty(
+    deps: Deps,
+    env: &Env,
+    info: &MessageInfo,
+    sale_price: Option<Coin>
+) -> Result<Vec<CosmosMsg>, ContractError> {
+    let state = STATE.load(deps.storage)?;
+    let sale_price = match sale_price {
+        Some(sale_price) => sale_price,
+        None if state.royalty_enforced && assert_owner(&state, &info.sender).is_err() => {
+            return Err(ContractError::RoyaltyRequired {});
+        }
+        None => {
+            return Ok(vec![]);
+        }
+    };
+
+    let royalty = sale_price.amount * collection_royalty_rate(deps.storage, env)?;
+    if royalty.is_zero() {
+        return Ok(vec![]);
+    }
+    let paid = must_pay(info, &sale_price.denom)?;
+    if paid < royalty {
+        return Err(ContractError::InsufficientFunds { required: royalty, provided: paid });
+    }
+
+    let coin = |amount| Coin { denom: sale_price.denom.clone(), amount };
+    let mut payouts = vec![state.treasury_payout(coin(royalty))];
+    if paid > royalty {
+        let change = BankMsg::Send {
+            to_address: info.sender.to_string(),
+            amount: vec![coin(paid - royalty)],
+        };
+        payouts.push(change.into());
+    }
+    Ok(payouts)
+}
+
+/// Replaces any approval of `spender` on a token of the sender
+pub fn approve(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    spender: String,
+    token_id: String,
+    expires: Option<Expiration>
+) -> Result<Response, ContractError> {
+    let mut token = assert_token_owner(deps.storage, &token_id, &info.sender)?;
+    let spender = validate_address(deps.api, "spender", &spender)?;
+    let expires = expires.unwrap_or_default();
+    if expires.is_expired(&env.block) {
+        return Err(ContractError::ApprovalExpired { expires });
+    }
+
+    token.approvals.retain(|approval| approval.spender != spender.as_str());
+    token.approvals.push(Approval { spender: spender.to_string(), expires });
+    tokens().save(deps.storage, &token_id, &token)?;
+
+    Ok(
+        Response::new().add_attributes(
+            vec![
+                attr("action", "approve"),
+                attr("sender", info.sender),
+                attr("spender", spender),
+                attr("token_id", token_id)
+            ]
+        )
+    )
+}
+
+pub fn revoke(
+    deps: DepsMut,
+    info: MessageInfo,
+    spender: String,
+    token_id: String
+) -> Result<Response, ContractError> {
+    let mut token = assert_token_owner(deps.storage, &token_id, &info.sender)?;
+    token.approvals.retain(|approval| approval.spender != spender);
+    tokens().save(deps.storage, &token_id, &token)?;
+
+    Ok(
+        Response::new().add_attributes(
+            vec![
+                attr("action", "revoke"),
+                attr("sender", info.sender),
+                attr("spender", spender),
+                attr("token_id", token_id)
+            ]
+        )
+    )
+}
+
+/// Sends a token to `contract` and notifies it through the cw721 receive hook.
+/// The sender must own the token or be an approved spender.
+pub fn send_nft(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    contract: String,
+    token_id: String,
+    msg: Binary
+) -> Result<Response, ContractError> {
+    let contract = validate_address(deps.api, "contract", &contract)?;
+    let token = load_token(deps.storage, &token_id)?;
+    assert_owner_or_approved(&token, &info.sender, &env.block)?;
+    assert_not_blacklisted(deps.storage, &token.owner)?;
+    assert_not_blacklisted(deps.storage, &contract)?;
+    let owner = token.owner.clone();
+    move_token(deps.storage, &env.block, &token_id, token, &contract)?;
+    let hooks = transfer_hook_msgs(
+        deps.storage,
+        "send",
+        &[token_id.clone()],
+        Some(&owner),
+        Some(&contract)
+    )?;
+
+    let receive = Cw721ReceiveMsg {
+        sender: info.sender.to_string(),
+        token_id: token_id.clone(),
+        msg,
+    };
+    Ok(
+        Response::new()
+            .add_message(receive.into_cosmos_msg(contract.clone())?)
+            .add_submessages(hooks)
+            .add_attributes(events::send_nft(info.sender.as_str(), contract.as_str(), &token_id))
+    )
+}
+
+/// Shares `amount` of `asset` equally between the tokens in existence
+pub fn fund_rewards(
+    deps: DepsMut,
+    funder: &Addr,
+    asset: RewardAsset,
+    amount: Uint128
+) -> Result<Response, ContractError> {
+    let mut rewards = match HOLDER_REWARDS.may_load(deps.storage)? {
+        Some(rewards) if rewards.asset != asset => {
+            return Err(ContractError::RewardAssetMismatch { expected: rewards.asset.to_string() });
+        }
+        Some(rewards) => rewards,
+        None => HolderRewards { asset, index: Decimal::zero(), undistributed: Uint128::zero() },
+    };
+    let state = STATE.load(deps.storage)?;
+    let supply = u128::from(state.minted_count - state.burned_count);
+    if supply == 0 {
+        return Err(ContractError::NoRewardHolders {});
+    }
+
+    // whatever doesn't divide evenly is kept for the next funding
+    let distributable = rewards.undistributed.checked_add(amount)?;
+    let per_token = Decimal::checked_from_ratio(distributable, supply).map_err(|err| {
+        StdError::generic_err(err.to_string())
+    })?;
+    rewards.undistributed = distributable.checked_sub(Uint128::new(supply) * per_token)?;
+    rewards.index = rewards.index.checked_add(per_token)?;
+    HOLDER_REWARDS.save(deps.storage, &rewards)?;
+
+    Ok(
+        Response::new().add_attributes(
+            vec![
+                attr("action", "fund_rewards"),
+                attr("funder", funder),
+                attr("asset", rewards.asset.to_string()),
+                attr("amount", amount),
+                attr("index", rewards.index.to_string())
+            ]
+        )
+    )
+}
+
+/// Pays the rewards accrued by tokens of the sender, moving each checkpoint by the whole
+/// amount paid so rounding remainders stay with the token
+pub fn claim_rewards(
+    deps: DepsMut,
+    info: MessageInfo,
+    token_ids: Vec<String>
+) -> Result<Response, ContractError> {
+    if token_ids.len() > MAX_METADATA_BATCH {
+        return Err(ContractError::BatchTooLarge {
+            size: token_ids.len(),
+            max: MAX_METADATA_BATCH,
+        });
+    }
+    let rewards = HOLDER_REWARDS.may_load(deps.storage)?;
+
+    let mut total = Uint128::zero();
+    for token_id in &token_ids {
+        assert_token_owner(deps.storage, token_id, &info.sender)?;
+        let Some(rewards) = &rewards else {
+            continue;
+        };
+        let (checkpoint, pending) = pending_rewards(deps.storage, rewards, token_id)?;
+        let checkpoint = checkpoint.checked_add(Decimal::from_ratio(pending, 1u128))?;
+        REWARD_CHECKPOINTS.save(deps.storage, token_id, &checkpoint)?;
+        total = total.checked_add(pending)?;
+    }
+
+    let payout = match &rewards {
+        Some(rewards) if !total.is_zero() => Some(rewards.asset.payout(&info.sender, total)?),
+        _ => None,
+    };
+    Ok(
+        Response::new()
+            .add_messages(payout)
+            .add_attributes(
+                events::batch(
+                    "claim_rewards",
+                    vec![attr("owner", info.sender), attr("amount", total)],
+                    &token_ids
+                )
+            )
+    )
+}
+
+/// Checkpoint of `token_id` and the whole rewards accrued since
+fn pending_rewards(
+    storage: &dyn Storage,
+    rewards: &HolderRewards,
+    token_id: &str
+) -> StdResult<(Decimal, Uint128)> {
+    let checkpoint = REWARD_CHECKPOINTS.may_load(storage, token_id)?.unwrap_or_default();
+    Ok((checkpoint, Uint128::one() * (rewards.index - checkpoint)))
+}
+
+/// Starts a new token at the current reward index, so it only earns later fundings
+fn checkpoint_rewards(storage: &mut dyn Storage, token_id: &str) -> StdResult<()> {
+    if let Some(rewards) = HOLDER_REWARDS.may_load(storage)? {
+        REWARD_CHECKPOINTS.save(storage, token_id, &rewards.index)?;
+    }
+    Ok(())
+}
+
+/// Handles a token sent to this contract by the collection in `info.sender`
+pub fn receive_nft(
+    deps: DepsMut,
+    info: MessageInfo,
+    msg: Cw721ReceiveMsg
+) -> Result<Response, ContractError> {
+    match from_binary(&msg.msg)? {
+        ReceiveNftMsg::RedeemPass {} => redeem_pass(deps, info, msg),
+    }
+}
+
+/// Burns a received pass and mints a token to whoever sent it
+fn redeem_pass(
+    deps: DepsMut,
+    info: MessageInfo,
+    msg: Cw721ReceiveMsg
+) -> Result<Response, ContractError> {
+    let mut state = STATE.load(deps.storage)?;
+    if state.pass_collection.as_ref() != Some(&info.sender) {
+        return Err(ContractError::unauthorized("pass collection"));
+    }
+    if REDEEMED_PASSES.has(deps.storage, &msg.token_id) {
+        return Err(ContractError::PassRedeemed { token_id: msg.token_id });
+    }
+
+    let owner = validate_address(deps.api, "sender", &msg.sender)?;
+    let number = state.mint(deps.storage, &owner, None)?;
+    REDEEMED_PASSES.save(deps.storage, &msg.token_id, &number)?;
+    STATE.save(deps.storage, &state)?;
+    let token_id = state.token_id(number);
+    let hooks = transfer_hook_msgs(
+        deps.storage,
+        "mint",
+        &[token_id.clone()],
+        None,
+        Some(&owner)
+    )?;
+
+    // The pass is owned by this contract now, so it can be burned right away
+    let burn = WasmMsg::Execute {
+        contract_addr: info.sender.into_string(),
+        msg: to_binary(&(Cw721ExecuteMsg::Burn { token_id: msg.token_id.clone() }))?,
+        funds: vec![],
+    };
+    Ok(
+        Response::new()
+            .add_message(burn)
+            .add_submessages(hooks)
+            .add_attributes(
+                vec![
+                    attr("action", "redeem_pass"),
+                    attr("pass_id", msg.token_id),
+                    attr("owner", owner),
+                    attr("token_id", token_id)
+                ]
+            )
+    )
+}
+
+/// Locks a token of the sender until `unlock_time`. A lock can be extended but never
+/// shortened, so it holds as a commitment.
+pub fn lock_until(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    token_id: String,
+    unlock_time: u64
+) -> Result<Response, ContractError> {
+    assert_token_owner(deps.storage, &token_id, &info.sender)?;
+    let earliest = self_lock(deps.storage, &env.block, &token_id)?.unwrap_or_default();
+    if unlock_time <= env.block.time.seconds() || unlock_time < earliest {
+        return Err(ContractError::InvalidUnlockTime { unlock_time });
+    }
+    SELF_LOCKS.save(deps.storage, &token_id, &unlock_time)?;
+
+    Ok(
+        Response::new().add_attributes(
+            vec![
+                attr("action", "lock_until"),
+                attr("token_id", token_id),
+                attr("unlock_time", unlock_time.to_string())
+            ]
+        )
+    )
+}
+
+/// Hands `token` over to `recipient` once the caller has been authorized
+fn move_token(
+    storage: &mut dyn Storage,
+    block: &BlockInfo,
+    token_id: &str,
+    token: TokenRecord,
+    recipient: &Addr
+) -> Result<(), ContractError> {
+    assert_transferable(storage, block, token_id, &token)?;
+    assert_recipient_allowed(storage, recipient)?;
+    if token.owner != *recipient {
+        STATE.load(storage)?.check_holding_cap(storage, recipient, 1)?;
+    }
+
+    LISTINGS.remove(storage, token_id);
+    SELF_LOCKS.remove(storage, token_id);
+    assign_owner(storage, token_id, token, recipient)
+}
+
+/// Makes `recipient` the owner of `token_id`, dropping approvals and the user, without any of
+/// the transfer checks
+fn assign_owner(
+    storage: &mut dyn Storage,
+    token_id: &str,
+    mut token: TokenRecord,
+    recipient: &Addr
+) -> Result<(), ContractError> {
+    let previous_owner = token.owner;
+    token.owner = recipient.clone();
+    token.approvals.clear();
+    token.user = None;
+    tokens().save(storage, token_id, &token)?;
+    remove_holding(storage, &previous_owner)?;
+    add_holding(storage, recipient)?;
+    Ok(())
+}
+
+pub fn list(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    token_id: String,
+    price: Coin
+) -> Result<Response, ContractError> {
+    if price.amount.is_zero() {
+        return Err(ContractError::ZeroListingPrice {});
+    }
+
+    // Only tokens that could be transferred may be listed
+    let token = assert_token_owner(deps.storage, &token_id, &info.sender)?;
+    assert_transferable(deps.storage, &env.block, &token_id, &token)?;
+
+    LISTINGS.save(
+        deps.storage,
+        &token_id,
+        &(Listing { seller: info.sender.clone(), price: price.clone() })
+    )?;
+
+    Ok(
+        Response::new().add_attributes(
+            vec![
+                attr("action", "list"),
+                attr("seller", info.sender),
+                attr("token_id", token_id),
+                attr("price", price.to_string())
+            ]
+        )
+    )
+}
+
+pub fn buy(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    token_id: String
+) -> Result<Response, ContractError> {
+    let state = STATE.load(deps.storage)?;
+    let listing = LISTINGS.may_load(deps.storage, &token_id)?.ok_or_else(|| {
+        ContractError::TokenNotListed { token_id: token_id.clone() }
+    })?;
+    state.assert_protocol_address_set()?;
+
+    let price = listing.price.amount;
+    let paid = must_pay(&info, &listing.price.denom)?;
+    if paid < price {
+        return Err(ContractError::InsufficientFunds { required: price, provided: paid });
+    }
+
+    let sale = SaleSplit::new(deps.storage, &env, &state, &listing.price)?;
+
+    // Moving the token also drops the listing
+    let response = transfer(
+        deps.branch(),
+        &env.block,
+        listing.seller.clone(),
+        info.sender.clone(),
+        token_id
+    )?;
+
+    let change = (paid > price).then(|| BankMsg::Send {
+        to_address: info.sender.to_string(),
+        amount: vec![Coin { denom: listing.price.denom.clone(), amount: paid - price }],
+    });
+
+    Ok(
+        response
+            .add_messages(sale.payouts(&state, &listing.seller))
+            .add_messages(change)
+            .add_attributes(
+                vec![
+                    attr("action", "buy"),
+                    attr("price", listing.price.to_string()),
+                    attr("royalty", sale.royalty),
+                    attr("protocol_fee", sale.protocol_fee)
+                ]
+            )
+    )
+}
+
+/// Split of a sale price between the treasury, the protocol and the seller
+struct SaleSplit {
+    price: Coin,
+    royalty: Uint128,
+    protocol_fee: Uint128,
+    seller_amount: Uint128,
+}
+
+impl SaleSplit {
+    fn new(
+        storage: &dyn Storage,
+        env: &Env,
+        state: &State,
+        price: &Coin
+    ) -> Result<Self, ContractError> {
+        let royalty = price.amount * collection_royalty_rate(storage, env)?;
+        let protocol_fee =
+            price.amount.checked_mul(Uint128::from(state.protocol_fee_bps))? / Uint128::new(10_000);
+        let seller_amount = price.amount.checked_sub(royalty)?.checked_sub(protocol_fee)?;
+        Ok(SaleSplit { price: price.clone(), royalty, protocol_fee, seller_amount })
+    }
+
+    /// Pays out every non zero share, the royalty through `treasury_payout`
+    fn payouts(&self, state: &State, seller: &Addr) -> Vec<CosmosMsg> {
+        let coin = |amount| Coin { denom: self.price.denom.clone(), amount };
+        let protocol = state.protocol_address.clone().map(String::from).unwrap_or_default();
+        let transfers = [(seller.to_string(), self.seller_amount), (protocol, self.protocol_fee)];
+        let mut messages: Vec<CosmosMsg> = transfers
+            .into_iter()
+            .filter(|(_, amount)| !amount.is_zero())
+            .map(|(to_address, amount)| {
+                BankMsg::Send { to_address, amount: vec![coin(amount)] }.into()
+            })
+            .collect();
+        if !self.royalty.is_zero() {
+            messages.push(state.treasury_payout(coin(self.royalty)));
+        }
+        messages
+    }
+}
+
+/// Escrows the attached funds as an offer of the sender on a token
+pub fn make_offer(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    token_id: String,
+    expires: Option<Expiration>
+) -> Result<Response, ContractError> {
+    let token = load_token(deps.storage, &token_id)?;
+    if token.owner == info.sender {
+        return Err(ContractError::OwnerOffer {});
+    }
+    assert_not_blacklisted(deps.storage, &info.sender)?;
+    let price = one_coin(&info)?;
+    let expires = expires.unwrap_or_default();
+    if expires.is_expired(&env.block) {
+        return Err(ContractError::OfferExpired { expires });
+    }
+
+    // a new offer replaces the previous one, whose escrow goes back to the bidder
+    let key = (token_id.as_str(), &info.sender);
+    let refund = offers().may_load(deps.storage, key)?.map(|previous| BankMsg::Send {
+        to_address: info.sender.to_string(),
+        amount: vec![previous.price],
+    });
+    let offer = Offer { bidder: info.sender.clone(), price: price.clone(), expires };
+    offers().save(deps.storage, key, &offer)?;
+
+    Ok(
+        Response::new()
+            .add_messages(refund)
+            .add_attributes(
+                vec![
+                    attr("action", "make_offer"),
+                    attr("bidder", info.sender),
+                    attr("token_id", token_id),
+                    attr("price", price.to_string()),
+                    attr("expires", expires.to_string())
+                ]
+            )
+    )
+}
+
+/// Sells a token of the sender to `bidder` out of the escrowed offer and refunds every
+/// other offer on the token
+pub fn accept_offer(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    token_id: String,
+    bidder: String
+) -> Result<Response, ContractError> {
+    let state = STATE.load(deps.storage)?;
+    let bidder = validate_address(deps.api, "bidder", &bidder)?;
+    let offer = offers()
+        .may_load(deps.storage, (&token_id, &bidder))?
+        .ok_or_else(|| ContractError::NoOffer {
+            token_id: token_id.clone(),
+            bidder: bidder.to_string(),
+        })?;
+    if offer.expires.is_expired(&env.block) {
+        return Err(ContractError::OfferExpired { expires: offer.expires });
+    }
+    state.assert_protocol_address_set()?;
+
+    let sale = SaleSplit::new(deps.storage, &env, &state, &offer.price)?;
+    let response = transfer(
+        deps.branch(),
+        &env.block,
+        info.sender.clone(),
+        bidder.clone(),
+        token_id.clone()
+    )?;
+
+    let open = offers()
+        .prefix(&token_id)
+        .range(deps.storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    let mut refunds = vec![];
+    for (other, other_offer) in open {
+        offers().remove(deps.storage, (&token_id, &other))?;
+        if other != bidder {
+            refunds.push(BankMsg::Send {
+                to_address: other.into_string(),
+                amount: vec![other_offer.price],
+            });
+        }
+    }
+
+    Ok(
+        response
+            .add_messages(sale.payouts(&state, &info.sender))
+            .add_messages(refunds)
+            .add_attributes(
+                vec![
+                    attr("action", "accept_offer"),
+                    attr("bidder", bidder),
+                    attr("price", offer.price.to_string()),
+                    attr("royalty", sale.royalty),
+                    attr("protocol_fee", sale.protocol_fee)
+                ]
+            )
+    )
+}
+
+/// Royalty rate of the class issued for the collection symbol, zero without one
+fn collection_royalty_rate(storage: &dyn Storage, env: &Env) -> Result<Decimal, ContractError> {
+    let rate = match collection_class(storage, env)?.and_then(|class| class.royalty_rate) {
+        Some(rate) => rate,
+        None => {
+            return Ok(Decimal::zero());
+        }
+    };
+    match Decimal::from_str(&rate) {
+        Ok(parsed) if parsed <= Decimal::one() => Ok(parsed),
+        _ => Err(ContractError::InvalidRoyaltyRate { rate }),
+    }
+}
+
+/// Class issued for the collection symbol, if any
+fn collection_class(storage: &dyn Storage, env: &Env) -> StdResult<Option<ClassInfo>> {
+    let info = Cw721Contract::<Empty, Empty, Empty, Empty>::default().contract_info.load(storage)?;
+    let class_id = format!("{}-{}", info.symbol.to_lowercase(), env.contract.address);
+    CLASSES.may_load(storage, &class_id)
+}
+
+/// Puts a token of the sender up for auction, dropping any listing of it
+pub fn start_auction(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    token_id: String,
+    reserve_price: Coin,
+    duration: u64,
+    min_bid_increment: Uint128
+) -> Result<Response, ContractError> {
+    if duration == 0 {
+        return Err(ContractError::ZeroAuctionDuration {});
+    }
+    // the proceeds have to be payable once the auction ends
+    STATE.load(deps.storage)?.assert_protocol_address_set()?;
+    let token = assert_token_owner(deps.storage, &token_id, &info.sender)?;
+    assert_transferable(deps.storage, &env.block, &token_id, &token)?;
+    LISTINGS.remove(deps.storage, &token_id);
+
+    let end_time = env.block.time.seconds() + duration;
+    let auction = Auction {
+        seller: info.sender.clone(),
+        reserve_price: reserve_price.clone(),
+        min_bid_increment,
+        end_time,
+        highest_bid: None,
+    };
+    AUCTIONS.save(deps.storage, &token_id, &auction)?;
+
+    Ok(
+        Response::new().add_attributes(
+            vec![
+                attr("action", "start_auction"),
+                attr("seller", info.sender),
+                attr("token_id", token_id),
+                attr("reserve_price", reserve_price.to_string()),
+                attr("end_time", end_time.to_string())
+            ]
+        )
+    )
+}
+
+/// Escrows the attached funds as the highest bid of an auction, refunding the bid it beats
+pub fn bid(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    token_id: String
+) -> Result<Response, ContractError> {
+    let mut auction = AUCTIONS.may_load(deps.storage, &token_id)?.ok_or_else(|| {
+        ContractError::NoAuction { token_id: token_id.clone() }
+    })?;
+    let now = env.block.time.seconds();
+    if now >= auction.end_time {
+        return Err(ContractError::AuctionEnded { token_id, end_time: auction.end_time });
+    }
+    if auction.seller == info.sender {
+        return Err(ContractError::OwnerOffer {});
+    }
+    assert_not_blacklisted(deps.storage, &info.sender)?;
+    // the winner has to be able to receive the token at settlement
+    assert_recipient_allowed(deps.storage, &info.sender)?;
+    STATE.load(deps.storage)?.check_holding_cap(deps.storage, &info.sender, 1)?;
+
+    let amount = must_pay(&info, &auction.reserve_price.denom)?;
+    let min_bid = auction.min_bid()?;
+    if amount < min_bid {
+        return Err(ContractError::BidTooLow { bid: amount, min_bid });
+    }
+
+    let refund = auction.highest_bid.take().map(|previous| BankMsg::Send {
+        to_address: previous.bidder.into_string(),
+        amount: vec![Coin { denom: auction.reserve_price.denom.clone(), amount: previous.amount }],
+    });
+    auction.highest_bid = Some(AuctionBid { bidder: info.sender.clone(), amount });
+    // anti-sniping, a late bid leaves the other bidders time to answer
+    auction.end_time = auction.end_time.max(now + AUCTION_EXTENSION_SECONDS);
+    AUCTIONS.save(deps.storage, &token_id, &auction)?;
+
+    Ok(
+        Response::new()
+            .add_messages(refund)
+            .add_attributes(
+                vec![
+                    attr("action", "bid"),
+                    attr("bidder", info.sender),
+                    attr("token_id", token_id),
+                    attr("amount", amount),
+                    attr("end_time", auction.end_time.to_string())
+                ]
+            )
+    )
+}
+
+/// Closes an ended auction, selling the token if the highest bid meets the reserve price.
+/// A token that can no longer move to the winner stays with the seller and the winning bid
+/// is refunded, so the auction can always be closed.
+pub fn settle(mut deps: DepsMut, env: Env, token_id: String) -> Result<Response, ContractError> {
+    let auction = AUCTIONS.may_load(deps.storage, &token_id)?.ok_or_else(|| {
+        ContractError::NoAuction { token_id: token_id.clone() }
+    })?;
+    if env.block.time.seconds() < auction.end_time {
+        return Err(ContractError::AuctionNotEnded { token_id, end_time: auction.end_time });
+    }
+    AUCTIONS.remove(deps.storage, &token_id);
+
+    let denom = auction.reserve_price.denom.clone();
+    let winning_bid = auction.highest_bid
+        .clone()
+        .filter(|bid| bid.amount >= auction.reserve_price.amount);
+    let Some(winning_bid) = winning_bid else {
+        return Ok(unsold_auction(token_id, denom, auction.highest_bid));
+    };
+
+    let state = STATE.load(deps.storage)?;
+    let price = Coin { denom, amount: winning_bid.amount };
+    let sale = SaleSplit::new(deps.storage, &env, &state, &price)?;
+    // the checks of the transfer run before it writes anything, so a failed one leaves the
+    // token untouched
+    let transferred = transfer(
+        deps.branch(),
+        &env.block,
+        auction.seller.clone(),
+        winning_bid.bidder.clone(),
+        token_id.clone()
+    );
+    let response = match transferred {
+        Ok(response) => response,
+        Err(ContractError::Std(err)) => {
+            return Err(err.into());
+        }
+        Err(err) => {
+            let response = unsold_auction(token_id, price.denom, Some(winning_bid));
+            return Ok(response.add_attribute("reason", err.to_string()));
+        }
+    };
+
+    Ok(
+        response
+            .add_messages(sale.payouts(&state, &auction.seller))
+            .add_attributes(
+                vec![
+                    attr("action", "settle"),
+                    attr("token_id", token_id),
+                    attr("sold", "true"),
+                    attr("winner", winning_bid.bidder),
+                    attr("price", price.to_string()),
+                    attr("royalty", sale.royalty),
+                    attr("protocol_fee", sale.protocol_fee)
+                ]
+            )
+    )
+}
+
+/// Closes an auction without a sale, refunding the highest bid if there is one
+fn unsold_auction(token_id: String, denom: String, bid: Option<AuctionBid>) -> Response {
+    let refund = bid.map(|bid| BankMsg::Send {
+        to_address: bid.bidder.into_string(),
+        amount: vec![Coin { denom, amount: bid.amount }],
+    });
+    Response::new()
+        .add_messages(refund)
+        .add_attributes(
+            vec![attr("action", "settle"), attr("token_id", token_id), attr("sold", "false")]
+        )
+}
+
+pub fn ibc_transfer(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    token_id: String,
+    channel: String,
+    receiver: String,
+    timeout: Timestamp
+) -> Result<Response, ContractError> {
+    let state = STATE.load(deps.storage)?;
+    let ics721_address = state.ics721_address.ok_or(ContractError::BridgeUnset {})?;
+
+    // Only tokens that could be transferred locally may leave the chain
+    let token = assert_token_owner(deps.storage, &token_id, &info.sender)?;
+    assert_transferable(deps.storage, &env.block, &token_id, &token)?;
+    // the bridge holds the token while it's in flight, as with SendNft
+    LISTINGS.remove(deps.storage, &token_id);
+    SELF_LOCKS.remove(deps.storage, &token_id);
+    assign_owner(deps.storage, &token_id, token, &ics721_address)?;
+    let hooks = transfer_hook_msgs(
+        deps.storage,
+        "send",
+        &[token_id.clone()],
+        Some(&info.sender),
+        Some(&ics721_address)
+    )?;
+
+    BRIDGED.save(
+        deps.storage,
+        &token_id,
+        &(BridgedToken {
+            owner: info.sender.clone(),
+            channel: channel.clone(),
+            receiver: receiver.clone(),
+            status: BridgeStatus::Pending,
+        })
+    )?;
+
+    let payload = IbcOutgoingMsg {
+        receiver: receiver.clone(),
+        channel_id: channel.clone(),
+        timeout: IbcTimeoutMsg { block: None, timestamp: Some(timeout) },
+        memo: None,
+    };
+    let send = WasmMsg::Execute {
+        contract_addr: ics721_address.into_string(),
+        msg: to_binary(
+            &Ics721ExecuteMsg::ReceiveNft(Cw721ReceiveMsg {
+                sender: info.sender.to_string(),
+                token_id: token_id.clone(),
+                msg: to_binary(&payload)?,
+            })
+        )?,
+        funds: vec![],
+    };
+
+    Ok(
+        Response::new()
+            .add_message(send)
+            .add_submessages(hooks)
+            .add_attributes(
+                vec![
+                    attr("action", "ibc_transfer"),
+                    attr("sender", info.sender),
+                    attr("token_id", token_id),
+                    attr("channel", channel),
+                    attr("receiver", receiver)
+                ]
+            )
+    )
+}
+
+pub fn ibc_callback(
+    deps: DepsMut,
+    info: MessageInfo,
+    token_id: String,
+    outcome: IbcOutcome
+) -> Result<Response, ContractError> {
+    let state = STATE.load(deps.storage)?;
+    if state.ics721_address.as_ref() != Some(&info.sender) {
+        return Err(ContractError::unauthorized("ics721 bridge"));
+    }
+
+    let mut bridged = BRIDGED.may_load(deps.storage, &token_id)?.ok_or_else(|| {
+        ContractError::UnexpectedBridgeCallback { token_id: token_id.clone() }
+    })?;
+    let result = match (outcome, bridged.status) {
+        // the token stays escrowed while it lives on the counterparty chain
+        (IbcOutcome::AckSuccess {}, BridgeStatus::Pending) => {
+            bridged.status = BridgeStatus::Transferred;
+            BRIDGED.save(deps.storage, &token_id, &bridged)?;
+            "transferred".to_string()
+        }
+        // the transfer failed, the owner gets the token back
+        (IbcOutcome::AckError { error }, BridgeStatus::Pending) => {
+            release_bridged(deps.storage, &token_id, &bridged.owner)?;
+            format!("ack_error: {}", error)
+        }
+        (IbcOutcome::Timeout {}, BridgeStatus::Pending) => {
+            release_bridged(deps.storage, &token_id, &bridged.owner)?;
+            "timeout".to_string()
+        }
+        (IbcOutcome::Returned { recipient }, BridgeStatus::Transferred) => {
+            let recipient = validate_address(deps.api, "recipient", &recipient)?;
+            release_bridged(deps.storage, &token_id, &recipient)?;
+            "returned".to_string()
+        }
+        _ => {
+            return Err(ContractError::UnexpectedBridgeCallback { token_id });
+        }
+    };
+
+    Ok(
+        Response::new().add_attributes(
+            vec![attr("action", "ibc_callback"), attr("token_id", token_id), attr("result", result)]
+        )
+    )
+}
+
+/// Hands a token the bridge was holding to `recipient` and forgets the transfer
+fn release_bridged(
+    storage: &mut dyn Storage,
+    token_id: &str,
+    recipient: &Addr
+) -> Result<(), ContractError> {
+    let token = load_token(storage, token_id)?;
+    assign_owner(storage, token_id, token, recipient)?;
+    BRIDGED.remove(storage, token_id);
+    Ok(())
+}
+
+pub fn query_listings(
+    deps: Deps,
+    start_after: Option<String>,
+    limit: Option<u32>
+) -> StdResult<ListingsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.as_deref().map(Bound::exclusive);
+
+    let listings = LISTINGS.range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| {
+            item.map(|(token_id, listing)| ListingInfo {
+                token_id,
+                seller: listing.seller.into_string(),
+                price: listing.price,
+            })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(ListingsResponse { listings })
+}
+
+pub fn query_offers_by_token(
+    deps: Deps,
+    token_id: String,
+    start_after: Option<String>,
+    limit: Option<u32>
+) -> StdResult<OffersResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start_after = start_after.map(|bidder| deps.api.addr_validate(&bidder)).transpose()?;
+    let start = start_after.as_ref().map(Bound::exclusive);
+
+    let offers = offers()
+        .prefix(&token_id)
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(_, offer)| offer_info(token_id.clone(), offer)))
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(OffersResponse { offers })
+}
+
+pub fn query_offers_by_bidder(
+    deps: Deps,
+    bidder: String,
+    start_after: Option<String>,
+    limit: Option<u32>
+) -> StdResult<OffersResponse> {
+    let bidder = deps.api.addr_validate(&bidder)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(|token_id| {
+        Bound::ExclusiveRaw((token_id.as_str(), &bidder).joined_key())
+    });
+
+    let offers = offers().idx.bidder
+        .prefix(bidder.clone())
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|((token_id, _), offer)| offer_info(token_id, offer)))
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(OffersResponse { offers })
+}
+
+fn offer_info(token_id: String, offer: Offer) -> OfferInfo {
+    OfferInfo {
+        token_id,
+        bidder: offer.bidder.into_string(),
+        price: offer.price,
+        expires: offer.expires,
+    }
+}
+
+pub fn query_pending_rewards(
+    deps: Deps,
+    token_id: String
+) -> Result<PendingRewardsResponse, ContractError> {
+    load_token(deps.storage, &token_id)?;
+    let Some(rewards) = HOLDER_REWARDS.may_load(deps.storage)? else {
+        return Ok(PendingRewardsResponse { asset: None, amount: Uint128::zero() });
+    };
+    let (_, amount) = pending_rewards(deps.storage, &rewards, &token_id)?;
+    Ok(PendingRewardsResponse { asset: Some(rewards.asset), amount })
+}
+
+pub fn query_auctions(
+    deps: Deps,
+    start_after: Option<String>,
+    limit: Option<u32>
+) -> StdResult<AuctionsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.as_deref().map(Bound::exclusive);
+
+    let auctions = AUCTIONS.range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| {
+            item.map(|(token_id, auction)| AuctionInfo {
+                token_id,
+                seller: auction.seller.into_string(),
+                reserve_price: auction.reserve_price,
+                min_bid_increment: auction.min_bid_increment,
+                end_time: auction.end_time,
+                highest_bidder: auction.highest_bid.as_ref().map(|bid| bid.bidder.to_string()),
+                highest_bid: auction.highest_bid.map(|bid| bid.amount),
+            })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(AuctionsResponse { auctions })
+}
+
+pub fn query_bridged(
+    deps: Deps,
+    start_after: Option<String>,
+    limit: Option<u32>
+) -> StdResult<BridgedResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.as_deref().map(Bound::exclusive);
+
+    let tokens = BRIDGED.range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| {
+            item.map(|(token_id, bridged)| BridgedTokenInfo {
+                token_id,
+                owner: bridged.owner.into_string(),
+                channel: bridged.channel,
+                receiver: bridged.receiver,
+                status: bridged.status,
+            })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(BridgedResponse { tokens })
+}
+
+pub fn query_config(deps: Deps, env: Env) -> StdResult<ConfigResponse> {
+    let state = STATE.load(deps.storage)?;
+    let remaining_supply = state.remaining_supply();
+    let in_window = matches!(
+        state.phase(env.block.time.seconds()),
+        SalePhase::Presale | SalePhase::Public
+    );
+    Ok(ConfigResponse {
+        owner: state.owner.into_string(),
+        base_token_uri: state.base_token_uri,
+        base_token_uri_extension: state.base_token_uri_extension,
+        prereveal_token_uri: state.prereveal_token_uri,
+        uri_status: state.revealed(env.block.time.seconds()),
+        treasury_address: state.treasury_address.into_string(),
+        protocol_address: state.protocol_address.map(String::from).unwrap_or_default(),
+        protocol_fee_bps: state.protocol_fee_bps,
+        mint_denom: state.mint_denom,
+        mint_price: state.mint_price,
+        presale_start_time: state.presale_start_time,
+        presale_end_time: state.presale_end_time,
+        sale_start_time: state.sale_start_time,
+        sale_end_time: state.sale_end_time,
+        active_tier: state.active_tier,
+        max_supply: state.max_total_mint,
+        remaining_supply,
+        sale_active: in_window && remaining_supply != Some(0),
+        token_id_format: state.token_id_format,
+        reveal_at: state.reveal_at,
+    })
+}
+
+pub fn query_contract_info(deps: Deps, env: Env) -> StdResult<ContractInfoResponse> {
+    let state = STATE.load(deps.storage)?;
+    let cw721 = Cw721Contract::<Empty, Empty, Empty, Empty>::default();
+    let info = cw721.contract_info.load(deps.storage)?;
+    let royalty_rate = collection_class(deps.storage, &env)?.and_then(|class| class.royalty_rate);
+
+    Ok(ContractInfoResponse {
+        name: info.name,
+        symbol: info.symbol,
+        minter: cw721.minter.load(deps.storage)?,
+        royalty_rate,
+        contract_uri: state.contract_uri,
+        metadata_frozen: state.metadata_frozen,
+    })
+}
+
+pub fn query_contract_version(deps: Deps) -> StdResult<ContractVersionResponse> {
+    let version = cw2::get_contract_version(deps.storage)?;
+    Ok(ContractVersionResponse {
+        contract: version.contract,
+        version: version.version,
+        commit: BUILD_COMMIT.map(str::to_string),
+    })
+}
+
+pub fn query_sale_stats(deps: Deps) -> StdResult<SaleStatsResponse> {
+    let state = STATE.load(deps.storage)?;
+    let mints_per_day = MINTS_PER_DAY.range(deps.storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(day, mints)| DailyMints { day, mints }))
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(SaleStatsResponse {
+        gross_proceeds: state.gross_proceeds,
+        protocol_fees_accrued: state.protocol_fees_accrued,
+        total_mints: state.total_mints,
+        mints_per_day,
+    })
+}
+
+pub fn query_supply_info(deps: Deps) -> StdResult<SupplyInfoResponse> {
+    let state = STATE.load(deps.storage)?;
+    Ok(SupplyInfoResponse {
+        minted: state.minted_count,
+        burned: state.burned_count,
+        current_supply: state.minted_count - state.burned_count,
+        max_total_mint: state.max_total_mint,
+    })
+}
+
+pub fn query_hooks(deps: Deps) -> StdResult<HooksResponse> {
+    let hooks = TRANSFER_HOOKS.keys(deps.storage, None, None, Order::Ascending)
+        .map(|hook| hook.map(Addr::into_string))
+        .collect::<StdResult<_>>()?;
+    Ok(HooksResponse { hooks })
+}
+
+pub fn query_is_blacklisted(deps: Deps, address: String) -> StdResult<IsBlacklistedResponse> {
+    let address = deps.api.addr_validate(&address)?;
+    Ok(IsBlacklistedResponse { blacklisted: BLACKLIST.has(deps.storage, &address) })
+}
+
+pub fn query_blacklist(
+    deps: Deps,
+    start_after: Option<String>,
+    limit: Option<u32>
+) -> StdResult<BlacklistResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start_after = start_after.map(|address| deps.api.addr_validate(&address)).transpose()?;
+    let start = start_after.as_ref().map(Bound::exclusive);
+
+    let addresses = BLACKLIST.keys(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(Addr::into_string))
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(BlacklistResponse { addresses })
+}
+
+pub fn query_holders(
+    deps: Deps,
+    start_after: Option<String>,
+    limit: Option<u32>
+) -> StdResult<HoldersResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start_after = start_after.map(|address| deps.api.addr_validate(&address)).transpose()?;
+    let start = start_after.as_ref().map(Bound::exclusive);
+
+    let holders = HOLDER_BALANCES.range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| {
+            item.map(|(address, count)| HolderBalance { address: address.into_string(), count })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(HoldersResponse { holders })
+}
+
+pub fn query_whitelist(
+    deps: Deps,
+    start_after: Option<String>,
+    limit: Option<u32>
+) -> StdResult<WhitelistResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_WHITELIST_LIMIT) as usize;
+    let start_after = start_after.map(|address| deps.api.addr_validate(&address)).transpose()?;
+    let start = start_after.as_ref().map(Bound::exclusive);
+
+    let entries = WHITELIST.range(deps.storage, start, None, Order::Ascending)
+        .filter(|item| !matches!(item, Ok((_, false))))
+        .take(limit)
+        .map(|item| {
+            let (address, _) = item?;
+            let allocation = WHITELIST_ALLOCATIONS.may_load(deps.storage, &address)?;
+            Ok(WhitelistEntry { address: address.into_string(), allocation })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(WhitelistResponse { entries })
+}
+
+pub fn query_early_birds(
+    deps: Deps,
+    start_after: Option<u64>,
+    limit: Option<u32>
+) -> StdResult<EarlyBirdsResponse> {
+    let state = STATE.load(deps.storage)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive);
+
+    let early_birds = EARLY_BIRD_RANKS.range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(rank, address)| EarlyBird { rank, address: address.into_string() }))
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(EarlyBirdsResponse {
+        early_birds,
+        closed: state.early_bird_cutoff > 0 && state.early_birds >= state.early_bird_cutoff,
+    })
+}
+
+pub fn query_balance_of(deps: Deps, owner: String) -> StdResult<BalanceOfResponse> {
+    let owner = deps.api.addr_validate(&owner)?;
+    let count = HOLDER_BALANCES.may_load(deps.storage, &owner)?.unwrap_or_default();
+    Ok(BalanceOfResponse { count })
+}
+
+pub fn query_accrued_by_denom(deps: Deps, address: String) -> StdResult<AccruedByDenomResponse> {
+    let address = deps.api.addr_validate(&address)?;
+    let balances = BALANCES.prefix(&address)
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(denom, amount)| Coin { denom, amount }))
+        .collect::<StdResult<Vec<_>>>()?;
+    Ok(AccruedByDenomResponse { balances })
+}
+
+pub fn query_owner_of(
+    deps: Deps,
+    env: Env,
+    token_id: String
+) -> Result<OwnerOfResponse, ContractError> {
+    let token = load_token(deps.storage, &token_id)?;
+    let approvals = token.approvals
+        .into_iter()
+        .filter(|approval| !approval.expires.is_expired(&env.block))
+        .collect();
+    Ok(OwnerOfResponse { owner: token.owner.into_string(), approvals })
+}
+
+pub fn query_user_of(
+    deps: Deps,
+    env: Env,
+    token_id: String
+) -> Result<UserOfResponse, ContractError> {
+    let token = load_token(deps.storage, &token_id)?;
+    let response = match token.user {
+        Some(TokenUser { user, expires }) if !expires.is_expired(&env.block) => {
+            UserOfResponse { user: Some(user.into_string()), expires: Some(expires) }
+        }
+        _ => UserOfResponse { user: None, expires: None },
+    };
+    Ok(response)
+}
+
+pub fn query_token_lock(
+    deps: Deps,
+    env: Env,
+    token_id: String
+) -> Result<TokenLockResponse, ContractError> {
+    let token = load_token(deps.storage, &token_id)?;
+    let locked_until = token.locked_until.filter(|until| !until.is_expired(&env.block));
+    Ok(TokenLockResponse { locked_until })
+}
+
+pub fn query_lock_status(
+    deps: Deps,
+    env: Env,
+    token_id: String
+) -> Result<LockStatusResponse, ContractError> {
+    load_token(deps.storage, &token_id)?;
+    let unlock_time = self_lock(deps.storage, &env.block, &token_id)?;
+    Ok(LockStatusResponse { unlock_time })
+}
+
+pub fn query_quote_purchase(
+    deps: Deps,
+    env: Env,
+    buyer: String,
+    count: u64,
+    promo_code: Option<String>
+) -> Result<PurchaseQuoteResponse, ContractError> {
+    validate_address(deps.api, "buyer", &buyer)?;
+    STATE.load(deps.storage)?.quote(count, promo_code.as_deref(), env.block.time.seconds())
+}
+
+pub fn query_batch_token_metadata(
+    deps: Deps,
+    env: Env,
+    token_ids: Vec<String>
+) -> Result<BatchTokenMetadataResponse, ContractError> {
+    if token_ids.len() > MAX_METADATA_BATCH {
+        return Err(ContractError::BatchTooLarge {
+            size: token_ids.len(),
+            max: MAX_METADATA_BATCH,
+        });
+    }
+
+    let state = STATE.load(deps.storage)?;
+    let now = env.block.time.seconds();
+    let entries = token_ids
+        .into_iter()
+        .map(|token_id| {
+            let metadata = match tokens().may_load(deps.storage, &token_id)? {
+                Some(token) => Some(TokenMetadata {
+                    token_uri: resolve_token_uri(
+                        deps,
+                        &state,
+                        &token_id,
+                        token.uri_override,
+                        now
+                    )?,
+                    owner: token.owner.into_string(),
+                    uri_hash: token.uri_hash,
+                    frozen: token.frozen,
+                }),
+                None => None,
+            };
+            Ok((token_id, metadata))
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+    Ok(BatchTokenMetadataResponse { tokens: entries })
+}
+
+pub fn query_token_uri(
+    deps: Deps,
+    env: Env,
+    token_id: String
+) -> Result<TokenUriResponse, ContractError> {
+    let token = load_token(deps.storage, &token_id)?;
+    let state = STATE.load(deps.storage)?;
+    let now = env.block.time.seconds();
+    let token_uri = resolve_token_uri(deps, &state, &token_id, token.uri_override, now)?;
+    Ok(TokenUriResponse { token_uri })
+}
+
+pub fn query_nft_info(
+    deps: Deps,
+    env: Env,
+    token_id: String
+) -> Result<NftInfoResponse<Option<Metadata>>, ContractError> {
+    let token = load_token(deps.storage, &token_id)?;
+    let state = STATE.load(deps.storage)?;
+    let now = env.block.time.seconds();
+    let token_uri = resolve_token_uri(deps, &state, &token_id, token.uri_override, now)?;
+    Ok(NftInfoResponse { token_uri: Some(token_uri), extension: token.extension })
+}
+
+pub fn query_all_nft_info(
+    deps: Deps,
+    env: Env,
+    token_id: String,
+    include_expired: Option<bool>
+) -> Result<AllNftInfoResponse<Option<Metadata>>, ContractError> {
+    let token = load_token(deps.storage, &token_id)?;
+    let include_expired = include_expired.unwrap_or(false);
+    let approvals = token.approvals
+        .into_iter()
+        .filter(|approval| include_expired || !approval.expires.is_expired(&env.block))
+        .collect();
+    Ok(AllNftInfoResponse {
+        access: OwnerOfResponse { owner: token.owner.into_string(), approvals },
+        info: query_nft_info(deps, env, token_id)?,
+    })
+}
+
+/// Uri of a minted token: the one given at mint, otherwise the metadata resolver's answer,
+/// otherwise the uri derived from the base uri
+fn resolve_token_uri(
+    deps: Deps,
+    state: &State,
+    token_id: &str,
+    uri_override: Option<String>,
+    now: u64
+) -> StdResult<String> {
+    if let Some(uri) = uri_override {
+        return Ok(uri);
+    }
+    match &state.metadata_resolver {
+        Some(resolver) => {
+            let query = MetadataResolverQueryMsg::TokenUri { token_id: token_id.to_string() };
+            let res: TokenUriResponse = deps.querier.query_wasm_smart(resolver, &query)?;
+            Ok(res.token_uri)
+        }
+        None => Ok(state.token_uri(token_id, now)),
+    }
+}
+
+pub fn query_simulate_purchase(
+    deps: Deps,
+    env: Env,
+    buyer: String,
+    count: u64,
+    proof: Option<Vec<Binary>>,
+    allowlist_index: Option<u32>
+) -> Result<SimulatePurchaseResponse, ContractError> {
+    let state = STATE.load(deps.storage)?;
+    let buyer = validate_address(deps.api, "buyer", &buyer)?;
+    let order = PurchaseOrder {
+        payer: &buyer,
+        recipient: &buyer,
+        count,
+        proof: proof.as_deref(),
+        max_price_per_token: None,
+        referrer: None,
+        voucher: false,
+        allowlist_index,
+    };
+
+    if let Err(err) = state.check_purchase(deps.storage, env.block.time.seconds(), &order) {
+        let reason = PurchaseBlocker::try_from(err)?;
+        return Ok(SimulatePurchaseResponse::Blocked { reason });
+    }
+    let total = state.quote(count, None, env.block.time.seconds())?.total;
+    let funds = vec![Coin { denom: state.mint_denom, amount: total }];
+    Ok(SimulatePurchaseResponse::Ok { funds })
+}
+
+pub fn query_buyer_profile(
+    deps: Deps,
+    env: Env,
+    address: String
+) -> Result<BuyerProfileResponse, ContractError> {
+    let state = STATE.load(deps.storage)?;
+    let buyer = validate_address(deps.api, "address", &address)?;
+    let now = env.block.time.seconds();
+
+    let minted = MINTED_PER_WALLET.may_load(deps.storage, &buyer)?.unwrap_or_default();
+    let phase = state.active_phase(now);
+    let minted_in_phase = match phase {
+        Some(phase) => {
+            MINTED_PER_PHASE.may_load(deps.storage, (phase.label.as_str(), &buyer))?
+                .unwrap_or_default()
+        }
+        None => 0,
+    };
+    // the same limits `check_wallet_mints` and `check_phase_mints` enforce
+    let limits = [
+        (state.max_per_wallet, minted),
+        (phase.map_or(0, |phase| phase.per_wallet_limit), minted_in_phase),
+    ];
+    let remaining_wallet_limit = limits
+        .into_iter()
+        .filter(|(limit, _)| *limit != 0)
+        .map(|(limit, minted)| limit.saturating_sub(minted))
+        .min();
+
+    let reveal_height = COMMITMENTS.may_load(deps.storage, &buyer)?.map(
+        |commitment| commitment.committed_at + MIN_REVEAL_DELAY_BLOCKS
+    );
+
+    Ok(BuyerProfileResponse {
+        whitelisted: is_whitelisted(deps.storage, &buyer)?,
+        blacklisted: BLACKLIST.has(deps.storage, &buyer),
+        active_tier: state.active_tier,
+        remaining_allocation: WHITELIST_ALLOCATIONS.may_load(deps.storage, &buyer)?,
+        minted,
+        minted_in_phase,
+        remaining_wallet_limit,
+        reveal_height,
+        price: state.price_at(now),
+    })
+}
+
+pub fn query_can_mint(
+    deps: Deps,
+    env: Env,
+    buyer: String,
+    count: u64
+) -> Result<CanMintResponse, ContractError> {
+    let reason = match query_simulate_purchase(deps, env, buyer, count, None, None)? {
+        SimulatePurchaseResponse::Ok { .. } => None,
+        SimulatePurchaseResponse::Blocked { reason } => {
+            Some(ContractError::from(reason).to_string())
+        }
+    };
+    Ok(CanMintResponse { allowed: reason.is_none(), reason })
+}
+
+pub fn query_schedule(deps: Deps, env: Env) -> StdResult<ScheduleResponse> {
+    let state = STATE.load(deps.storage)?;
+    Ok(ScheduleResponse {
+        presale_start: state.presale_start_time,
+        presale_end: state.presale_end_time,
+        sale_start_time: state.sale_start_time,
+        sale_end_time: state.sale_end_time,
+        effective_sale_end_time: state.effective_sale_end(),
+        phase: state.phase(env.block.time.seconds()),
+    })
+}
+
+pub fn query_tokens(
+    deps: Deps,
+    owner: String,
+    start_after: Option<String>,
+    limit: Option<u32>
+) -> StdResult<TokensResponse> {
+    let owner = deps.api.addr_validate(&owner)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(|token_id| Bound::ExclusiveRaw(token_id.into()));
+
+    let tokens = tokens().idx.owner
+        .prefix(owner)
+        .keys(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(TokensResponse { tokens })
+}
+
+pub fn query_all_tokens(
+    deps: Deps,
+    start_after: Option<String>,
+    limit: Option<u32>
+) -> StdResult<TokensResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.as_deref().map(Bound::exclusive);
+
+    let tokens = tokens()
+        .keys(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(TokensResponse { tokens })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::{ coin, coins, from_slice };
+    use cosmwasm_std::testing::{
+        mock_dependencies,
+        mock_env,
+        mock_info,
+        MockApi,
+        MockQuerier,
+        MockStorage,
+    };
+    use cosmwasm_std::{
+        from_binary,
+        ContractResult,
+        OwnedDeps,
+        Record,
+        SystemError,
+        SystemResult,
+        Timestamp,
+        WasmQuery,
+    };
+    use std::cell::Cell;
+    use std::marker::PhantomData;
+    use cosmwasm_std::Attribute;
+    use cw_utils::Expiration;
+    use cw_multi_test::{ App, AppResponse, BankSudo, Contract, ContractWrapper, Executor };
+
+    const OWNER: &str = "owner";
+    const BUYER: &str = "buyer";
+    const TREASURY: &str = "treasury";
+    const PROTOCOL: &str = "protocol";
+    const DENOM: &str = "ucore";
+
+    fn collection_contract() -> Box<dyn Contract<Empty>> {
+        Box::new(
+            ContractWrapper::new(execute, instantiate, query)
+                .with_migrate(migrate)
+                .with_reply(reply)
+        )
+    }
+
+    fn instantiate_msg(start: u64, end: u64) -> InstantiateMsg {
+        InstantiateMsg {
+            deployment_config: ProptixDeploymentConfig {
+                name: "Proptix".to_string(),
+                symbol: "PTX".to_string(),
+                max_supply: 10,
+                treasury_address: TREASURY.to_string(),
+                protocol_address: None,
+                users_disabled: false,
+                royalty_enforced: false,
+                max_per_wallet_holding: None,
+                minter: None,
+                token_id_format: None,
+                factory_fee_bps: 0,
+            },
+            runtime_config: ProptixRuntimeConfig {
+                base_token_uri: "ipfs://base/".to_string(),
+                base_token_uri_extension: ".json".to_string(),
+                prereveal_token_uri: "ipfs://prereveal".to_string(),
+                mint_denom: DENOM.to_string(),
+                mint_price: Uint128::new(100),
+                sale_start_time: start,
+                sale_end_time: end,
+                protocol_fee: 0,
+                presale_start_time: 0,
+                presale_end_time: 0,
+                max_per_wallet: 0,
+                lock_schedule_on_start: false,
+                lock_whitelist_after_start: false,
+                pass_collection: None,
+                referral_bps: 0,
+                reveal_at: None,
+                phases: vec![],
+                grace_period_secs: 0,
+                grace_threshold_pct: 0,
+                max_uri_len: 0,
+                min_threshold: 0,
+                early_bird_cutoff: 0,
+                finalize_actions: FinalizeActions::default(),
+                mint_receipts: false,
+                uri_veto_threshold: 0,
+                max_mints_per_block: 0,
+            },
+        }
+    }
+
+    /// State instantiated from `instantiate_msg` without a sale schedule
+    fn blank_state() -> State {
+        let msg = instantiate_msg(0, 0);
+        let api = MockApi::default();
+        State::new(&api, Addr::unchecked(OWNER), &msg.deployment_config, &msg.runtime_config)
+            .unwrap()
+    }
+
+    /// Makes `OWNER` the cw721 minter of a state saved without `instantiate`
+    fn save_minter(storage: &mut dyn Storage) {
+        let cw721 = Cw721Contract::<Empty, Empty, Empty, Empty>::default();
+        cw721.minter.save(storage, &Addr::unchecked(OWNER)).unwrap();
+    }
+
+    fn setup_app() -> (App, Addr) {
+        setup_app_with(|_| {})
+    }
+
+    fn setup_app_with(customize: impl FnOnce(&mut InstantiateMsg)) -> (App, Addr) {
+        let mut app = App::new(|router, _, storage| {
+            router.bank.init_balance(storage, &Addr::unchecked(BUYER), coins(1_000, DENOM)).unwrap();
+        });
+        let code_id = app.store_code(collection_contract());
+        let now = app.block_info().time.seconds();
+        let mut msg = instantiate_msg(now, now + 3_600);
+        customize(&mut msg);
+        let contract = app
+            .instantiate_contract(code_id, Addr::unchecked(OWNER), &msg, &[], "collection", None)
+            .unwrap();
+        (app, contract)
+    }
+
+    fn purchase_msg(count: u64) -> ExecuteMsg {
+        ExecuteMsg::Purchase {
+            count,
+            proof: None,
+            max_price_per_token: None,
+            recipient: None,
+            referrer: None,
+            allowlist_index: None,
+        }
+    }
+
+    fn whitelist_buyer(app: &mut App, contract: &Addr) {
+        app.execute_contract(
+            Addr::unchecked(OWNER),
+            contract.clone(),
+            &(ExecuteMsg::Whitelist { address: BUYER.to_string(), status: true }),
+            &[]
+        ).unwrap();
+    }
+
+    fn load_state(app: &App, contract: &Addr) -> State {
+        let raw = app.wrap().query_wasm_raw(contract, STATE.as_slice()).unwrap().unwrap();
+        from_slice(&raw).unwrap()
+    }
+
+    fn load_owner(app: &App, contract: &Addr, token_id: &str) -> Option<Addr> {
+        app.wrap()
+            .query_wasm_raw(contract, tokens().key(token_id).to_vec())
+            .unwrap()
+            .map(|raw| from_slice::<TokenRecord>(&raw).unwrap().owner)
+    }
+
+    fn query_all_tokens(app: &App, contract: &Addr) -> Vec<String> {
+        let res: TokensResponse = app
+            .wrap()
+            .query_wasm_smart(contract, &(QueryMsg::AllTokens { start_after: None, limit: None }))
+            .unwrap();
+        res.tokens
+    }
+
+    /// Attributes the contract emitted, without the `_contract_addr` added by the runtime
+    fn wasm_attributes(res: &AppResponse) -> Vec<Attribute> {
+        let wasm = res.events.iter().find(|event| event.ty == "wasm").unwrap();
+        wasm.attributes.iter().filter(|attr| attr.key != "_contract_addr").cloned().collect()
+    }
+
+    fn load_balance(app: &App, contract: &Addr, addr: &str) -> Uint128 {
+        app.wrap()
+            .query_wasm_raw(contract, BALANCES.key((&Addr::unchecked(addr), DENOM)).to_vec())
+            .unwrap()
+            .map(|raw| from_slice(&raw).unwrap())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn state_is_saved_at_instantiate() {
+        let (app, contract) = setup_app();
+
+        let state = load_state(&app, &contract);
+        assert_eq!(state.max_total_mint, 10);
+        assert_eq!(state.mint_price, Uint128::new(100));
+        assert_eq!(state.mint_denom, DENOM);
+        assert_eq!(state.treasury_address, TREASURY);
+        assert_eq!(state.current_token_id, 0);
+    }
+
+    /// Checks the supply counters against the tokens actually stored
+    fn assert_supply(app: &App, contract: &Addr, minted: u64, burned: u64) {
+        let info: SupplyInfoResponse = app
+            .wrap()
+            .query_wasm_smart(contract, &(QueryMsg::SupplyInfo {}))
+            .unwrap();
+        assert_eq!(info, SupplyInfoResponse {
+            minted,
+            burned,
+            current_supply: minted - burned,
+            max_total_mint: 10,
+        });
+        let num_tokens: NumTokensResponse = app
+            .wrap()
+            .query_wasm_smart(contract, &(QueryMsg::NumTokens {}))
+            .unwrap();
+        assert_eq!(num_tokens.count, info.current_supply);
+        assert_eq!(query_all_tokens(app, contract).len() as u64, num_tokens.count);
+    }
+
+    #[test]
+    fn first_buyers_are_ranked_until_the_cutoff() {
+        let (mut app, contract) = setup_app_with(|msg| {
+            msg.runtime_config.early_bird_cutoff = 2;
+        });
+        for buyer in [BUYER, "second", "third"] {
+            app.sudo(
+                (BankSudo::Mint { to_address: buyer.to_string(), amount: coins(300, DENOM) }).into()
+            ).unwrap();
+            app.execute_contract(
+                Addr::unchecked(OWNER),
+                contract.clone(),
+                &(ExecuteMsg::Whitelist { address: buyer.to_string(), status: true }),
+                &[]
+            ).unwrap();
+        }
+        let buy = |app: &mut App, buyer: &str, count: u64| {
+            app.execute_contract(
+                Addr::unchecked(buyer),
+                contract.clone(),
+                &purchase_msg(count),
+                &coins(100 * u128::from(count), DENOM)
+            ).unwrap();
+        };
+        let rank = |app: &App, address: &str| -> Option<u64> {
+            let res: EarlyBirdRankResponse = app
+                .wrap()
+                .query_wasm_smart(&contract, &(QueryMsg::EarlyBirdRank {
+                    address: address.to_string(),
+                }))
+                .unwrap();
+            res.rank
+        };
+        let early_birds = |app: &App, start_after: Option<u64>| -> EarlyBirdsResponse {
+            app.wrap()
+                .query_wasm_smart(&contract, &(QueryMsg::EarlyBirds { start_after, limit: None }))
+                .unwrap()
+        };
+
+        // a batch purchase ranks its buyer once, buying again keeps the rank
+        buy(&mut app, BUYER, 3);
+        buy(&mut app, BUYER, 1);
+        assert_eq!(rank(&app, BUYER), Some(1));
+        assert!(!early_birds(&app, None).closed);
+
+        // the buyer reaching the cutoff is the last one ranked
+        buy(&mut app, "second", 2);
+        buy(&mut app, "third", 2);
+        assert_eq!(rank(&app, "second"), Some(2));
+        assert_eq!(rank(&app, "third"), None);
+        assert_eq!(early_birds(&app, None), EarlyBirdsResponse {
+            early_birds: vec![
+                EarlyBird { rank: 1, address: BUYER.to_string() },
+                EarlyBird { rank: 2, address: "second".to_string() }
+            ],
+            closed: true,
+        });
+        assert_eq!(early_birds(&app, Some(1)).early_birds, vec![EarlyBird {
+            rank: 2,
+            address: "second".to_string(),
+        }]);
+    }
+
+    #[test]
+    fn finalize_sale_runs_its_actions_once_the_sale_is_over() {
+        let (mut app, contract) = setup_app_with(|msg| {
+            msg.runtime_config.finalize_actions = FinalizeActions {
+                sweep_proceeds: true,
+                freeze_supply: true,
+                reveal: true,
+            };
+        });
+        whitelist_buyer(&mut app, &contract);
+        app.execute_contract(
+            Addr::unchecked(BUYER),
+            contract.clone(),
+            &purchase_msg(2),
+            &coins(200, DENOM)
+        ).unwrap();
+        let finalize = ExecuteMsg::FinalizeSale {};
+        let now = app.block_info().time.seconds();
+        let err = app
+            .execute_contract(Addr::unchecked(BUYER), contract.clone(), &finalize, &[])
+            .unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::SaleNotEnded {
+            ends_at: now + 3_600,
+            now,
+        });
+
+        app.update_block(|block| {
+            block.time = block.time.plus_seconds(3_601);
+        });
+        let accrued = load_balance(&app, &contract, TREASURY);
+        assert!(!accrued.is_zero());
+        let res = app
+            .execute_contract(Addr::unchecked(BUYER), contract.clone(), &finalize, &[])
+            .unwrap();
+        assert_eq!(
+            wasm_attributes(&res),
+            vec![
+                attr("action", "finalize_sale"),
+                attr("minted", "2"),
+                attr("burned", "0"),
+                attr("sold", "2"),
+                attr("max_supply", "2")
+            ]
+        );
+        assert_eq!(load_balance(&app, &contract, TREASURY), Uint128::zero());
+        assert_eq!(app.wrap().query_balance(TREASURY, DENOM).unwrap().amount, accrued);
+        let state = load_state(&app, &contract);
+        assert!(state.supply_frozen && state.uri_status && state.sale_finalized);
+        assert_eq!(state.max_total_mint, 2);
+
+        // the second call changes nothing
+        let err = app
+            .execute_contract(Addr::unchecked(BUYER), contract.clone(), &finalize, &[])
+            .unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::SaleFinalized {});
+        assert_eq!(load_state(&app, &contract), state);
+    }
+
+    #[test]
+    fn sale_reaching_its_threshold_releases_the_escrow() {
+        let (mut app, contract) = setup_app_with(|msg| {
+            msg.runtime_config.min_threshold = 2;
+        });
+        whitelist_buyer(&mut app, &contract);
+        app.execute_contract(
+            Addr::unchecked(BUYER),
+            contract.clone(),
+            &purchase_msg(2),
+            &coins(200, DENOM)
+        ).unwrap();
+
+        // nothing is credited while the sale runs
+        assert_eq!(load_balance(&app, &contract, TREASURY), Uint128::zero());
+        let release = ExecuteMsg::ReleaseEscrow {};
+        let now = app.block_info().time.seconds();
+        let err = app
+            .execute_contract(Addr::unchecked(BUYER), contract.clone(), &release, &[])
+            .unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::SaleNotEnded {
+            ends_at: now + 3_600,
+            now,
+        });
+
+        app.update_block(|block| {
+            block.time = block.time.plus_seconds(3_601);
+        });
+        let res = app
+            .execute_contract(Addr::unchecked(BUYER), contract.clone(), &release, &[])
+            .unwrap();
+        assert_eq!(
+            wasm_attributes(&res),
+            vec![attr("action", "release_escrow"), attr("sold", "2")]
+        );
+        let credited =
+            load_balance(&app, &contract, TREASURY) + load_balance(&app, &contract, PROTOCOL);
+        assert_eq!(credited, Uint128::new(200));
+        assert!(!load_state(&app, &contract).escrows_sales());
+
+        let err = app
+            .execute_contract(Addr::unchecked(BUYER), contract.clone(), &release, &[])
+            .unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::EscrowReleased {});
+        let refund = ExecuteMsg::ClaimRefund {};
+        let err = app
+            .execute_contract(Addr::unchecked(BUYER), contract.clone(), &refund, &[])
+            .unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::ThresholdMet {
+            threshold: 2,
+        });
+    }
+
+    #[test]
+    fn sale_missing_its_threshold_refunds_buyers() {
+        let (mut app, contract) = setup_app_with(|msg| {
+            msg.runtime_config.min_threshold = 5;
+        });
+        whitelist_buyer(&mut app, &contract);
+        app.execute_contract(
+            Addr::unchecked(BUYER),
+            contract.clone(),
+            &purchase_msg(2),
+            &coins(200, DENOM)
+        ).unwrap();
+        let refund = ExecuteMsg::ClaimRefund {};
+        let err = app
+            .execute_contract(Addr::unchecked(BUYER), contract.clone(), &refund, &[])
+            .unwrap_err();
+        let err = err.downcast::<ContractError>().unwrap();
+        assert!(matches!(err, ContractError::SaleNotEnded { .. }));
+
+        app.update_block(|block| {
+            block.time = block.time.plus_seconds(3_601);
+        });
+        let release = ExecuteMsg::ReleaseEscrow {};
+        let err = app
+            .execute_contract(Addr::unchecked(OWNER), contract.clone(), &release, &[])
+            .unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::ThresholdNotMet {
+            sold: 2,
+            threshold: 5,
+        });
+
+        // the bought tokens have to be back with the buyer to be refunded
+        let transfer = |recipient: &str| ExecuteMsg::TransferNft {
+            recipient: recipient.to_string(),
+            token_id: "1".to_string(),
+            sale_price: None,
+        };
+        app.execute_contract(Addr::unchecked(BUYER), contract.clone(), &transfer("friend"), &[])
+            .unwrap();
+        let err = app
+            .execute_contract(Addr::unchecked(BUYER), contract.clone(), &refund, &[])
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::unauthorized("token owner")
+        );
+        app.execute_contract(Addr::unchecked("friend"), contract.clone(), &transfer(BUYER), &[])
+            .unwrap();
+
+        let res = app
+            .execute_contract(Addr::unchecked(BUYER), contract.clone(), &refund, &[])
+            .unwrap();
+        assert_eq!(
+            wasm_attributes(&res),
+            vec![
+                attr("action", "claim_refund"),
+                attr("buyer", BUYER),
+                attr("amount", "200ucore"),
+                attr("token_id", "0"),
+                attr("token_id", "1")
+            ]
+        );
+        let balance = app.wrap().query_balance(BUYER, DENOM).unwrap();
+        assert_eq!(balance.amount, Uint128::new(1_000));
+        // the tokens are gone, the payment can only be refunded once
+        assert_eq!(load_owner(&app, &contract, "0"), None);
+        assert_eq!(load_owner(&app, &contract, "1"), None);
+        assert_eq!(load_state(&app, &contract).burned_count, 2);
+        let err = app
+            .execute_contract(Addr::unchecked(BUYER), contract.clone(), &refund, &[])
+            .unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::NothingToRefund {});
+    }
+
+    #[test]
+    fn refund_is_refused_once_a_bought_token_was_burned() {
+        let (mut app, contract) = setup_app_with(|msg| {
+            msg.runtime_config.min_threshold = 5;
+        });
+        whitelist_buyer(&mut app, &contract);
+        app.execute_contract(
+            Addr::unchecked(BUYER),
+            contract.clone(),
+            &purchase_msg(2),
+            &coins(200, DENOM)
+        ).unwrap();
+        let burn = ExecuteMsg::Burn { class_id: "ptx".to_string(), id: "0".to_string() };
+        app.execute_contract(Addr::unchecked(BUYER), contract.clone(), &burn, &[]).unwrap();
+        app.update_block(|block| {
+            block.time = block.time.plus_seconds(3_601);
+        });
+
+        // the full payment can't come back for half the tokens
+        let refund = ExecuteMsg::ClaimRefund {};
+        let err = app
+            .execute_contract(Addr::unchecked(BUYER), contract.clone(), &refund, &[])
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::TokenNotFound { token_id: "0".to_string() }
+        );
+        assert_eq!(load_owner(&app, &contract, "1"), Some(Addr::unchecked(BUYER)));
+    }
+
+    #[test]
+    fn supply_counts_mints_and_burns() {
+        let (mut app, contract) = setup_app();
+        whitelist_buyer(&mut app, &contract);
+        assert_supply(&app, &contract, 0, 0);
+
+        app.execute_contract(
+            Addr::unchecked(BUYER),
+            contract.clone(),
+            &purchase_msg(2),
+            &coins(200, DENOM)
+        ).unwrap();
+        assert_supply(&app, &contract, 2, 0);
+
+        let burn = |id: &str| ExecuteMsg::Burn { class_id: "ptx".to_string(), id: id.to_string() };
+        app.execute_contract(Addr::unchecked(BUYER), contract.clone(), &burn("0"), &[]).unwrap();
+        assert_supply(&app, &contract, 2, 1);
+
+        // owner mints count towards the supply like sales
+        app.execute_contract(
+            Addr::unchecked(OWNER),
+            contract.clone(),
+            &(ExecuteMsg::Mint {
+                class_id: "ptx".to_string(),
+                id: "a".to_string(),
+                uri: None,
+                uri_hash: None,
+                data: None,
+            }),
+            &[]
+        ).unwrap();
+        assert_supply(&app, &contract, 3, 1);
+
+        app.execute_contract(Addr::unchecked(OWNER), contract.clone(), &burn("a"), &[]).unwrap();
+        app.execute_contract(Addr::unchecked(BUYER), contract.clone(), &burn("1"), &[]).unwrap();
+        assert_supply(&app, &contract, 3, 3);
+
+        // burned ids are never reissued, the next sale mints a new token
+        app.execute_contract(
+            Addr::unchecked(BUYER),
+            contract.clone(),
+            &purchase_msg(1),
+            &coins(100, DENOM)
+        ).unwrap();
+        assert_supply(&app, &contract, 4, 3);
+        assert_eq!(query_all_tokens(&app, &contract), vec!["2".to_string()]);
+    }
+
+    #[test]
+    fn state_persists_across_messages() {
+        let (mut app, contract) = setup_app();
+
+        // only whitelisted buyers can purchase
+        app.execute_contract(
+            Addr::unchecked(BUYER),
+            contract.clone(),
+            &purchase_msg(1),
+            &coins(100, DENOM)
+        ).unwrap_err();
+
+        app.execute_contract(
+            Addr::unchecked(OWNER),
+            contract.clone(),
+            &(ExecuteMsg::Whitelist { address: BUYER.to_string(), status: true }),
+            &[]
+        ).unwrap();
+
+        app.execute_contract(
+            Addr::unchecked(BUYER),
+            contract.clone(),
+            &purchase_msg(1),
+            &coins(100, DENOM)
+        ).unwrap();
+        assert_eq!(load_state(&app, &contract).current_token_id, 1);
+
+        // the second purchase continues from the persisted cursor
+        app.execute_contract(
+            Addr::unchecked(BUYER),
+            contract.clone(),
+            &purchase_msg(1),
+            &coins(100, DENOM)
+        ).unwrap();
+        assert_eq!(load_state(&app, &contract).current_token_id, 2);
+
+        let minted = query_all_tokens(&app, &contract);
+        assert_eq!(minted, vec!["0".to_string(), "1".to_string()]);
+        for token_id in minted {
+            assert_eq!(load_owner(&app, &contract, &token_id), Some(Addr::unchecked(BUYER)));
+        }
+
+        // sales income accumulated in the treasury balance
+        assert_eq!(load_balance(&app, &contract, TREASURY), Uint128::new(200));
+        assert_eq!(load_balance(&app, &contract, BUYER), Uint128::zero());
+    }
+
+    #[test]
+    fn minted_metadata_is_returned_by_nft_info() {
+        let mut deps = mock_dependencies();
+        STATE.save(deps.as_mut().storage, &blank_state()).unwrap();
+        save_minter(deps.as_mut().storage);
+        let metadata = Metadata {
+            name: Some("Villa #1".to_string()),
+            image: Some("ipfs://villa/1.png".to_string()),
+            attributes: Some(vec![Trait {
+                display_type: None,
+                trait_type: "rooms".to_string(),
+                value: "4".to_string(),
+            }]),
+            ..Metadata::default()
+        };
+        let mint = |id: &str, data: Option<Binary>| ExecuteMsg::Mint {
+            class_id: "ptx".to_string(),
+            id: id.to_string(),
+            uri: Some(format!("ipfs://{}.json", id)),
+            uri_hash: None,
+            data,
+        };
+        let info = mock_info(OWNER, &[]);
+        let data = to_binary(&metadata).unwrap();
+        execute(deps.as_mut(), mock_env(), info.clone(), mint("a", Some(data))).unwrap();
+        execute(deps.as_mut(), mock_env(), info.clone(), mint("b", None)).unwrap();
+        let garbage = Some(Binary::from(b"not metadata".to_vec()));
+        let err = execute(deps.as_mut(), mock_env(), info, mint("c", garbage)).unwrap_err();
+        assert_eq!(err, ContractError::invalid_config("data", "must hold the token metadata"));
+
+        let nft_info = |token_id: &str| -> NftInfoResponse<Option<Metadata>> {
+            let msg = QueryMsg::NftInfo { token_id: token_id.to_string() };
+            from_binary(&query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap()
+        };
+        assert_eq!(nft_info("a"), NftInfoResponse {
+            token_uri: Some("ipfs://a.json".to_string()),
+            extension: Some(metadata.clone()),
+        });
+        assert_eq!(nft_info("b").extension, None);
+
+        let msg = QueryMsg::AllNftInfo { token_id: "a".to_string(), include_expired: None };
+        let res: AllNftInfoResponse<Option<Metadata>> = from_binary(
+            &query(deps.as_ref(), mock_env(), msg).unwrap()
+        ).unwrap();
+        assert_eq!(res.access, OwnerOfResponse { owner: OWNER.to_string(), approvals: vec![] });
+        assert_eq!(res.info, nft_info("a"));
+        assert!(query(deps.as_ref(), mock_env(), QueryMsg::NftInfo {
+            token_id: "c".to_string(),
+        }).is_err());
+    }
+
+    #[test]
+    fn token_uri_resolves_through_the_metadata_resolver() {
+        let mut deps = mock_dependencies();
+        STATE.save(deps.as_mut().storage, &State { uri_status: true, ..blank_state() }).unwrap();
+        let buyer = Addr::unchecked(BUYER);
+        tokens()
+            .save(deps.as_mut().storage, "a", &TokenRecord::new(buyer.clone(), None))
+            .unwrap();
+        let pinned = TokenRecord::new(buyer, Some("ipfs://pinned".to_string()));
+        tokens().save(deps.as_mut().storage, "b", &pinned).unwrap();
+        let token_uri = |deps: Deps, token_id: &str| -> String {
+            let msg = QueryMsg::TokenUri { token_id: token_id.to_string() };
+            let res = query(deps, mock_env(), msg).unwrap();
+            from_binary::<TokenUriResponse>(&res).unwrap().token_uri
+        };
+
+        // without a resolver uris come from the base uri
+        assert_eq!(token_uri(deps.as_ref(), "a"), "ipfs://base/a.json");
+        assert_eq!(token_uri(deps.as_ref(), "b"), "ipfs://pinned");
+
+        deps.querier.update_wasm(|query| match query {
+            WasmQuery::Smart { contract_addr, msg } if contract_addr == "resolver" => {
+                let MetadataResolverQueryMsg::TokenUri { token_id } = from_binary(msg).unwrap();
+                let res = TokenUriResponse { token_uri: format!("https://art/{}", token_id) };
+                SystemResult::Ok(ContractResult::Ok(to_binary(&res).unwrap()))
+            }
+            _ => SystemResult::Err(SystemError::UnsupportedRequest { kind: "wasm".to_string() }),
+        });
+        STATE.update(deps.as_mut().storage, |state| -> StdResult<_> {
+            Ok(State { metadata_resolver: Some(Addr::unchecked("resolver")), ..state })
+        }).unwrap();
+
+        // the resolver answers for every token without a uri of its own
+        assert_eq!(token_uri(deps.as_ref(), "a"), "https://art/a");
+        assert_eq!(token_uri(deps.as_ref(), "b"), "ipfs://pinned");
+        let res = query_batch_token_metadata(
+            deps.as_ref(),
+            mock_env(),
+            vec!["a".to_string(), "missing".to_string()]
+        ).unwrap();
+        let uris: Vec<_> = res.tokens
+            .into_iter()
+            .map(|(_, metadata)| metadata.map(|metadata| metadata.token_uri))
+            .collect();
+        assert_eq!(uris, vec![Some("https://art/a".to_string()), None]);
+    }
+
+    #[test]
+    fn collection_reveals_at_the_scheduled_time() {
+        const REVEAL_AT: u64 = 5_000;
+        let mut deps = mock_dependencies();
+        let state = State { reveal_at: Some(REVEAL_AT), ..blank_state() };
+        STATE.save(deps.as_mut().storage, &state).unwrap();
+        let owner = Addr::unchecked(BUYER);
+        tokens().save(deps.as_mut().storage, "a", &TokenRecord::new(owner, None)).unwrap();
+        let at = |seconds: u64| {
+            let mut env = mock_env();
+            env.block.time = Timestamp::from_seconds(seconds);
+            env
+        };
+        let reveal_state = |deps: Deps, seconds: u64| {
+            let token_uri = query_token_uri(deps, at(seconds), "a".to_string()).unwrap().token_uri;
+            let config = query_config(deps, at(seconds)).unwrap();
+            (token_uri, config.uri_status, config.reveal_at)
+        };
+
+        // one second before the reveal tokens keep the prereveal uri
+        assert_eq!(
+            reveal_state(deps.as_ref(), REVEAL_AT - 1),
+            ("ipfs://prereveal".to_string(), false, Some(REVEAL_AT))
+        );
+        assert_eq!(
+            reveal_state(deps.as_ref(), REVEAL_AT + 1),
+            ("ipfs://base/a.json".to_string(), true, Some(REVEAL_AT))
+        );
+
+        // the owner can still reveal early
+        execute(deps.as_mut(), at(REVEAL_AT - 1), mock_info(OWNER, &[]), ExecuteMsg::Reveal {})
+            .unwrap();
+        assert_eq!(
+            reveal_state(deps.as_ref(), REVEAL_AT - 1),
+            ("ipfs://base/a.json".to_string(), true, Some(REVEAL_AT))
+        );
+    }
+
+    #[test]
+    fn schedule_reports_phase_at_block_time() {
+        let mut deps = mock_dependencies();
+        let state = State {
+            presale_start_time: 1_000,
+            presale_end_time: 1_500,
+            sale_start_time: 2_000,
+            sale_end_time: 3_000,
+            ..blank_state()
+        };
+        STATE.save(deps.as_mut().storage, &state).unwrap();
+
+        let cases = [
+            (999, SalePhase::Upcoming),
+            (1_000, SalePhase::Presale),
+            (1_500, SalePhase::Presale),
+            (1_501, SalePhase::Upcoming),
+            (2_000, SalePhase::Public),
+            (3_000, SalePhase::Public),
+            (3_001, SalePhase::Ended),
+        ];
+        for (now, phase) in cases {
+            let mut env = mock_env();
+            env.block.time = Timestamp::from_seconds(now);
+            let res: ScheduleResponse = from_binary(
+                &query(deps.as_ref(), env, QueryMsg::Schedule {}).unwrap()
+            ).unwrap();
+            assert_eq!(res.phase, phase, "phase at {}", now);
+            assert_eq!(
+                (res.presale_start, res.presale_end, res.sale_start_time, res.sale_end_time),
+                (1_000, 1_500, 2_000, 3_000)
+            );
+        }
+
+        // without a presale the drop is upcoming until the public sale
+        let state = State { presale_start_time: 0, presale_end_time: 0, ..state };
+        assert_eq!(state.phase(0), SalePhase::Upcoming);
+        assert_eq!(state.phase(1_000), SalePhase::Upcoming);
+    }
+
+    #[test]
+    fn sale_is_extended_while_supply_remains() {
+        let (mut app, contract) = setup_app_with(|msg| {
+            msg.runtime_config.grace_period_secs = 600;
+            msg.runtime_config.grace_threshold_pct = 20;
+        });
+        whitelist_buyer(&mut app, &contract);
+        let state = load_state(&app, &contract);
+        let (start, end) = (state.sale_start_time, state.sale_end_time);
+        let schedule = |app: &App| -> ScheduleResponse {
+            app.wrap().query_wasm_smart(&contract, &(QueryMsg::Schedule {})).unwrap()
+        };
+        let purchase = |app: &mut App, count: u64| {
+            app.execute_contract(
+                Addr::unchecked(BUYER),
+                contract.clone(),
+                &purchase_msg(count),
+                &coins(100 * u128::from(count), DENOM)
+            )
+        };
+        assert_eq!(schedule(&app).effective_sale_end_time, end + 600);
+
+        // with all of the supply left the sale runs into the grace period
+        app.update_block(|block| {
+            block.time = Timestamp::from_seconds(end + 1);
+        });
+        assert_eq!(schedule(&app).phase, SalePhase::Public);
+        purchase(&mut app, 7).unwrap();
+        assert_eq!(schedule(&app).effective_sale_end_time, end + 600);
+
+        // once no more than 20% is left the sale ends at its scheduled time
+        purchase(&mut app, 1).unwrap();
+        let res = schedule(&app);
+        assert_eq!((res.sale_end_time, res.effective_sale_end_time), (end, end));
+        assert_eq!(res.phase, SalePhase::Ended);
+        let err = purchase(&mut app, 1).unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::SaleNotActive { starts_at: start, ends_at: end, now: end + 1 }
+        );
+
+        // without a grace period the sale is never extended
+        let state = State { grace_period_secs: 0, ..load_state(&app, &contract) };
+        assert_eq!(state.effective_sale_end(), end);
+        let state = State { current_token_id: 0, ..state };
+        assert_eq!(state.effective_sale_end(), end);
+    }
+
+    #[test]
+    fn sale_window_includes_its_whole_start_and_end_seconds() {
+        let (mut app, contract) = setup_app();
+        whitelist_buyer(&mut app, &contract);
+        let state = load_state(&app, &contract);
+        let (start, end) = (state.sale_start_time, state.sale_end_time);
+        let at_nanos = |seconds: u64, nanos: u64| {
+            Timestamp::from_seconds(seconds).plus_nanos(nanos)
+        };
+        assert_eq!(sale_timestamp(start), Some(Timestamp::from_seconds(start)));
+        assert_eq!(sale_timestamp(u64::MAX), None);
+
+        // the last nanosecond before the start and the first one after the end are outside
+        let outside = [
+            (at_nanos(start - 1, 999_999_999), start - 1),
+            (at_nanos(end + 1, 0), end + 1),
+        ];
+        for (time, now) in outside {
+            app.update_block(|block| block.time = time);
+            let err = app
+                .execute_contract(
+                    Addr::unchecked(BUYER),
+                    contract.clone(),
+                    &purchase_msg(1),
+                    &coins(100, DENOM)
+                )
+                .unwrap_err();
+            assert_eq!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::SaleNotActive { starts_at: start, ends_at: end, now }
+            );
+        }
+
+        // both boundary seconds are inside, up to their last nanosecond
+        for time in [at_nanos(start, 0), at_nanos(end, 0), at_nanos(end, 999_999_999)] {
+            app.update_block(|block| block.time = time);
+            app.execute_contract(
+                Addr::unchecked(BUYER),
+                contract.clone(),
+                &purchase_msg(1),
+                &coins(100, DENOM)
+            ).unwrap();
+        }
+        assert_eq!(load_state(&app, &contract).current_token_id, 3);
+    }
+
+    #[test]
+    fn config_reflects_instantiate_and_updates() {
+        let (mut app, contract) = setup_app();
+        let now = app.block_info().time.seconds();
+        let query_config = |app: &App| -> ConfigResponse {
+            app.wrap().query_wasm_smart(&contract, &(QueryMsg::Config {})).unwrap()
+        };
+
+        let mut expected = ConfigResponse {
+            owner: OWNER.to_string(),
+            base_token_uri: "ipfs://base/".to_string(),
+            base_token_uri_extension: ".json".to_string(),
+            prereveal_token_uri: "ipfs://prereveal".to_string(),
+            uri_status: false,
+            treasury_address: TREASURY.to_string(),
+            protocol_address: "".to_string(),
+            protocol_fee_bps: 0,
+            mint_denom: DENOM.to_string(),
+            mint_price: Uint128::new(100),
+            presale_start_time: 0,
+            presale_end_time: 0,
+            sale_start_time: now,
+            sale_end_time: now + 3_600,
+            active_tier: None,
+            max_supply: 10,
+            remaining_supply: Some(10),
+            sale_active: true,
+            token_id_format: None,
+            reveal_at: None,
+        };
+        assert_eq!(query_config(&app), expected);
+
+        whitelist_buyer(&mut app, &contract);
+        app.execute_contract(
+            Addr::unchecked(BUYER),
+            contract.clone(),
+            &purchase_msg(3),
+            &coins(300, DENOM)
+        ).unwrap();
+        for msg in [
+            ExecuteMsg::SetProtocolAddress { address: PROTOCOL.to_string() },
+            ExecuteMsg::SetActiveTier { tier: Some(TIER_WL) },
+        ] {
+            app.execute_contract(Addr::unchecked(OWNER), contract.clone(), &msg, &[]).unwrap();
+        }
+        expected.protocol_address = PROTOCOL.to_string();
+        expected.active_tier = Some(TIER_WL);
+        expected.remaining_supply = Some(7);
+        assert_eq!(query_config(&app), expected);
+
+        // the sale is reported inactive once its window has passed
+        app.update_block(|block| {
+            block.time = block.time.plus_seconds(3_601);
+        });
+        expected.sale_active = false;
+        assert_eq!(query_config(&app), expected);
+    }
+
+    #[test]
+    fn sale_windows_can_be_rescheduled() {
+        let (mut app, contract) = setup_app_with(|msg| {
+            msg.runtime_config.sale_start_time = 0;
+            msg.runtime_config.sale_end_time = 0;
+        });
+        let now = app.block_info().time.seconds();
+        let schedule = |app: &App| -> ScheduleResponse {
+            app.wrap().query_wasm_smart(&contract, &(QueryMsg::Schedule {})).unwrap()
+        };
+
+        for msg in [
+            ExecuteMsg::SetSaleWindow { start: now + 200, end: now + 300 },
+            ExecuteMsg::SetPresaleWindow { start: now + 100, end: now + 150 },
+        ] {
+            app.execute_contract(Addr::unchecked(OWNER), contract.clone(), &msg, &[]).unwrap();
+        }
+        assert_eq!(
+            schedule(&app),
+            ScheduleResponse {
+                presale_start: now + 100,
+                presale_end: now + 150,
+                sale_start_time: now + 200,
+                sale_end_time: now + 300,
+                effective_sale_end_time: now + 300,
+                phase: SalePhase::Upcoming,
+            }
+        );
+
+        let invalid = [
+            (
+                ExecuteMsg::SetSaleWindow { start: now + 300, end: now + 200 },
+                "sale must start before it ends",
+            ),
+            (
+                ExecuteMsg::SetPresaleWindow { start: now + 150, end: now + 150 },
+                "presale must start before it ends",
+            ),
+            (
+                ExecuteMsg::SetPresaleWindow { start: now + 100, end: now + 250 },
+                "presale must end before the public sale starts",
+            ),
+            // moving the sale into the presale is just as inconsistent
+            (
+                ExecuteMsg::SetSaleWindow { start: now + 120, end: now + 300 },
+                "presale must end before the public sale starts",
+            ),
+            // block times in milliseconds can't be mistaken for seconds
+            (
+                ExecuteMsg::SetSaleWindow { start: now + 200, end: (now + 300) * 1_000 },
+                "times must be in seconds",
+            ),
+        ];
+        for (msg, reason) in invalid {
+            let err = app
+                .execute_contract(Addr::unchecked(OWNER), contract.clone(), &msg, &[])
+                .unwrap_err();
+            assert_eq!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::InvalidSchedule { reason: reason.to_string() }
+            );
+        }
+        assert_eq!(schedule(&app).sale_start_time, now + 200);
+
+        // the presale can be dropped again, only by the owner
+        let no_presale = ExecuteMsg::SetPresaleWindow { start: 0, end: 0 };
+        let err = app
+            .execute_contract(Addr::unchecked(BUYER), contract.clone(), &no_presale, &[])
+            .unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::unauthorized("owner"));
+        app.execute_contract(Addr::unchecked(OWNER), contract.clone(), &no_presale, &[]).unwrap();
+        assert_eq!(schedule(&app).presale_end, 0);
+    }
+
+    #[test]
+    fn locked_schedule_cannot_change_after_the_sale_started() {
+        let (mut app, contract) = setup_app_with(|msg| {
+            msg.runtime_config.lock_schedule_on_start = true;
+        });
+        let now = app.block_info().time.seconds();
+
+        let err = app
+            .execute_contract(
+                Addr::unchecked(OWNER),
+                contract.clone(),
+                &(ExecuteMsg::SetSaleWindow { start: now, end: now + 7_200 }),
+                &[]
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::InvalidSchedule { reason: "the sale has already started".to_string() }
+        );
+    }
+
+    #[test]
+    fn locked_whitelist_only_grows_once_the_sale_started() {
+        let (mut app, contract) = setup_app_with(|msg| {
+            msg.runtime_config.sale_start_time += 100;
+            msg.runtime_config.lock_whitelist_after_start = true;
+        });
+        let start = load_state(&app, &contract).sale_start_time;
+        let set_whitelisted = |app: &mut App, address: &str, status: bool| {
+            app.execute_contract(
+                Addr::unchecked(OWNER),
+                contract.clone(),
+                &(ExecuteMsg::Whitelist { address: address.to_string(), status }),
+                &[]
+            )
+        };
+
+        // one second before the start removals are still possible
+        app.update_block(|block| {
+            block.time = Timestamp::from_seconds(start - 1);
+        });
+        set_whitelisted(&mut app, BUYER, true).unwrap();
+        set_whitelisted(&mut app, BUYER, false).unwrap();
+
+        app.update_block(|block| {
+            block.time = Timestamp::from_seconds(start);
+        });
+        set_whitelisted(&mut app, BUYER, true).unwrap();
+        let err = set_whitelisted(&mut app, BUYER, false).unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::WhitelistLocked { sale_start_time: start }
+        );
+        let err = app
+            .execute_contract(
+                Addr::unchecked(OWNER),
+                contract.clone(),
+                &(ExecuteMsg::RemoveFromWhitelist {
+                    class_id: "ptx".to_string(),
+                    id: "0".to_string(),
+                    account: BUYER.to_string(),
+                }),
+                &[]
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::WhitelistLocked { sale_start_time: start }
+        );
+        let err = app
+            .execute_contract(
+                Addr::unchecked(OWNER),
+                contract.clone(),
+                &(ExecuteMsg::WhitelistBatch {
+                    addresses: vec!["another".to_string(), BUYER.to_string()],
+                    status: false,
+                }),
+                &[]
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::WhitelistLocked { sale_start_time: start }
+        );
+        set_whitelisted(&mut app, "another", true).unwrap();
+        let buyer_entry = app
+            .wrap()
+            .query_wasm_raw(&contract, WHITELIST.key(&Addr::unchecked(BUYER)).to_vec())
+            .unwrap();
+        assert_eq!(buyer_entry, Some(b"true".to_vec()));
+    }
+
+    #[test]
+    fn open_edition_is_bounded_by_the_sale_window_until_finalized() {
+        let (mut app, contract) = setup_app_with(|msg| {
+            msg.deployment_config.max_supply = 0;
+        });
+        whitelist_buyer(&mut app, &contract);
+        let config = |app: &App| -> ConfigResponse {
+            app.wrap().query_wasm_smart(&contract, &(QueryMsg::Config {})).unwrap()
+        };
+
+        // no supply limit, so buyers can go past any nominal size
+        app.execute_contract(
+            Addr::unchecked(BUYER),
+            contract.clone(),
+            &purchase_msg(3),
+            &coins(300, DENOM)
+        ).unwrap();
+        assert_eq!(query_all_tokens(&app, &contract), vec!["0", "1", "2"]);
+        assert_eq!(config(&app).remaining_supply, None);
+        assert!(config(&app).sale_active);
+
+        // the supply can only be finalized once the sale has ended
+        let err = app
+            .execute_contract(
+                Addr::unchecked(BUYER),
+                contract.clone(),
+                &(ExecuteMsg::FinalizeSupply {}),
+                &[]
+            )
+            .unwrap_err();
+        let now = app.block_info().time.seconds();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::SaleNotEnded { ends_at: now + 3_600, now }
+        );
+
+        app.update_block(|block| {
+            block.time = block.time.plus_seconds(3_601);
+        });
+        let err = app
+            .execute_contract(
+                Addr::unchecked(BUYER),
+                contract.clone(),
+                &purchase_msg(1),
+                &coins(100, DENOM)
+            )
+            .unwrap_err();
+        assert!(
+            matches!(err.downcast::<ContractError>().unwrap(), ContractError::SaleNotActive { .. })
+        );
+
+        // anyone can finalize, and doing it again changes nothing
+        for _ in 0..2 {
+            app.execute_contract(
+                Addr::unchecked(BUYER),
+                contract.clone(),
+                &(ExecuteMsg::FinalizeSupply {}),
+                &[]
+            ).unwrap();
+            let state = load_state(&app, &contract);
+            assert_eq!(state.max_total_mint, 3);
+            assert!(!state.open_edition);
+        }
+        assert_eq!(config(&app).remaining_supply, Some(0));
+
+        // reopening the sale doesn't lift the finalized supply
+        let now = app.block_info().time.seconds();
+        app.execute_contract(
+            Addr::unchecked(OWNER),
+            contract.clone(),
+            &(ExecuteMsg::SetSaleWindow { start: now, end: now + 3_600 }),
+            &[]
+        ).unwrap();
+        let err = app
+            .execute_contract(
+                Addr::unchecked(BUYER),
+                contract.clone(),
+                &purchase_msg(1),
+                &coins(100, DENOM)
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::SoldOut { remaining: 0 }
+        );
+        assert!(!config(&app).sale_active);
+    }
+
+    #[test]
+    fn config_response_accepts_missing_fields() {
+        let res: ConfigResponse = from_slice(br#"{"owner":"owner","max_supply":10}"#).unwrap();
+        assert_eq!(res.owner, "owner");
+        assert_eq!(res.max_supply, 10);
+        assert!(!res.sale_active);
+    }
+
+    #[test]
+    fn contract_uri_can_be_set_until_metadata_is_frozen() {
+        let (mut app, contract) = setup_app();
+        let query_info = |app: &App| -> ContractInfoResponse {
+            app.wrap().query_wasm_smart(&contract, &(QueryMsg::ContractInfo {})).unwrap()
+        };
+        app.execute_contract(
+            Addr::unchecked(OWNER),
+            contract.clone(),
+            &(ExecuteMsg::IssueClass {
+                name: "Proptix".to_string(),
+                symbol: "PTX".to_string(),
+                description: None,
+                uri: None,
+                uri_hash: None,
+                data: None,
+                features: None,
+                royalty_rate: Some("0.05".to_string()),
+            }),
+            &[]
+        ).unwrap();
+        assert_eq!(query_info(&app).contract_uri, None);
+
+        let set_uri = ExecuteMsg::SetContractUri {
+            contract_uri: "ipfs://collection.json".to_string(),
+        };
+        let err = app
+            .execute_contract(Addr::unchecked(BUYER), contract.clone(), &set_uri, &[])
+            .unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::unauthorized("owner"));
+
+        app.execute_contract(Addr::unchecked(OWNER), contract.clone(), &set_uri, &[]).unwrap();
+        assert_eq!(
+            query_info(&app),
+            ContractInfoResponse {
+                name: "Proptix".to_string(),
+                symbol: "PTX".to_string(),
+                minter: Addr::unchecked(OWNER),
+                royalty_rate: Some("0.05".to_string()),
+                contract_uri: Some("ipfs://collection.json".to_string()),
+                metadata_frozen: false,
+            }
+        );
+
+        app.execute_contract(
+            Addr::unchecked(OWNER),
+            contract.clone(),
+            &(ExecuteMsg::FreezeMetadata {}),
+            &[]
+        ).unwrap();
+        let err = app
+            .execute_contract(Addr::unchecked(OWNER), contract.clone(), &set_uri, &[])
+            .unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::MetadataFrozen {});
+        assert!(query_info(&app).metadata_frozen);
+    }
+
+    #[test]
+    fn uris_are_limited_to_max_uri_len() {
+        let too_long = |field: &str| ContractError::UriTooLong {
+            field: field.to_string(),
+            max_len: 20,
+        };
+        let mut msg = instantiate_msg(1_000, 2_000);
+        msg.runtime_config.max_uri_len = 20;
+        msg.runtime_config.prereveal_token_uri = "ipfs://prereveal/0123".to_string();
+        let validate = |msg: &InstantiateMsg| {
+            msg.runtime_config.validate().map_err(ContractError::from)
+        };
+        assert_eq!(validate(&msg), Err(too_long("prereveal_token_uri")));
+        msg.runtime_config.prereveal_token_uri = "ipfs://prereveal/012".to_string();
+        msg.runtime_config.base_token_uri = "ipfs://base/012345678".to_string();
+        assert_eq!(validate(&msg), Err(too_long("base_token_uri")));
+        // without a limit any length passes
+        msg.runtime_config.max_uri_len = 0;
+        assert_eq!(validate(&msg), Ok(()));
+
+        let (mut app, contract) = setup_app_with(|msg| {
+            msg.runtime_config.max_uri_len = 20;
+        });
+        let set_base_uri = |uri: &str| ExecuteMsg::SetBaseUri { base_token_uri: uri.to_string() };
+        let err = app
+            .execute_contract(
+                Addr::unchecked(OWNER),
+                contract.clone(),
+                &set_base_uri("ipfs://revealed/01234"),
+                &[]
+            )
+            .unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), too_long("base_token_uri"));
+        app.execute_contract(
+            Addr::unchecked(OWNER),
+            contract.clone(),
+            &set_base_uri("ipfs://revealed/0123"),
+            &[]
+        ).unwrap();
+        assert_eq!(load_state(&app, &contract).base_token_uri, "ipfs://revealed/0123");
+
+        let mint = |id: &str, uri: &str| ExecuteMsg::Mint {
+            class_id: "ptx".to_string(),
+            id: id.to_string(),
+            uri: Some(uri.to_string()),
+            uri_hash: None,
+            data: None,
+        };
+        let err = app
+            .execute_contract(
+                Addr::unchecked(OWNER),
+                contract.clone(),
+                &mint("a", "ipfs://token/a/012345"),
+                &[]
+            )
+            .unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), too_long("uri"));
+        app.execute_contract(
+            Addr::unchecked(OWNER),
+            contract.clone(),
+            &mint("a", "ipfs://token/a/01234"),
+            &[]
+        ).unwrap();
+    }
+
+    #[test]
+    fn max_supply_can_be_reduced_until_the_supply_is_frozen() {
+        let (mut app, contract) = setup_app();
+        whitelist_buyer(&mut app, &contract);
+        app.execute_contract(
+            Addr::unchecked(BUYER),
+            contract.clone(),
+            &purchase_msg(3),
+            &coins(300, DENOM)
+        ).unwrap();
+        let reduce = |app: &mut App, sender: &str, new_max: u64| {
+            app.execute_contract(
+                Addr::unchecked(sender),
+                contract.clone(),
+                &(ExecuteMsg::ReduceMaxSupply { new_max }),
+                &[]
+            )
+        };
+
+        let err = reduce(&mut app, BUYER, 5).unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::unauthorized("owner"));
+        // the supply can neither stay nor grow, nor drop below the minted tokens
+        for new_max in [10, 11, 2] {
+            let err = reduce(&mut app, OWNER, new_max).unwrap_err();
+            assert_eq!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::InvalidSupplyReduction { new_max, max_supply: 10, minted: 3 }
+            );
+        }
+
+        let res = reduce(&mut app, OWNER, 5).unwrap();
+        assert_eq!(
+            wasm_attributes(&res),
+            vec![
+                attr("action", "reduce_max_supply"),
+                attr("old_max_supply", "10"),
+                attr("new_max_supply", "5")
+            ]
+        );
+
+        // frozen metadata doesn't freeze the supply
+        for msg in [ExecuteMsg::FreezeMetadata {}, ExecuteMsg::ReduceMaxSupply { new_max: 4 }] {
+            app.execute_contract(Addr::unchecked(OWNER), contract.clone(), &msg, &[]).unwrap();
+        }
+        let err = app
+            .execute_contract(
+                Addr::unchecked(BUYER),
+                contract.clone(),
+                &(ExecuteMsg::FreezeSupply {}),
+                &[]
+            )
+            .unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::unauthorized("owner"));
+        app.execute_contract(
+            Addr::unchecked(OWNER),
+            contract.clone(),
+            &(ExecuteMsg::FreezeSupply {}),
+            &[]
+        ).unwrap();
+        let err = reduce(&mut app, OWNER, 3).unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::SupplyFrozen {});
+
+        let state = load_state(&app, &contract);
+        assert_eq!(state.max_total_mint, 4);
+        assert!(state.supply_frozen);
+        let err = app
+            .execute_contract(
+                Addr::unchecked(BUYER),
+                contract.clone(),
+                &purchase_msg(2),
+                &coins(200, DENOM)
+            )
+            .unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::SoldOut {
+            remaining: 1,
+        });
+    }
+
+    #[test]
+    fn purchase_assigns_consecutive_ids() {
+        let (mut app, contract) = setup_app();
+        whitelist_buyer(&mut app, &contract);
+
+        app.execute_contract(
+            Addr::unchecked(BUYER),
+            contract.clone(),
+            &purchase_msg(3),
+            &coins(300, DENOM)
+        ).unwrap();
+
+        assert_eq!(query_all_tokens(&app, &contract), vec!["0", "1", "2"]);
+        for token_id in ["0", "1", "2"] {
+            assert_eq!(load_owner(&app, &contract, token_id), Some(Addr::unchecked(BUYER)));
+        }
+        assert_eq!(load_state(&app, &contract).current_token_id, 3);
+    }
+
+    #[test]
+    fn token_handlers_emit_cw721_attributes() {
+        let (mut app, contract) = setup_app();
+        whitelist_buyer(&mut app, &contract);
+
+        let res = app
+            .execute_contract(
+                Addr::unchecked(OWNER),
+                contract.clone(),
+                &(ExecuteMsg::Mint {
+                    class_id: "ptx".to_string(),
+                    id: "a".to_string(),
+                    uri: None,
+                    uri_hash: None,
+                    data: None,
+                }),
+                &[]
+            )
+            .unwrap();
+        assert_eq!(
+            wasm_attributes(&res),
+            vec![
+                attr("action", "mint"),
+                attr("minter", OWNER),
+                attr("owner", OWNER),
+                attr("token_id", "a")
+            ]
+        );
+
+        let res = app
+            .execute_contract(
+                Addr::unchecked(BUYER),
+                contract.clone(),
+                &purchase_msg(3),
+                &coins(300, DENOM)
+            )
+            .unwrap();
+        assert_eq!(
+            wasm_attributes(&res),
+            vec![
+                attr("action", "purchase"),
+                attr("payer", BUYER),
+                attr("owner", BUYER),
+                attr("count", "3"),
+                attr("token_id", "0"),
+                attr("token_id", "1"),
+                attr("token_id", "2")
+            ]
+        );
+
+        let res = app
+            .execute_contract(
+                Addr::unchecked(BUYER),
+                contract.clone(),
+                &(ExecuteMsg::Burn { class_id: "ptx".to_string(), id: "1".to_string() }),
+                &[]
+            )
+            .unwrap();
+        assert_eq!(
+            wasm_attributes(&res),
+            vec![attr("action", "burn"), attr("sender", BUYER), attr("token_id", "1")]
+        );
+
+        let res = app
+            .execute_contract(
+                Addr::unchecked(OWNER),
+                contract.clone(),
+                &(ExecuteMsg::Freeze { class_id: "ptx".to_string(), id: "a".to_string() }),
+                &[]
+            )
+            .unwrap();
+        assert_eq!(wasm_attributes(&res), vec![attr("action", "freeze"), attr("token_id", "a")]);
+
+        let mut deps = mock_dependencies();
+        save_token(deps.as_mut().storage, "7", OWNER);
+        let res = transfer(
+            deps.as_mut(),
+            &mock_env().block,
+            Addr::unchecked(OWNER),
+            Addr::unchecked(BUYER),
+            "7".to_string()
+        ).unwrap();
+        assert_eq!(
+            res.attributes,
+            vec![
+                attr("action", "transfer_nft"),
+                attr("sender", OWNER),
+                attr("recipient", BUYER),
+                attr("token_id", "7")
+            ]
+        );
+    }
+
+    #[test]
+    fn execute_routes_every_message_to_its_handler() {
+        const STRANGER: &str = "stranger";
+        let (mut app, contract) = setup_app();
+        let now = app.block_info().time.seconds();
+        let token_id = || "a".to_string();
+        let class_id = || "ptx".to_string();
+
+        // every variant either reaches its handler's attributes or the first check the
+        // handler makes
+        let cases: Vec<(&str, ExecuteMsg, Result<&str, ContractError>)> = vec![
+            (
+                OWNER,
+                ExecuteMsg::IssueClass {
+                    name: "Proptix".to_string(),
+                    symbol: "PTX".to_string(),
+                    description: None,
+                    uri: None,
+                    uri_hash: None,
+                    data: None,
+                    features: None,
+                    royalty_rate: None,
+                },
+                Ok("issue_class"),
+            ),
+            (
+                OWNER,
+                ExecuteMsg::Mint {
+                    class_id: class_id(),
+                    id: token_id(),
+                    uri: None,
+                    uri_hash: None,
+                    data: None,
+                },
+                Ok("mint"),
+            ),
+            (
+                OWNER,
+                ExecuteMsg::Approve {
+                    spender: BUYER.to_string(),
+                    token_id: token_id(),
+                    expires: None,
+                },
+                Ok("approve"),
+            ),
+            (
+                OWNER,
+                ExecuteMsg::Revoke { spender: BUYER.to_string(), token_id: token_id() },
+                Ok("revoke"),
+            ),
+            (OWNER, ExecuteMsg::Freeze { class_id: class_id(), id: token_id() }, Ok("freeze")),
+            (OWNER, ExecuteMsg::Unfreeze { class_id: class_id(), id: token_id() }, Ok("unfreeze")),
+            (
+                OWNER,
+                ExecuteMsg::LockToken { token_id: token_id(), until: Expiration::AtHeight(1) },
+                Ok("lock_token"),
+            ),
+            (
+                STRANGER,
+                ExecuteMsg::Burn { class_id: class_id(), id: token_id() },
+                Err(ContractError::unauthorized("token owner")),
+            ),
+            (
+                STRANGER,
+                ExecuteMsg::BurnBatch { class_id: class_id(), ids: vec![token_id()] },
+                Err(ContractError::unauthorized("token owner")),
+            ),
+            (
+                STRANGER,
+                ExecuteMsg::LockUntil { token_id: token_id(), unlock_time: now + 1 },
+                Err(ContractError::unauthorized("token owner")),
+            ),
+            (
+                STRANGER,
+                ExecuteMsg::SendNft {
+                    contract: BUYER.to_string(),
+                    token_id: token_id(),
+                    msg: Binary::default(),
+                },
+                Err(ContractError::unauthorized("token owner or approved spender")),
+            ),
+            (
+                STRANGER,
+                ExecuteMsg::SetUser {
+                    token_id: token_id(),
+                    user: STRANGER.to_string(),
+                    expires: Expiration::Never {},
+                },
+                Err(ContractError::unauthorized("token owner or approved spender")),
+            ),
+            (
+                STRANGER,
+                ExecuteMsg::List { token_id: token_id(), price: Coin::new(0, DENOM) },
+                Err(ContractError::ZeroListingPrice {}),
+            ),
+            (
+                STRANGER,
+                ExecuteMsg::CancelListing { token_id: token_id() },
+                Err(ContractError::TokenNotListed { token_id: token_id() }),
+            ),
+            (
+                STRANGER,
+                ExecuteMsg::Buy { token_id: token_id() },
+                Err(ContractError::TokenNotListed { token_id: token_id() }),
+            ),
+            (
+                STRANGER,
+                ExecuteMsg::IbcTransfer {
+                    token_id: token_id(),
+                    channel: "channel-0".to_string(),
+                    receiver: "receiver".to_string(),
+                    timeout: Timestamp::from_seconds(now + 60),
+                },
+                Err(ContractError::BridgeUnset {}),
+            ),
+            (
+                STRANGER,
+                ExecuteMsg::IbcCallback { token_id: token_id(), outcome: IbcOutcome::Timeout {} },
+                Err(ContractError::unauthorized("ics721 bridge")),
+            ),
+            (
+                OWNER,
+                ExecuteMsg::SetIcs721Address { address: "bridge".to_string() },
+                Ok("set_ics721_address"),
+            ),
+            (
+                OWNER,
+                ExecuteMsg::TransferNft {
+                    recipient: BUYER.to_string(),
+                    token_id: token_id(),
+                    sale_price: None,
+                },
+                Ok("transfer_nft"),
+            ),
+            (
+                STRANGER,
+                ExecuteMsg::BatchTransfer {
+                    transfers: vec![BatchTransferEntry {
+                        recipient: STRANGER.to_string(),
+                        token_id: token_id(),
+                    }],
+                },
+                Err(ContractError::unauthorized("token owner or approved spender")),
+            ),
+            (
+                OWNER,
+                ExecuteMsg::AddToWhitelist {
+                    class_id: class_id(),
+                    id: token_id(),
+                    account: BUYER.to_string(),
+                    allocation: None,
+                },
+                Ok("whitelist"),
+            ),
+            (
+                OWNER,
+                ExecuteMsg::RemoveFromWhitelist {
+                    class_id: class_id(),
+                    id: token_id(),
+                    account: BUYER.to_string(),
+                },
+                Ok("whitelist"),
+            ),
+            (
+                OWNER,
+                ExecuteMsg::Whitelist { address: BUYER.to_string(), status: true },
+                Ok("whitelist"),
+            ),
+            (
+                OWNER,
+                ExecuteMsg::WhitelistBatch { addresses: vec![BUYER.to_string()], status: true },
+                Ok("whitelist_batch"),
+            ),
+            (
+                OWNER,
+                ExecuteMsg::AddToBlacklist { address: STRANGER.to_string() },
+                Ok("blacklist"),
+            ),
+            (
+                OWNER,
+                ExecuteMsg::RemoveFromBlacklist { address: STRANGER.to_string() },
+                Ok("blacklist"),
+            ),
+            (
+                STRANGER,
+                ExecuteMsg::AddToBlocklist { address: STRANGER.to_string() },
+                Err(ContractError::unauthorized("owner")),
+            ),
+            (
+                OWNER,
+                ExecuteMsg::AddToBlocklist { address: STRANGER.to_string() },
+                Ok("blocklist"),
+            ),
+            (
+                OWNER,
+                ExecuteMsg::RemoveFromBlocklist { address: STRANGER.to_string() },
+                Ok("blocklist"),
+            ),
+            (
+                OWNER,
+                ExecuteMsg::SetHoldingCapExemption { address: TREASURY.to_string(), exempt: true },
+                Ok("set_holding_cap_exemption"),
+            ),
+            (
+                STRANGER,
+                ExecuteMsg::StartAuction {
+                    token_id: token_id(),
+                    reserve_price: Coin::new(1, DENOM),
+                    duration: 0,
+                    min_bid_increment: Uint128::one(),
+                },
+                Err(ContractError::ZeroAuctionDuration {}),
+            ),
+            (
+                STRANGER,
+                ExecuteMsg::Bid { token_id: token_id() },
+                Err(ContractError::NoAuction { token_id: token_id() }),
+            ),
+            (
+                STRANGER,
+                ExecuteMsg::Settle { token_id: token_id() },
+                Err(ContractError::NoAuction { token_id: token_id() }),
+            ),
+            (
+                STRANGER,
+                ExecuteMsg::MakeOffer { token_id: token_id(), expires: None },
+                Err(PaymentError::NoFunds {}.into()),
+            ),
+            (
+                STRANGER,
+                ExecuteMsg::WithdrawOffer { token_id: token_id() },
+                Err(ContractError::NoOffer { token_id: token_id(), bidder: STRANGER.to_string() }),
+            ),
+            (
+                OWNER,
+                ExecuteMsg::AcceptOffer { token_id: token_id(), bidder: STRANGER.to_string() },
+                Err(ContractError::NoOffer { token_id: token_id(), bidder: STRANGER.to_string() }),
+            ),
+            (OWNER, ExecuteMsg::SetBurnReward { reward: None }, Ok("set_burn_reward")),
+            (STRANGER, ExecuteMsg::FundRewards {}, Err(PaymentError::NoFunds {}.into())),
+            (
+                STRANGER,
+                ExecuteMsg::ClaimRewards { token_ids: vec![token_id()] },
+                Err(ContractError::unauthorized("token owner")),
+            ),
+            (
+                STRANGER,
+                ExecuteMsg::Receive(Cw20ReceiveMsg {
+                    sender: STRANGER.to_string(),
+                    amount: Uint128::one(),
+                    msg: to_binary(&ReceiveMsg::FundRewards {}).unwrap(),
+                }),
+                Ok("fund_rewards"),
+            ),
+            (STRANGER, ExecuteMsg::FundRewardPool {}, Err(ContractError::unauthorized("owner"))),
+            (
+                STRANGER,
+                ExecuteMsg::SetMintDenom { denom: "uusd".to_string() },
+                Err(ContractError::unauthorized("owner")),
+            ),
+            (
+                STRANGER,
+                ExecuteMsg::Withdraw { denom: DENOM.to_string() },
+                Err(ContractError::NothingToWithdraw { denom: DENOM.to_string() }),
+            ),
+            (
+                OWNER,
+                ExecuteMsg::RegisterTransferHook { contract: TREASURY.to_string() },
+                Ok("register_transfer_hook"),
+            ),
+            (
+                OWNER,
+                ExecuteMsg::RemoveTransferHook { contract: TREASURY.to_string() },
+                Ok("remove_transfer_hook"),
+            ),
+            (STRANGER, purchase_msg(1), Err(PaymentError::NoFunds {}.into())),
+            (
+                STRANGER,
+                ExecuteMsg::CommitMint {
+                    commitment: Binary::default(),
+                    proof: None,
+                    allowlist_index: None,
+                },
+                Err(PaymentError::NoFunds {}.into()),
+            ),
+            (
+                STRANGER,
+                ExecuteMsg::RevealMint {
+                    salt: Binary::default(),
+                    count: 1,
+                    proof: None,
+                    allowlist_index: None,
+                },
+                Err(ContractError::NoCommitment {}),
+            ),
+            (STRANGER, ExecuteMsg::CancelCommit {}, Err(ContractError::NoCommitment {})),
+            (
+                STRANGER,
+                ExecuteMsg::RedeemVoucher { count: 1, signature: Binary::default() },
+                Err(ContractError::VoucherSignerUnset {}),
+            ),
+            (
+                OWNER,
+                ExecuteMsg::SetVoucherSigner {
+                    pubkey: Binary::from(vec![1; 32]),
+                    scheme: SignatureScheme::Ed25519,
+                },
+                Ok("set_voucher_signer"),
+            ),
+            (
+                STRANGER,
+                ExecuteMsg::ReceiveNft(Cw721ReceiveMsg {
+                    sender: STRANGER.to_string(),
+                    token_id: "pass".to_string(),
+                    msg: to_binary(&(ReceiveNftMsg::RedeemPass {})).unwrap(),
+                }),
+                Err(ContractError::unauthorized("pass collection")),
+            ),
+            (
+                OWNER,
+                ExecuteMsg::SetProtocolAddress { address: PROTOCOL.to_string() },
+                Ok("set_protocol_address"),
+            ),
+            (
+                OWNER,
+                ExecuteMsg::UpdateProtocolAddress { address: OWNER.to_string() },
+                Err(ContractError::unauthorized("protocol")),
+            ),
+            (
+                PROTOCOL,
+                ExecuteMsg::UpdateProtocolAddress { address: PROTOCOL.to_string() },
+                Ok("update_protocol_address"),
+            ),
+            (
+                OWNER,
+                ExecuteMsg::SetContractUri { contract_uri: "ipfs://collection.json".to_string() },
+                Ok("set_contract_uri"),
+            ),
+            (
+                OWNER,
+                ExecuteMsg::SetBaseUri { base_token_uri: "ipfs://revealed/".to_string() },
+                Ok("set_base_uri"),
+            ),
+            (
+                OWNER,
+                ExecuteMsg::ProposeBaseUriChange {
+                    new_uri: "ipfs://v2/".to_string(),
+                    effective_after: now + 60,
+                },
+                Ok("propose_base_uri_change"),
+            ),
+            (
+                OWNER,
+                ExecuteMsg::ApplyBaseUriChange {},
+                Err(ContractError::UriChangeNotDue { effective_after: now + 60, now }),
+            ),
+            (
+                STRANGER,
+                ExecuteMsg::VetoUriChange {},
+                Err(ContractError::unauthorized("token holder")),
+            ),
+            (
+                OWNER,
+                ExecuteMsg::SetMetadataResolver { resolver: None },
+                Ok("set_metadata_resolver"),
+            ),
+            (STRANGER, ExecuteMsg::Reveal {}, Err(ContractError::unauthorized("owner"))),
+            (OWNER, ExecuteMsg::Reveal {}, Ok("reveal")),
+            (OWNER, ExecuteMsg::FreezeMetadata {}, Ok("freeze_metadata")),
+            (
+                OWNER,
+                ExecuteMsg::SetMetadataResolver { resolver: Some("resolver".to_string()) },
+                Err(ContractError::MetadataFrozen {}),
+            ),
+            (
+                STRANGER,
+                ExecuteMsg::FinalizeSupply {},
+                Err(ContractError::SaleNotEnded { ends_at: now + 3_600, now }),
+            ),
+            (STRANGER, ExecuteMsg::ReleaseEscrow {}, Err(ContractError::NoSaleThreshold {})),
+            (
+                STRANGER,
+                ExecuteMsg::FinalizeSale {},
+                Err(ContractError::SaleNotEnded { ends_at: now + 3_600, now }),
+            ),
+            (STRANGER, ExecuteMsg::ClaimRefund {}, Err(ContractError::NoSaleThreshold {})),
+            (OWNER, ExecuteMsg::ReduceMaxSupply { new_max: 5 }, Ok("reduce_max_supply")),
+            (OWNER, ExecuteMsg::FreezeSupply {}, Ok("freeze_supply")),
+            (
+                OWNER,
+                ExecuteMsg::SetSaleWindow { start: now, end: now + 3_600 },
+                Ok("set_sale_window"),
+            ),
+            (OWNER, ExecuteMsg::SetPresaleWindow { start: 0, end: 0 }, Ok("set_presale_window")),
+            (
+                OWNER,
+                ExecuteMsg::SetPhaseRoot { tier: TIER_OG, root: Binary::from(vec![0; 32]) },
+                Ok("set_phase_root"),
+            ),
+            (OWNER, ExecuteMsg::SetActiveTier { tier: None }, Ok("set_active_tier")),
+            (
+                OWNER,
+                ExecuteMsg::SetAllowlistBit { index: 0, value: true },
+                Ok("set_allowlist_bit"),
+            ),
+            (
+                OWNER,
+                ExecuteMsg::SetAllowlistRange { start: 0, count: 8, value: false },
+                Ok("set_allowlist_range"),
+            ),
+            (
+                OWNER,
+                ExecuteMsg::SetProceedsHook { contract: None, msg: Binary::default() },
+                Ok("set_proceeds_hook"),
+            )
+        ];
+
+        for (sender, msg, expected) in cases {
+            let res = app.execute_contract(Addr::unchecked(sender), contract.clone(), &msg, &[]);
+            match expected {
+                Ok(action) => {
+                    let attributes = wasm_attributes(&res.unwrap());
+                    let routed = attributes.iter().find(|attr| attr.key == "action").unwrap();
+                    assert_eq!(routed.value, action, "{:?}", msg);
+                }
+                Err(expected) => {
+                    let err = res.unwrap_err().downcast::<ContractError>().unwrap();
+                    assert_eq!(err, expected, "{:?}", msg);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn approved_spender_transfers_until_revoked() {
+        let (mut app, contract) = setup_app();
+        mint_to_owner(&mut app, &contract, "a");
+        mint_to_owner(&mut app, &contract, "b");
+        for msg in [
+            ExecuteMsg::Approve {
+                spender: BUYER.to_string(),
+                token_id: "a".to_string(),
+                expires: None,
+            },
+            ExecuteMsg::Approve {
+                spender: BUYER.to_string(),
+                token_id: "b".to_string(),
+                expires: None,
+            },
+            ExecuteMsg::Revoke { spender: BUYER.to_string(), token_id: "b".to_string() },
+        ] {
+            app.execute_contract(Addr::unchecked(OWNER), contract.clone(), &msg, &[]).unwrap();
+        }
+        let transfer_msg = |token_id: &str| ExecuteMsg::TransferNft {
+            recipient: "friend".to_string(),
+            token_id: token_id.to_string(),
+            sale_price: None,
+        };
+
+        app.execute_contract(Addr::unchecked(BUYER), contract.clone(), &transfer_msg("a"), &[])
+            .unwrap();
+        assert_eq!(load_owner(&app, &contract, "a"), Some(Addr::unchecked("friend")));
+        // the transfer dropped the approval along with the previous owner
+        let err = app
+            .execute_contract(Addr::unchecked(BUYER), contract.clone(), &transfer_msg("a"), &[])
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::unauthorized("token owner or approved spender")
+        );
+        let err = app
+            .execute_contract(Addr::unchecked(BUYER), contract.clone(), &transfer_msg("b"), &[])
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::unauthorized("token owner or approved spender")
+        );
+
+        // approvals that already expired are rejected
+        let height = app.block_info().height;
+        let err = app
+            .execute_contract(
+                Addr::unchecked(OWNER),
+                contract.clone(),
+                &(ExecuteMsg::Approve {
+                    spender: BUYER.to_string(),
+                    token_id: "b".to_string(),
+                    expires: Some(Expiration::AtHeight(height)),
+                }),
+                &[]
+            )
+            .unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::ApprovalExpired {
+            expires: Expiration::AtHeight(height),
+        });
+    }
+
+    #[test]
+    fn batch_transfers_move_every_token_or_none() {
+        let (mut app, contract) = setup_app();
+        let mint = |id: String| ExecuteMsg::Mint {
+            class_id: "ptx".to_string(),
+            id,
+            uri: None,
+            uri_hash: None,
+            data: None,
+        };
+        for i in 0..20 {
+            let msg = mint(i.to_string());
+            app.execute_contract(Addr::unchecked(OWNER), contract.clone(), &msg, &[]).unwrap();
+        }
+        app.execute_contract(
+            Addr::unchecked(OWNER),
+            contract.clone(),
+            &(ExecuteMsg::TransferNft {
+                recipient: BUYER.to_string(),
+                token_id: "19".to_string(),
+                sale_price: None,
+            }),
+            &[]
+        ).unwrap();
+        let batch = |ids: std::ops::Range<u32>| ExecuteMsg::BatchTransfer {
+            transfers: ids
+                .map(|i| BatchTransferEntry {
+                    recipient: format!("friend{}", i),
+                    token_id: i.to_string(),
+                })
+                .collect(),
+        };
+
+        // token 19 belongs to the buyer now, so nothing moves
+        let err = app
+            .execute_contract(Addr::unchecked(OWNER), contract.clone(), &batch(0..20), &[])
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::unauthorized("token owner or approved spender")
+        );
+        assert_eq!(load_owner(&app, &contract, "0"), Some(Addr::unchecked(OWNER)));
+
+        app.execute_contract(
+            Addr::unchecked(BUYER),
+            contract.clone(),
+            &(ExecuteMsg::TransferNft {
+                recipient: OWNER.to_string(),
+                token_id: "19".to_string(),
+                sale_price: None,
+            }),
+            &[]
+        ).unwrap();
+        let res = app
+            .execute_contract(Addr::unchecked(OWNER), contract.clone(), &batch(0..20), &[])
+            .unwrap();
+        let attributes = wasm_attributes(&res);
+        for i in 0..20 {
+            let recipient = format!("friend{}", i);
+            let owner = load_owner(&app, &contract, &i.to_string());
+            assert_eq!(owner, Some(Addr::unchecked(&recipient)));
+            let pair = [attr("token_id", i.to_string()), attr("recipient", recipient)];
+            assert!(attributes.windows(2).any(|window| window == pair));
+        }
+
+        let err = app
+            .execute_contract(Addr::unchecked(OWNER), contract.clone(), &batch(0..51), &[])
+            .unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::BatchTooLarge {
+            size: 51,
+            max: MAX_TRANSFER_BATCH,
+        });
+    }
+
+    #[test]
+    fn token_ids_follow_the_configured_format() {
+        let format = TokenIdFormat { prefix: "PROPTIX-".to_string(), width: 4 };
+        assert_eq!(format.format(1), "PROPTIX-0001");
+        assert_eq!(format.format(42), "PROPTIX-0042");
+        // numbers wider than the padding keep all their digits
+        assert_eq!(format.format(12_345), "PROPTIX-12345");
+
+        let (mut app, contract) = setup_app_with(|msg| {
+            msg.deployment_config.token_id_format = Some(format.clone());
+        });
+        whitelist_buyer(&mut app, &contract);
+        // the owner takes an id the sale has yet to reach
+        app.execute_contract(
+            Addr::unchecked(OWNER),
+            contract.clone(),
+            &(ExecuteMsg::Mint {
+                class_id: "ptx".to_string(),
+                id: "PROPTIX-0001".to_string(),
+                uri: None,
+                uri_hash: None,
+                data: None,
+            }),
+            &[]
+        ).unwrap();
+
+        // the sale skips it instead of getting stuck on it
+        let res = app
+            .execute_contract(
+                Addr::unchecked(BUYER),
+                contract.clone(),
+                &purchase_msg(2),
+                &coins(200, DENOM)
+            )
+            .unwrap();
+        assert!(wasm_attributes(&res).contains(&attr("token_id", "PROPTIX-0000")));
+        assert!(wasm_attributes(&res).contains(&attr("token_id", "PROPTIX-0002")));
+        assert_eq!(
+            query_all_tokens(&app, &contract),
+            vec!["PROPTIX-0000", "PROPTIX-0001", "PROPTIX-0002"]
+        );
+        assert_eq!(load_owner(&app, &contract, "PROPTIX-0001"), Some(Addr::unchecked(OWNER)));
+        assert_eq!(load_owner(&app, &contract, "PROPTIX-0002"), Some(Addr::unchecked(BUYER)));
+        assert_eq!(load_state(&app, &contract).current_token_id, 3);
+
+        for (format, reason) in [
+            (
+                TokenIdFormat { prefix: "PROPTIX #".to_string(), width: 4 },
+                "prefix must be at most 32 letters, digits, '-' or '_'",
+            ),
+            (TokenIdFormat { prefix: "PTX".to_string(), width: 21 }, "width must be at most 20"),
+        ] {
+            let deployment_config = ProptixDeploymentConfig {
+                token_id_format: Some(format),
+                ..instantiate_msg(0, 0).deployment_config
+            };
+            assert_eq!(
+                ContractError::from(deployment_config.validate().unwrap_err()),
+                ContractError::invalid_config("token_id_format", reason)
+            );
+        }
+    }
+
+    #[test]
+    fn mint_never_passes_max_supply() {
+        let mut deps = mock_dependencies();
+        let mut state = State {
+            max_total_mint: 2,
+            ..blank_state()
+        };
+        let buyer = Addr::unchecked(BUYER);
+
+        assert_eq!(state.mint(deps.as_mut().storage, &buyer, None).unwrap(), 0);
+        assert_eq!(state.mint(deps.as_mut().storage, &buyer, None).unwrap(), 1);
+        let err = state.mint(deps.as_mut().storage, &buyer, None).unwrap_err();
+        assert_eq!(err, ContractError::SoldOut { remaining: 0 });
+        assert_eq!(state.current_token_id, 2);
+    }
+
+    /// Sets one two-leaf allowlist per tier: the buyer is on the OG list only
+    fn setup_phase_roots(app: &mut App, contract: &Addr) -> Vec<Binary> {
+        let og_sibling = merkle_leaf(&Addr::unchecked("og_holder"));
+        let og_root = merkle_parent(&merkle_leaf(&Addr::unchecked(BUYER)), &og_sibling);
+        let wl_root = merkle_parent(
+            &merkle_leaf(&Addr::unchecked("wl_one")),
+            &merkle_leaf(&Addr::unchecked("wl_two"))
+        );
+        for (tier, root) in [(TIER_OG, og_root), (TIER_WL, wl_root)] {
+            app.execute_contract(
+                Addr::unchecked(OWNER),
+                contract.clone(),
+                &(ExecuteMsg::SetPhaseRoot { tier, root: Binary::from(root.to_vec()) }),
+                &[]
+            ).unwrap();
+        }
+        vec![Binary::from(og_sibling.to_vec())]
+    }
+
+    fn set_active_tier(app: &mut App, contract: &Addr, tier: Option<u8>) {
+        app.execute_contract(
+            Addr::unchecked(OWNER),
+            contract.clone(),
+            &(ExecuteMsg::SetActiveTier { tier }),
+            &[]
+        ).unwrap();
+    }
+
+    #[test]
+    fn purchase_verifies_proof_against_active_tier() {
+        let (mut app, contract) = setup_app();
+        let proof = setup_phase_roots(&mut app, &contract);
+
+        set_active_tier(&mut app, &contract, Some(TIER_OG));
+        app.execute_contract(
+            Addr::unchecked(BUYER),
+            contract.clone(),
+            &(ExecuteMsg::Purchase {
+                count: 1,
+                proof: Some(proof.clone()),
+                max_price_per_token: None,
+                recipient: None,
+                referrer: None,
+                allowlist_index: None,
+            }),
+            &coins(100, DENOM)
+        ).unwrap();
+        assert_eq!(load_owner(&app, &contract, "0"), Some(Addr::unchecked(BUYER)));
+
+        // without a proof the buyer is not on the list
+        let err = app
+            .execute_contract(
+                Addr::unchecked(BUYER),
+                contract.clone(),
+                &purchase_msg(1),
+                &coins(100, DENOM)
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::InvalidMerkleProof { tier: TIER_OG }
+        );
+    }
+
+    #[test]
+    fn bitmap_allowlist_checks_the_bit_of_the_proven_index() {
+        let (mut app, contract) = setup_app();
+        let sibling = indexed_merkle_leaf(9_000, &Addr::unchecked("other"));
+        let root = merkle_parent(&indexed_merkle_leaf(3, &Addr::unchecked(BUYER)), &sibling);
+        let proof = vec![Binary::from(sibling.to_vec())];
+        app.execute_contract(
+            Addr::unchecked(OWNER),
+            contract.clone(),
+            &(ExecuteMsg::SetPhaseRoot { tier: TIER_BITMAP, root: Binary::from(root.to_vec()) }),
+            &[]
+        ).unwrap();
+        set_active_tier(&mut app, &contract, Some(TIER_BITMAP));
+
+        let simulate = |app: &App, index: Option<u32>| -> SimulatePurchaseResponse {
+            app.wrap()
+                .query_wasm_smart(&contract, &(QueryMsg::SimulatePurchase {
+                    buyer: BUYER.to_string(),
+                    count: 1,
+                    proof: Some(proof.clone()),
+                    allowlist_index: index,
+                }))
+                .unwrap()
+        };
+        let blocked = |reason| SimulatePurchaseResponse::Blocked { reason };
+        let eligible = SimulatePurchaseResponse::Ok { funds: coins(100, DENOM) };
+        let set_bits = |app: &mut App, sender: &str, msg: ExecuteMsg| {
+            app.execute_contract(Addr::unchecked(sender), contract.clone(), &msg, &[])
+        };
+
+        // the proof holds but the bit of the index is not set yet
+        assert_eq!(
+            simulate(&app, Some(3)),
+            blocked(PurchaseBlocker::AllowlistIndexUnset { index: 3 })
+        );
+
+        // a range across two pages sets the bit
+        set_bits(&mut app, OWNER, ExecuteMsg::SetAllowlistRange {
+            start: 0,
+            count: 10_000,
+            value: true,
+        }).unwrap();
+        assert_eq!(simulate(&app, Some(3)), eligible);
+
+        // the proof only holds for the buyer's own index
+        let invalid = blocked(PurchaseBlocker::InvalidMerkleProof { tier: TIER_BITMAP });
+        assert_eq!(simulate(&app, Some(4)), invalid);
+        assert_eq!(simulate(&app, None), invalid);
+
+        // toggling the bit off and on again
+        set_bits(&mut app, OWNER, ExecuteMsg::SetAllowlistBit { index: 3, value: false }).unwrap();
+        assert_eq!(
+            simulate(&app, Some(3)),
+            blocked(PurchaseBlocker::AllowlistIndexUnset { index: 3 })
+        );
+        set_bits(&mut app, OWNER, ExecuteMsg::SetAllowlistBit { index: 3, value: true }).unwrap();
+        assert_eq!(simulate(&app, Some(3)), eligible);
+
+        let err = set_bits(&mut app, STRANGER, ExecuteMsg::SetAllowlistBit {
+            index: 3,
+            value: false,
+        }).unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::unauthorized("owner"));
+        for (start, count) in [(0, 0), (0, MAX_ALLOWLIST_RANGE + 1), (u32::MAX, 2)] {
+            let err = set_bits(&mut app, OWNER, ExecuteMsg::SetAllowlistRange {
+                start,
+                count,
+                value: true,
+            }).unwrap_err();
+            assert_eq!(
+                err.downcast::<ContractError>().unwrap(),
+                ContractError::InvalidAllowlistRange { start, count }
+            );
+        }
+
+        app.execute_contract(
+            Addr::unchecked(BUYER),
+            contract.clone(),
+            &(ExecuteMsg::Purchase {
+                count: 1,
+                proof: Some(proof.clone()),
+                max_price_per_token: None,
+                recipient: None,
+                referrer: None,
+                allowlist_index: Some(3),
+            }),
+            &coins(100, DENOM)
+        ).unwrap();
+        assert_eq!(load_owner(&app, &contract, "0"), Some(Addr::unchecked(BUYER)));
+    }
+
+    // ed25519 key from the seed [7; 32] and its signature over the voucher digest
+    // of `BUYER` for the first contract instantiated in the app
+    const VOUCHER_PUBKEY: &str = "6kpsY+KcUgq+9VB7Ey7F+ZVHdq6+vnuSQh7qaRRG0iw=";
+    const BUYER_VOUCHER: &str =
+        "8X19jsZ6Z7CH9+n5f4cGKhR87C6iAZh+bw4BGwTo+h/Masi6IpnDYsalpFZ5K1CxEiOm0ze7pL4Uv7yMpRCwBw==";
+
+    fn setup_ed25519_signer(app: &mut App, contract: &Addr) {
+        app.execute_contract(
+            Addr::unchecked(OWNER),
+            contract.clone(),
+            &(ExecuteMsg::SetVoucherSigner {
+                pubkey: Binary::from_base64(VOUCHER_PUBKEY).unwrap(),
+                scheme: SignatureScheme::Ed25519,
+            }),
+            &[]
+        ).unwrap();
+    }
+
+    fn redeem_voucher_msg(signature: Binary) -> ExecuteMsg {
+        ExecuteMsg::RedeemVoucher { count: 1, signature }
+    }
+
+    #[test]
+    fn ed25519_voucher_replaces_the_allowlist() {
+        let (mut app, contract) = setup_app();
+        let signature = Binary::from_base64(BUYER_VOUCHER).unwrap();
+
+        // vouchers are refused until a signer is configured
+        let err = app
+            .execute_contract(
+                Addr::unchecked(BUYER),
+                contract.clone(),
+                &redeem_voucher_msg(signature.clone()),
+                &coins(100, DENOM)
+            )
+            .unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::VoucherSignerUnset {});
+
+        setup_ed25519_signer(&mut app, &contract);
+        assert_eq!(load_state(&app, &contract).signature_scheme, SignatureScheme::Ed25519);
+
+        // the buyer is not whitelisted, the voucher alone admits them
+        app.execute_contract(
+            Addr::unchecked(BUYER),
+            contract.clone(),
+            &redeem_voucher_msg(signature),
+            &coins(100, DENOM)
+        ).unwrap();
+        assert_eq!(load_owner(&app, &contract, "0"), Some(Addr::unchecked(BUYER)));
+        assert_eq!(
+            app.wrap().query_balance(&contract, DENOM).unwrap().amount,
+            Uint128::new(100)
+        );
+    }
+
+    #[test]
+    fn tampered_ed25519_voucher_is_rejected() {
+        let (mut app, contract) = setup_app();
+        setup_ed25519_signer(&mut app, &contract);
+
+        // the signature of another buyer's voucher
+        let err = app
+            .execute_contract(
+                Addr::unchecked("intruder"),
+                contract.clone(),
+                &redeem_voucher_msg(Binary::from_base64(BUYER_VOUCHER).unwrap()),
+                &[]
+            )
+            .unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::InvalidVoucher {});
+
+        // a signature with a flipped bit
+        let mut signature = Binary::from_base64(BUYER_VOUCHER).unwrap().to_vec();
+        signature[0] ^= 1;
+        let err = app
+            .execute_contract(
+                Addr::unchecked(BUYER),
+                contract.clone(),
+                &redeem_voucher_msg(Binary::from(signature)),
+                &coins(100, DENOM)
+            )
+            .unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::InvalidVoucher {});
+        assert_eq!(load_state(&app, &contract).current_token_id, 0);
+
+        // keys must match the length of the scheme
+        let err = app
+            .execute_contract(
+                Addr::unchecked(OWNER),
+                contract.clone(),
+                &(ExecuteMsg::SetVoucherSigner {
+                    pubkey: Binary::from_base64(VOUCHER_PUBKEY).unwrap(),
+                    scheme: SignatureScheme::Secp256k1,
+                }),
+                &[]
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::invalid_config("signer_pubkey", "wrong length for the scheme")
+        );
+    }
+
+    #[test]
+    fn proof_for_one_tier_is_rejected_for_another() {
+        let (mut app, contract) = setup_app();
+        let proof = setup_phase_roots(&mut app, &contract);
+
+        set_active_tier(&mut app, &contract, Some(TIER_WL));
+        let err = app
+            .execute_contract(
+                Addr::unchecked(BUYER),
+                contract.clone(),
+                &(ExecuteMsg::Purchase {
+                    count: 1,
+                    proof: Some(proof),
+                    max_price_per_token: None,
+                    recipient: None,
+                    referrer: None,
+                    allowlist_index: None,
+                }),
+                &coins(100, DENOM)
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::InvalidMerkleProof { tier: TIER_WL }
+        );
+        assert_eq!(load_state(&app, &contract).current_token_id, 0);
+    }
+
+    #[test]
+    fn phase_roots_are_owner_only_and_validated() {
+        let (mut app, contract) = setup_app();
+
+        let err = app
+            .execute_contract(
+                Addr::unchecked(BUYER),
+                contract.clone(),
+                &(ExecuteMsg::SetPhaseRoot { tier: TIER_OG, root: Binary::from([0u8; 32].to_vec()) }),
+                &[]
+            )
+            .unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::unauthorized("owner"));
+
+        let err = app
+            .execute_contract(
+                Addr::unchecked(OWNER),
+                contract.clone(),
+                &(ExecuteMsg::SetPhaseRoot { tier: TIER_OG, root: Binary::from(b"short".to_vec()) }),
+                &[]
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::InvalidMerkleRoot { length: 5 }
+        );
+
+        // an active tier without a root blocks purchases
+        set_active_tier(&mut app, &contract, Some(TIER_WL));
+        let err = app
+            .execute_contract(
+                Addr::unchecked(BUYER),
+                contract.clone(),
+                &purchase_msg(1),
+                &coins(100, DENOM)
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::PhaseRootUnset { tier: TIER_WL }
+        );
+    }
+
+    #[test]
+    fn sale_stats_accumulate_across_days() {
+        let (mut app, contract) = setup_app_with(|msg| {
+            msg.runtime_config.sale_end_time += 3 * SECONDS_PER_DAY;
+            msg.runtime_config.protocol_fee = 5;
+            msg.deployment_config.protocol_address = Some(PROTOCOL.to_string());
+        });
+        whitelist_buyer(&mut app, &contract);
+        let first_day = app.block_info().time.seconds() / SECONDS_PER_DAY;
+
+        for (count, days_later) in [(1, 0), (2, 0), (3, 2)] {
+            app.update_block(|block| {
+                block.time = block.time.plus_seconds(days_later * SECONDS_PER_DAY);
+            });
+            app.execute_contract(
+                Addr::unchecked(BUYER),
+                contract.clone(),
+                &purchase_msg(count),
+                &coins(100 * u128::from(count), DENOM)
+            ).unwrap();
+        }
+
+        let stats: SaleStatsResponse = app
+            .wrap()
+            .query_wasm_smart(&contract, &(QueryMsg::SaleStats {}))
+            .unwrap();
+        assert_eq!(
+            stats,
+            SaleStatsResponse {
+                gross_proceeds: Uint128::new(600),
+                protocol_fees_accrued: Uint128::new(30),
+                total_mints: 6,
+                mints_per_day: vec![
+                    DailyMints { day: first_day, mints: 3 },
+                    DailyMints { day: first_day + 2, mints: 3 }
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn purchase_can_mint_to_another_recipient() {
+        let (mut app, contract) = setup_app_with(|msg| {
+            msg.runtime_config.max_per_wallet = 2;
+        });
+        whitelist_buyer(&mut app, &contract);
+        let gift = |count: u64| ExecuteMsg::Purchase {
+            count,
+            proof: None,
+            max_price_per_token: None,
+            recipient: Some("friend".to_string()),
+            referrer: None,
+            allowlist_index: None,
+        };
+
+        // self-mint counts against the payer
+        app.execute_contract(
+            Addr::unchecked(BUYER),
+            contract.clone(),
+            &purchase_msg(1),
+            &coins(100, DENOM)
+        ).unwrap();
+        assert_eq!(load_owner(&app, &contract, "0"), Some(Addr::unchecked(BUYER)));
+
+        // the payer pays, the recipient receives
+        let res = app
+            .execute_contract(Addr::unchecked(BUYER), contract.clone(), &gift(2), &coins(200, DENOM))
+            .unwrap();
+        assert_eq!(
+            wasm_attributes(&res)[..4],
+            [
+                attr("action", "purchase"),
+                attr("payer", BUYER),
+                attr("owner", "friend"),
+                attr("count", "2"),
+            ]
+        );
+        for token_id in ["1", "2"] {
+            assert_eq!(load_owner(&app, &contract, token_id), Some(Addr::unchecked("friend")));
+        }
+        assert_eq!(load_balance(&app, &contract, TREASURY), Uint128::new(300));
+
+        // the recipient reached its limit, even though the payer has one mint left
+        let err = app
+            .execute_contract(Addr::unchecked(BUYER), contract.clone(), &gift(1), &coins(100, DENOM))
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::WalletLimitExceeded { wallet: "friend".to_string(), limit: 2, minted: 2 }
+        );
+        let err = app
+            .execute_contract(
+                Addr::unchecked(BUYER),
+                contract.clone(),
+                &purchase_msg(2),
+                &coins(200, DENOM)
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::WalletLimitExceeded { wallet: BUYER.to_string(), limit: 2, minted: 1 }
+        );
+        app.execute_contract(
+            Addr::unchecked(BUYER),
+            contract.clone(),
+            &purchase_msg(1),
+            &coins(100, DENOM)
+        ).unwrap();
+    }
+
+    #[test]
+    fn referrer_earns_a_share_of_the_treasury_income() {
+        let (mut app, contract) = setup_app_with(|msg| {
+            msg.runtime_config.protocol_fee = 10;
+            msg.runtime_config.referral_bps = 500;
+            msg.deployment_config.protocol_address = Some(PROTOCOL.to_string());
+        });
+        whitelist_buyer(&mut app, &contract);
+        let referred = |referrer: &str| ExecuteMsg::Purchase {
+            count: 2,
+            proof: None,
+            max_price_per_token: None,
+            recipient: None,
+            referrer: Some(referrer.to_string()),
+            allowlist_index: None,
+        };
+
+        let err = app
+            .execute_contract(
+                Addr::unchecked(BUYER),
+                contract.clone(),
+                &referred(BUYER),
+                &coins(200, DENOM)
+            )
+            .unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::SelfReferral {});
+
+        app.execute_contract(
+            Addr::unchecked(BUYER),
+            contract.clone(),
+            &referred("referrer"),
+            &coins(200, DENOM)
+        ).unwrap();
+        assert_eq!(load_balance(&app, &contract, "referrer"), Uint128::new(10));
+        assert_eq!(load_balance(&app, &contract, PROTOCOL), Uint128::new(20));
+        assert_eq!(load_balance(&app, &contract, TREASURY), Uint128::new(170));
+    }
+
+    #[test]
+    fn the_deploying_factory_receives_its_fee_on_every_purchase() {
+        // `setup_app` instantiates the collection from OWNER, which stands for the factory
+        let (mut app, contract) = setup_app_with(|msg| {
+            msg.deployment_config.factory_fee_bps = 500;
+            msg.deployment_config.protocol_address = Some(PROTOCOL.to_string());
+            msg.runtime_config.protocol_fee = 10;
+        });
+        whitelist_buyer(&mut app, &contract);
+        let factory_balance = |app: &App| app.wrap().query_balance(OWNER, DENOM).unwrap().amount;
+        let before = factory_balance(&app);
+
+        for purchase in 1..=2u128 {
+            app.execute_contract(
+                Addr::unchecked(BUYER),
+                contract.clone(),
+                &purchase_msg(2),
+                &coins(200, DENOM)
+            ).unwrap();
+            // 5% of 200 is paid out right away, the other shares wait in the ledger
+            assert_eq!(factory_balance(&app), before + Uint128::new(10 * purchase));
+            assert_eq!(load_balance(&app, &contract, OWNER), Uint128::zero());
+            assert_eq!(load_balance(&app, &contract, PROTOCOL), Uint128::new(20 * purchase));
+            assert_eq!(load_balance(&app, &contract, TREASURY), Uint128::new(170 * purchase));
+        }
+        assert_eq!(load_state(&app, &contract).factory_address, Some(Addr::unchecked(OWNER)));
+
+        // the protocol and factory fees can't take more than the whole sale
+        let mut deps = mock_dependencies();
+        let mut msg = instantiate_msg(1_000, 2_000);
+        msg.deployment_config.factory_fee_bps = 9_500;
+        msg.deployment_config.protocol_address = Some(PROTOCOL.to_string());
+        msg.runtime_config.protocol_fee = 10;
+        let err = instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::invalid_config(
+                "factory_fee_bps",
+                "can't exceed 100% with the protocol fee"
+            )
+        );
+    }
+
+    #[test]
+    fn purchases_within_a_block_share_its_mint_limit() {
+        let (mut app, contract) = setup_app_with(|msg| {
+            msg.runtime_config.max_mints_per_block = 3;
+        });
+        whitelist_buyer(&mut app, &contract);
+        let purchase = |app: &mut App, count: u64| {
+            app.execute_contract(
+                Addr::unchecked(BUYER),
+                contract.clone(),
+                &purchase_msg(count),
+                &coins(100 * (count as u128), DENOM)
+            )
+        };
+
+        purchase(&mut app, 2).unwrap();
+        let err = purchase(&mut app, 2).unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::BlockMintLimitReached { limit: 3, minted: 2 }
+        );
+        purchase(&mut app, 1).unwrap();
+        assert_eq!(load_state(&app, &contract).current_token_id, 3);
+
+        // the limit starts over in the next block
+        app.update_block(|block| {
+            block.height += 1;
+        });
+        purchase(&mut app, 3).unwrap();
+        assert_eq!(load_state(&app, &contract).current_token_id, 6);
+    }
+
+    #[test]
+    fn purchases_emit_a_parseable_mint_receipt() {
+        let (mut app, contract) = setup_app_with(|msg| {
+            msg.runtime_config.protocol_fee = 10;
+            msg.runtime_config.referral_bps = 500;
+            msg.runtime_config.mint_receipts = true;
+            msg.deployment_config.protocol_address = Some(PROTOCOL.to_string());
+        });
+        whitelist_buyer(&mut app, &contract);
+        let res = app
+            .execute_contract(
+                Addr::unchecked(BUYER),
+                contract.clone(),
+                &(ExecuteMsg::Purchase {
+                    count: 3,
+                    proof: None,
+                    max_price_per_token: None,
+                    recipient: Some("friend".to_string()),
+                    referrer: Some("referrer".to_string()),
+                    allowlist_index: None,
+                }),
+                &coins(300, DENOM)
+            )
+            .unwrap();
+
+        let attributes = wasm_attributes(&res);
+        let receipts: Vec<_> = attributes.iter().filter(|a| a.key == "mint_receipt").collect();
+        assert_eq!(receipts.len(), 1);
+        let height = app.block_info().height;
+        let expected = MintReceipt {
+            buyer: BUYER.to_string(),
+            recipient: "friend".to_string(),
+            token_ids: vec!["0".to_string(), "1".to_string(), "2".to_string()],
+            price_paid: coin(300, DENOM),
+            fee_split: SaleSplit {
+                protocol_fee: Uint128::new(30),
+                factory_fee: Uint128::zero(),
+                referral_reward: Uint128::new(15),
+                treasury_share: Uint128::new(255),
+            },
+            height,
+        };
+        assert_eq!(from_slice::<MintReceipt>(receipts[0].value.as_bytes()).unwrap(), expected);
+        // the encoding is fixed, so integrators can rely on it byte for byte
+        assert_eq!(
+            receipts[0].value,
+            format!(
+                "{{\"buyer\":\"{BUYER}\",\"recipient\":\"friend\",\
+                \"token_ids\":[\"0\",\"1\",\"2\"],\
+                \"price_paid\":{{\"denom\":\"{DENOM}\",\"amount\":\"300\"}},\
+                \"fee_split\":{{\"protocol_fee\":\"30\",\"factory_fee\":\"0\",\
+                \"referral_reward\":\"15\",\
+                \"treasury_share\":\"255\"}},\"height\":{height}}}"
+            )
+        );
+
+        // receipts stay off unless the collection enables them
+        let (mut app, contract) = setup_app();
+        whitelist_buyer(&mut app, &contract);
+        let res = app
+            .execute_contract(
+                Addr::unchecked(BUYER),
+                contract.clone(),
+                &purchase_msg(1),
+                &coins(100, DENOM)
+            )
+            .unwrap();
+        assert!(wasm_attributes(&res).iter().all(|a| a.key != "mint_receipt"));
+    }
+
+    fn simulate_purchase(app: &App, contract: &Addr, count: u64) -> SimulatePurchaseResponse {
+        app.wrap()
+            .query_wasm_smart(contract, &(QueryMsg::SimulatePurchase {
+                buyer: BUYER.to_string(),
+                count,
+                proof: None,
+                allowlist_index: None,
+            }))
+            .unwrap()
+    }
+
+    fn can_mint(app: &App, contract: &Addr, count: u64) -> CanMintResponse {
+        app.wrap()
+            .query_wasm_smart(contract, &(QueryMsg::CanMint { buyer: BUYER.to_string(), count }))
+            .unwrap()
+    }
+
+    /// Asserts the simulation and `CanMint` report `reason` and the purchase fails with
+    /// its error
+    fn assert_purchase_blocked(
+        app: &mut App,
+        contract: &Addr,
+        count: u64,
+        reason: PurchaseBlocker
+    ) {
+        assert_eq!(
+            simulate_purchase(app, contract, count),
+            SimulatePurchaseResponse::Blocked { reason: reason.clone() }
+        );
+        assert_eq!(can_mint(app, contract, count), CanMintResponse {
+            allowed: false,
+            reason: Some(ContractError::from(reason.clone()).to_string()),
+        });
+        let err = app
+            .execute_contract(
+                Addr::unchecked(BUYER),
+                contract.clone(),
+                &purchase_msg(count),
+                &coins(100, DENOM)
+            )
+            .unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::from(reason));
+    }
+
+    #[test]
+    fn simulated_purchases_match_execution() {
+        let (mut app, contract) = setup_app_with(|msg| {
+            msg.runtime_config.max_per_wallet = 2;
+        });
+
+        assert_purchase_blocked(&mut app, &contract, 1, PurchaseBlocker::NotWhitelisted {});
+        whitelist_buyer(&mut app, &contract);
+        assert_purchase_blocked(&mut app, &contract, 11, PurchaseBlocker::SoldOut {
+            remaining: 10,
+        });
+
+        // the simulated funds are exactly what the purchase takes
+        let SimulatePurchaseResponse::Ok { funds } = simulate_purchase(&app, &contract, 2) else {
+            panic!("purchase should be possible");
+        };
+        assert_eq!(can_mint(&app, &contract, 2), CanMintResponse { allowed: true, reason: None });
+        assert_eq!(funds, coins(200, DENOM));
+        app.execute_contract(Addr::unchecked(BUYER), contract.clone(), &purchase_msg(2), &funds)
+            .unwrap();
+        assert_eq!(load_balance(&app, &contract, BUYER), Uint128::zero());
+
+        assert_purchase_blocked(&mut app, &contract, 1, PurchaseBlocker::WalletLimitExceeded {
+            wallet: BUYER.to_string(),
+            limit: 2,
+            minted: 2,
+        });
+
+        set_active_tier(&mut app, &contract, Some(TIER_OG));
+        assert_purchase_blocked(&mut app, &contract, 1, PurchaseBlocker::PhaseRootUnset {
+            tier: TIER_OG,
+        });
+        set_active_tier(&mut app, &contract, None);
+
+        let state = load_state(&app, &contract);
+        app.update_block(|block| {
+            block.time = Timestamp::from_seconds(state.sale_end_time + 1);
+        });
+        assert_purchase_blocked(&mut app, &contract, 1, PurchaseBlocker::SaleNotActive {
+            starts_at: state.sale_start_time,
+            ends_at: state.sale_end_time,
+            now: state.sale_end_time + 1,
+        });
+    }
+
+    #[test]
+    fn purchase_counts_are_checked_before_funds() {
+        let (mut app, contract) = setup_app();
+        whitelist_buyer(&mut app, &contract);
+
+        // each bound is reported even though no funds are attached
+        let cases = [
+            (0, ContractError::ZeroPurchaseCount {}),
+            (51, ContractError::MintPerTxExceeded { max: 50 }),
+            (u64::MAX, ContractError::MintPerTxExceeded { max: 50 }),
+            (11, ContractError::SoldOut { remaining: 10 }),
+            (1, ContractError::Payment(PaymentError::NoFunds {})),
+        ];
+        for (count, expected) in cases {
+            let msg = purchase_msg(count);
+            let err = app
+                .execute_contract(Addr::unchecked(BUYER), contract.clone(), &msg, &[])
+                .unwrap_err();
+            assert_eq!(err.downcast::<ContractError>().unwrap(), expected);
+        }
+        assert_purchase_blocked(&mut app, &contract, 0, PurchaseBlocker::ZeroPurchaseCount {});
+        assert_purchase_blocked(&mut app, &contract, 51, PurchaseBlocker::MintPerTxExceeded {
+            max: 50,
+        });
+        assert_eq!(load_state(&app, &contract).current_token_id, 0);
+    }
+
+    #[test]
+    fn whitelist_allocation_is_consumed_across_purchases() {
+        let (mut app, contract) = setup_app_with(|msg| {
+            msg.runtime_config.presale_start_time = msg.runtime_config.sale_start_time;
+            msg.runtime_config.presale_end_time = msg.runtime_config.sale_start_time + 100;
+            msg.runtime_config.sale_start_time += 200;
+        });
+        let add_to_whitelist = |allocation| ExecuteMsg::AddToWhitelist {
+            class_id: "ptx".to_string(),
+            id: "0".to_string(),
+            account: BUYER.to_string(),
+            allocation,
+        };
+        let res = app
+            .execute_contract(
+                Addr::unchecked(OWNER),
+                contract.clone(),
+                &add_to_whitelist(Some(3)),
+                &[]
+            )
+            .unwrap();
+        assert!(wasm_attributes(&res).contains(&attr("allocation", "3")));
+        let buy = |app: &mut App, count: u64| {
+            app.execute_contract(
+                Addr::unchecked(BUYER),
+                contract.clone(),
+                &purchase_msg(count),
+                &coins(100 * u128::from(count), DENOM)
+            )
+        };
+
+        // the allocation carries over between transactions and phases
+        buy(&mut app, 2).unwrap();
+        let allocation = WHITELIST_ALLOCATIONS
+            .query(&app.wrap(), contract.clone(), &Addr::unchecked(BUYER))
+            .unwrap();
+        assert_eq!(allocation, Some(1));
+        assert_purchase_blocked(&mut app, &contract, 2, PurchaseBlocker::AllocationExceeded {
+            remaining: 1,
+        });
+        let state = load_state(&app, &contract);
+        app.update_block(|block| {
+            block.time = Timestamp::from_seconds(state.sale_start_time);
+        });
+        buy(&mut app, 1).unwrap();
+        assert_purchase_blocked(&mut app, &contract, 1, PurchaseBlocker::AllocationExceeded {
+            remaining: 0,
+        });
+
+        // whitelisting again without an allocation lifts the limit
+        app.execute_contract(
+            Addr::unchecked(OWNER),
+            contract.clone(),
+            &add_to_whitelist(None),
+            &[]
+        ).unwrap();
+        buy(&mut app, 1).unwrap();
+        assert_eq!(query_all_tokens(&app, &contract).len(), 4);
+    }
+
+    #[test]
+    fn whitelist_is_paginated_by_address() {
+        let (mut app, contract) = setup_app();
+        let addresses: Vec<String> = (0..40).map(|i| format!("wallet{:02}", i)).collect();
+        app.execute_contract(
+            Addr::unchecked(OWNER),
+            contract.clone(),
+            &(ExecuteMsg::WhitelistBatch { addresses: addresses.clone(), status: true }),
+            &[]
+        ).unwrap();
+        app.execute_contract(
+            Addr::unchecked(OWNER),
+            contract.clone(),
+            &(ExecuteMsg::AddToWhitelist {
+                class_id: "ptx".to_string(),
+                id: "0".to_string(),
+                account: "wallet05".to_string(),
+                allocation: Some(2),
+            }),
+            &[]
+        ).unwrap();
+        // removed addresses leave the whitelist
+        app.execute_contract(
+            Addr::unchecked(OWNER),
+            contract.clone(),
+            &(ExecuteMsg::Whitelist { address: "wallet39".to_string(), status: false }),
+            &[]
+        ).unwrap();
+        let removed = app
+            .wrap()
+            .query_wasm_raw(&contract, WHITELIST.key(&Addr::unchecked("wallet39")).to_vec())
+            .unwrap();
+        assert_eq!(removed, None);
+        let page = |start_after: Option<&str>, limit: Option<u32>| {
+            let res: WhitelistResponse = app
+                .wrap()
+                .query_wasm_smart(&contract, &(QueryMsg::Whitelist {
+                    start_after: start_after.map(String::from),
+                    limit,
+                }))
+                .unwrap();
+            res.entries
+        };
+
+        assert_eq!(page(None, None).len(), 10);
+        // the limit is capped
+        let first = page(None, Some(50));
+        assert_eq!(first.len(), 30);
+        assert_eq!(first[0], WhitelistEntry { address: "wallet00".to_string(), allocation: None });
+        assert_eq!(first[5], WhitelistEntry {
+            address: "wallet05".to_string(),
+            allocation: Some(2),
+        });
+        let second = page(Some(&first[29].address), Some(30));
+        let listed: Vec<&str> = second.iter().map(|entry| entry.address.as_str()).collect();
+        assert_eq!(listed, addresses[30..39].iter().map(String::as_str).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn whitelisted_bech32_address_can_purchase() {
+        let (mut app, contract) = setup_app();
+        let wallet = "coreum1qyqszqgpqyqszqgpqyqszqgpqyqszqgpjnp7du";
+        app.sudo(
+            (BankSudo::Mint {
+                to_address: wallet.to_string(),
+                amount: coins(100, DENOM),
+            }).into()
+        ).unwrap();
+
+        // the owner whitelists the address exactly as the wallet sends from it
+        app.execute_contract(
+            Addr::unchecked(OWNER),
+            contract.clone(),
+            &(ExecuteMsg::AddToWhitelist {
+                class_id: "ptx".to_string(),
+                id: "1".to_string(),
+                account: wallet.to_string(),
+                allocation: Some(1),
+            }),
+            &[]
+        ).unwrap();
+        app.execute_contract(
+            Addr::unchecked(wallet),
+            contract.clone(),
+            &purchase_msg(1),
+            &coins(100, DENOM)
+        ).unwrap();
+        assert_eq!(load_owner(&app, &contract, "0"), Some(Addr::unchecked(wallet)));
+
+        // the allocation was found under the same key and is used up
+        let profile: BuyerProfileResponse = app
+            .wrap()
+            .query_wasm_smart(&contract, &(QueryMsg::BuyerProfile { address: wallet.to_string() }))
+            .unwrap();
+        assert!(profile.whitelisted);
+        assert_eq!((profile.minted, profile.remaining_allocation), (1, Some(0)));
+
+        // the same address in upper case is not a valid address
+        let err = app
+            .execute_contract(
+                Addr::unchecked(OWNER),
+                contract.clone(),
+                &(ExecuteMsg::Whitelist { address: wallet.to_uppercase(), status: true }),
+                &[]
+            )
+            .unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::InvalidAddress {
+            field: "address".to_string(),
+            value: wallet.to_uppercase(),
+        });
+    }
+
+    #[test]
+    fn can_mint_explains_the_first_failing_condition() {
+        let (mut app, contract) = setup_app();
+        let reason = |app: &App, count: u64| can_mint(app, &contract, count).reason;
+
+        assert_eq!(
+            reason(&app, 1),
+            Some("Unauthorized: sender is not the whitelisted buyer".to_string())
+        );
+        whitelist_buyer(&mut app, &contract);
+        // the supply is checked before the wallet limits
+        assert_eq!(reason(&app, 11), Some("Sold out: 10 tokens remaining".to_string()));
+        assert_eq!(can_mint(&app, &contract, 1), CanMintResponse { allowed: true, reason: None });
+
+        // checking mints nothing
+        assert_eq!(reason(&app, 10), None);
+        assert_eq!(load_state(&app, &contract).current_token_id, 0);
+    }
+
+    #[test]
+    fn purchase_respects_max_price_per_token() {
+        let (mut app, contract) = setup_app();
+        whitelist_buyer(&mut app, &contract);
+
+        let err = app
+            .execute_contract(
+                Addr::unchecked(BUYER),
+                contract.clone(),
+                &(ExecuteMsg::Purchase {
+                    count: 1,
+                    proof: None,
+                    max_price_per_token: Some(Uint128::new(99)),
+                    recipient: None,
+                    referrer: None,
+                    allowlist_index: None,
+                }),
+                &coins(100, DENOM)
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::PriceSlippage { quoted: Uint128::new(100), max: Uint128::new(99) }
+        );
+        assert_eq!(load_state(&app, &contract).current_token_id, 0);
+
+        for max in [Uint128::new(100), Uint128::new(150)] {
+            app.execute_contract(
+                Addr::unchecked(BUYER),
+                contract.clone(),
+                &(ExecuteMsg::Purchase {
+                    count: 1,
+                    proof: None,
+                    max_price_per_token: Some(max),
+                    recipient: None,
+                    referrer: None,
+                    allowlist_index: None,
+                }),
+                &coins(100, DENOM)
+            ).unwrap();
+        }
+        assert_eq!(load_state(&app, &contract).current_token_id, 2);
+    }
+
+    #[test]
+    fn quote_matches_the_amount_a_purchase_charges() {
+        let (mut app, contract) = setup_app_with(|msg| {
+            msg.runtime_config.protocol_fee = 5;
+            msg.deployment_config.protocol_address = Some(PROTOCOL.to_string());
+        });
+        whitelist_buyer(&mut app, &contract);
+        let quote = |app: &App, promo_code: Option<&str>| -> StdResult<PurchaseQuoteResponse> {
+            app.wrap().query_wasm_smart(&contract, &(QueryMsg::QuotePurchase {
+                buyer: BUYER.to_string(),
+                count: 3,
+                promo_code: promo_code.map(str::to_string),
+            }))
+        };
+
+        let res = quote(&app, None).unwrap();
+        assert_eq!(
+            res,
+            PurchaseQuoteResponse {
+                per_token_price: Uint128::new(100),
+                subtotal: Uint128::new(300),
+                discount: Uint128::zero(),
+                total: Uint128::new(300),
+            }
+        );
+        assert!(quote(&app, Some("LAUNCH")).unwrap_err().to_string().contains("LAUNCH"));
+
+        // the quoted total is exactly enough, one less is not
+        let err = app
+            .execute_contract(
+                Addr::unchecked(BUYER),
+                contract.clone(),
+                &purchase_msg(3),
+                &coins(res.total.u128() - 1, DENOM)
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::InsufficientFunds {
+                required: res.total,
+                provided: res.total - Uint128::one(),
+            }
+        );
+        app.execute_contract(
+            Addr::unchecked(BUYER),
+            contract.clone(),
+            &purchase_msg(3),
+            &coins(res.total.u128(), DENOM)
+        ).unwrap();
+        assert_eq!(
+            app.wrap().query_balance(BUYER, DENOM).unwrap().amount,
+            Uint128::new(1_000) - res.total
+        );
+        assert_eq!(load_state(&app, &contract).gross_proceeds, res.total);
+    }
+
+    #[test]
+    fn purchase_with_fee_requires_protocol_address() {
+        // collections migrated without a protocol address refuse fee bearing sales
+        let state = State { protocol_fee_bps: 500, ..blank_state() };
+        let err = state.assert_protocol_address_set().unwrap_err();
+        assert_eq!(err, ContractError::ProtocolAddressUnset {});
+
+        let (mut app, contract) = setup_app();
+        let set_protocol = |app: &mut App, sender: &str| {
+            app.execute_contract(
+                Addr::unchecked(sender),
+                contract.clone(),
+                &(ExecuteMsg::SetProtocolAddress { address: PROTOCOL.to_string() }),
+                &[]
+            )
+        };
+
+        // only the owner can set the protocol address, and only while it is unset
+        set_protocol(&mut app, BUYER).unwrap_err();
+        set_protocol(&mut app, OWNER).unwrap();
+        assert_eq!(load_state(&app, &contract).protocol_address, Some(Addr::unchecked(PROTOCOL)));
+        let err = set_protocol(&mut app, OWNER).unwrap_err();
+        let err = err.downcast::<ContractError>().unwrap();
+        assert_eq!(err, ContractError::unauthorized("protocol"));
+    }
+
+    #[test]
+    fn protocol_address_is_rotated_by_the_protocol() {
+        const NEXT_PROTOCOL: &str = "next_protocol";
+        let (mut app, contract) = setup_app_with(|msg| {
+            msg.deployment_config.protocol_address = Some(PROTOCOL.to_string());
+            msg.runtime_config.protocol_fee = 5;
+        });
+        whitelist_buyer(&mut app, &contract);
+        let rotate = |app: &mut App, sender: &str| {
+            app.execute_contract(
+                Addr::unchecked(sender),
+                contract.clone(),
+                &(ExecuteMsg::UpdateProtocolAddress { address: NEXT_PROTOCOL.to_string() }),
+                &[]
+            )
+        };
+
+        // the collection owner can't redirect the protocol fee
+        let err = rotate(&mut app, OWNER).unwrap_err();
+        let err = err.downcast::<ContractError>().unwrap();
+        assert_eq!(err, ContractError::unauthorized("protocol"));
+
+        rotate(&mut app, PROTOCOL).unwrap();
+        let state = load_state(&app, &contract);
+        assert_eq!(state.protocol_address, Some(Addr::unchecked(NEXT_PROTOCOL)));
+        // the previous address gave up control
+        rotate(&mut app, PROTOCOL).unwrap_err();
+
+        app.execute_contract(
+            Addr::unchecked(BUYER),
+            contract.clone(),
+            &purchase_msg(1),
+            &coins(100, DENOM)
+        ).unwrap();
+        assert_eq!(load_balance(&app, &contract, NEXT_PROTOCOL), Uint128::new(5));
+        assert_eq!(load_balance(&app, &contract, PROTOCOL), Uint128::zero());
+        assert_eq!(load_balance(&app, &contract, TREASURY), Uint128::new(95));
+    }
+
+    #[test]
+    fn protocol_address_can_be_set_at_instantiate() {
+        let (app, contract) = setup_app_with(|msg| {
+            msg.deployment_config.protocol_address = Some(PROTOCOL.to_string());
+        });
+        assert_eq!(load_state(&app, &contract).protocol_address, Some(Addr::unchecked(PROTOCOL)));
+    }
+
+    fn phase(
+        label: &str,
+        start: u64,
+        end: u64,
+        price: u128,
+        limit: u64,
+        whitelist_only: bool
+    ) -> Phase {
+        Phase {
+            label: label.to_string(),
+            start,
+            end,
+            price: Uint128::new(price),
+            per_wallet_limit: limit,
+            whitelist_only,
+        }
+    }
+
+    #[test]
+    fn purchases_follow_the_active_phase() {
+        let mut base = 0;
+        let (mut app, contract) = setup_app_with(|msg| {
+            base = msg.runtime_config.sale_start_time;
+            // a gap separates the allowlist from the public phase
+            msg.runtime_config.phases = vec![
+                phase("og", base + 1_000, base + 1_999, 50, 1, true),
+                phase("allowlist", base + 2_000, base + 2_999, 80, 2, true),
+                phase("public", base + 4_000, base + 4_999, 100, 0, false)
+            ];
+        });
+        whitelist_buyer(&mut app, &contract);
+        let at = |app: &mut App, offset: u64| {
+            app.update_block(|block| block.time = Timestamp::from_seconds(base + offset));
+        };
+        let purchase = |app: &mut App, count: u64, paid: u128| {
+            app.execute_contract(
+                Addr::unchecked(BUYER),
+                contract.clone(),
+                &purchase_msg(count),
+                &coins(paid, DENOM)
+            )
+        };
+        let active_phase = |app: &App| -> Option<String> {
+            let res: ActivePhaseResponse = app
+                .wrap()
+                .query_wasm_smart(&contract, &(QueryMsg::ActivePhase {}))
+                .unwrap();
+            res.phase.map(|phase| phase.label)
+        };
+        let wallet_limit = |limit: u64, minted: u64| ContractError::WalletLimitExceeded {
+            wallet: BUYER.to_string(),
+            limit,
+            minted,
+        };
+
+        let res: PhasesResponse = app
+            .wrap()
+            .query_wasm_smart(&contract, &(QueryMsg::Phases {}))
+            .unwrap();
+        assert_eq!(res.phases.len(), 3);
+
+        at(&mut app, 999);
+        assert_eq!(active_phase(&app), None);
+        let err = purchase(&mut app, 1, 50).unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::SaleNotActive {
+                starts_at: base + 1_000,
+                ends_at: base + 1_999,
+                now: base + 999,
+            }
+        );
+
+        // every phase has its own price and wallet limit
+        at(&mut app, 1_000);
+        assert_eq!(active_phase(&app), Some("og".to_string()));
+        let err = purchase(&mut app, 2, 100).unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), wallet_limit(1, 0));
+        purchase(&mut app, 1, 50).unwrap();
+
+        at(&mut app, 2_000);
+        assert_eq!(active_phase(&app), Some("allowlist".to_string()));
+        purchase(&mut app, 2, 160).unwrap();
+        let err = purchase(&mut app, 1, 80).unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), wallet_limit(2, 2));
+
+        // nothing is sold between phases
+        at(&mut app, 3_500);
+        assert_eq!(active_phase(&app), None);
+        let err = purchase(&mut app, 1, 100).unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::SaleNotActive {
+                starts_at: base + 4_000,
+                ends_at: base + 4_999,
+                now: base + 3_500,
+            }
+        );
+
+        // the public phase lets buyers off the whitelist through
+        app.execute_contract(
+            Addr::unchecked(OWNER),
+            contract.clone(),
+            &(ExecuteMsg::Whitelist { address: BUYER.to_string(), status: false }),
+            &[]
+        ).unwrap();
+        at(&mut app, 4_000);
+        assert_eq!(active_phase(&app), Some("public".to_string()));
+        purchase(&mut app, 1, 100).unwrap();
+        assert_eq!(load_balance(&app, &contract, TREASURY), Uint128::new(310));
+        assert_eq!(load_state(&app, &contract).total_mints, 4);
+
+        at(&mut app, 5_000);
+        let err = purchase(&mut app, 1, 100).unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::SaleNotActive {
+                starts_at: base + 4_000,
+                ends_at: base + 4_999,
+                now: base + 5_000,
+            }
+        );
+    }
+
+    #[test]
+    fn buyer_profile_reports_every_purchase_gate() {
+        let (mut app, contract) = setup_app_with(|msg| {
+            msg.runtime_config.max_per_wallet = 5;
+        });
+        app.execute_contract(
+            Addr::unchecked(OWNER),
+            contract.clone(),
+            &(ExecuteMsg::AddToWhitelist {
+                class_id: "ptx".to_string(),
+                id: "0".to_string(),
+                account: BUYER.to_string(),
+                allocation: Some(3),
+            }),
+            &[]
+        ).unwrap();
+        app.execute_contract(
+            Addr::unchecked(BUYER),
+            contract.clone(),
+            &purchase_msg(2),
+            &coins(200, DENOM)
+        ).unwrap();
+        app.execute_contract(
+            Addr::unchecked(BUYER),
+            contract.clone(),
+            &(ExecuteMsg::CommitMint {
+                commitment: Binary::from(vec![0; 32]),
+                proof: None,
+                allowlist_index: None,
+            }),
+            &coins(100, DENOM)
+        ).unwrap();
+        let committed_at = app.block_info().height;
+
+        let profile: BuyerProfileResponse = app
+            .wrap()
+            .query_wasm_smart(&contract, &(QueryMsg::BuyerProfile { address: BUYER.to_string() }))
+            .unwrap();
+        assert_eq!(profile, BuyerProfileResponse {
+            whitelisted: true,
+            blacklisted: false,
+            active_tier: None,
+            remaining_allocation: Some(1),
+            minted: 2,
+            minted_in_phase: 0,
+            remaining_wallet_limit: Some(3),
+            reveal_height: Some(committed_at + MIN_REVEAL_DELAY_BLOCKS),
+            price: Uint128::new(100),
+        });
+
+        // the profile agrees with what a purchase would do
+        let can_mint = |app: &App, count: u64| -> CanMintResponse {
+            let msg = QueryMsg::CanMint { buyer: BUYER.to_string(), count };
+            app.wrap().query_wasm_smart(&contract, &msg).unwrap()
+        };
+        assert!(can_mint(&app, 1).allowed);
+        assert!(!can_mint(&app, 2).allowed);
+    }
+
+    fn mint_to_owner(app: &mut App, contract: &Addr, id: &str) {
+        app.execute_contract(
+            Addr::unchecked(OWNER),
+            contract.clone(),
+            &(ExecuteMsg::Mint {
+                class_id: "ptx".to_string(),
+                id: id.to_string(),
+                uri: None,
+                uri_hash: None,
+                data: None,
+            }),
+            &[]
+        ).unwrap();
+    }
+
+    #[test]
+    fn burn_batch() {
+        let (mut app, contract) = setup_app();
+        for id in ["a", "b", "c"] {
+            mint_to_owner(&mut app, &contract, id);
+        }
+
+        let res = app
+            .execute_contract(
+                Addr::unchecked(OWNER),
+                contract.clone(),
+                &(ExecuteMsg::BurnBatch {
+                    class_id: "ptx".to_string(),
+                    ids: vec!["a".to_string(), "c".to_string()],
+                }),
+                &[]
+            )
+            .unwrap();
+        assert_eq!(
+            wasm_attributes(&res),
+            vec![
+                attr("action", "burn_batch"),
+                attr("sender", OWNER),
+                attr("token_id", "a"),
+                attr("token_id", "c")
+            ]
+        );
+
+        assert_eq!(load_owner(&app, &contract, "a"), None);
+        assert_eq!(load_owner(&app, &contract, "b"), Some(Addr::unchecked(OWNER)));
+        assert_eq!(load_owner(&app, &contract, "c"), None);
+
+        let state = load_state(&app, &contract);
+        assert_eq!(state.burned_count, 2);
+        assert_eq!(state.current_token_id, 0);
+    }
+
+    #[test]
+    fn burn_batch_is_atomic() {
+        let (mut app, contract) = setup_app();
+        mint_to_owner(&mut app, &contract, "a");
+        whitelist_buyer(&mut app, &contract);
+        app.execute_contract(
+            Addr::unchecked(BUYER),
+            contract.clone(),
+            &purchase_msg(1),
+            &coins(100, DENOM)
+        ).unwrap();
+        let bought: TokensResponse = app
+            .wrap()
+            .query_wasm_smart(
+                &contract,
+                &(QueryMsg::Tokens { owner: BUYER.to_string(), start_after: None, limit: None })
+            )
+            .unwrap();
+        let bought = bought.tokens[0].clone();
+
+        // one of the ids belongs to the buyer, so nothing is burned
+        let err = app
+            .execute_contract(
+                Addr::unchecked(OWNER),
+                contract.clone(),
+                &(ExecuteMsg::BurnBatch {
+                    class_id: "ptx".to_string(),
+                    ids: vec!["a".to_string(), bought.clone()],
+                }),
+                &[]
+            )
+            .unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::unauthorized("token owner"));
+
+        assert_eq!(load_owner(&app, &contract, "a"), Some(Addr::unchecked(OWNER)));
+        assert_eq!(load_owner(&app, &contract, &bought), Some(Addr::unchecked(BUYER)));
+        assert_eq!(load_state(&app, &contract).burned_count, 0);
+
+        // listing an id twice is rejected as well
+        let err = app
+            .execute_contract(
+                Addr::unchecked(OWNER),
+                contract.clone(),
+                &(ExecuteMsg::BurnBatch {
+                    class_id: "ptx".to_string(),
+                    ids: vec!["a".to_string(), "a".to_string()],
+                }),
+                &[]
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::DuplicateTokenId { token_id: "a".to_string() }
+        );
+    }
+
+    #[test]
+    fn freezes_persist_across_messages() {
+        let (mut app, contract) = setup_app();
+
+        for id in ["1", "2"] {
+            mint_to_owner(&mut app, &contract, id);
+            app.execute_contract(
+                Addr::unchecked(OWNER),
+                contract.clone(),
+                &(ExecuteMsg::Freeze { class_id: "ptx".to_string(), id: id.to_string() }),
+                &[]
+            ).unwrap();
+        }
+        app.execute_contract(
+            Addr::unchecked(OWNER),
+            contract.clone(),
+            &(ExecuteMsg::Unfreeze { class_id: "ptx".to_string(), id: "1".to_string() }),
+            &[]
+        ).unwrap();
+
+        let res: FrozenTokensResponse = app
+            .wrap()
+            .query_wasm_smart(&contract, &(QueryMsg::FrozenTokens { start_after: None, limit: None }))
+            .unwrap();
+        assert_eq!(res.tokens, vec!["2".to_string()]);
+
+        // only the owner may freeze
+        app.execute_contract(
+            Addr::unchecked(BUYER),
+            contract,
+            &(ExecuteMsg::Freeze { class_id: "ptx".to_string(), id: "3".to_string() }),
+            &[]
+        ).unwrap_err();
+    }
+
+    fn save_token(storage: &mut dyn Storage, id: &str, owner: &str) {
+        tokens().save(storage, id, &TokenRecord::new(Addr::unchecked(owner), None)).unwrap();
+    }
+
+    #[test]
+    fn frozen_tokens_are_paginated() {
+        let mut deps = mock_dependencies();
+
+        for id in ["1", "2", "3", "4", "5"] {
+            save_token(deps.as_mut().storage, id, OWNER);
+            freeze_token(deps.as_mut().storage, id).unwrap();
+        }
+        unfreeze_token(deps.as_mut().storage, "3").unwrap();
+
+        let page = query_frozen_tokens(deps.as_ref(), None, Some(2)).unwrap();
+        assert_eq!(page.tokens, vec!["1".to_string(), "2".to_string()]);
+
+        let page = query_frozen_tokens(deps.as_ref(), Some("2".to_string()), Some(2)).unwrap();
+        assert_eq!(page.tokens, vec!["4".to_string(), "5".to_string()]);
+
+        let page = query_frozen_tokens(deps.as_ref(), Some("5".to_string()), None).unwrap();
+        assert!(page.tokens.is_empty());
+    }
+
+    #[test]
+    fn owner_of_follows_mint_and_transfer() {
+        let (mut app, contract) = setup_app();
+        mint_to_owner(&mut app, &contract, "a");
+        let query_owner = |app: &App, token_id: &str| -> StdResult<OwnerOfResponse> {
+            app.wrap().query_wasm_smart(
+                &contract,
+                &(QueryMsg::OwnerOf { token_id: token_id.to_string() })
+            )
+        };
+        assert_eq!(
+            query_owner(&app, "a").unwrap(),
+            OwnerOfResponse { owner: OWNER.to_string(), approvals: vec![] }
+        );
+        let err = query_owner(&app, "b").unwrap_err();
+        assert!(err.to_string().contains("Token b not found"));
+
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        let token = TokenRecord {
+            approvals: vec![
+                Approval {
+                    spender: "expired".to_string(),
+                    expires: Expiration::AtHeight(env.block.height - 1),
+                },
+                Approval { spender: "active".to_string(), expires: Expiration::Never {} }
+            ],
+            ..TokenRecord::new(Addr::unchecked(OWNER), None)
+        };
+        tokens().save(deps.as_mut().storage, "a", &token).unwrap();
+        let res = query_owner_of(deps.as_ref(), env.clone(), "a".to_string()).unwrap();
+        assert_eq!(res.owner, OWNER);
+        assert_eq!(res.approvals, vec![token.approvals[1].clone()]);
+
+        // transfers hand the token over and drop its approvals
+        transfer(
+            deps.as_mut(),
+            &mock_env().block,
+            Addr::unchecked(OWNER),
+            Addr::unchecked(BUYER),
+            "a".to_string()
+        ).unwrap();
+        env.block.height += 1;
+        let res = query_owner_of(deps.as_ref(), env.clone(), "a".to_string()).unwrap();
+        assert_eq!(res, OwnerOfResponse { owner: BUYER.to_string(), approvals: vec![] });
+
+        let err = query_owner_of(deps.as_ref(), env, "b".to_string()).unwrap_err();
+        assert_eq!(err, ContractError::TokenNotFound { token_id: "b".to_string() });
+    }
+
+    #[test]
+    fn blacklisted_holder_can_burn_but_not_transfer() {
+        let mut deps = mock_dependencies();
+        let state = State {
+            owner: Addr::unchecked(OWNER),
+            ..blank_state()
+        };
+        STATE.save(deps.as_mut().storage, &state).unwrap();
+        for id in ["1", "2"] {
+            save_token(deps.as_mut().storage, id, BUYER);
+        }
+        let set_blacklisted = |deps: DepsMut, sender: &str, status: bool| {
+            let msg = if status {
+                ExecuteMsg::AddToBlacklist { address: BUYER.to_string() }
+            } else {
+                ExecuteMsg::RemoveFromBlacklist { address: BUYER.to_string() }
+            };
+            execute(deps, mock_env(), mock_info(sender, &[]), msg)
+        };
+
+        let err = set_blacklisted(deps.as_mut(), BUYER, true).unwrap_err();
+        assert_eq!(err, ContractError::unauthorized("owner"));
+        set_blacklisted(deps.as_mut(), OWNER, true).unwrap();
+        assert!(query_is_blacklisted(deps.as_ref(), BUYER.to_string()).unwrap().blacklisted);
+        assert_eq!(
+            query_blacklist(deps.as_ref(), None, None).unwrap().addresses,
+            vec![BUYER.to_string()]
+        );
+
+        // the holder can neither send nor receive tokens
+        let blacklisted = ContractError::Blacklisted { address: BUYER.to_string() };
+        let send_to_owner = |deps: DepsMut| {
+            transfer(
+                deps,
+                &mock_env().block,
+                Addr::unchecked(BUYER),
+                Addr::unchecked(OWNER),
+                "1".to_string()
+            )
+        };
+        assert_eq!(send_to_owner(deps.as_mut()).unwrap_err(), blacklisted);
+        save_token(deps.as_mut().storage, "3", OWNER);
+        let err = transfer(
+            deps.as_mut(),
+            &mock_env().block,
+            Addr::unchecked(OWNER),
+            Addr::unchecked(BUYER),
+            "3".to_string()
+        ).unwrap_err();
+        assert_eq!(err, blacklisted);
+
+        // but burning is still possible
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info(BUYER, &[]),
+            ExecuteMsg::Burn { class_id: "ptx".to_string(), id: "2".to_string() }
+        ).unwrap();
+        assert!(tokens().may_load(deps.as_ref().storage, "2").unwrap().is_none());
+
+        // lifting the bar restores transfers
+        set_blacklisted(deps.as_mut(), OWNER, false).unwrap();
+        assert!(!query_is_blacklisted(deps.as_ref(), BUYER.to_string()).unwrap().blacklisted);
+        send_to_owner(deps.as_mut()).unwrap();
+        assert_eq!(load_token(deps.as_ref().storage, "1").unwrap().owner, Addr::unchecked(OWNER));
+    }
+
+    #[test]
+    fn holders_can_veto_a_base_uri_change_until_it_is_due() {
+        let (mut app, contract) = setup_app_with(|msg| {
+            msg.runtime_config.uri_veto_threshold = 2;
+        });
+        whitelist_buyer(&mut app, &contract);
+        let gift = ExecuteMsg::Purchase {
+            count: 1,
+            proof: None,
+            max_price_per_token: None,
+            recipient: Some("friend".to_string()),
+            referrer: None,
+            allowlist_index: None,
+        };
+        app.execute_contract(Addr::unchecked(BUYER), contract.clone(), &gift, &coins(100, DENOM))
+            .unwrap();
+        app.execute_contract(
+            Addr::unchecked(BUYER),
+            contract.clone(),
+            &purchase_msg(2),
+            &coins(200, DENOM)
+        ).unwrap();
+
+        let now = app.block_info().time.seconds();
+        let propose = |effective_after| ExecuteMsg::ProposeBaseUriChange {
+            new_uri: "ipfs://v2/".to_string(),
+            effective_after,
+        };
+        let pending = |app: &App| -> Option<UriChange> {
+            let query = QueryMsg::PendingUriChange {};
+            let res: PendingUriChangeResponse = app
+                .wrap()
+                .query_wasm_smart(&contract, &query)
+                .unwrap();
+            res.change
+        };
+        let execute = |app: &mut App, sender: &str, msg: &ExecuteMsg| {
+            app.execute_contract(Addr::unchecked(sender), contract.clone(), msg, &[]).map_err(|err|
+                err.downcast::<ContractError>().unwrap()
+            )
+        };
+        assert_eq!(
+            execute(&mut app, OWNER, &propose(now)).unwrap_err(),
+            ContractError::InvalidUriChangeTime { effective_after: now }
+        );
+        execute(&mut app, OWNER, &propose(now + 600)).unwrap();
+        assert_eq!(
+            pending(&app),
+            Some(UriChange {
+                new_uri: "ipfs://v2/".to_string(),
+                effective_after: now + 600,
+                proposed_by: Addr::unchecked(OWNER),
+            })
+        );
+        assert_eq!(
+            execute(&mut app, OWNER, &ExecuteMsg::ApplyBaseUriChange {}).unwrap_err(),
+            ContractError::UriChangeNotDue { effective_after: now + 600, now }
+        );
+
+        // a single token is below the veto threshold, two are enough
+        assert_eq!(
+            execute(&mut app, "friend", &ExecuteMsg::VetoUriChange {}).unwrap_err(),
+            ContractError::unauthorized("token holder")
+        );
+        execute(&mut app, BUYER, &ExecuteMsg::VetoUriChange {}).unwrap();
+        assert_eq!(pending(&app), None);
+        assert_eq!(
+            execute(&mut app, OWNER, &ExecuteMsg::ApplyBaseUriChange {}).unwrap_err(),
+            ContractError::NoPendingUriChange {}
+        );
+
+        // once due, the change can't be vetoed anymore and applies
+        execute(&mut app, OWNER, &propose(now + 600)).unwrap();
+        app.update_block(|block| {
+            block.time = block.time.plus_seconds(601);
+        });
+        assert_eq!(
+            execute(&mut app, BUYER, &ExecuteMsg::VetoUriChange {}).unwrap_err(),
+            ContractError::VetoWindowClosed { effective_after: now + 600 }
+        );
+        execute(&mut app, OWNER, &ExecuteMsg::ApplyBaseUriChange {}).unwrap();
+        assert_eq!(load_state(&app, &contract).base_token_uri, "ipfs://v2/");
+        assert_eq!(pending(&app), None);
+
+        // a frozen collection can't propose changes
+        execute(&mut app, OWNER, &ExecuteMsg::FreezeMetadata {}).unwrap();
+        assert_eq!(
+            execute(&mut app, OWNER, &propose(now + 1_200)).unwrap_err(),
+            ContractError::MetadataFrozen {}
+        );
+    }
+
+    #[test]
+    fn blocked_recipients_cannot_receive_tokens() {
+        let (mut app, contract) = setup_app();
+        whitelist_buyer(&mut app, &contract);
+        app.execute_contract(
+            Addr::unchecked(BUYER),
+            contract.clone(),
+            &purchase_msg(2),
+            &coins(200, DENOM)
+        ).unwrap();
+        let market = "market";
+        app.execute_contract(
+            Addr::unchecked(OWNER),
+            contract.clone(),
+            &(ExecuteMsg::AddToBlocklist { address: market.to_string() }),
+            &[]
+        ).unwrap();
+        let is_blocked = |app: &App, address: &str| -> bool {
+            let query = QueryMsg::IsBlocked { address: address.to_string() };
+            let res: IsBlockedResponse = app.wrap().query_wasm_smart(&contract, &query).unwrap();
+            res.blocked
+        };
+        assert!(is_blocked(&app, market));
+        assert!(!is_blocked(&app, "friend"));
+
+        let transfer = |recipient: &str, token_id: &str| ExecuteMsg::TransferNft {
+            recipient: recipient.to_string(),
+            token_id: token_id.to_string(),
+            sale_price: None,
+        };
+        let blocked = ContractError::RecipientBlocked { address: market.to_string() };
+        let err = app
+            .execute_contract(Addr::unchecked(BUYER), contract.clone(), &transfer(market, "0"), &[])
+            .unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), blocked);
+        let msg = transfer("friend", "0");
+        app.execute_contract(Addr::unchecked(BUYER), contract.clone(), &msg, &[]).unwrap();
+        assert_eq!(load_owner(&app, &contract, "0"), Some(Addr::unchecked("friend")));
+
+        // purchases can't mint to the blocked address either
+        let err = app
+            .execute_contract(
+                Addr::unchecked(BUYER),
+                contract.clone(),
+                &(ExecuteMsg::Purchase {
+                    count: 1,
+                    proof: None,
+                    max_price_per_token: None,
+                    recipient: Some(market.to_string()),
+                    referrer: None,
+                    allowlist_index: None,
+                }),
+                &coins(100, DENOM)
+            )
+            .unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), blocked);
+
+        app.execute_contract(
+            Addr::unchecked(OWNER),
+            contract.clone(),
+            &(ExecuteMsg::RemoveFromBlocklist { address: market.to_string() }),
+            &[]
+        ).unwrap();
+        assert!(!is_blocked(&app, market));
+        app.execute_contract(Addr::unchecked(BUYER), contract.clone(), &transfer(market, "1"), &[])
+            .unwrap();
+        assert_eq!(load_owner(&app, &contract, "1"), Some(Addr::unchecked(market)));
+    }
+
+    #[test]
+    fn blacklisted_buyer_cannot_purchase() {
+        let (mut app, contract) = setup_app();
+        whitelist_buyer(&mut app, &contract);
+        app.execute_contract(
+            Addr::unchecked(OWNER),
+            contract.clone(),
+            &(ExecuteMsg::AddToBlacklist { address: BUYER.to_string() }),
+            &[]
+        ).unwrap();
+
+        assert_purchase_blocked(&mut app, &contract, 1, PurchaseBlocker::Blacklisted {
+            address: BUYER.to_string(),
+        });
+    }
+
+    #[test]
+    fn presale_mints_respect_the_holding_cap() {
+        let (mut app, contract) = setup_app_with(|msg| {
+            let start = msg.runtime_config.sale_start_time;
+            msg.runtime_config.presale_start_time = start;
+            msg.runtime_config.presale_end_time = start + 50;
+            msg.runtime_config.sale_start_time = start + 100;
+            msg.deployment_config.max_per_wallet_holding = Some(2);
+        });
+        whitelist_buyer(&mut app, &contract);
+        let now = app.block_info().time.seconds();
+        assert_eq!(load_state(&app, &contract).phase(now), SalePhase::Presale);
+
+        assert_purchase_blocked(&mut app, &contract, 3, PurchaseBlocker::HoldingCapExceeded {
+            cap: 2,
+            current: 0,
+        });
+        app.execute_contract(
+            Addr::unchecked(BUYER),
+            contract.clone(),
+            &purchase_msg(2),
+            &coins(200, DENOM)
+        ).unwrap();
+        assert_purchase_blocked(&mut app, &contract, 1, PurchaseBlocker::HoldingCapExceeded {
+            cap: 2,
+            current: 2,
+        });
+    }
+
+    #[test]
+    fn holding_cap_limits_transfers_unless_exempt() {
+        let (mut app, contract) = setup_app_with(|msg| {
+            msg.deployment_config.max_per_wallet_holding = Some(2);
+        });
+        let set_exemption = |app: &mut App, sender: &str, address: &str, exempt: bool| {
+            app.execute_contract(
+                Addr::unchecked(sender),
+                contract.clone(),
+                &(ExecuteMsg::SetHoldingCapExemption { address: address.to_string(), exempt }),
+                &[]
+            )
+        };
+        let transfer_to_buyer = |app: &mut App, token_id: &str| {
+            app.execute_contract(
+                Addr::unchecked(OWNER),
+                contract.clone(),
+                &(ExecuteMsg::TransferNft {
+                    recipient: BUYER.to_string(),
+                    token_id: token_id.to_string(),
+                    sale_price: None,
+                }),
+                &[]
+            )
+        };
+
+        // the owner mints beyond the cap only once exempted
+        mint_to_owner(&mut app, &contract, "a");
+        mint_to_owner(&mut app, &contract, "b");
+        let mint_c = ExecuteMsg::Mint {
+            class_id: "ptx".to_string(),
+            id: "c".to_string(),
+            uri: None,
+            uri_hash: None,
+            data: None,
+        };
+        let err = app
+            .execute_contract(Addr::unchecked(OWNER), contract.clone(), &mint_c, &[])
+            .unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::HoldingCapExceeded {
+            cap: 2,
+            current: 2,
+        });
+        let err = set_exemption(&mut app, BUYER, BUYER, true).unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::unauthorized("owner"));
+        set_exemption(&mut app, OWNER, OWNER, true).unwrap();
+        app.execute_contract(Addr::unchecked(OWNER), contract.clone(), &mint_c, &[]).unwrap();
+
+        transfer_to_buyer(&mut app, "a").unwrap();
+        transfer_to_buyer(&mut app, "b").unwrap();
+        let err = transfer_to_buyer(&mut app, "c").unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::HoldingCapExceeded {
+            cap: 2,
+            current: 2,
+        });
+
+        set_exemption(&mut app, OWNER, BUYER, true).unwrap();
+        transfer_to_buyer(&mut app, "c").unwrap();
+        assert_eq!(load_owner(&app, &contract, "c"), Some(Addr::unchecked(BUYER)));
+
+        // marketplace purchases are capped like transfers once the exemption ends
+        set_exemption(&mut app, OWNER, BUYER, false).unwrap();
+        mint_to_owner(&mut app, &contract, "d");
+        app.execute_contract(Addr::unchecked(OWNER), contract.clone(), &list_msg("d", 100), &[])
+            .unwrap();
+        let err = app
+            .execute_contract(
+                Addr::unchecked(BUYER),
+                contract.clone(),
+                &buy_msg("d"),
+                &coins(100, DENOM)
+            )
+            .unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::HoldingCapExceeded {
+            cap: 2,
+            current: 3,
+        });
+    }
+
+    #[test]
+    fn holder_counts_follow_every_mutation() {
+        let mut deps = mock_dependencies();
+        let state = State {
+            owner: Addr::unchecked(OWNER),
+            ..blank_state()
+        };
+        STATE.save(deps.as_mut().storage, &state).unwrap();
+        save_minter(deps.as_mut().storage);
+
+        for id in ["1", "2", "3", "4", "5"] {
+            execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info(OWNER, &[]),
+                ExecuteMsg::Mint {
+                    class_id: "ptx".to_string(),
+                    id: id.to_string(),
+                    uri: None,
+                    uri_hash: None,
+                    data: None,
+                }
+            ).unwrap();
+        }
+        for id in ["1", "2"] {
+            transfer(
+                deps.as_mut(),
+                &mock_env().block,
+                Addr::unchecked(OWNER),
+                Addr::unchecked(BUYER),
+                id.to_string()
+            ).unwrap();
+        }
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info(BUYER, &[]),
+            ExecuteMsg::Burn { class_id: "ptx".to_string(), id: "1".to_string() }
+        ).unwrap();
+
+        for (holder, count) in [(OWNER, 3), (BUYER, 1)] {
+            assert_eq!(query_balance_of(deps.as_ref(), holder.to_string()).unwrap().count, count);
+            let held = query_tokens(deps.as_ref(), holder.to_string(), None, None).unwrap();
+            assert_eq!(held.tokens.len() as u64, count);
+        }
+
+        let page = query_holders(deps.as_ref(), None, None).unwrap();
+        assert_eq!(
+            page.holders,
+            vec![
+                HolderBalance { address: BUYER.to_string(), count: 1 },
+                HolderBalance { address: OWNER.to_string(), count: 3 }
+            ]
+        );
+        let page = query_holders(deps.as_ref(), Some(BUYER.to_string()), Some(1)).unwrap();
+        assert_eq!(page.holders, vec![HolderBalance { address: OWNER.to_string(), count: 3 }]);
+
+        // holders are dropped once they hold nothing
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info(BUYER, &[]),
+            ExecuteMsg::Burn { class_id: "ptx".to_string(), id: "2".to_string() }
+        ).unwrap();
+        assert_eq!(query_balance_of(deps.as_ref(), BUYER.to_string()).unwrap().count, 0);
+        let page = query_holders(deps.as_ref(), None, None).unwrap();
+        assert_eq!(page.holders, vec![HolderBalance { address: OWNER.to_string(), count: 3 }]);
+    }
+
+    #[test]
+    fn transfer_moves_token_between_owner_indexes() {
+        let mut deps = mock_dependencies();
+        for id in ["1", "2"] {
+            save_token(deps.as_mut().storage, id, OWNER);
+        }
+
+        transfer(
+            deps.as_mut(),
+            &mock_env().block,
+            Addr::unchecked(OWNER),
+            Addr::unchecked(BUYER),
+            "1".to_string()
+        ).unwrap();
+        let held = query_tokens(deps.as_ref(), OWNER.to_string(), None, None).unwrap();
+        assert_eq!(held.tokens, vec!["2".to_string()]);
+        let held = query_tokens(deps.as_ref(), BUYER.to_string(), None, None).unwrap();
+        assert_eq!(held.tokens, vec!["1".to_string()]);
+
+        // frozen tokens cannot be moved
+        freeze_token(deps.as_mut().storage, "2").unwrap();
+        let err = transfer(
+            deps.as_mut(),
+            &mock_env().block,
+            Addr::unchecked(OWNER),
+            Addr::unchecked(BUYER),
+            "2".to_string()
+        ).unwrap_err();
+        assert_eq!(err, ContractError::TokenFrozen { token_id: "2".to_string() });
+    }
+
+    #[test]
+    fn batch_token_metadata_skips_unknown_ids() {
+        let (mut app, contract) = setup_app();
+        mint_to_owner(&mut app, &contract, "a");
+        app.execute_contract(
+            Addr::unchecked(OWNER),
+            contract.clone(),
+            &(ExecuteMsg::Mint {
+                class_id: "ptx".to_string(),
+                id: "b".to_string(),
+                uri: Some("ipfs://b.json".to_string()),
+                uri_hash: None,
+                data: None,
+            }),
+            &[]
+        ).unwrap();
+
+        let res: BatchTokenMetadataResponse = app
+            .wrap()
+            .query_wasm_smart(&contract, &(QueryMsg::BatchTokenMetadata {
+                token_ids: vec!["b".to_string(), "missing".to_string(), "a".to_string()],
+            }))
+            .unwrap();
+        let metadata = |token_uri: &str| TokenMetadata {
+            owner: OWNER.to_string(),
+            token_uri: token_uri.to_string(),
+            uri_hash: None,
+            frozen: false,
+        };
+        assert_eq!(
+            res.tokens,
+            vec![
+                ("b".to_string(), Some(metadata("ipfs://b.json"))),
+                ("missing".to_string(), None),
+                ("a".to_string(), Some(metadata("ipfs://prereveal")))
+            ]
+        );
+
+        let deps = mock_dependencies();
+        let token_ids = (0..=50).map(|id| id.to_string()).collect();
+        let err = query_batch_token_metadata(deps.as_ref(), mock_env(), token_ids).unwrap_err();
+        assert_eq!(err, ContractError::BatchTooLarge { size: 51, max: 50 });
+    }
+
+    #[test]
+    fn uri_hashes_round_trip_through_mint_and_queries() {
+        let (mut app, contract) = setup_app();
+        let token_hash = hex_digest(b"token metadata");
+        let class_hash = Binary::from(Sha256::digest(b"class metadata").as_slice()).to_base64();
+        let mint = |uri_hash: &str| ExecuteMsg::Mint {
+            class_id: "ptx".to_string(),
+            id: "a".to_string(),
+            uri: Some("ipfs://a.json".to_string()),
+            uri_hash: Some(uri_hash.to_string()),
+            data: None,
+        };
+        let issue_class = |uri_hash: &str| ExecuteMsg::IssueClass {
+            name: "Proptix".to_string(),
+            symbol: "PTX".to_string(),
+            description: None,
+            uri: Some("ipfs://class.json".to_string()),
+            uri_hash: Some(uri_hash.to_string()),
+            data: None,
+            features: None,
+            royalty_rate: None,
+        };
+
+        // too short, not a digest, and hex of the wrong length
+        for malformed in ["abc".to_string(), "z".repeat(64), format!("{}00", token_hash)] {
+            for msg in [mint(&malformed), issue_class(&malformed)] {
+                let err = app
+                    .execute_contract(Addr::unchecked(OWNER), contract.clone(), &msg, &[])
+                    .unwrap_err();
+                assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::InvalidUriHash {
+                    uri_hash: malformed.clone(),
+                });
+            }
+        }
+
+        for msg in [mint(&token_hash), issue_class(&class_hash)] {
+            app.execute_contract(Addr::unchecked(OWNER), contract.clone(), &msg, &[]).unwrap();
+        }
+        let res: BatchTokenMetadataResponse = app
+            .wrap()
+            .query_wasm_smart(&contract, &(QueryMsg::BatchTokenMetadata {
+                token_ids: vec!["a".to_string()],
+            }))
+            .unwrap();
+        let metadata = res.tokens[0].1.clone().unwrap();
+        assert_eq!(metadata.token_uri, "ipfs://a.json");
+        assert_eq!(metadata.uri_hash, Some(token_hash));
+
+        let class: ClassInfo = app
+            .wrap()
+            .query_wasm_smart(&contract, &(QueryMsg::ClassInfo {
+                class_id: format!("ptx-{}", contract),
+            }))
+            .unwrap();
+        assert_eq!(class.uri, Some("ipfs://class.json".to_string()));
+        assert_eq!(class.uri_hash, Some(class_hash));
+    }
+
+    fn hex_digest(data: &[u8]) -> String {
+        Sha256::digest(data)
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    fn issue_collection_class(app: &mut App, contract: &Addr, royalty_rate: Option<&str>) {
+        app.execute_contract(
+            Addr::unchecked(OWNER),
+            contract.clone(),
+            &(ExecuteMsg::IssueClass {
+                name: "Proptix".to_string(),
+                symbol: "PTX".to_string(),
+                description: None,
+                uri: None,
+                uri_hash: None,
+                data: None,
+                features: None,
+                royalty_rate: royalty_rate.map(str::to_string),
+            }),
+            &[]
+        ).unwrap();
+    }
+
+    fn list_msg(id: &str, amount: u128) -> ExecuteMsg {
+        ExecuteMsg::List { token_id: id.to_string(), price: Coin::new(amount, DENOM) }
+    }
+
+    fn buy_msg(id: &str) -> ExecuteMsg {
+        ExecuteMsg::Buy { token_id: id.to_string() }
+    }
+
+    #[test]
+    fn buy_splits_price_between_seller_royalty_and_protocol() {
+        let (mut app, contract) = setup_app_with(|msg| {
+            msg.runtime_config.protocol_fee = 5;
+            msg.deployment_config.protocol_address = Some(PROTOCOL.to_string());
+        });
+        issue_collection_class(&mut app, &contract, Some("0.1"));
+        mint_to_owner(&mut app, &contract, "a");
+        app.execute_contract(Addr::unchecked(OWNER), contract.clone(), &list_msg("a", 1_000), &[])
+            .unwrap();
+
+        let listings: ListingsResponse = app
+            .wrap()
+            .query_wasm_smart(&contract, &(QueryMsg::Listings { start_after: None, limit: None }))
+            .unwrap();
+        assert_eq!(
+            listings.listings,
+            vec![ListingInfo {
+                token_id: "a".to_string(),
+                seller: OWNER.to_string(),
+                price: Coin::new(1_000, DENOM),
+            }]
+        );
+
+        app.execute_contract(
+            Addr::unchecked(BUYER),
+            contract.clone(),
+            &buy_msg("a"),
+            &coins(1_000, DENOM)
+        ).unwrap();
+
+        // 10% royalty to the treasury, 5% to the protocol, the rest to the seller
+        let balance = |app: &App, addr: &str| app.wrap().query_balance(addr, DENOM).unwrap().amount;
+        assert_eq!(balance(&app, TREASURY), Uint128::new(100));
+        assert_eq!(balance(&app, PROTOCOL), Uint128::new(50));
+        assert_eq!(balance(&app, OWNER), Uint128::new(850));
+        assert_eq!(balance(&app, BUYER), Uint128::zero());
+        assert_eq!(load_owner(&app, &contract, "a"), Some(Addr::unchecked(BUYER)));
+
+        // the listing went with the token
+        let err = app
+            .execute_contract(Addr::unchecked(OWNER), contract.clone(), &buy_msg("a"), &[])
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::TokenNotListed { token_id: "a".to_string() }
+        );
+    }
+
+    #[test]
+    fn enforced_royalties_are_paid_on_transfers() {
+        let transfer_msg = |recipient: &str, sale_price: Option<u128>| ExecuteMsg::TransferNft {
+            recipient: recipient.to_string(),
+            token_id: "a".to_string(),
+            sale_price: sale_price.map(|amount| Coin::new(amount, DENOM)),
+        };
+        let balance = |app: &App, addr: &str| app.wrap().query_balance(addr, DENOM).unwrap().amount;
+
+        // without enforcement a transfer is free
+        let (mut app, contract) = setup_app();
+        issue_collection_class(&mut app, &contract, Some("0.1"));
+        mint_to_owner(&mut app, &contract, "a");
+        let msg = transfer_msg(BUYER, None);
+        app.execute_contract(Addr::unchecked(OWNER), contract.clone(), &msg, &[]).unwrap();
+        let msg = transfer_msg("friend", None);
+        app.execute_contract(Addr::unchecked(BUYER), contract.clone(), &msg, &[]).unwrap();
+        assert_eq!(load_owner(&app, &contract, "a"), Some(Addr::unchecked("friend")));
+        assert_eq!(balance(&app, TREASURY), Uint128::zero());
+
+        let (mut app, contract) = setup_app_with(|msg| {
+            msg.deployment_config.royalty_enforced = true;
+        });
+        issue_collection_class(&mut app, &contract, Some("0.1"));
+        mint_to_owner(&mut app, &contract, "a");
+        // the owner hands tokens out without a sale
+        let msg = transfer_msg(BUYER, None);
+        app.execute_contract(Addr::unchecked(OWNER), contract.clone(), &msg, &[]).unwrap();
+
+        let transfer = |app: &mut App, sale_price: Option<u128>, paid: u128| {
+            let funds = if paid == 0 { vec![] } else { coins(paid, DENOM) };
+            app.execute_contract(
+                Addr::unchecked(BUYER),
+                contract.clone(),
+                &transfer_msg("friend", sale_price),
+                &funds
+            )
+        };
+        let err = transfer(&mut app, None, 0).unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::RoyaltyRequired {});
+        let err = transfer(&mut app, Some(1_000), 50).unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::InsufficientFunds {
+                required: Uint128::new(100),
+                provided: Uint128::new(50),
+            }
+        );
+
+        // 10% of the sale price goes to the treasury, the excess back to the sender
+        transfer(&mut app, Some(1_000), 120).unwrap();
+        assert_eq!(load_owner(&app, &contract, "a"), Some(Addr::unchecked("friend")));
+        assert_eq!(balance(&app, TREASURY), Uint128::new(100));
+        assert_eq!(balance(&app, BUYER), Uint128::new(900));
+    }
+
+    fn offer_msg(id: &str, expires: Option<Expiration>) -> ExecuteMsg {
+        ExecuteMsg::MakeOffer { token_id: id.to_string(), expires }
+    }
+
+    fn query_offers(app: &App, contract: &Addr, msg: QueryMsg) -> Vec<OfferInfo> {
+        let res: OffersResponse = app.wrap().query_wasm_smart(contract, &msg).unwrap();
+        res.offers
+    }
+
+    #[test]
+    fn accepted_offer_pays_the_owner_and_refunds_the_other_bids() {
+        const BIDDER: &str = "bidder";
+        let (mut app, contract) = setup_app_with(|msg| {
+            msg.runtime_config.protocol_fee = 5;
+            msg.deployment_config.protocol_address = Some(PROTOCOL.to_string());
+        });
+        issue_collection_class(&mut app, &contract, Some("0.1"));
+        mint_to_owner(&mut app, &contract, "a");
+        app.send_tokens(Addr::unchecked(BUYER), Addr::unchecked(BIDDER), &coins(350, DENOM))
+            .unwrap();
+        let balance = |app: &App, addr: &str| app.wrap().query_balance(addr, DENOM).unwrap().amount;
+
+        let err = app
+            .execute_contract(
+                Addr::unchecked(OWNER),
+                contract.clone(),
+                &offer_msg("a", None),
+                &coins(1, DENOM)
+            )
+            .unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::OwnerOffer {});
+
+        // a second offer of the same bidder replaces the first and refunds it
+        let bid = |app: &mut App, bidder: &str, amount: u128| {
+            app.execute_contract(
+                Addr::unchecked(bidder),
+                contract.clone(),
+                &offer_msg("a", None),
+                &coins(amount, DENOM)
+            ).unwrap();
+        };
+        bid(&mut app, BIDDER, 300);
+        bid(&mut app, BIDDER, 350);
+        bid(&mut app, BUYER, 400);
+        assert_eq!(balance(&app, BIDDER), Uint128::zero());
+        assert_eq!(balance(&app, contract.as_str()), Uint128::new(750));
+
+        let by_token = QueryMsg::OffersByToken {
+            token_id: "a".to_string(),
+            start_after: None,
+            limit: None,
+        };
+        let offer = |bidder: &str, amount: u128| OfferInfo {
+            token_id: "a".to_string(),
+            bidder: bidder.to_string(),
+            price: Coin::new(amount, DENOM),
+            expires: Expiration::Never {},
+        };
+        assert_eq!(query_offers(&app, &contract, by_token), vec![
+            offer(BIDDER, 350),
+            offer(BUYER, 400)
+        ]);
+        let second_page = QueryMsg::OffersByToken {
+            token_id: "a".to_string(),
+            start_after: Some(BIDDER.to_string()),
+            limit: Some(1),
+        };
+        assert_eq!(query_offers(&app, &contract, second_page), vec![offer(BUYER, 400)]);
+        let by_bidder = QueryMsg::OffersByBidder {
+            bidder: BUYER.to_string(),
+            start_after: None,
+            limit: None,
+        };
+        assert_eq!(query_offers(&app, &contract, by_bidder.clone()), vec![offer(BUYER, 400)]);
+
+        let accept = ExecuteMsg::AcceptOffer {
+            token_id: "a".to_string(),
+            bidder: BUYER.to_string(),
+        };
+        let err = app
+            .execute_contract(Addr::unchecked(BIDDER), contract.clone(), &accept, &[])
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::unauthorized("token owner")
+        );
+        app.execute_contract(Addr::unchecked(OWNER), contract.clone(), &accept, &[]).unwrap();
+
+        // 10% royalty to the treasury, 5% to the protocol, the rest to the owner, and the
+        // escrow is emptied by the payouts and the refund
+        assert_eq!(load_owner(&app, &contract, "a"), Some(Addr::unchecked(BUYER)));
+        assert_eq!(balance(&app, TREASURY), Uint128::new(40));
+        assert_eq!(balance(&app, PROTOCOL), Uint128::new(20));
+        assert_eq!(balance(&app, OWNER), Uint128::new(340));
+        assert_eq!(balance(&app, BIDDER), Uint128::new(350));
+        assert_eq!(balance(&app, BUYER), Uint128::new(250));
+        assert_eq!(balance(&app, contract.as_str()), Uint128::zero());
+        assert!(query_offers(&app, &contract, by_bidder).is_empty());
+        let by_token = QueryMsg::OffersByToken {
+            token_id: "a".to_string(),
+            start_after: None,
+            limit: None,
+        };
+        assert!(query_offers(&app, &contract, by_token).is_empty());
+    }
+
+    #[test]
+    fn expired_and_orphaned_offers_can_only_be_withdrawn() {
+        let (mut app, contract) = setup_app();
+        mint_to_owner(&mut app, &contract, "a");
+        mint_to_owner(&mut app, &contract, "b");
+        let height = app.block_info().height;
+        let balance = |app: &App, addr: &str| app.wrap().query_balance(addr, DENOM).unwrap().amount;
+
+        let err = app
+            .execute_contract(
+                Addr::unchecked(BUYER),
+                contract.clone(),
+                &offer_msg("a", Some(Expiration::AtHeight(height))),
+                &coins(100, DENOM)
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::OfferExpired { expires: Expiration::AtHeight(height) }
+        );
+        app.execute_contract(
+            Addr::unchecked(BUYER),
+            contract.clone(),
+            &offer_msg("a", Some(Expiration::AtHeight(height + 1))),
+            &coins(100, DENOM)
+        ).unwrap();
+        app.execute_contract(
+            Addr::unchecked(BUYER),
+            contract.clone(),
+            &offer_msg("b", None),
+            &coins(200, DENOM)
+        ).unwrap();
+        assert_eq!(balance(&app, contract.as_str()), Uint128::new(300));
+
+        // the offer on a lapses with the block, the one on b with the token
+        app.update_block(|block| {
+            block.height += 1;
+        });
+        app.execute_contract(
+            Addr::unchecked(OWNER),
+            contract.clone(),
+            &(ExecuteMsg::Burn { class_id: "ptx".to_string(), id: "b".to_string() }),
+            &[]
+        ).unwrap();
+        let accept = |token_id: &str| ExecuteMsg::AcceptOffer {
+            token_id: token_id.to_string(),
+            bidder: BUYER.to_string(),
+        };
+        let err = app
+            .execute_contract(Addr::unchecked(OWNER), contract.clone(), &accept("a"), &[])
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::OfferExpired { expires: Expiration::AtHeight(height + 1) }
+        );
+        let err = app
+            .execute_contract(Addr::unchecked(OWNER), contract.clone(), &accept("b"), &[])
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::TokenNotFound { token_id: "b".to_string() }
+        );
+
+        for token_id in ["a", "b"] {
+            let withdraw = ExecuteMsg::WithdrawOffer { token_id: token_id.to_string() };
+            app.execute_contract(Addr::unchecked(BUYER), contract.clone(), &withdraw, &[]).unwrap();
+        }
+        assert_eq!(balance(&app, BUYER), Uint128::new(1_000));
+        assert_eq!(balance(&app, contract.as_str()), Uint128::zero());
+
+        let withdraw = ExecuteMsg::WithdrawOffer { token_id: "a".to_string() };
+        let err = app
+            .execute_contract(Addr::unchecked(BUYER), contract.clone(), &withdraw, &[])
+            .unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::NoOffer {
+            token_id: "a".to_string(),
+            bidder: BUYER.to_string(),
+        });
+    }
+
+    /// Auctions `id` for an hour with bids going up by at least 50
+    fn start_auction(
+        app: &mut App,
+        contract: &Addr,
+        sender: &str,
+        id: &str,
+        reserve: u128
+    ) -> Result<AppResponse, ContractError> {
+        let msg = ExecuteMsg::StartAuction {
+            token_id: id.to_string(),
+            reserve_price: Coin::new(reserve, DENOM),
+            duration: 3_600,
+            min_bid_increment: Uint128::new(50),
+        };
+        app.execute_contract(Addr::unchecked(sender), contract.clone(), &msg, &[])
+            .map_err(|err| err.downcast().unwrap())
+    }
+
+    fn bid_on(
+        app: &mut App,
+        contract: &Addr,
+        bidder: &str,
+        id: &str,
+        amount: u128
+    ) -> Result<AppResponse, ContractError> {
+        app.execute_contract(
+            Addr::unchecked(bidder),
+            contract.clone(),
+            &(ExecuteMsg::Bid { token_id: id.to_string() }),
+            &coins(amount, DENOM)
+        ).map_err(|err| err.downcast().unwrap())
+    }
+
+    fn settle_msg(id: &str) -> ExecuteMsg {
+        ExecuteMsg::Settle { token_id: id.to_string() }
+    }
+
+    fn query_auctions(app: &App, contract: &Addr) -> Vec<AuctionInfo> {
+        let res: AuctionsResponse = app
+            .wrap()
+            .query_wasm_smart(contract, &(QueryMsg::Auctions { start_after: None, limit: None }))
+            .unwrap();
+        res.auctions
+    }
+
+    #[test]
+    fn outbid_bidders_are_refunded_and_the_winner_pays_the_split() {
+        const BIDDER: &str = "bidder";
+        let (mut app, contract) = setup_app_with(|msg| {
+            msg.runtime_config.protocol_fee = 5;
+            msg.deployment_config.protocol_address = Some(PROTOCOL.to_string());
+        });
+        issue_collection_class(&mut app, &contract, Some("0.1"));
+        mint_to_owner(&mut app, &contract, "a");
+        app.send_tokens(Addr::unchecked(BUYER), Addr::unchecked(BIDDER), &coins(400, DENOM))
+            .unwrap();
+        let balance = |app: &App, addr: &str| app.wrap().query_balance(addr, DENOM).unwrap().amount;
+
+        let err = start_auction(&mut app, &contract, BUYER, "a", 200).unwrap_err();
+        assert_eq!(err, ContractError::unauthorized("token owner"));
+        start_auction(&mut app, &contract, OWNER, "a", 200).unwrap();
+        let end_time = app.block_info().time.seconds() + 3_600;
+
+        // the token is held in place until the auction is settled
+        let err = app
+            .execute_contract(
+                Addr::unchecked(OWNER),
+                contract.clone(),
+                &(ExecuteMsg::TransferNft {
+                    recipient: BUYER.to_string(),
+                    token_id: "a".to_string(),
+                    sale_price: None,
+                }),
+                &[]
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::TokenAuctioned { token_id: "a".to_string() }
+        );
+
+        // bids below the reserve are taken, every bid has to beat the last by the increment
+        bid_on(&mut app, &contract, BIDDER, "a", 100).unwrap();
+        let err = bid_on(&mut app, &contract, BUYER, "a", 120).unwrap_err();
+        assert_eq!(err, ContractError::BidTooLow {
+            bid: Uint128::new(120),
+            min_bid: Uint128::new(150),
+        });
+        bid_on(&mut app, &contract, BUYER, "a", 150).unwrap();
+        assert_eq!(balance(&app, BIDDER), Uint128::new(400));
+        bid_on(&mut app, &contract, BIDDER, "a", 300).unwrap();
+        assert_eq!(balance(&app, BUYER), Uint128::new(600));
+        assert_eq!(balance(&app, contract.as_str()), Uint128::new(300));
+        assert_eq!(query_auctions(&app, &contract), vec![AuctionInfo {
+            token_id: "a".to_string(),
+            seller: OWNER.to_string(),
+            reserve_price: Coin::new(200, DENOM),
+            min_bid_increment: Uint128::new(50),
+            end_time,
+            highest_bidder: Some(BIDDER.to_string()),
+            highest_bid: Some(Uint128::new(300)),
+        }]);
+
+        let err = app
+            .execute_contract(Addr::unchecked(BUYER), contract.clone(), &settle_msg("a"), &[])
+            .unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::AuctionNotEnded {
+            token_id: "a".to_string(),
+            end_time,
+        });
+        app.update_block(|block| {
+            block.time = Timestamp::from_seconds(end_time);
+        });
+        let err = bid_on(&mut app, &contract, BUYER, "a", 400).unwrap_err();
+        assert_eq!(err, ContractError::AuctionEnded {
+            token_id: "a".to_string(),
+            end_time,
+        });
+
+        // anyone can settle, 10% royalty to the treasury, 5% to the protocol, the rest to
+        // the seller
+        app.execute_contract(Addr::unchecked(BUYER), contract.clone(), &settle_msg("a"), &[])
+            .unwrap();
+        assert_eq!(load_owner(&app, &contract, "a"), Some(Addr::unchecked(BIDDER)));
+        assert_eq!(balance(&app, TREASURY), Uint128::new(30));
+        assert_eq!(balance(&app, PROTOCOL), Uint128::new(15));
+        assert_eq!(balance(&app, OWNER), Uint128::new(255));
+        assert_eq!(balance(&app, BIDDER), Uint128::new(100));
+        assert_eq!(balance(&app, contract.as_str()), Uint128::zero());
+        assert!(query_auctions(&app, &contract).is_empty());
+    }
+
+    #[test]
+    fn auction_below_the_reserve_returns_the_token() {
+        let (mut app, contract) = setup_app();
+        mint_to_owner(&mut app, &contract, "a");
+        start_auction(&mut app, &contract, OWNER, "a", 500).unwrap();
+        bid_on(&mut app, &contract, BUYER, "a", 100).unwrap();
+
+        app.update_block(|block| {
+            block.time = block.time.plus_seconds(3_600);
+        });
+        let res = app
+            .execute_contract(Addr::unchecked(OWNER), contract.clone(), &settle_msg("a"), &[])
+            .unwrap();
+        assert_eq!(
+            wasm_attributes(&res),
+            vec![attr("action", "settle"), attr("token_id", "a"), attr("sold", "false")]
+        );
+        assert_eq!(app.wrap().query_balance(BUYER, DENOM).unwrap().amount, Uint128::new(1_000));
+        assert_eq!(load_owner(&app, &contract, "a"), Some(Addr::unchecked(OWNER)));
+
+        // the settled token moves freely again and can't be settled twice
+        let err = app
+            .execute_contract(Addr::unchecked(OWNER), contract.clone(), &settle_msg("a"), &[])
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::NoAuction { token_id: "a".to_string() }
+        );
+        app.execute_contract(
+            Addr::unchecked(OWNER),
+            contract.clone(),
+            &(ExecuteMsg::Burn { class_id: "ptx".to_string(), id: "a".to_string() }),
+            &[]
+        ).unwrap();
+    }
+
+    #[test]
+    fn auction_refunds_a_winner_who_can_no_longer_receive_the_token() {
+        let (mut app, contract) = setup_app();
+        let execute = |app: &mut App, msg: ExecuteMsg| {
+            app.execute_contract(Addr::unchecked(OWNER), contract.clone(), &msg, &[]).unwrap();
+        };
+        for id in ["a", "b"] {
+            mint_to_owner(&mut app, &contract, id);
+            start_auction(&mut app, &contract, OWNER, id, 100).unwrap();
+            bid_on(&mut app, &contract, BUYER, id, 100).unwrap();
+        }
+
+        // a blocklisted wallet can't bid at all
+        execute(&mut app, ExecuteMsg::AddToBlocklist { address: STRANGER.to_string() });
+        app.send_tokens(Addr::unchecked(BUYER), Addr::unchecked(STRANGER), &coins(200, DENOM))
+            .unwrap();
+        let err = bid_on(&mut app, &contract, STRANGER, "a", 200).unwrap_err();
+        assert_eq!(err, ContractError::RecipientBlocked { address: STRANGER.to_string() });
+
+        // the winner is blocklisted after bidding on "a", "b" is frozen before settlement
+        execute(&mut app, ExecuteMsg::AddToBlocklist { address: BUYER.to_string() });
+        execute(&mut app, ExecuteMsg::Freeze { class_id: "ptx".to_string(), id: "b".to_string() });
+        app.update_block(|block| {
+            block.time = block.time.plus_seconds(3_600);
+        });
+        let reasons = [
+            ("a", ContractError::RecipientBlocked { address: BUYER.to_string() }),
+            ("b", ContractError::TokenFrozen { token_id: "b".to_string() }),
+        ];
+        for (id, reason) in reasons {
+            let res = app
+                .execute_contract(Addr::unchecked(OWNER), contract.clone(), &settle_msg(id), &[])
+                .unwrap();
+            assert_eq!(
+                wasm_attributes(&res),
+                vec![
+                    attr("action", "settle"),
+                    attr("token_id", id),
+                    attr("sold", "false"),
+                    attr("reason", reason.to_string())
+                ]
+            );
+            assert_eq!(load_owner(&app, &contract, id), Some(Addr::unchecked(OWNER)));
+        }
+        assert_eq!(app.wrap().query_balance(BUYER, DENOM).unwrap().amount, Uint128::new(800));
+        assert_eq!(app.wrap().query_balance(&contract, DENOM).unwrap().amount, Uint128::zero());
+        assert!(query_auctions(&app, &contract).is_empty());
+    }
+
+    #[test]
+    fn late_bids_extend_the_auction() {
+        let (mut app, contract) = setup_app();
+        mint_to_owner(&mut app, &contract, "a");
+        start_auction(&mut app, &contract, OWNER, "a", 0).unwrap();
+        let end_time = app.block_info().time.seconds() + 3_600;
+
+        // a bid well before the end leaves it alone
+        bid_on(&mut app, &contract, BUYER, "a", 100).unwrap();
+        assert_eq!(query_auctions(&app, &contract)[0].end_time, end_time);
+
+        app.update_block(|block| {
+            block.time = Timestamp::from_seconds(end_time - 100);
+        });
+        bid_on(&mut app, &contract, BUYER, "a", 150).unwrap();
+        let extended = end_time - 100 + AUCTION_EXTENSION_SECONDS;
+        assert_eq!(query_auctions(&app, &contract)[0].end_time, extended);
+
+        // the original end passed, but the auction is still running
+        app.update_block(|block| {
+            block.time = Timestamp::from_seconds(end_time + 1);
+        });
+        let err = app
+            .execute_contract(Addr::unchecked(OWNER), contract.clone(), &settle_msg("a"), &[])
+            .unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::AuctionNotEnded {
+            token_id: "a".to_string(),
+            end_time: extended,
+        });
+        bid_on(&mut app, &contract, BUYER, "a", 200).unwrap();
+        assert_eq!(
+            query_auctions(&app, &contract)[0].end_time,
+            end_time + 1 + AUCTION_EXTENSION_SECONDS
+        );
+    }
+
+    #[test]
+    fn burns_are_rewarded_out_of_the_funded_pool() {
+        let (mut app, contract) = setup_app();
+        app.send_tokens(Addr::unchecked(BUYER), Addr::unchecked(OWNER), &coins(120, DENOM))
+            .unwrap();
+        for id in ["a", "b", "c"] {
+            mint_to_owner(&mut app, &contract, id);
+            app.execute_contract(
+                Addr::unchecked(OWNER),
+                contract.clone(),
+                &(ExecuteMsg::TransferNft {
+                    recipient: BUYER.to_string(),
+                    token_id: id.to_string(),
+                    sale_price: None,
+                }),
+                &[]
+            ).unwrap();
+        }
+        let burn = |app: &mut App, id: &str| {
+            app.execute_contract(
+                Addr::unchecked(BUYER),
+                contract.clone(),
+                &(ExecuteMsg::Burn { class_id: "ptx".to_string(), id: id.to_string() }),
+                &[]
+            ).map_err(|err| err.downcast::<ContractError>().unwrap())
+        };
+        let fund = ExecuteMsg::FundRewardPool {};
+
+        // the pool can only be funded in the reward denom, and only by the owner
+        let err = app
+            .execute_contract(Addr::unchecked(OWNER), contract.clone(), &fund, &coins(120, DENOM))
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::invalid_config("burn_reward", "must be set")
+        );
+        let set_reward = ExecuteMsg::SetBurnReward { reward: Some(Coin::new(50, DENOM)) };
+        app.execute_contract(Addr::unchecked(OWNER), contract.clone(), &set_reward, &[]).unwrap();
+        let err = app
+            .execute_contract(Addr::unchecked(BUYER), contract.clone(), &fund, &coins(120, DENOM))
+            .unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::unauthorized("owner"));
+        app.execute_contract(Addr::unchecked(OWNER), contract.clone(), &fund, &coins(120, DENOM))
+            .unwrap();
+
+        let balance = |app: &App| app.wrap().query_balance(BUYER, DENOM).unwrap().amount;
+        let before = balance(&app);
+        burn(&mut app, "a").unwrap();
+        assert_eq!(balance(&app), before + Uint128::new(50));
+
+        // a batch is rewarded per token and rejected as a whole when the pool falls short
+        let err = app
+            .execute_contract(
+                Addr::unchecked(BUYER),
+                contract.clone(),
+                &(ExecuteMsg::BurnBatch {
+                    class_id: "ptx".to_string(),
+                    ids: vec!["b".to_string(), "c".to_string()],
+                }),
+                &[]
+            )
+            .unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::RewardPoolEmpty {
+            required: Uint128::new(100),
+            available: Uint128::new(70),
+        });
+        assert_eq!(load_owner(&app, &contract, "b"), Some(Addr::unchecked(BUYER)));
+        burn(&mut app, "b").unwrap();
+        assert_eq!(burn(&mut app, "c").unwrap_err(), ContractError::RewardPoolEmpty {
+            required: Uint128::new(50),
+            available: Uint128::new(20),
+        });
+        let res: BurnRewardResponse = app
+            .wrap()
+            .query_wasm_smart(&contract, &(QueryMsg::BurnReward {}))
+            .unwrap();
+        assert_eq!(res, BurnRewardResponse {
+            reward: Some(Coin::new(50, DENOM)),
+            pool: Uint128::new(20),
+        });
+
+        // the funded pool keeps its denom, without a reward burns are free again
+        let err = app
+            .execute_contract(
+                Addr::unchecked(OWNER),
+                contract.clone(),
+                &(ExecuteMsg::SetBurnReward { reward: Some(Coin::new(50, "uother")) }),
+                &[]
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::invalid_config("burn_reward", "must keep the funded pool denom")
+        );
+        app.execute_contract(
+            Addr::unchecked(OWNER),
+            contract.clone(),
+            &(ExecuteMsg::SetBurnReward { reward: None }),
+            &[]
+        ).unwrap();
+        let before = balance(&app);
+        burn(&mut app, "c").unwrap();
+        assert_eq!(balance(&app), before);
+    }
+
+    #[test]
+    fn holder_rewards_stay_with_the_token_across_transfers() {
+        let (mut app, contract) = setup_app();
+        mint_to_owner(&mut app, &contract, "a");
+        mint_to_owner(&mut app, &contract, "b");
+        let fund = |app: &mut App, amount: u128| {
+            app.execute_contract(
+                Addr::unchecked(BUYER),
+                contract.clone(),
+                &(ExecuteMsg::FundRewards {}),
+                &coins(amount, DENOM)
+            ).unwrap();
+        };
+        let pending = |app: &App, id: &str| {
+            let msg = QueryMsg::PendingRewards { token_id: id.to_string() };
+            let res: PendingRewardsResponse = app
+                .wrap()
+                .query_wasm_smart(&contract, &msg)
+                .unwrap();
+            res.amount.u128()
+        };
+        let claim = |app: &mut App, sender: &str, ids: &[&str]| {
+            let token_ids = ids.iter().map(|id| id.to_string()).collect();
+            app.execute_contract(
+                Addr::unchecked(sender),
+                contract.clone(),
+                &(ExecuteMsg::ClaimRewards { token_ids }),
+                &[]
+            ).map_err(|err| err.downcast::<ContractError>().unwrap())
+        };
+        let balance = |app: &App, addr: &str| {
+            app.wrap().query_balance(addr, DENOM).unwrap().amount.u128()
+        };
+
+        // 50.5 per token, the half stays with each token until it adds up
+        fund(&mut app, 101);
+        assert_eq!((pending(&app, "a"), pending(&app, "b")), (50, 50));
+
+        // unclaimed rewards move with the token
+        app.execute_contract(
+            Addr::unchecked(OWNER),
+            contract.clone(),
+            &(ExecuteMsg::TransferNft {
+                recipient: BUYER.to_string(),
+                token_id: "a".to_string(),
+                sale_price: None,
+            }),
+            &[]
+        ).unwrap();
+        assert_eq!(
+            claim(&mut app, OWNER, &["a"]).unwrap_err(),
+            ContractError::unauthorized("token owner")
+        );
+        let before = balance(&app, BUYER);
+        claim(&mut app, BUYER, &["a"]).unwrap();
+        assert_eq!(balance(&app, BUYER), before + 50);
+        assert_eq!(pending(&app, "a"), 0);
+
+        // tokens minted later only earn later fundings
+        mint_to_owner(&mut app, &contract, "c");
+        assert_eq!(pending(&app, "c"), 0);
+        fund(&mut app, 30);
+        assert_eq!((pending(&app, "a"), pending(&app, "b"), pending(&app, "c")), (10, 60, 10));
+        claim(&mut app, OWNER, &["b", "c"]).unwrap();
+        assert_eq!(balance(&app, OWNER), 70);
+
+        // a funding too small to split is carried over to the next one
+        fund(&mut app, 1);
+        assert_eq!((pending(&app, "a"), pending(&app, "b"), pending(&app, "c")), (10, 0, 0));
+        fund(&mut app, 2);
+        assert_eq!((pending(&app, "a"), pending(&app, "b"), pending(&app, "c")), (11, 1, 1));
+
+        // the escrow always covers what is pending
+        let funded = 101 + 30 + 1 + 2;
+        let claimed = 50 + 70;
+        assert_eq!(balance(&app, contract.as_str()), funded - claimed);
+        assert!(funded - claimed >= 11 + 1 + 1);
+
+        // what a burned token didn't claim goes to the holders left at the next funding
+        app.execute_contract(
+            Addr::unchecked(BUYER),
+            contract.clone(),
+            &(ExecuteMsg::Burn { class_id: "ptx".to_string(), id: "a".to_string() }),
+            &[]
+        ).unwrap();
+        fund(&mut app, 9);
+        assert_eq!((pending(&app, "b"), pending(&app, "c")), (11, 11));
+    }
+
+    #[test]
+    fn holder_rewards_can_be_funded_in_cw20() {
+        let mut deps = mock_dependencies();
+        let state = State {
+            owner: Addr::unchecked(OWNER),
+            ..blank_state()
+        };
+        STATE.save(deps.as_mut().storage, &state).unwrap();
+        save_minter(deps.as_mut().storage);
+        let fund = |amount: u128| {
+            ExecuteMsg::Receive(Cw20ReceiveMsg {
+                sender: TREASURY.to_string(),
+                amount: Uint128::new(amount),
+                msg: to_binary(&ReceiveMsg::FundRewards {}).unwrap(),
+            })
+        };
+
+        let err = execute(deps.as_mut(), mock_env(), mock_info("cw20", &[]), fund(10));
+        assert_eq!(err.unwrap_err(), ContractError::NoRewardHolders {});
+        for id in ["a", "b"] {
+            execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info(OWNER, &[]),
+                ExecuteMsg::Mint {
+                    class_id: "ptx".to_string(),
+                    id: id.to_string(),
+                    uri: None,
+                    uri_hash: None,
+                    data: None,
+                }
+            ).unwrap();
+        }
+        execute(deps.as_mut(), mock_env(), mock_info("cw20", &[]), fund(10)).unwrap();
+
+        // the first funding fixed the asset
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info(BUYER, &coins(10, DENOM)),
+            ExecuteMsg::FundRewards {}
+        ).unwrap_err();
+        assert_eq!(err, ContractError::RewardAssetMismatch { expected: "cw20:cw20".to_string() });
+
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info(OWNER, &[]),
+            ExecuteMsg::ClaimRewards { token_ids: vec!["a".to_string(), "b".to_string()] }
+        ).unwrap();
+        assert_eq!(res.messages.len(), 1);
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "cw20".to_string(),
+                msg: to_binary(
+                    &(Cw20ExecuteMsg::Transfer {
+                        recipient: OWNER.to_string(),
+                        amount: Uint128::new(10),
+                    })
+                ).unwrap(),
+                funds: vec![],
+            })
+        );
+        let pending = query_pending_rewards(deps.as_ref(), "a".to_string()).unwrap();
+        assert_eq!(pending, PendingRewardsResponse {
+            asset: Some(RewardAsset::Cw20(Addr::unchecked("cw20"))),
+            amount: Uint128::zero(),
+        });
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    #[serde(rename_all = "snake_case")]
+    enum DaoExecuteMsg {
+        Deposit {},
+    }
+
+    // Stand-in for a governance contract, it accepts deposits and keeps the funds
+    fn dao_contract() -> Box<dyn Contract<Empty>> {
+        Box::new(
+            ContractWrapper::new(
+                |_: DepsMut, _: Env, _: MessageInfo, msg: DaoExecuteMsg| -> StdResult<Response> {
+                    match msg {
+                        DaoExecuteMsg::Deposit {} => Ok(Response::new()),
+                    }
+                },
+                |_: DepsMut, _: Env, _: MessageInfo, _: Empty| -> StdResult<Response> {
+                    Ok(Response::new())
+                },
+                |_: Deps, _: Env, _: Empty| -> StdResult<Binary> { to_binary(&Empty {}) }
+            )
+        )
+    }
+
+    #[test]
+    fn proceeds_are_accrued_and_withdrawn_per_denom() {
+        let (mut app, contract) = setup_app();
+        whitelist_buyer(&mut app, &contract);
+        app.sudo(
+            (BankSudo::Mint {
+                to_address: BUYER.to_string(),
+                amount: coins(1_000, "uusd"),
+            }).into()
+        ).unwrap();
+        let accrued = |app: &App| {
+            let msg = QueryMsg::AccruedByDenom { address: TREASURY.to_string() };
+            let res: AccruedByDenomResponse = app.wrap().query_wasm_smart(&contract, &msg).unwrap();
+            res.balances
+        };
+        let withdraw = |app: &mut App, denom: &str| {
+            let msg = ExecuteMsg::Withdraw { denom: denom.to_string() };
+            app.execute_contract(Addr::unchecked(TREASURY), contract.clone(), &msg, &[])
+        };
+
+        // a sale before and after the mint denom changes
+        app.execute_contract(
+            Addr::unchecked(BUYER),
+            contract.clone(),
+            &purchase_msg(1),
+            &coins(100, DENOM)
+        ).unwrap();
+        app.execute_contract(
+            Addr::unchecked(OWNER),
+            contract.clone(),
+            &(ExecuteMsg::SetMintDenom { denom: "uusd".to_string() }),
+            &[]
+        ).unwrap();
+        app.execute_contract(
+            Addr::unchecked(BUYER),
+            contract.clone(),
+            &purchase_msg(2),
+            &coins(200, "uusd")
+        ).unwrap();
+        assert_eq!(accrued(&app), vec![coin(100, DENOM), coin(200, "uusd")]);
+
+        // each denom is withdrawn on its own
+        withdraw(&mut app, "uusd").unwrap();
+        assert_eq!(app.wrap().query_balance(TREASURY, "uusd").unwrap().amount.u128(), 200);
+        assert_eq!(accrued(&app), vec![coin(100, DENOM)]);
+        let err = withdraw(&mut app, "uusd").unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::NothingToWithdraw {
+            denom: "uusd".to_string(),
+        });
+
+        withdraw(&mut app, DENOM).unwrap();
+        assert_eq!(app.wrap().query_balance(TREASURY, DENOM).unwrap().amount.u128(), 100);
+        assert_eq!(accrued(&app), vec![]);
+    }
+
+    #[test]
+    fn proceeds_hook_receives_the_treasury_share() {
+        let (mut app, contract) = setup_app();
+        whitelist_buyer(&mut app, &contract);
+        issue_collection_class(&mut app, &contract, Some("0.1"));
+        let dao_code_id = app.store_code(dao_contract());
+        let dao = app
+            .instantiate_contract(dao_code_id, Addr::unchecked(OWNER), &Empty {}, &[], "dao", None)
+            .unwrap();
+        let set_hook = |contract: Option<&Addr>, msg: Binary| ExecuteMsg::SetProceedsHook {
+            contract: contract.map(Addr::to_string),
+            msg,
+        };
+
+        // a hook needs a message to execute
+        let err = app
+            .execute_contract(
+                Addr::unchecked(OWNER),
+                contract.clone(),
+                &set_hook(Some(&dao), Binary::default()),
+                &[]
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::invalid_config("proceeds_hook_msg", "must be set with a hook")
+        );
+        app.execute_contract(
+            Addr::unchecked(OWNER),
+            contract.clone(),
+            &set_hook(Some(&dao), to_binary(&(DaoExecuteMsg::Deposit {})).unwrap()),
+            &[]
+        ).unwrap();
+
+        // sales income is deposited into the dao instead of the treasury ledger
+        app.execute_contract(
+            Addr::unchecked(BUYER),
+            contract.clone(),
+            &purchase_msg(1),
+            &coins(100, DENOM)
+        ).unwrap();
+        let balance = |app: &App, addr: &str| app.wrap().query_balance(addr, DENOM).unwrap().amount;
+        assert_eq!(balance(&app, dao.as_str()), Uint128::new(100));
+        assert_eq!(load_balance(&app, &contract, TREASURY), Uint128::zero());
+
+        // and so are royalties
+        mint_to_owner(&mut app, &contract, "a");
+        app.execute_contract(Addr::unchecked(OWNER), contract.clone(), &list_msg("a", 500), &[])
+            .unwrap();
+        app.execute_contract(
+            Addr::unchecked(BUYER),
+            contract.clone(),
+            &buy_msg("a"),
+            &coins(500, DENOM)
+        ).unwrap();
+        assert_eq!(balance(&app, dao.as_str()), Uint128::new(150));
+        assert_eq!(balance(&app, TREASURY), Uint128::zero());
+
+        // without the hook the treasury is paid again
+        app.execute_contract(
+            Addr::unchecked(OWNER),
+            contract.clone(),
+            &set_hook(None, Binary::default()),
+            &[]
+        ).unwrap();
+        app.execute_contract(
+            Addr::unchecked(BUYER),
+            contract.clone(),
+            &purchase_msg(1),
+            &coins(100, DENOM)
+        ).unwrap();
+        assert_eq!(balance(&app, dao.as_str()), Uint128::new(150));
+        assert_eq!(load_balance(&app, &contract, TREASURY), Uint128::new(100));
+    }
+
+    #[test]
+    fn purchase_response_carries_every_message() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        let now = env.block.time.seconds();
+        let deposit = to_binary(&(DaoExecuteMsg::Deposit {})).unwrap();
+        let state = State {
+            sale_start_time: now,
+            sale_end_time: now + 3_600,
+            proceeds_hook: Some(Addr::unchecked("dao")),
+            proceeds_hook_msg: deposit.clone(),
+            ..blank_state()
+        };
+        let storage = deps.as_mut().storage;
+        STATE.save(storage, &state).unwrap();
+        WHITELIST.save(storage, &Addr::unchecked(BUYER), &true).unwrap();
+        TRANSFER_HOOKS.save(storage, &Addr::unchecked("hook"), &Empty {}).unwrap();
+
+        let info = mock_info(BUYER, &coins(200, DENOM));
+        let res = execute(deps.as_mut(), env, info, purchase_msg(2)).unwrap();
+        let notify = TransferHookExecuteMsg::TransferHook(TransferHookMsg {
+            token_ids: vec!["0".to_string(), "1".to_string()],
+            from: None,
+            to: Some(BUYER.to_string()),
+            action: "mint".to_string(),
+        });
+        let notify = WasmMsg::Execute {
+            contract_addr: "hook".to_string(),
+            msg: to_binary(&notify).unwrap(),
+            funds: vec![],
+        };
+        assert_eq!(res.messages, vec![
+            SubMsg::new(WasmMsg::Execute {
+                contract_addr: "dao".to_string(),
+                msg: deposit,
+                funds: coins(200, DENOM),
+            }),
+            SubMsg::reply_on_error(notify, TRANSFER_HOOK_REPLY_ID).with_gas_limit(
+                TRANSFER_HOOK_GAS_LIMIT
+            )
+        ]);
+        assert_eq!(res.attributes, vec![
+            attr("action", "purchase"),
+            attr("payer", BUYER),
+            attr("owner", BUYER),
+            attr("count", "2"),
+            attr("token_id", "0"),
+            attr("token_id", "1")
+        ]);
+
+        // the state the messages run against already holds the purchase
+        let state = STATE.load(deps.as_ref().storage).unwrap();
+        assert_eq!(state.current_token_id, 2);
+        let treasury = get_balance(deps.as_ref().storage, &Addr::unchecked(TREASURY), DENOM);
+        assert_eq!(treasury.unwrap(), Uint128::zero());
+    }
+
+    #[test]
+    fn buy_rejects_underpayment_and_unlisted_tokens() {
+        let (mut app, contract) = setup_app();
+        for id in ["a", "b"] {
+            mint_to_owner(&mut app, &contract, id);
+        }
+        app.execute_contract(Addr::unchecked(OWNER), contract.clone(), &list_msg("a", 500), &[])
+            .unwrap();
+
+        let err = app
+            .execute_contract(
+                Addr::unchecked(BUYER),
+                contract.clone(),
+                &buy_msg("a"),
+                &coins(499, DENOM)
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::InsufficientFunds {
+                required: Uint128::new(500),
+                provided: Uint128::new(499),
+            }
+        );
+
+        let err = app
+            .execute_contract(
+                Addr::unchecked(BUYER),
+                contract.clone(),
+                &buy_msg("b"),
+                &coins(500, DENOM)
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::TokenNotListed { token_id: "b".to_string() }
+        );
+
+        // only the owner lists, only the seller cancels
+        let err = app
+            .execute_contract(Addr::unchecked(BUYER), contract.clone(), &list_msg("b", 500), &[])
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::unauthorized("token owner")
+        );
+        let cancel = ExecuteMsg::CancelListing { token_id: "a".to_string() };
+        let err = app
+            .execute_contract(Addr::unchecked(BUYER), contract.clone(), &cancel, &[])
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::unauthorized("seller")
+        );
+
+        // overpayment is refunded
+        app.execute_contract(
+            Addr::unchecked(BUYER),
+            contract.clone(),
+            &buy_msg("a"),
+            &coins(600, DENOM)
+        ).unwrap();
+        let buyer_balance = app.wrap().query_balance(BUYER, DENOM).unwrap().amount;
+        assert_eq!(buyer_balance, Uint128::new(500));
+    }
+
+    #[test]
+    fn listings_are_dropped_on_transfer_and_burn() {
+        let mut deps = mock_dependencies();
+        for id in ["1", "2"] {
+            save_token(deps.as_mut().storage, id, OWNER);
+            let listing = Listing { seller: Addr::unchecked(OWNER), price: Coin::new(10, DENOM) };
+            LISTINGS.save(deps.as_mut().storage, id, &listing).unwrap();
+        }
+
+        transfer(
+            deps.as_mut(),
+            &mock_env().block,
+            Addr::unchecked(OWNER),
+            Addr::unchecked(BUYER),
+            "1".to_string()
+        ).unwrap();
+        let mut state = blank_state();
+        burn_token(deps.as_mut().storage, &mut state, "2").unwrap();
+
+        let listings = query_listings(deps.as_ref(), None, None).unwrap();
+        assert!(listings.listings.is_empty());
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    #[serde(rename_all = "snake_case")]
+    enum ReceiverExecuteMsg {
+        ReceiveNft(Cw721ReceiveMsg),
+    }
+
+    // Last message handed to the mock receiver
+    const RECEIVED: Item<Cw721ReceiveMsg> = Item::new("received");
+
+    #[test]
+    fn send_nft_calls_the_receive_hook() {
+        let (mut app, contract) = setup_app();
+        mint_to_owner(&mut app, &contract, "a");
+        let code_id = app.store_code(
+            Box::new(
+                ContractWrapper::new(
+                    |deps: DepsMut, _: Env, _: MessageInfo, msg: ReceiverExecuteMsg| {
+                        let ReceiverExecuteMsg::ReceiveNft(received) = msg;
+                        RECEIVED.save(deps.storage, &received)?;
+                        Ok::<_, StdError>(Response::new())
+                    },
+                    |_: DepsMut, _: Env, _: MessageInfo, _: Empty| Ok::<_, StdError>(Response::new()),
+                    |_: Deps, _: Env, _: Empty| to_binary(&Empty {})
+                )
+            )
+        );
+        let receiver = app
+            .instantiate_contract(code_id, Addr::unchecked(OWNER), &Empty {}, &[], "receiver", None)
+            .unwrap();
+        let send = ExecuteMsg::SendNft {
+            contract: receiver.to_string(),
+            token_id: "a".to_string(),
+            msg: Binary::from(b"escrow".to_vec()),
+        };
+
+        let err = app
+            .execute_contract(Addr::unchecked(BUYER), contract.clone(), &send, &[])
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::unauthorized("token owner or approved spender")
+        );
+
+        let res = app.execute_contract(Addr::unchecked(OWNER), contract.clone(), &send, &[]).unwrap();
+        assert_eq!(
+            wasm_attributes(&res),
+            vec![
+                attr("action", "send_nft"),
+                attr("sender", OWNER),
+                attr("recipient", receiver.as_str()),
+                attr("token_id", "a")
+            ]
+        );
+        assert_eq!(load_owner(&app, &contract, "a"), Some(receiver.clone()));
+
+        let received: Cw721ReceiveMsg = app
+            .wrap()
+            .query_wasm_raw(&receiver, RECEIVED.as_slice().to_vec())
+            .unwrap()
+            .map(|raw| from_slice(&raw).unwrap())
+            .unwrap();
+        assert_eq!(
+            received,
+            Cw721ReceiveMsg {
+                sender: OWNER.to_string(),
+                token_id: "a".to_string(),
+                msg: Binary::from(b"escrow".to_vec()),
+            }
+        );
+    }
+
+    // Notifications handed to the recording transfer hook
+    const HOOK_CALLS: Item<Vec<TransferHookMsg>> = Item::new("hook_calls");
+
+    fn record_hook_call(
+        deps: DepsMut,
+        _: Env,
+        _: MessageInfo,
+        msg: TransferHookExecuteMsg
+    ) -> StdResult<Response> {
+        let TransferHookExecuteMsg::TransferHook(call) = msg;
+        let mut calls = HOOK_CALLS.may_load(deps.storage)?.unwrap_or_default();
+        calls.push(call);
+        HOOK_CALLS.save(deps.storage, &calls)?;
+        Ok(Response::new())
+    }
+
+    fn reject_hook_call(
+        _: DepsMut,
+        _: Env,
+        _: MessageInfo,
+        _: TransferHookExecuteMsg
+    ) -> StdResult<Response> {
+        Err(StdError::generic_err("hook rejected"))
+    }
+
+    /// Transfer hook recording every notification, or rejecting all of them if `failing`
+    fn hook_contract(failing: bool) -> Box<dyn Contract<Empty>> {
+        Box::new(
+            ContractWrapper::new(
+                if failing { reject_hook_call } else { record_hook_call },
+                |_: DepsMut, _: Env, _: MessageInfo, _: Empty| Ok::<_, StdError>(Response::new()),
+                |_: Deps, _: Env, _: Empty| to_binary(&Empty {})
+            )
+        )
+    }
+
+    #[test]
+    fn transfer_hooks_are_notified_and_cannot_revert_transfers() {
+        let (mut app, contract) = setup_app();
+        let recorder_code_id = app.store_code(hook_contract(false));
+        let failing_code_id = app.store_code(hook_contract(true));
+        let mut instantiate_hook = |code_id: u64| {
+            app.instantiate_contract(code_id, Addr::unchecked(OWNER), &Empty {}, &[], "hook", None)
+                .unwrap()
+        };
+        let recorder = instantiate_hook(recorder_code_id);
+        let failing = instantiate_hook(failing_code_id);
+        let register = |contract: &Addr| ExecuteMsg::RegisterTransferHook {
+            contract: contract.to_string(),
+        };
+
+        let err = app
+            .execute_contract(Addr::unchecked(BUYER), contract.clone(), &register(&recorder), &[])
+            .unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::unauthorized("owner"));
+        for hook in [&failing, &recorder] {
+            app.execute_contract(Addr::unchecked(OWNER), contract.clone(), &register(hook), &[])
+                .unwrap();
+        }
+        let hooks: HooksResponse = app
+            .wrap()
+            .query_wasm_smart(&contract, &(QueryMsg::Hooks {}))
+            .unwrap();
+        let mut expected = vec![failing.to_string(), recorder.to_string()];
+        expected.sort();
+        assert_eq!(hooks.hooks, expected);
+
+        // the failing hook is swallowed, so every operation still goes through
+        mint_to_owner(&mut app, &contract, "a");
+        let res = app
+            .execute_contract(
+                Addr::unchecked(OWNER),
+                contract.clone(),
+                &(ExecuteMsg::TransferNft {
+                    recipient: BUYER.to_string(),
+                    token_id: "a".to_string(),
+                    sale_price: None,
+                }),
+                &[]
+            )
+            .unwrap();
+        assert!(
+            res.events
+                .iter()
+                .flat_map(|event| &event.attributes)
+                .any(|attr| attr.key == "action" && attr.value == "transfer_hook_failed")
+        );
+        assert_eq!(load_owner(&app, &contract, "a"), Some(Addr::unchecked(BUYER)));
+        app.execute_contract(
+            Addr::unchecked(BUYER),
+            contract.clone(),
+            &(ExecuteMsg::Burn { class_id: "ptx".to_string(), id: "a".to_string() }),
+            &[]
+        ).unwrap();
+        assert_eq!(load_owner(&app, &contract, "a"), None);
+
+        let call = |token_id: &str, from: Option<&str>, to: Option<&str>, action: &str| {
+            TransferHookMsg {
+                token_ids: vec![token_id.to_string()],
+                from: from.map(str::to_string),
+                to: to.map(str::to_string),
+                action: action.to_string(),
+            }
+        };
+        let calls: Vec<TransferHookMsg> = app
+            .wrap()
+            .query_wasm_raw(&recorder, HOOK_CALLS.as_slice().to_vec())
+            .unwrap()
+            .map(|raw| from_slice(&raw).unwrap())
+            .unwrap();
+        assert_eq!(calls, vec![
+            call("a", None, Some(OWNER), "mint"),
+            call("a", Some(OWNER), Some(BUYER), "transfer"),
+            call("a", Some(BUYER), None, "burn")
+        ]);
+
+        // removed hooks aren't notified anymore
+        app.execute_contract(
+            Addr::unchecked(OWNER),
+            contract.clone(),
+            &(ExecuteMsg::RemoveTransferHook { contract: recorder.to_string() }),
+            &[]
+        ).unwrap();
+        mint_to_owner(&mut app, &contract, "b");
+        let hooks: HooksResponse = app
+            .wrap()
+            .query_wasm_smart(&contract, &(QueryMsg::Hooks {}))
+            .unwrap();
+        assert_eq!(hooks.hooks, vec![failing.to_string()]);
+        let calls: Vec<TransferHookMsg> = app
+            .wrap()
+            .query_wasm_raw(&recorder, HOOK_CALLS.as_slice().to_vec())
+            .unwrap()
+            .map(|raw| from_slice(&raw).unwrap())
+            .unwrap();
+        assert_eq!(calls.len(), 3);
+    }
+
+    #[test]
+    fn transfer_hooks_are_limited() {
+        let mut deps = mock_dependencies();
+        let state = State {
+            owner: Addr::unchecked(OWNER),
+            ..blank_state()
+        };
+        STATE.save(deps.as_mut().storage, &state).unwrap();
+        let register = |deps: DepsMut, contract: String| {
+            execute(
+                deps,
+                mock_env(),
+                mock_info(OWNER, &[]),
+                ExecuteMsg::RegisterTransferHook { contract }
+            )
+        };
+
+        for i in 0..MAX_TRANSFER_HOOKS {
+            register(deps.as_mut(), format!("hook{}", i)).unwrap();
+        }
+        // registering a hook again doesn't take another slot
+        register(deps.as_mut(), "hook0".to_string()).unwrap();
+        let err = register(deps.as_mut(), "hook9".to_string()).unwrap_err();
+        assert_eq!(err, ContractError::TooManyTransferHooks { max: MAX_TRANSFER_HOOKS });
+        assert_eq!(query_hooks(deps.as_ref()).unwrap().hooks.len(), MAX_TRANSFER_HOOKS);
+
+        let msgs = transfer_hook_msgs(
+            deps.as_ref().storage,
+            "burn",
+            &["a".to_string(), "b".to_string()],
+            Some(&Addr::unchecked(OWNER)),
+            None
+        ).unwrap();
+        // a batch is one notification per hook, each with its own gas budget
+        assert_eq!(msgs.len(), MAX_TRANSFER_HOOKS);
+        assert!(
+            msgs
+                .iter()
+                .all(|msg| {
+                    msg.id == TRANSFER_HOOK_REPLY_ID &&
+                        msg.gas_limit == Some(TRANSFER_HOOK_GAS_LIMIT)
+                })
+        );
+    }
+
+    #[test]
+    fn reply_swallows_failed_hooks_only() {
+        let mut deps = mock_dependencies();
+        let failed = Reply {
+            id: TRANSFER_HOOK_REPLY_ID,
+            result: SubMsgResult::Err("hook rejected".to_string()),
+        };
+        let res = reply(deps.as_mut(), mock_env(), failed).unwrap();
+        assert_eq!(res.attributes, vec![
+            attr("action", "transfer_hook_failed"),
+            attr("error", "hook rejected")
+        ]);
+
+        let unknown = Reply { id: 7, result: SubMsgResult::Err("boom".to_string()) };
+        let err = reply(deps.as_mut(), mock_env(), unknown).unwrap_err();
+        assert_eq!(err, ContractError::UnknownReplyId { id: 7 });
+    }
+
+    type PassExecuteMsg = cw721_base::ExecuteMsg<Empty, Empty>;
+
+    /// Plain cw721-base collection standing in for the mint pass collection
+    fn pass_contract() -> Box<dyn Contract<Empty>> {
+        Box::new(
+            ContractWrapper::new(
+                |deps: DepsMut, env: Env, info: MessageInfo, msg: PassExecuteMsg| {
+                    Cw721Contract::<Empty, Empty, Empty, Empty>
+                        ::default()
+                        .execute(deps, env, info, msg)
+                },
+                |deps: DepsMut, env: Env, info: MessageInfo, msg: Cw721InstantiateMsg| {
+                    Cw721Contract::<Empty, Empty, Empty, Empty>
+                        ::default()
+                        .instantiate(deps, env, info, msg)
+                },
+                |deps: Deps, env: Env, msg: cw721_base::QueryMsg<Empty>| {
+                    Cw721Contract::<Empty, Empty, Empty, Empty>::default().query(deps, env, msg)
+                }
+            )
+        )
+    }
+
+    #[test]
+    fn passes_are_burned_for_a_free_mint() {
+        let mut app = App::default();
+        let pass_code_id = app.store_code(pass_contract());
+        let pass = app
+            .instantiate_contract(
+                pass_code_id,
+                Addr::unchecked(OWNER),
+                &(Cw721InstantiateMsg {
+                    name: "Mint pass".to_string(),
+                    symbol: "PASS".to_string(),
+                    minter: OWNER.to_string(),
+                }),
+                &[],
+                "pass",
+                None
+            )
+            .unwrap();
+        let code_id = app.store_code(collection_contract());
+        let now = app.block_info().time.seconds();
+        let mut msg = instantiate_msg(now, now + 3_600);
+        msg.deployment_config.max_supply = 1;
+        msg.runtime_config.pass_collection = Some(pass.to_string());
+        let contract = app
+            .instantiate_contract(code_id, Addr::unchecked(OWNER), &msg, &[], "collection", None)
+            .unwrap();
+
+        for id in ["p1", "p2"] {
+            app.execute_contract(
+                Addr::unchecked(OWNER),
+                pass.clone(),
+                &(PassExecuteMsg::Mint {
+                    token_id: id.to_string(),
+                    owner: BUYER.to_string(),
+                    token_uri: None,
+                    extension: Empty {},
+                }),
+                &[]
+            ).unwrap();
+        }
+        app.execute_contract(
+            Addr::unchecked(BUYER),
+            pass.clone(),
+            &(PassExecuteMsg::SendNft {
+                contract: contract.to_string(),
+                token_id: "p1".to_string(),
+                msg: to_binary(&(ReceiveNftMsg::RedeemPass {})).unwrap(),
+            }),
+            &[]
+        ).unwrap();
+
+        // the pass is gone and its holder got a token without paying
+        assert_eq!(load_owner(&app, &contract, "0"), Some(Addr::unchecked(BUYER)));
+        assert!(
+            app
+                .wrap()
+                .query_wasm_smart::<OwnerOfResponse>(
+                    &pass,
+                    &(cw721_base::QueryMsg::<Empty>::OwnerOf {
+                        token_id: "p1".to_string(),
+                        include_expired: None,
+                    })
+                )
+                .is_err()
+        );
+        assert_eq!(load_balance(&app, &contract, TREASURY), Uint128::zero());
+
+        let redeem = |token_id: &str| {
+            ExecuteMsg::ReceiveNft(Cw721ReceiveMsg {
+                sender: BUYER.to_string(),
+                token_id: token_id.to_string(),
+                msg: to_binary(&(ReceiveNftMsg::RedeemPass {})).unwrap(),
+            })
+        };
+        let cases = [
+            ("rogue", "p2", ContractError::unauthorized("pass collection")),
+            (pass.as_str(), "p1", ContractError::PassRedeemed { token_id: "p1".to_string() }),
+            // redemptions count against the max supply
+            (pass.as_str(), "p2", ContractError::SoldOut { remaining: 0 }),
+        ];
+        for (sender, token_id, expected) in cases {
+            let err = app
+                .execute_contract(Addr::unchecked(sender), contract.clone(), &redeem(token_id), &[])
+                .unwrap_err();
+            assert_eq!(err.downcast::<ContractError>().unwrap(), expected);
+        }
+    }
+
+    /// Registers a stub ics721 bridge accepting every message and points the collection at it
+    fn setup_bridge(app: &mut App, contract: &Addr) -> Addr {
+        let code_id = app.store_code(
+            Box::new(
+                ContractWrapper::new(
+                    |_: DepsMut, _: Env, _: MessageInfo, _: Ics721ExecuteMsg| {
+                        Ok::<_, StdError>(Response::new())
+                    },
+                    |_: DepsMut, _: Env, _: MessageInfo, _: Empty| Ok::<_, StdError>(Response::new()),
+                    |_: Deps, _: Env, _: Empty| to_binary(&Empty {})
+                )
+            )
+        );
+        let bridge = app
+            .instantiate_contract(code_id, Addr::unchecked(OWNER), &Empty {}, &[], "ics721", None)
+            .unwrap();
+        app.execute_contract(
+            Addr::unchecked(OWNER),
+            contract.clone(),
+            &(ExecuteMsg::SetIcs721Address { address: bridge.to_string() }),
+            &[]
+        ).unwrap();
+        bridge
+    }
+
+    fn ibc_transfer_msg(id: &str) -> ExecuteMsg {
+        ExecuteMsg::IbcTransfer {
+            token_id: id.to_string(),
+            channel: "channel-0".to_string(),
+            receiver: "stars1receiver".to_string(),
+            timeout: Timestamp::from_seconds(2_000_000_000),
+        }
+    }
+
+    fn report_ibc_outcome(
+        app: &mut App,
+        contract: &Addr,
+        bridge: &Addr,
+        id: &str,
+        outcome: IbcOutcome
+    ) -> Result<AppResponse, ContractError> {
+        app.execute_contract(
+            bridge.clone(),
+            contract.clone(),
+            &(ExecuteMsg::IbcCallback { token_id: id.to_string(), outcome }),
+            &[]
+        ).map_err(|err| err.downcast::<ContractError>().unwrap())
+    }
+
+    fn bridged_tokens(app: &App, contract: &Addr) -> Vec<BridgedTokenInfo> {
+        let res: BridgedResponse = app
+            .wrap()
+            .query_wasm_smart(contract, &(QueryMsg::Bridged { start_after: None, limit: None }))
+            .unwrap();
+        res.tokens
+    }
+
+    #[test]
+    fn ibc_transfer_escrows_token_until_returned() {
+        let (mut app, contract) = setup_app();
+        mint_to_owner(&mut app, &contract, "a");
+
+        // the bridge has to be configured first
+        let err = app
+            .execute_contract(Addr::unchecked(OWNER), contract.clone(), &ibc_transfer_msg("a"), &[])
+            .unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::BridgeUnset {});
+
+        let bridge = setup_bridge(&mut app, &contract);
+        let res = app
+            .execute_contract(Addr::unchecked(OWNER), contract.clone(), &ibc_transfer_msg("a"), &[])
+            .unwrap();
+        assert!(wasm_attributes(&res).contains(&attr("action", "ibc_transfer")));
+        assert_eq!(
+            bridged_tokens(&app, &contract),
+            vec![BridgedTokenInfo {
+                token_id: "a".to_string(),
+                owner: OWNER.to_string(),
+                channel: "channel-0".to_string(),
+                receiver: "stars1receiver".to_string(),
+                status: BridgeStatus::Pending,
+            }]
+        );
+        // the bridge holds the token while it's in flight
+        assert_eq!(load_owner(&app, &contract, "a"), Some(bridge.clone()));
+
+        // escrowed tokens can't be burned or sent again, not even by the bridge
+        let err = app
+            .execute_contract(Addr::unchecked(OWNER), contract.clone(), &ibc_transfer_msg("a"), &[])
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::unauthorized("token owner")
+        );
+        let err = app
+            .execute_contract(
+                bridge.clone(),
+                contract.clone(),
+                &(ExecuteMsg::Burn { class_id: "ptx".to_string(), id: "a".to_string() }),
+                &[]
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::TokenBridged { token_id: "a".to_string() }
+        );
+        let err = app
+            .execute_contract(bridge.clone(), contract.clone(), &ibc_transfer_msg("a"), &[])
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::TokenBridged { token_id: "a".to_string() }
+        );
+
+        // only the bridge reports outcomes
+        let err = report_ibc_outcome(
+            &mut app,
+            &contract,
+            &Addr::unchecked(OWNER),
+            "a",
+            IbcOutcome::AckSuccess {}
+        ).unwrap_err();
+        assert_eq!(err, ContractError::unauthorized("ics721 bridge"));
+
+        report_ibc_outcome(&mut app, &contract, &bridge, "a", IbcOutcome::AckSuccess {}).unwrap();
+        assert_eq!(bridged_tokens(&app, &contract)[0].status, BridgeStatus::Transferred);
+
+        // a late timeout for a delivered token is rejected
+        let err = report_ibc_outcome(
+            &mut app,
+            &contract,
+            &bridge,
+            "a",
+            IbcOutcome::Timeout {}
+        ).unwrap_err();
+        assert_eq!(err, ContractError::UnexpectedBridgeCallback { token_id: "a".to_string() });
+
+        report_ibc_outcome(
+            &mut app,
+            &contract,
+            &bridge,
+            "a",
+            IbcOutcome::Returned { recipient: BUYER.to_string() }
+        ).unwrap();
+        assert!(bridged_tokens(&app, &contract).is_empty());
+        assert_eq!(load_owner(&app, &contract, "a"), Some(Addr::unchecked(BUYER)));
+    }
+
+    #[test]
+    fn failed_ibc_transfers_release_the_token() {
+        let (mut app, contract) = setup_app();
+        let bridge = setup_bridge(&mut app, &contract);
+        for id in ["a", "b"] {
+            mint_to_owner(&mut app, &contract, id);
+            app.execute_contract(Addr::unchecked(OWNER), contract.clone(), &ibc_transfer_msg(id), &[])
+                .unwrap();
+        }
+
+        report_ibc_outcome(
+            &mut app,
+            &contract,
+            &bridge,
+            "a",
+            IbcOutcome::AckError { error: "class not allowed".to_string() }
+        ).unwrap();
+        report_ibc_outcome(&mut app, &contract, &bridge, "b", IbcOutcome::Timeout {}).unwrap();
+        assert!(bridged_tokens(&app, &contract).is_empty());
+
+        // released tokens go back to their owner and can be burned again
+        assert_eq!(load_owner(&app, &contract, "a"), Some(Addr::unchecked(OWNER)));
+        assert_eq!(load_owner(&app, &contract, "b"), Some(Addr::unchecked(OWNER)));
+        app.execute_contract(
+            Addr::unchecked(OWNER),
+            contract.clone(),
+            &(ExecuteMsg::Burn { class_id: "ptx".to_string(), id: "b".to_string() }),
+            &[]
+        ).unwrap();
+
+        let err = report_ibc_outcome(
+            &mut app,
+            &contract,
+            &bridge,
+            "a",
+            IbcOutcome::AckSuccess {}
+        ).unwrap_err();
+        assert_eq!(err, ContractError::UnexpectedBridgeCallback { token_id: "a".to_string() });
+    }
+
+    #[test]
+    fn height_lock_holds_until_its_expiry() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        save_token(deps.as_mut().storage, "1", OWNER);
+        let until = Expiration::AtHeight(env.block.height + 1);
+        let mut token = tokens().load(deps.as_ref().storage, "1").unwrap();
+        token.locked_until = Some(until);
+        tokens().save(deps.as_mut().storage, "1", &token).unwrap();
+
+        let err = transfer(
+            deps.as_mut(),
+            &env.block,
+            Addr::unchecked(OWNER),
+            Addr::unchecked(BUYER),
+            "1".to_string()
+        ).unwrap_err();
+        assert_eq!(err, ContractError::TokenLocked { token_id: "1".to_string(), until });
+        let lock = query_token_lock(deps.as_ref(), env.clone(), "1".to_string()).unwrap();
+        assert_eq!(lock.locked_until, Some(until));
+
+        // the lock is over at the exact expiry height
+        env.block.height += 1;
+        let lock = query_token_lock(deps.as_ref(), env.clone(), "1".to_string()).unwrap();
+        assert_eq!(lock.locked_until, None);
+        transfer(
+            deps.as_mut(),
+            &env.block,
+            Addr::unchecked(OWNER),
+            Addr::unchecked(BUYER),
+            "1".to_string()
+        ).unwrap();
+    }
+
+    #[test]
+    fn holder_lock_blocks_transfer_until_unlock_time() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        save_token(deps.as_mut().storage, "1", OWNER);
+        let now = env.block.time.seconds();
+        let lock = |unlock_time| ExecuteMsg::LockUntil { token_id: "1".to_string(), unlock_time };
+
+        // only the holder locks, and only into the future
+        let err = execute(deps.as_mut(), env.clone(), mock_info(BUYER, &[]), lock(now + 60))
+            .unwrap_err();
+        assert_eq!(err, ContractError::unauthorized("token owner"));
+        let err = execute(deps.as_mut(), env.clone(), mock_info(OWNER, &[]), lock(now))
+            .unwrap_err();
+        assert_eq!(err, ContractError::InvalidUnlockTime { unlock_time: now });
+
+        execute(deps.as_mut(), env.clone(), mock_info(OWNER, &[]), lock(now + 60)).unwrap();
+        let status = query_lock_status(deps.as_ref(), env.clone(), "1".to_string()).unwrap();
+        assert_eq!(status.unlock_time, Some(now + 60));
+
+        // a lock can't be shortened
+        let err = execute(deps.as_mut(), env.clone(), mock_info(OWNER, &[]), lock(now + 30))
+            .unwrap_err();
+        assert_eq!(err, ContractError::InvalidUnlockTime { unlock_time: now + 30 });
+
+        env.block.time = env.block.time.plus_seconds(59);
+        let err = transfer(
+            deps.as_mut(),
+            &env.block,
+            Addr::unchecked(OWNER),
+            Addr::unchecked(BUYER),
+            "1".to_string()
+        ).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::TokenLocked {
+                token_id: "1".to_string(),
+                until: Expiration::AtTime(Timestamp::from_seconds(now + 60)),
+            }
+        );
+
+        // the token is transferable again at the unlock time
+        env.block.time = env.block.time.plus_seconds(1);
+        let status = query_lock_status(deps.as_ref(), env.clone(), "1".to_string()).unwrap();
+        assert_eq!(status.unlock_time, None);
+        transfer(
+            deps.as_mut(),
+            &env.block,
+            Addr::unchecked(OWNER),
+            Addr::unchecked(BUYER),
+            "1".to_string()
+        ).unwrap();
+        assert!(!SELF_LOCKS.has(deps.as_ref().storage, "1"));
+    }
+
+    #[test]
+    fn time_lock_blocks_transfer_and_listing_but_not_burn() {
+        let (mut app, contract) = setup_app();
+        for id in ["a", "b"] {
+            mint_to_owner(&mut app, &contract, id);
+        }
+        let until = Expiration::AtTime(app.block_info().time.plus_seconds(60));
+        for id in ["a", "b"] {
+            let lock = ExecuteMsg::LockToken { token_id: id.to_string(), until };
+            app.execute_contract(Addr::unchecked(OWNER), contract.clone(), &lock, &[]).unwrap();
+        }
+
+        // only the owner role locks tokens
+        let lock = ExecuteMsg::LockToken { token_id: "a".to_string(), until: Expiration::Never {} };
+        let err = app
+            .execute_contract(Addr::unchecked(BUYER), contract.clone(), &lock, &[])
+            .unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::unauthorized("owner"));
+
+        let list = ExecuteMsg::List { token_id: "a".to_string(), price: Coin::new(10, DENOM) };
+        app.update_block(|block| {
+            block.time = block.time.plus_nanos(59_999_999_999);
+        });
+        let err = app
+            .execute_contract(Addr::unchecked(OWNER), contract.clone(), &list, &[])
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::TokenLocked { token_id: "a".to_string(), until }
+        );
+
+        // the holder can still burn a locked token
+        app.execute_contract(
+            Addr::unchecked(OWNER),
+            contract.clone(),
+            &(ExecuteMsg::Burn { class_id: "ptx".to_string(), id: "b".to_string() }),
+            &[]
+        ).unwrap();
+
+        // the lock is over at the exact expiry time
+        app.update_block(|block| {
+            block.time = block.time.plus_nanos(1);
+        });
+        let res: TokenLockResponse = app
+            .wrap()
+            .query_wasm_smart(&contract, &(QueryMsg::TokenLock { token_id: "a".to_string() }))
+            .unwrap();
+        assert_eq!(res.locked_until, None);
+        app.execute_contract(Addr::unchecked(OWNER), contract.clone(), &list, &[]).unwrap();
+    }
+
+    #[test]
+    fn token_user_expires_and_is_cleared_on_transfer() {
+        let (mut app, contract) = setup_app();
+        mint_to_owner(&mut app, &contract, "a");
+        let expires = Expiration::AtTime(app.block_info().time.plus_seconds(100));
+        let set_user = ExecuteMsg::SetUser {
+            token_id: "a".to_string(),
+            user: "gamer".to_string(),
+            expires,
+        };
+        let user_of = |app: &App| -> UserOfResponse {
+            app.wrap()
+                .query_wasm_smart(&contract, &(QueryMsg::UserOf { token_id: "a".to_string() }))
+                .unwrap()
+        };
+
+        // only the owner or an approved spender assigns the user
+        let err = app
+            .execute_contract(Addr::unchecked(BUYER), contract.clone(), &set_user, &[])
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::unauthorized("token owner or approved spender")
+        );
+
+        app.execute_contract(Addr::unchecked(OWNER), contract.clone(), &set_user, &[]).unwrap();
+        assert_eq!(
+            user_of(&app),
+            UserOfResponse { user: Some("gamer".to_string()), expires: Some(expires) }
+        );
+
+        app.update_block(|block| {
+            block.time = block.time.plus_seconds(99);
+        });
+        assert_eq!(user_of(&app).user, Some("gamer".to_string()));
+        app.update_block(|block| {
+            block.time = block.time.plus_seconds(1);
+        });
+        assert_eq!(user_of(&app), UserOfResponse { user: None, expires: None });
+
+        // a transfer ends the assignment right away
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        let mut token = TokenRecord::new(Addr::unchecked(OWNER), None);
+        token.user = Some(TokenUser {
+            user: Addr::unchecked("gamer"),
+            expires: Expiration::Never {},
+        });
+        tokens().save(deps.as_mut().storage, "1", &token).unwrap();
+        transfer(
+            deps.as_mut(),
+            &env.block,
+            Addr::unchecked(OWNER),
+            Addr::unchecked(BUYER),
+            "1".to_string()
+        ).unwrap();
+        let res = query_user_of(deps.as_ref(), env, "1".to_string()).unwrap();
+        assert_eq!(res.user, None);
+    }
+
+    #[test]
+    fn approved_spender_sets_user_unless_disabled() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        let mut token = TokenRecord::new(Addr::unchecked(OWNER), None);
+        token.approvals.push(Approval { spender: BUYER.to_string(), expires: Expiration::Never {} });
+        assert!(assert_owner_or_approved(&token, &Addr::unchecked(BUYER), &env.block).is_ok());
+        assert!(assert_owner_or_approved(&token, &Addr::unchecked("stranger"), &env.block).is_err());
+
+        let (mut app, contract) = setup_app_with(|msg| {
+            msg.deployment_config.users_disabled = true;
+        });
+        mint_to_owner(&mut app, &contract, "a");
+        let err = app
+            .execute_contract(
+                Addr::unchecked(OWNER),
+                contract.clone(),
+                &(ExecuteMsg::SetUser {
+                    token_id: "a".to_string(),
+                    user: "gamer".to_string(),
+                    expires: Expiration::Never {},
+                }),
+                &[]
+            )
+            .unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::UsersDisabled {});
+    }
+
+    /// Mock storage counting the records read through it
+    struct CountingStorage {
+        inner: MockStorage,
+        reads: Cell<usize>,
+    }
+
+    impl Storage for CountingStorage {
+        fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+            self.reads.set(self.reads.get() + 1);
+            self.inner.get(key)
+        }
+
+        fn range<'a>(
+            &'a self,
+            start: Option<&[u8]>,
+            end: Option<&[u8]>,
+            order: Order
+        ) -> Box<dyn Iterator<Item = Record> + 'a> {
+            Box::new(
+                self.inner
+                    .range(start, end, order)
+                    .inspect(move |_| self.reads.set(self.reads.get() + 1))
+            )
+        }
+
+        fn set(&mut self, key: &[u8], value: &[u8]) {
+            self.inner.set(key, value)
+        }
+
+        fn remove(&mut self, key: &[u8]) {
+            self.inner.remove(key)
+        }
+    }
+
+    #[test]
+    fn holder_queries_only_read_the_holders_tokens() {
+        let mut deps = OwnedDeps {
+            storage: CountingStorage { inner: MockStorage::new(), reads: Cell::new(0) },
+            api: MockApi::default(),
+            querier: MockQuerier::<Empty>::new(&[]),
+            custom_query_type: PhantomData,
+        };
+        let mut state = State {
+            max_total_mint: 1_000,
+            ..blank_state()
+        };
+
+        // every hundredth token goes to the buyer
+        for i in 0..1_000u64 {
+            let recipient = if i % 100 == 0 { BUYER } else { OWNER };
+            state.mint(&mut deps.storage, &Addr::unchecked(recipient), None).unwrap();
+        }
+
+        // every returned token costs one index entry and one record read
+        deps.storage.reads.set(0);
+        let held = query_tokens(deps.as_ref(), BUYER.to_string(), None, Some(MAX_LIMIT)).unwrap();
+        assert_eq!(held.tokens.len(), 10);
+        assert!(deps.storage.reads.get() <= 2 * 10);
+
+        // a page of a large holder stops after the page
+        deps.storage.reads.set(0);
+        let held = query_tokens(deps.as_ref(), OWNER.to_string(), None, Some(20)).unwrap();
+        assert_eq!(held.tokens.len(), 20);
+        assert!(deps.storage.reads.get() <= 2 * 20);
+    }
+
+    #[test]
+    fn frozen_tokens_limit_is_capped() {
+        let mut deps = mock_dependencies();
+
+        for i in 0..60 {
+            let id = format!("{:03}", i);
+            save_token(deps.as_mut().storage, &id, OWNER);
+            freeze_token(deps.as_mut().storage, &id).unwrap();
+        }
+
+        let page = query_frozen_tokens(deps.as_ref(), None, Some(100)).unwrap();
+        assert_eq!(page.tokens.len(), 50);
+    }
+
+    /// Stores a state with an open sale that whitelists `buyer`, for the commit tests
+    fn commit_deps(buyer: &Addr) -> OwnedDeps<MockStorage, MockApi, MockQuerier> {
+        let mut deps = mock_dependencies();
+        let now = mock_env().block.time.seconds();
+        let state = State { sale_start_time: now, sale_end_time: now + 3_600, ..blank_state() };
+        STATE.save(deps.as_mut().storage, &state).unwrap();
+        WHITELIST.save(deps.as_mut().storage, buyer, &true).unwrap();
+        deps
+    }
+
+    #[test]
+    fn commit_and_reveal() {
+        let buyer = Addr::unchecked("buyer");
+        let mut deps = commit_deps(&buyer);
+        let salt = b"pepper".to_vec();
+
+        let commitment = commitment_hash(&buyer, &salt, 2);
+        commit_mint(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("buyer", &coins(200, "ucore")),
+            commitment.clone(),
+            None,
+            None
+        ).unwrap();
+
+        // a second commit while one is pending is rejected
+        let err = commit_mint(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("buyer", &coins(200, "ucore")),
+            commitment.clone(),
+            None,
+            None
+        ).unwrap_err();
+        assert_eq!(err, ContractError::CommitmentExists {});
+
+        // revealing in the commit block is too early
+        let env = mock_env();
+        let err = take_commitment(deps.as_mut().storage, &env.block, &buyer, &salt, 2).unwrap_err();
+        assert_eq!(err, ContractError::RevealTooEarly {
+            reveal_height: env.block.height + MIN_REVEAL_DELAY_BLOCKS,
+        });
+
+        // so is revealing after the reveal window
+        let mut env = mock_env();
+        env.block.height += REVEAL_WINDOW_BLOCKS + 1;
+        let err = take_commitment(deps.as_mut().storage, &env.block, &buyer, &salt, 2).unwrap_err();
+        assert_eq!(err, ContractError::CommitmentExpired {});
+
+        // a missed block still leaves the rest of the window
+        env.block.height -= 2;
+        let taken = take_commitment(deps.as_mut().storage, &env.block, &buyer, &salt, 2).unwrap();
+        assert_eq!(taken.hash, commitment);
+        assert_eq!(taken.escrow, Coin::new(200, "ucore"));
+        assert_eq!(taken.committed_time, mock_env().block.time);
+        assert!(!COMMITMENTS.has(deps.as_ref().storage, &buyer));
+    }
+
+    #[test]
+    fn commit_is_checked_as_a_purchase() {
+        let buyer = Addr::unchecked("buyer");
+        let mut deps = commit_deps(&buyer);
+        let mut commit = |sender: &str, funds: &[Coin]| {
+            let commitment = commitment_hash(&Addr::unchecked(sender), b"pepper", 1);
+            commit_mint(deps.as_mut(), mock_env(), mock_info(sender, funds), commitment, None, None)
+        };
+
+        assert_eq!(
+            commit("buyer", &coins(100, "uother")).unwrap_err(),
+            ContractError::Payment(PaymentError::MissingDenom("ucore".to_string()))
+        );
+        assert_eq!(
+            commit("buyer", &coins(99, "ucore")).unwrap_err(),
+            ContractError::InsufficientFunds {
+                required: Uint128::new(100),
+                provided: Uint128::new(99),
+            }
+        );
+        assert_eq!(
+            commit("stranger", &coins(100, "ucore")).unwrap_err(),
+            ContractError::unauthorized("whitelisted buyer")
+        );
+        commit("buyer", &coins(100, "ucore")).unwrap();
+    }
+
+    #[test]
+    fn reveal_is_checked_as_a_purchase() {
+        let (mut app, contract) = setup_app_with(|msg| {
+            msg.runtime_config.max_per_wallet = 2;
+        });
+        whitelist_buyer(&mut app, &contract);
+        let commit = |count: u64| ExecuteMsg::CommitMint {
+            commitment: commitment_hash(&Addr::unchecked(BUYER), b"pepper", count),
+            proof: None,
+            allowlist_index: None,
+        };
+        let reveal = |count: u64| ExecuteMsg::RevealMint {
+            salt: Binary::from(b"pepper".to_vec()),
+            count,
+            proof: None,
+            allowlist_index: None,
+        };
+
+        // three tokens are over the wallet limit, whatever the escrow covers
+        app.execute_contract(
+            Addr::unchecked(BUYER),
+            contract.clone(),
+            &commit(3),
+            &coins(300, DENOM)
+        ).unwrap();
+        app.update_block(|block| block.height += MIN_REVEAL_DELAY_BLOCKS);
+        let err = app
+            .execute_contract(Addr::unchecked(BUYER), contract.clone(), &reveal(3), &[])
+            .unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::WalletLimitExceeded {
+            wallet: BUYER.to_string(),
+            limit: 2,
+            minted: 0,
+        });
+        app.update_block(|block| block.height += COMMIT_TIMEOUT_BLOCKS);
+        app.execute_contract(
+            Addr::unchecked(BUYER),
+            contract.clone(),
+            &ExecuteMsg::CancelCommit {},
+            &[]
+        ).unwrap();
+
+        // a reveal a few blocks late mints and counts towards the wallet limit
+        app.execute_contract(
+            Addr::unchecked(BUYER),
+            contract.clone(),
+            &commit(2),
+            &coins(200, DENOM)
+        ).unwrap();
+        app.update_block(|block| block.height += REVEAL_WINDOW_BLOCKS);
+        app.execute_contract(Addr::unchecked(BUYER), contract.clone(), &reveal(2), &[]).unwrap();
+        assert_eq!(query_all_tokens(&app, &contract).len(), 2);
+        assert!(!can_mint(&app, &contract, 1).allowed);
+    }
+
+    #[test]
+    fn reveal_with_mismatched_salt_is_rejected() {
+        let buyer = Addr::unchecked("buyer");
+        let mut deps = commit_deps(&buyer);
+
+        commit_mint(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("buyer", &coins(100, "ucore")),
+            commitment_hash(&buyer, b"pepper", 1),
+            None,
+            None
+        ).unwrap();
+
+        let mut env = mock_env();
+        env.block.height += MIN_REVEAL_DELAY_BLOCKS;
+        let err = take_commitment(
+            deps.as_mut().storage,
+            &env.block,
+            &buyer,
+            b"paprika",
+            1
+        ).unwrap_err();
+        assert_eq!(err, ContractError::CommitmentMismatch {});
+
+        // the commitment survives a failed reveal and can be cancelled after the timeout
+        env.block.height += COMMIT_TIMEOUT_BLOCKS;
+        let res = cancel_commit(deps.as_mut(), env, mock_info("buyer", &[])).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            (BankMsg::Send {
+                to_address: "buyer".to_string(),
+                amount: coins(100, "ucore"),
+            }).into()
+        );
+    }
+
+    #[test]
+    fn drawn_token_ids_are_unique() {
+        let mut deps = mock_dependencies();
+
+        let mut ids = vec![];
+        for cursor in 0..10u64 {
+            let seed = Sha256::digest(cursor.to_be_bytes());
+            let entropy = if cursor % 2 == 0 { Some(seed.as_slice()) } else { None };
+            ids.push(draw_token_id(deps.as_mut().storage, cursor, 10, entropy).unwrap());
+        }
+        ids.sort_unstable();
+        assert_eq!(ids, (0..10).collect::<Vec<_>>());
+
+        let err = draw_token_id(deps.as_mut().storage, 10, 10, None).unwrap_err();
+        assert_eq!(err, ContractError::SoldOut { remaining: 0 });
+    }
+
+    #[test]
+    fn reduced_supply_shrinks_the_id_pool() {
+        let mut deps = mock_dependencies();
+        let mut state = State {
+            max_total_mint: 10,
+            ..blank_state()
+        };
+        let buyer = Addr::unchecked(BUYER);
+        let seed = |i: u64| Sha256::digest(i.to_be_bytes());
+
+        let mut ids = vec![];
+        for i in 0..3u64 {
+            ids.push(state.mint(deps.as_mut().storage, &buyer, Some(seed(i).as_slice())).unwrap());
+        }
+        assert_eq!(state.reduce_max_supply(deps.as_mut().storage, 6).unwrap(), 10);
+        let beyond = TOKEN_ID_POOL.keys(
+            deps.as_ref().storage,
+            Some(Bound::inclusive(6u64)),
+            None,
+            Order::Ascending
+        );
+        assert_eq!(beyond.count(), 0);
+
+        // the remaining draws hand out distinct ids until the reduced supply is reached
+        for i in 3..6u64 {
+            ids.push(state.mint(deps.as_mut().storage, &buyer, Some(seed(i).as_slice())).unwrap());
+        }
+        let err = state.mint(deps.as_mut().storage, &buyer, None).unwrap_err();
+        assert_eq!(err, ContractError::SoldOut { remaining: 0 });
+        ids.sort_unstable();
+        ids.dedup();
+        assert_eq!(ids.len(), 6);
+        assert!(ids.iter().all(|id| *id < 10));
+
+        // an open edition has no max supply to reduce
+        let mut open_edition = State { open_edition: true, max_total_mint: 0, ..blank_state() };
+        let err = open_edition.reduce_max_supply(deps.as_mut().storage, 0).unwrap_err();
+        assert_eq!(err, ContractError::InvalidSupplyReduction {
+            new_max: 0,
+            max_supply: 0,
+            minted: 0,
+        });
+    }
+
+    fn seed_v1_state(storage: &mut dyn Storage) {
+        cw2::set_contract_version(storage, CONTRACT_NAME, "0.1.0").unwrap();
+        STATE_V1.save(
+            storage,
+            &(StateV1 {
+                owner: MockApi::default().addr_canonicalize(OWNER).unwrap(),
+                base_token_uri: "ipfs://base/".to_string(),
+                base_token_uri_extension: ".json".to_string(),
+                prereveal_token_uri: "ipfs://prereveal".to_string(),
+                treasury_address: "treasury".to_string(),
+                protocol_address: "protocol".to_string(),
+                mint_denom: "ucore".to_string(),
+                mint_price: 100,
+                sale_start_time: 1_000,
+                sale_end_time: 2_000,
+                protocol_fee: 5,
+                max_total_mint: 10,
+                current_token_id: 3,
+                uri_status: false,
+            })
+        ).unwrap();
+    }
+
+    fn seed_v2_state(storage: &mut dyn Storage) {
+        cw2::set_contract_version(storage, CONTRACT_NAME, "0.2.0").unwrap();
+        STATE_V2.save(
+            storage,
+            &(StateV2 {
+                owner: MockApi::default().addr_canonicalize(OWNER).unwrap(),
+                base_token_uri: "ipfs://base/".to_string(),
+                base_token_uri_extension: ".json".to_string(),
+                prereveal_token_uri: "ipfs://prereveal".to_string(),
+                treasury_address: "treasury".to_string(),
+                protocol_address: "protocol".to_string(),
+                mint_denom: "ucore".to_string(),
+                mint_price: 100,
+                sale_start_time: 1_000,
+                sale_end_time: 2_000,
+                protocol_fee_bps: 500,
+                max_total_mint: 10,
+                current_token_id: 3,
+                uri_status: false,
+                burned_count: 0,
+                active_tier: None,
+                presale_start_time: 0,
+                presale_end_time: 0,
+                gross_proceeds: 300,
+                protocol_fees_accrued: 15,
+                total_mints: 3,
+                max_per_wallet: 0,
+                ics721_address: String::new(),
+                contract_uri: None,
+                metadata_frozen: false,
+            })
+        ).unwrap();
+    }
+
+    #[test]
+    fn migrate_converts_amounts_to_uint128() {
+        let mut deps = mock_dependencies();
+        seed_v2_state(deps.as_mut().storage);
+        BALANCES_V2.save(deps.as_mut().storage, &Addr::unchecked(TREASURY), &285).unwrap();
+
+        migrate(deps.as_mut(), mock_env(), MigrateMsg::Upgrade { new_max_supply: None }).unwrap();
+
+        let state = STATE.load(deps.as_ref().storage).unwrap();
+        assert_eq!(state.owner, Addr::unchecked(OWNER));
+        assert_eq!(state.mint_price, Uint128::new(100));
+        assert_eq!(state.protocol_fee_bps, 500);
+        assert_eq!(state.gross_proceeds, Uint128::new(300));
+        assert_eq!(state.protocol_fees_accrued, Uint128::new(15));
+        assert_eq!(state.total_mints, 3);
+        let treasury = Addr::unchecked(TREASURY);
+        let balance = get_balance(deps.as_ref().storage, &treasury, "ucore").unwrap();
+        assert_eq!(balance, Uint128::new(285));
+        assert!(BALANCES_V4.is_empty(deps.as_ref().storage));
+    }
+
+    #[test]
+    fn purchase_cost_overflow_is_rejected() {
+        let (mut app, contract) = setup_app_with(|msg| {
+            msg.runtime_config.mint_price = Uint128::MAX;
+        });
+        whitelist_buyer(&mut app, &contract);
+
+        // u128::MAX * 2 doesn't fit, the purchase fails instead of panicking
+        let err = app
+            .execute_contract(
+                Addr::unchecked(BUYER),
+                contract.clone(),
+                &purchase_msg(2),
+                &coins(1, DENOM)
+            )
+            .unwrap_err();
+        assert!(matches!(err.downcast::<ContractError>().unwrap(), ContractError::Overflow(_)));
+        assert_eq!(load_state(&app, &contract).current_token_id, 0);
+    }
+
+    #[test]
+    fn balance_and_proceeds_overflow_is_rejected() {
+        let mut deps = mock_dependencies();
+        let treasury = Addr::unchecked(TREASURY);
+        credit_balance(deps.as_mut().storage, &treasury, DENOM, Uint128::MAX).unwrap();
+        let err = credit_balance(deps.as_mut().storage, &treasury, DENOM, Uint128::one())
+            .unwrap_err();
+        assert!(matches!(err, ContractError::Overflow(_)));
+        assert_eq!(get_balance(deps.as_ref().storage, &treasury, DENOM).unwrap(), Uint128::MAX);
+
+        let mut state = State { gross_proceeds: Uint128::MAX, ..blank_state() };
+        let block = mock_env().block;
+        let err = state
+            .record_sale(deps.as_mut().storage, &block, 1, Uint128::one(), Uint128::zero())
+            .unwrap_err();
+        assert!(matches!(err, ContractError::Overflow(_)));
+
+        // a fee above 100% can't take more than the sale brought in
+        let state = State { protocol_fee_bps: 20_000, ..blank_state() };
+        let buyer = Addr::unchecked(BUYER);
+        let err = state
+            .distribute_sale(deps.as_mut().storage, &buyer, Uint128::new(100), None)
+            .unwrap_err();
+        assert!(matches!(err, ContractError::Overflow(_)));
+    }
+
+    #[test]
+    fn migrate_from_v1_converts_protocol_fee_to_bps() {
+        let mut deps = mock_dependencies();
+        seed_v1_state(deps.as_mut().storage);
+
+        let msg = MigrateMsg::Upgrade { new_max_supply: None };
+        let res = migrate(deps.as_mut(), mock_env(), msg).unwrap();
+        assert_eq!(
+            res.attributes,
+            vec![
+                attr("action", "migrate"),
+                attr("from_version", "0.1.0"),
+                attr("to_version", CONTRACT_VERSION)
+            ]
+        );
+
+        let state = STATE.load(deps.as_ref().storage).unwrap();
+        assert_eq!(state.owner, Addr::unchecked(OWNER));
+        assert_eq!(state.protocol_fee_bps, 500);
+        assert_eq!(state.current_token_id, 3);
+        assert_eq!(state.treasury_address, "treasury");
+
+        let version = cw2::get_contract_version(deps.as_ref().storage).unwrap();
+        assert_eq!(version.version, CONTRACT_VERSION);
+    }
+
+    #[test]
+    fn migrate_humanizes_a_canonical_owner() {
+        let mut deps = mock_dependencies();
+        cw2::set_contract_version(deps.as_mut().storage, CONTRACT_NAME, "0.4.0").unwrap();
+        // the canonical address was serialized as base64
+        let canonical = deps.api.addr_canonicalize(OWNER).unwrap();
+        let stored = Addr::unchecked(Binary::from(canonical.as_slice()).to_base64());
+        STATE.save(deps.as_mut().storage, &(State { owner: stored, ..blank_state() })).unwrap();
+
+        migrate(deps.as_mut(), mock_env(), MigrateMsg::Upgrade { new_max_supply: None }).unwrap();
+
+        let state = STATE.load(deps.as_ref().storage).unwrap();
+        assert_eq!(state.owner, Addr::unchecked(OWNER));
+    }
+
+    #[test]
+    fn migrate_converts_tokens_to_records() {
+        let mut deps = mock_dependencies();
+        seed_v2_state(deps.as_mut().storage);
+        let storage = deps.as_mut().storage;
+        TOKENS_V1.save(storage, "0", &Addr::unchecked(BUYER)).unwrap();
+        TOKENS_V1.save(storage, "1", &Addr::unchecked(OWNER)).unwrap();
+        TOKEN_URIS_V1.save(storage, "1", &"ipfs://one".to_string()).unwrap();
+        FROZEN_TOKENS_V1.save(storage, "0", &Empty {}).unwrap();
+        FROZEN_TOKENS_V1.save(storage, "9", &Empty {}).unwrap();
+        NFTS_V1.save(storage, &vec![0]).unwrap();
+
+        migrate(deps.as_mut(), mock_env(), MigrateMsg::Upgrade { new_max_supply: None }).unwrap();
+
+        let token = tokens().load(deps.as_ref().storage, "0").unwrap();
+        assert_eq!(token.owner, Addr::unchecked(BUYER));
+        assert!(token.frozen);
+        let token = tokens().load(deps.as_ref().storage, "1").unwrap();
+        assert_eq!(token.uri_override, Some("ipfs://one".to_string()));
+        assert!(!token.frozen);
+
+        let held = query_tokens(deps.as_ref(), BUYER.to_string(), None, None).unwrap();
+        assert_eq!(held.tokens, vec!["0".to_string()]);
+        assert_eq!(query_balance_of(deps.as_ref(), BUYER.to_string()).unwrap().count, 1);
+        let frozen = query_frozen_tokens(deps.as_ref(), None, None).unwrap();
+        assert_eq!(frozen.tokens, vec!["0".to_string()]);
+        assert!(!FROZEN_TOKENS_V1.has(deps.as_ref().storage, "9"));
+        assert!(!TOKEN_URIS_V1.has(deps.as_ref().storage, "1"));
+        assert!(NFTS_V1.may_load(deps.as_ref().storage).unwrap().is_none());
+    }
+
+    #[test]
+    fn migrate_can_raise_the_max_supply() {
+        let mut deps = mock_dependencies();
+        seed_v2_state(deps.as_mut().storage);
+
+        let msg = MigrateMsg::Upgrade { new_max_supply: Some(25) };
+        let res = migrate(deps.as_mut(), mock_env(), msg).unwrap();
+        assert_eq!(
+            res.attributes,
+            vec![
+                attr("action", "migrate"),
+                attr("from_version", "0.2.0"),
+                attr("to_version", CONTRACT_VERSION),
+                attr("old_max_supply", "10"),
+                attr("new_max_supply", "25")
+            ]
+        );
+        assert_eq!(STATE.load(deps.as_ref().storage).unwrap().max_total_mint, 25);
+
+        // omitting the cap keeps it unchanged
+        let msg: MigrateMsg = from_slice(br#"{"Upgrade":{}}"#).unwrap();
+        assert_eq!(msg, MigrateMsg::Upgrade { new_max_supply: None });
+        migrate(deps.as_mut(), mock_env(), msg).unwrap();
+        assert_eq!(STATE.load(deps.as_ref().storage).unwrap().max_total_mint, 25);
+    }
+
+    #[test]
+    fn migrate_rejects_a_max_supply_below_the_minted_count() {
+        let mut deps = mock_dependencies();
+        seed_v2_state(deps.as_mut().storage);
+
+        let msg = MigrateMsg::Upgrade { new_max_supply: Some(2) };
+        let err = migrate(deps.as_mut(), mock_env(), msg).unwrap_err();
+        assert_eq!(err, ContractError::InvalidMaxSupply { new_max: 2, minted: 3 });
+
+        // the minted count itself is the lowest valid cap
+        let msg = MigrateMsg::Upgrade { new_max_supply: Some(3) };
+        migrate(deps.as_mut(), mock_env(), msg).unwrap();
+        assert_eq!(STATE.load(deps.as_ref().storage).unwrap().max_total_mint, 3);
+    }
+
+    #[test]
+    fn migrate_rejects_other_contract() {
+        let mut deps = mock_dependencies();
+        cw2::set_contract_version(deps.as_mut().storage, "crates.io:cw721-base", "0.1.0").unwrap();
+
+        let msg = MigrateMsg::Upgrade { new_max_supply: None };
+        let err = migrate(deps.as_mut(), mock_env(), msg).unwrap_err();
+        assert_eq!(err, ContractError::InvalidContractName {
+            contract: "crates.io:cw721-base".to_string(),
+        });
+    }
+
+    #[test]
+    fn migrate_rejects_downgrade() {
+        let mut deps = mock_dependencies();
+        cw2::set_contract_version(deps.as_mut().storage, CONTRACT_NAME, "999.0.0").unwrap();
+
+        let msg = MigrateMsg::Upgrade { new_max_supply: None };
+        let err = migrate(deps.as_mut(), mock_env(), msg).unwrap_err();
+        assert_eq!(err, ContractError::CannotDowngrade { version: "999.0.0".to_string() });
+    }
+
+    #[test]
+    fn collection_deployed_by_a_factory_is_minted_by_the_creator() {
+        let mut app = App::default();
+        let code_id = app.store_code(collection_contract());
+        let mut msg = instantiate_msg(0, 0);
+        msg.deployment_config.name = "Proptix Genesis".to_string();
+        msg.deployment_config.symbol = "ptxGen".to_string();
+        msg.deployment_config.minter = Some("creator".to_string());
+        let factory = Addr::unchecked("factory");
+        let contract = app
+            .instantiate_contract(code_id, factory.clone(), &msg, &[], "collection", None)
+            .unwrap();
+
+        let info: ContractInfoResponse = app
+            .wrap()
+            .query_wasm_smart(&contract, &(QueryMsg::ContractInfo {}))
+            .unwrap();
+        assert_eq!(info.name, "Proptix Genesis");
+        assert_eq!(info.symbol, "PTXGEN");
+        assert_eq!(info.minter, Addr::unchecked("creator"));
+
+        // the factory still administers the collection
+        app.execute_contract(
+            factory.clone(),
+            contract.clone(),
+            &(ExecuteMsg::Whitelist { address: BUYER.to_string(), status: true }),
+            &[]
+        ).unwrap();
+
+        // but only the creator mints
+        let mint = ExecuteMsg::Mint {
+            class_id: "ptx".to_string(),
+            id: "genesis".to_string(),
+            uri: None,
+            uri_hash: None,
+            data: None,
+        };
+        let err = app.execute_contract(factory, contract.clone(), &mint, &[]).unwrap_err();
+        assert_eq!(err.downcast::<ContractError>().unwrap(), ContractError::unauthorized("minter"));
+        app.execute_contract(Addr::unchecked("creator"), contract.clone(), &mint, &[]).unwrap();
+        assert_eq!(load_owner(&app, &contract, "genesis"), Some(Addr::unchecked("creator")));
+    }
+
+    #[test]
+    fn contract_version_query_matches_stored_cw2_info() {
+        let (app, contract) = setup_app();
+        let raw = app.wrap().query_wasm_raw(&contract, cw2::CONTRACT.as_slice()).unwrap().unwrap();
+        let stored: cw2::ContractVersion = from_slice(&raw).unwrap();
+        assert_eq!(stored.contract, CONTRACT_NAME);
+        assert_eq!(stored.version, CONTRACT_VERSION);
+
+        let res: ContractVersionResponse = app
+            .wrap()
+            .query_wasm_smart(&contract, &(QueryMsg::ContractVersion {}))
+            .unwrap();
+        assert_eq!(res, ContractVersionResponse {
+            contract: stored.contract,
+            version: stored.version,
+            commit: BUILD_COMMIT.map(str::to_string),
+        });
+    }
+
+    #[test]
+    fn invalid_configs_are_rejected_at_instantiate() {
+        let cases: [(fn(&mut InstantiateMsg), &str, &str); 19] = [
+            (|msg| msg.deployment_config.name = " ".to_string(), "name", "must not be empty"),
+            (|msg| msg.deployment_config.symbol = String::new(), "symbol", "must not be empty"),
+            (
+                |msg| msg.deployment_config.symbol = "PT".to_string(),
+                "symbol",
+                "must be 3 to 12 characters",
+            ),
+            (
+                |msg| msg.deployment_config.symbol = "PT-X".to_string(),
+                "symbol",
+                "must only hold letters and digits",
+            ),
+            (
+                |msg| msg.runtime_config.sale_end_time = 1_000,
+                "sale_end_time",
+                "must be after the sale start",
+            ),
+            (
+                |msg| msg.runtime_config.sale_end_time = 900,
+                "sale_end_time",
+                "must be after the sale start",
+            ),
+            (
+                |msg| {
+                    msg.runtime_config.presale_start_time = 500;
+                    msg.runtime_config.presale_end_time = 500;
+                },
+                "presale_end_time",
+                "must be after the presale start",
+            ),
+            (
+                |msg| {
+                    msg.runtime_config.presale_start_time = 500;
+                    msg.runtime_config.presale_end_time = 1_000;
+                },
+                "presale_end_time",
+                "must be before the sale start",
+            ),
+            (
+                |msg| msg.runtime_config.mint_denom = String::new(),
+                "mint_denom",
+                "must not be empty",
+            ),
+            (|msg| msg.runtime_config.protocol_fee = 101, "protocol_fee", "must be at most 100"),
+            (
+                |msg| msg.runtime_config.grace_threshold_pct = 101,
+                "grace_threshold_pct",
+                "must be at most 100",
+            ),
+            (
+                |msg| {
+                    msg.runtime_config.phases = vec![
+                        phase("og", 100, 200, 50, 0, true),
+                        phase("public", 200, 300, 100, 0, false)
+                    ];
+                },
+                "phases",
+                "must be sorted without overlaps",
+            ),
+            (
+                |msg| msg.runtime_config.phases = vec![phase("og", 200, 100, 50, 0, true)],
+                "phases",
+                "must end after they start",
+            ),
+            (
+                |msg| msg.runtime_config.protocol_fee = 5,
+                "protocol_address",
+                "required with a protocol fee",
+            ),
+            (
+                |msg| {
+                    msg.runtime_config.protocol_fee = 5;
+                    msg.runtime_config.mint_price = Uint128::zero();
+                },
+                "mint_price",
+                "must be set when a protocol fee is charged",
+            ),
+            (
+                |msg| {
+                    msg.runtime_config.protocol_fee = 50;
+                    msg.runtime_config.referral_bps = 5_001;
+                },
+                "referral_bps",
+                "can't exceed the treasury share",
+            ),
+            (
+                |msg| msg.deployment_config.max_per_wallet_holding = Some(0),
+                "max_per_wallet_holding",
+                "must be above zero",
+            ),
+            (
+                |msg| msg.runtime_config.min_threshold = 11,
+                "min_threshold",
+                "must not exceed max_supply",
+            ),
+            (
+                |msg| {
+                    msg.runtime_config.reveal_at = Some(1_000);
+                    msg.runtime_config.base_token_uri = String::new();
+                },
+                "base_token_uri",
+                "must be set to reveal from the start",
+            ),
+        ];
+        for (customize, field, reason) in cases {
+            let mut deps = mock_dependencies();
+            let mut msg = instantiate_msg(1_000, 2_000);
+            customize(&mut msg);
+            let err = instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg)
+                .unwrap_err();
+            assert_eq!(err, ContractError::invalid_config(field, reason));
+        }
+        // the validation pass runs before anything is stored
+        let mut deps = mock_dependencies();
+        let mut msg = instantiate_msg(1_000, 2_000);
+        msg.runtime_config.protocol_fee = 101;
+        instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap_err();
+        assert_eq!(cw2::CONTRACT.may_load(deps.as_ref().storage).unwrap(), None);
+
+        // a base uri can be set later when the reveal comes after the sale start
+        let mut deps = mock_dependencies();
+        let mut msg = instantiate_msg(1_000, 2_000);
+        msg.runtime_config.reveal_at = Some(1_500);
+        msg.runtime_config.base_token_uri = String::new();
+        instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+
+        // an unscheduled sale can be set up later
+        let mut deps = mock_dependencies();
+        let msg = instantiate_msg(0, 0);
+        instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg).unwrap();
+    }
+
+    #[test]
+    fn invalid_addresses_are_rejected_with_their_field() {
+        // too short and not normalized
+        for invalid in ["ab", "Treasury"] {
+            let instantiate_cases: [(&str, fn(&mut InstantiateMsg, String)); 4] = [
+                ("treasury_address", |msg, value| msg.deployment_config.treasury_address = value),
+                ("minter", |msg, value| msg.deployment_config.minter = Some(value)),
+                ("protocol_address", |msg, value| {
+                    msg.deployment_config.protocol_address = Some(value);
+                }),
+                ("pass_collection", |msg, value| msg.runtime_config.pass_collection = Some(value)),
+            ];
+            for (field, customize) in instantiate_cases {
+                let mut deps = mock_dependencies();
+                let mut msg = instantiate_msg(0, 100);
+                customize(&mut msg, invalid.to_string());
+                let err = instantiate(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), msg)
+                    .unwrap_err();
+                assert_eq!(
+                    err,
+                    ContractError::InvalidAddress {
+                        field: field.to_string(),
+                        value: invalid.to_string(),
+                    }
+                );
+            }
+
+            let mut deps = mock_dependencies();
+            let env = mock_env();
+            let now = env.block.time.seconds();
+            let msg = instantiate_msg(now, now + 100);
+            instantiate(deps.as_mut(), env.clone(), mock_info(OWNER, &[]), msg).unwrap();
+            whitelist(deps.as_mut().storage, &Addr::unchecked(OWNER), true).unwrap();
+            save_token(deps.as_mut().storage, "1", OWNER);
+
+            let purchase = |recipient: Option<&str>, referrer: Option<&str>| ExecuteMsg::Purchase {
+                count: 1,
+                proof: None,
+                max_price_per_token: None,
+                recipient: recipient.map(String::from),
+                referrer: referrer.map(String::from),
+                allowlist_index: None,
+            };
+            let cases = [
+                ("address", ExecuteMsg::SetProtocolAddress { address: invalid.to_string() }),
+                ("address", ExecuteMsg::SetIcs721Address { address: invalid.to_string() }),
+                ("address", ExecuteMsg::Whitelist { address: invalid.to_string(), status: true }),
+                (
+                    "account",
+                    ExecuteMsg::AddToWhitelist {
+                        class_id: "ptx".to_string(),
+                        id: "1".to_string(),
+                        account: invalid.to_string(),
+                        allocation: None,
+                    },
+                ),
+                (
+                    "addresses",
+                    ExecuteMsg::WhitelistBatch {
+                        addresses: vec![BUYER.to_string(), invalid.to_string()],
+                        status: true,
+                    },
+                ),
+                ("recipient", purchase(Some(invalid), None)),
+                ("referrer", purchase(None, Some(invalid))),
+                (
+                    "contract",
+                    ExecuteMsg::SendNft {
+                        contract: invalid.to_string(),
+                        token_id: "1".to_string(),
+                        msg: Binary::default(),
+                    },
+                ),
+                (
+                    "user",
+                    ExecuteMsg::SetUser {
+                        token_id: "1".to_string(),
+                        user: invalid.to_string(),
+                        expires: Expiration::Never {},
+                    },
+                ),
+            ];
+            for (field, msg) in cases {
+                let info = mock_info(OWNER, &coins(100, DENOM));
+                let err = execute(deps.as_mut(), env.clone(), info, msg).unwrap_err();
+                assert_eq!(
+                    err,
+                    ContractError::InvalidAddress {
+                        field: field.to_string(),
+                        value: invalid.to_string(),
+                    }
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn error_messages_are_stable() {
+        let cases = vec![
+            (ContractError::unauthorized("owner"), "Unauthorized: sender is not the owner"),
+            (
+                ContractError::invalid_config("protocol_fee", "must be at most 100"),
+                "Invalid protocol_fee: must be at most 100",
+            ),
+            (
+                ContractError::InvalidAddress {
+                    field: "treasury_address".to_string(),
+                    value: "ab".to_string(),
+                },
+                "Invalid address for treasury_address: ab",
+            ),
+            (
+                ContractError::SaleNotActive { starts_at: 100, ends_at: 200, now: 50 },
+                "Sale is not active: it runs from 100 to 200, now is 50",
+            ),
+            (
+                ContractError::SaleNotEnded { ends_at: 200, now: 150 },
+                "Sale has not ended: it ends at 200, now is 150",
+            ),
+            (ContractError::NoSaleThreshold {}, "The sale has no minimum threshold"),
+            (
+                ContractError::ThresholdNotMet { sold: 2, threshold: 5 },
+                "The sale sold 2 of the 5 tokens it needed",
+            ),
+            (
+                ContractError::ThresholdMet { threshold: 5 },
+                "The sale sold its threshold of 5 tokens",
+            ),
+            (ContractError::EscrowReleased {}, "The sale escrow was already released"),
+            (ContractError::SaleFinalized {}, "The sale was already finalized"),
+            (
+                ContractError::InvalidUriChangeTime { effective_after: 5 },
+                "Base uri change time 5 must be later than the block time",
+            ),
+            (ContractError::NoPendingUriChange {}, "No base uri change is pending"),
+            (
+                ContractError::UriChangeNotDue { effective_after: 5, now: 3 },
+                "The base uri change can only be applied after 5, it is 3",
+            ),
+            (
+                ContractError::VetoWindowClosed { effective_after: 5 },
+                "The veto window of the base uri change closed at 5",
+            ),
+            (
+                ContractError::BlockMintLimitReached { limit: 3, minted: 2 },
+                "At most 3 tokens can be minted per block, 2 already were",
+            ),
+            (ContractError::NothingToRefund {}, "No payment to refund"),
+            (ContractError::ZeroPurchaseCount {}, "Purchase count must be at least 1"),
+            (
+                ContractError::MintPerTxExceeded { max: 50 },
+                "At most 50 tokens can be minted per transaction",
+            ),
+            (ContractError::SoldOut { remaining: 2 }, "Sold out: 2 tokens remaining"),
+            (
+                ContractError::InsufficientFunds {
+                    required: Uint128::new(300),
+                    provided: Uint128::new(100),
+                },
+                "Insufficient funds: 300 required, 100 provided",
+            ),
+            (
+                ContractError::PriceSlippage { quoted: Uint128::new(120), max: Uint128::new(100) },
+                "Price per token is 120, above the accepted maximum of 100",
+            ),
+            (
+                ContractError::WalletLimitExceeded {
+                    wallet: "buyer".to_string(),
+                    limit: 2,
+                    minted: 1,
+                },
+                "buyer can receive at most 2 tokens, 1 already minted",
+            ),
+            (
+                ContractError::AllocationExceeded { remaining: 1 },
+                "Whitelist allocation exceeded: 1 tokens remaining",
+            ),
+            (ContractError::TokenFrozen { token_id: "7".to_string() }, "Token 7 is frozen"),
+            (
+                ContractError::TokenBridged { token_id: "7".to_string() },
+                "Token 7 is escrowed for an interchain transfer",
+            ),
+            (
+                ContractError::InvalidSchedule { reason: "sale must start before it ends".to_string() },
+                "Invalid schedule: sale must start before it ends",
+            ),
+            (ContractError::MetadataFrozen {}, "Collection metadata is frozen"),
+            (
+                ContractError::UriTooLong { field: "uri".to_string(), max_len: 64 },
+                "uri is longer than 64 bytes",
+            ),
+            (
+                ContractError::TokenLocked {
+                    token_id: "7".to_string(),
+                    until: Expiration::AtHeight(100),
+                },
+                "Token 7 is locked (expiration height: 100)",
+            ),
+            (
+                ContractError::InvalidUnlockTime { unlock_time: 60 },
+                "Unlock time 60 must be later than the block time and any current lock",
+            ),
+            (
+                ContractError::PassRedeemed { token_id: "p1".to_string() },
+                "Pass p1 has already been redeemed",
+            ),
+            (ContractError::SelfReferral {}, "Buyers can't refer themselves"),
+            (ContractError::UsersDisabled {}, "Token users are disabled for this collection"),
+            (ContractError::TokenNotListed { token_id: "7".to_string() }, "Token 7 is not listed"),
+            (ContractError::ZeroListingPrice {}, "Listing price must be above zero"),
+            (
+                ContractError::InvalidRoyaltyRate { rate: "2".to_string() },
+                "Invalid royalty rate 2, expected a decimal between 0 and 1",
+            ),
+            (
+                ContractError::RoyaltyRequired {},
+                "Transfers must pay the royalty on their sale price",
+            ),
+            (ContractError::BridgeUnset {}, "No ics721 bridge is configured"),
+            (
+                ContractError::UnexpectedBridgeCallback { token_id: "7".to_string() },
+                "Unexpected bridge callback for token 7",
+            ),
+            (ContractError::TokenNotFound { token_id: "7".to_string() }, "Token 7 not found"),
+            (ContractError::TokenExists { token_id: "7".to_string() }, "Token 7 already exists"),
+            (
+                ContractError::DuplicateTokenId { token_id: "7".to_string() },
+                "Token 7 is listed more than once",
+            ),
+            (
+                ContractError::ClassExists { class_id: "ptx-core1".to_string() },
+                "Class ptx-core1 already exists",
+            ),
+            (ContractError::PhaseRootUnset { tier: 1 }, "No merkle root is set for tier 1"),
+            (ContractError::InvalidMerkleProof { tier: 1 }, "Invalid merkle proof for tier 1"),
+            (
+                ContractError::InvalidMerkleRoot { length: 5 },
+                "Merkle root must be 32 bytes, got 5",
+            ),
+            (ContractError::AllowlistIndexUnset { index: 3 }, "Allowlist index 3 is not eligible"),
+            (
+                ContractError::InvalidAllowlistRange { start: 0, count: 0 },
+                "Invalid allowlist range of 0 indexes from 0",
+            ),
+            (ContractError::VoucherSignerUnset {}, "No voucher signer is set"),
+            (ContractError::InvalidVoucher {}, "Invalid voucher signature"),
+            (
+                ContractError::BatchTooLarge { size: 51, max: 50 },
+                "Batch of 51 items exceeds the limit of 50",
+            ),
+            (ContractError::Blacklisted { address: "buyer".to_string() }, "buyer is blacklisted"),
+            (
+                ContractError::RecipientBlocked { address: "market".to_string() },
+                "market is blocked from receiving tokens",
+            ),
+            (
+                ContractError::InvalidUriHash { uri_hash: "abc".to_string() },
+                "Invalid uri hash abc, expected a hex or base64 sha256 digest",
+            ),
+            (
+                ContractError::HoldingCapExceeded { cap: 2, current: 2 },
+                "Holding cap of 2 tokens exceeded, 2 already held",
+            ),
+            (
+                ContractError::ApprovalExpired { expires: Expiration::AtHeight(5) },
+                "Approval expiry expiration height: 5 has already passed",
+            ),
+            (ContractError::SupplyFrozen {}, "Collection supply is frozen"),
+            (
+                ContractError::InvalidMaxSupply { new_max: 2, minted: 3 },
+                "Max supply 2 is below the 3 tokens already minted",
+            ),
+            (
+                ContractError::NoOffer { token_id: "a".to_string(), bidder: "buyer".to_string() },
+                "No offer from buyer on token a",
+            ),
+            (
+                ContractError::OfferExpired { expires: Expiration::AtHeight(5) },
+                "Offer expiry expiration height: 5 has already passed",
+            ),
+            (ContractError::OwnerOffer {}, "Owners can't bid on their own tokens"),
+            (
+                ContractError::RewardAssetMismatch { expected: "cw20:token".to_string() },
+                "Rewards are paid in cw20:token",
+            ),
+            (ContractError::NoRewardHolders {}, "No tokens to share the rewards between"),
+            (
+                ContractError::RewardPoolEmpty {
+                    required: Uint128::new(100),
+                    available: Uint128::new(70),
+                },
+                "Burn reward pool holds 70, 100 needed",
+            ),
+            (
+                ContractError::TokenAuctioned { token_id: "a".to_string() },
+                "Token a is up for auction",
+            ),
+            (
+                ContractError::NoAuction { token_id: "a".to_string() },
+                "Token a isn't up for auction",
+            ),
+            (ContractError::ZeroAuctionDuration {}, "Auction duration must be above zero"),
+            (
+                ContractError::AuctionEnded { token_id: "a".to_string(), end_time: 9 },
+                "Auction of token a ended at 9",
+            ),
+            (
+                ContractError::AuctionNotEnded { token_id: "a".to_string(), end_time: 9 },
+                "Auction of token a only ends at 9",
+            ),
+            (
+                ContractError::BidTooLow { bid: Uint128::new(5), min_bid: Uint128::new(6) },
+                "Bid of 5 is below the minimum of 6",
+            ),
+            (
+                ContractError::InvalidSupplyReduction { new_max: 2, max_supply: 10, minted: 3 },
+                "Max supply can only be lowered from 10 down to 3, got 2",
+            ),
+            (
+                ContractError::WhitelistLocked { sale_start_time: 100 },
+                "Whitelist removals are locked since the sale started at 100",
+            ),
+            (
+                ContractError::TooManyTransferHooks { max: 5 },
+                "At most 5 transfer hooks can be registered",
+            ),
+            (ContractError::UnknownReplyId { id: 7 }, "Unknown reply id 7"),
+            (
+                ContractError::UnknownPromoCode { code: "LAUNCH".to_string() },
+                "Unknown promo code LAUNCH",
+            ),
+            (
+                ContractError::ProtocolAddressUnset {},
+                "Protocol fee is set but the protocol address is unset",
+            ),
+            (ContractError::CommitmentExists {}, "A commitment is already pending"),
+            (
+                ContractError::NothingToWithdraw { denom: "ucore".to_string() },
+                "No ucore balance to withdraw",
+            ),
+            (ContractError::NoCommitment {}, "No pending commitment"),
+            (
+                ContractError::RevealTooEarly { reveal_height: 12 },
+                "Commitment can only be revealed from height 12",
+            ),
+            (ContractError::CommitmentExpired {}, "Commitment expired"),
+            (
+                ContractError::CommitmentNotExpired { expires_at: 112 },
+                "Commitment can only be cancelled after height 112",
+            ),
+            (
+                ContractError::CommitmentMismatch {},
+                "Commitment does not match the revealed salt and count",
+            ),
+            (
+                ContractError::InvalidContractName { contract: "crates.io:cw721-base".to_string() },
+                "Cannot migrate from a different contract: crates.io:cw721-base",
+            ),
+            (
+                ContractError::CannotDowngrade { version: "9.0.0".to_string() },
+                "Cannot migrate from newer version 9.0.0",
+            ),
+            (
+                ContractError::Payment(PaymentError::NoFunds {}),
+                "No funds sent",
+            ),
+        ];
+
+        for (err, message) in cases {
+            assert_eq!(err.to_string(), message);
+        }
+    }
+}
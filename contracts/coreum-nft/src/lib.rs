@@ -0,0 +1,3 @@
+pub mod contract;
+
+pub use crate::contract::ContractError;
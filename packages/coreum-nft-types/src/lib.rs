@@ -0,0 +1,351 @@
+//! Configs a coreum-nft collection is instantiated with. The factory validates them with the
+//! same checks as the collection, so a bad config fails before its instantiation is paid for.
+
+use cosmwasm_std::{StdError, Uint128};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Latest time in seconds a `Timestamp` can hold. Sale times beyond it were given in a finer
+/// unit, e.g. milliseconds.
+pub const MAX_TIME_SECONDS: u64 = u64::MAX / 1_000_000_000;
+
+/// Most sale phases a runtime config may hold
+pub const MAX_PHASES: usize = 10;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum ConfigError {
+    #[error("Invalid {field}: {reason}")]
+    Invalid { field: String, reason: String },
+
+    #[error("{field} is longer than {max_len} bytes")]
+    UriTooLong { field: String, max_len: u32 },
+}
+
+impl ConfigError {
+    pub fn invalid(field: &str, reason: &str) -> Self {
+        ConfigError::Invalid {
+            field: field.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+}
+
+impl From<ConfigError> for StdError {
+    fn from(err: ConfigError) -> Self {
+        StdError::generic_err(err.to_string())
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ProptixDeploymentConfig {
+    pub name: String,
+    /// 3 to 12 letters or digits, stored uppercased
+    pub symbol: String,
+    /// 0 deploys an open edition without a supply limit
+    pub max_supply: u64,
+    pub treasury_address: String,
+    /// Receives the protocol fee and is required with one. Without a fee it can be set
+    /// later through `SetProtocolAddress`.
+    pub protocol_address: Option<String>,
+    /// Forbids assigning token users through `SetUser`
+    #[serde(default)]
+    pub users_disabled: bool,
+    /// Requires `TransferNft` to pay the royalty on a sale price
+    #[serde(default)]
+    pub royalty_enforced: bool,
+    /// Tokens a single wallet may hold at once, without a limit when unset. Wallets
+    /// exempted through `SetHoldingCapExemption` aren't limited.
+    #[serde(default)]
+    pub max_per_wallet_holding: Option<u64>,
+    /// cw721 minter of the collection, the instantiating account when unset. A factory
+    /// stays the collection owner but sets the minter to the creator.
+    #[serde(default)]
+    pub minter: Option<String>,
+    /// Format of the ids minted by the collection, plain numbers when unset
+    #[serde(default)]
+    pub token_id_format: Option<TokenIdFormat>,
+    /// Share of every sale paid to the factory deploying the collection, in basis points.
+    /// Set by the factory, 0 for collections deployed without one.
+    #[serde(default)]
+    pub factory_fee_bps: u16,
+}
+
+/// Minted ids are `prefix` followed by the token number, zero padded to `width` digits.
+/// `PROPTIX-` with a width of 4 mints `PROPTIX-0001`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TokenIdFormat {
+    pub prefix: String,
+    pub width: u8,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ProptixRuntimeConfig {
+    pub base_token_uri: String,
+    pub base_token_uri_extension: String,
+    pub prereveal_token_uri: String,
+    /// Denom purchases are paid in
+    pub mint_denom: String,
+    pub mint_price: Uint128,
+    /// Public sale window in seconds, both ends inclusive
+    pub sale_start_time: u64,
+    pub sale_end_time: u64,
+    /// Protocol fee as a whole percent
+    pub protocol_fee: u8,
+    /// Optional presale window before the public sale
+    #[serde(default)]
+    pub presale_start_time: u64,
+    #[serde(default)]
+    pub presale_end_time: u64,
+    /// Tokens a single wallet may receive from sales, 0 for no limit
+    #[serde(default)]
+    pub max_per_wallet: u64,
+    /// Forbids `SetSaleWindow` and `SetPresaleWindow` once the first sale window has opened
+    #[serde(default)]
+    pub lock_schedule_on_start: bool,
+    /// Forbids removing buyers from the whitelist from `sale_start_time` on, additions
+    /// stay possible
+    #[serde(default)]
+    pub lock_whitelist_after_start: bool,
+    /// cw721 collection of mint passes redeemable through `RedeemPass`
+    #[serde(default)]
+    pub pass_collection: Option<String>,
+    /// Share of a sale paid to the referrer of a purchase in basis points
+    #[serde(default)]
+    pub referral_bps: u16,
+    /// Time in seconds the base uri replaces the prereveal uri at, without a `Reveal`
+    #[serde(default)]
+    pub reveal_at: Option<u64>,
+    /// Sale phases, sorted and not overlapping. They replace the sale and presale windows
+    /// and `mint_price`, which only apply without phases.
+    #[serde(default)]
+    pub phases: Vec<Phase>,
+    /// Seconds the public sale is extended by when more than `grace_threshold_pct` percent
+    /// of the supply is left at `sale_end_time`, 0 to never extend it
+    #[serde(default)]
+    pub grace_period_secs: u64,
+    #[serde(default)]
+    pub grace_threshold_pct: u8,
+    /// Longest `base_token_uri`, `prereveal_token_uri` or minted token uri accepted in
+    /// bytes, 0 for no limit
+    #[serde(default)]
+    pub max_uri_len: u32,
+    /// Tokens that must be sold by the end of the sale, 0 for no threshold. Payments are
+    /// escrowed until then and refunded through `ClaimRefund` if fewer tokens were sold.
+    #[serde(default)]
+    pub min_threshold: u64,
+    /// Number of first buyers recorded as early birds for later drops, 0 to keep no list
+    #[serde(default)]
+    pub early_bird_cutoff: u64,
+    /// Actions `FinalizeSale` performs once the sale is over
+    #[serde(default)]
+    pub finalize_actions: FinalizeActions,
+    /// Whether purchases emit a JSON `mint_receipt` attribute, see `MintReceipt`
+    #[serde(default)]
+    pub mint_receipts: bool,
+    /// Tokens a holder needs to veto a base uri change proposed through
+    /// `ProposeBaseUriChange`, any holder can when 0
+    #[serde(default)]
+    pub uri_veto_threshold: u64,
+    /// Tokens purchases can mint within a single block, so one buyer can't take a whole
+    /// block, 0 for no limit
+    #[serde(default)]
+    pub max_mints_per_block: u64,
+}
+
+/// Window of the sale with its own price and limits. Both ends are inclusive, in seconds.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, JsonSchema)]
+pub struct Phase {
+    pub label: String,
+    pub start: u64,
+    pub end: u64,
+    pub price: Uint128,
+    /// Tokens a single wallet may receive within the phase, 0 for no limit
+    #[serde(default)]
+    pub per_wallet_limit: u64,
+    /// Only lets through buyers allowed by the whitelist or the active tier
+    #[serde(default)]
+    pub whitelist_only: bool,
+}
+
+/// What `FinalizeSale` does once the sale is over, fixed at instantiation
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug, JsonSchema)]
+pub struct FinalizeActions {
+    /// Pays the ledger balances of the treasury out to it
+    #[serde(default)]
+    pub sweep_proceeds: bool,
+    /// Caps the supply at the tokens minted so far and freezes it
+    #[serde(default)]
+    pub freeze_supply: bool,
+    /// Switches token uris to the base uri like `Reveal`
+    #[serde(default)]
+    pub reveal: bool,
+}
+
+impl TokenIdFormat {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let valid_char = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+        if self.prefix.len() > 32 || !self.prefix.chars().all(valid_char) {
+            return Err(ConfigError::invalid(
+                "token_id_format",
+                "prefix must be at most 32 letters, digits, '-' or '_'",
+            ));
+        }
+        // u64 numbers have at most 20 digits
+        if self.width > 20 {
+            return Err(ConfigError::invalid(
+                "token_id_format",
+                "width must be at most 20",
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn format(&self, number: u64) -> String {
+        format!(
+            "{}{:0width$}",
+            self.prefix,
+            number,
+            width = usize::from(self.width)
+        )
+    }
+}
+
+impl ProptixDeploymentConfig {
+    /// Checks the collection metadata, addresses are left to the contract holding an `Api`
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.name.trim().is_empty() {
+            return Err(ConfigError::invalid("name", "must not be empty"));
+        }
+        if self.symbol.trim().is_empty() {
+            return Err(ConfigError::invalid("symbol", "must not be empty"));
+        }
+        if !(3..=12).contains(&self.symbol.len()) {
+            return Err(ConfigError::invalid("symbol", "must be 3 to 12 characters"));
+        }
+        if !self.symbol.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(ConfigError::invalid(
+                "symbol",
+                "must only hold letters and digits",
+            ));
+        }
+        if self.max_per_wallet_holding == Some(0) {
+            return Err(ConfigError::invalid(
+                "max_per_wallet_holding",
+                "must be above zero",
+            ));
+        }
+        self.token_id_format
+            .as_ref()
+            .map(TokenIdFormat::validate)
+            .transpose()?;
+        Ok(())
+    }
+}
+
+impl ProptixRuntimeConfig {
+    /// Checks the sale parameters are consistent. A sale window of all zeros is left to be
+    /// scheduled later through `SetSaleWindow`.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = ConfigError::invalid;
+        let scheduled = self.sale_start_time != 0 || self.sale_end_time != 0;
+        if scheduled && self.sale_end_time <= self.sale_start_time {
+            return Err(invalid("sale_end_time", "must be after the sale start"));
+        }
+        if self.presale_start_time != 0 || self.presale_end_time != 0 {
+            if self.presale_end_time <= self.presale_start_time {
+                return Err(invalid(
+                    "presale_end_time",
+                    "must be after the presale start",
+                ));
+            }
+            if scheduled && self.presale_end_time >= self.sale_start_time {
+                return Err(invalid("presale_end_time", "must be before the sale start"));
+            }
+        }
+        if self.mint_denom.is_empty() {
+            return Err(invalid("mint_denom", "must not be empty"));
+        }
+        if self.protocol_fee > 100 {
+            return Err(invalid("protocol_fee", "must be at most 100"));
+        }
+        if self.mint_price.is_zero() && self.protocol_fee > 0 {
+            return Err(invalid(
+                "mint_price",
+                "must be set when a protocol fee is charged",
+            ));
+        }
+        if u32::from(self.protocol_fee) * 100 + u32::from(self.referral_bps) > 10_000 {
+            return Err(invalid("referral_bps", "can't exceed the treasury share"));
+        }
+        if self.grace_threshold_pct > 100 {
+            return Err(invalid("grace_threshold_pct", "must be at most 100"));
+        }
+        // tokens revealed from the first sale resolve to the base uri right away
+        let revealed_from_start = self
+            .reveal_at
+            .map_or(false, |at| at <= self.sale_start_time);
+        if revealed_from_start && self.base_token_uri.trim().is_empty() {
+            return Err(invalid(
+                "base_token_uri",
+                "must be set to reveal from the start",
+            ));
+        }
+        let uris = [
+            ("base_token_uri", &self.base_token_uri),
+            ("prereveal_token_uri", &self.prereveal_token_uri),
+        ];
+        for (field, uri) in uris {
+            if self.max_uri_len > 0 && uri.len() > self.max_uri_len as usize {
+                return Err(ConfigError::UriTooLong {
+                    field: field.to_string(),
+                    max_len: self.max_uri_len,
+                });
+            }
+        }
+        if self.phases.len() > MAX_PHASES {
+            return Err(invalid("phases", "must hold at most 10 phases"));
+        }
+        for (i, phase) in self.phases.iter().enumerate() {
+            let duplicate = self.phases[..i]
+                .iter()
+                .any(|other| other.label == phase.label);
+            if phase.label.is_empty() || duplicate {
+                return Err(invalid("phases", "labels must be unique and not empty"));
+            }
+            if phase.end <= phase.start {
+                return Err(invalid("phases", "must end after they start"));
+            }
+            if phase.price.is_zero() && self.protocol_fee > 0 {
+                return Err(invalid(
+                    "phases",
+                    "must have a price when a protocol fee is charged",
+                ));
+            }
+        }
+        if self
+            .phases
+            .windows(2)
+            .any(|pair| pair[1].start <= pair[0].end)
+        {
+            return Err(invalid("phases", "must be sorted without overlaps"));
+        }
+        let times = [
+            ("sale_start_time", self.sale_start_time),
+            ("sale_end_time", self.sale_end_time),
+            ("presale_start_time", self.presale_start_time),
+            ("presale_end_time", self.presale_end_time),
+            ("reveal_at", self.reveal_at.unwrap_or_default()),
+        ];
+        let phase_times = self
+            .phases
+            .iter()
+            .flat_map(|phase| [("phases", phase.start), ("phases", phase.end)]);
+        for (field, time) in times.into_iter().chain(phase_times) {
+            if time > MAX_TIME_SECONDS {
+                return Err(invalid(field, "must be in seconds"));
+            }
+        }
+        Ok(())
+    }
+}